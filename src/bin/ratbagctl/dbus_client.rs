@@ -4,9 +4,13 @@
 //!
 //! All communication with the daemon goes through this module.
 
+use std::collections::HashMap;
+use std::time::Duration;
+
 use anyhow::{anyhow, Context, Result};
-use zbus::zvariant::{OwnedValue, Value};
-use zbus::Connection;
+use tokio_stream::StreamExt;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::{Connection, MatchRule, MessageStream};
 
 const BUS_NAME: &str = "org.freedesktop.ratbag1";
 const MANAGER_PATH: &str = "/org/freedesktop/ratbag1";
@@ -16,6 +20,98 @@ const PROFILE_IFACE: &str = "org.freedesktop.ratbag1.Profile";
 const RESOLUTION_IFACE: &str = "org.freedesktop.ratbag1.Resolution";
 const BUTTON_IFACE: &str = "org.freedesktop.ratbag1.Button";
 const LED_IFACE: &str = "org.freedesktop.ratbag1.Led";
+const RECEIVER_IFACE: &str = "org.freedesktop.ratbag1.Receiver";
+const POWER_IFACE: &str = "org.freedesktop.ratbag1.Power";
+
+/// The Device `Sensor` property: name, minimum, maximum and step DPI,
+/// separate X/Y support and lift-off distances in millimetres.
+pub type SensorDescriptor = (String, u32, u32, u32, bool, Vec<u32>);
+
+/// One device's outcome of `SetLightingAll`: device path, the
+/// `(profile, led, mode)` triples applied to its LEDs and a commit error,
+/// empty on success.
+pub type LightingOutcome = (String, Vec<(u32, u32, u32)>, String);
+
+/// An entry of the Led `Effects` property: id, name and the
+/// `(name, min, max)` schema of its parameters.
+pub type LedEffect = (u32, String, Vec<(String, u32, u32)>);
+
+/// One step of a button macro, as carried by the Button `MacroEvents`
+/// property.  Key and consumer values are HID usages, button values
+/// 1-based mouse buttons and waits milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroEvent {
+    KeyPress(u32),
+    KeyRelease(u32),
+    Wait(u32),
+    ButtonPress(u32),
+    ButtonRelease(u32),
+    Consumer(u32),
+    Wheel(i32),
+    PointerMove { x: i32, y: i32 },
+    WaitForRelease,
+    RepeatWhilePressed,
+}
+
+impl MacroEvent {
+    /// Encode as the `(type, value, data)` triple the daemon expects.
+    fn to_dbus(self) -> (u32, u32, Value<'static>) {
+        match self {
+            MacroEvent::KeyPress(v) => (0, v, Value::from(0u32)),
+            MacroEvent::KeyRelease(v) => (1, v, Value::from(0u32)),
+            MacroEvent::Wait(v) => (2, v, Value::from(0u32)),
+            MacroEvent::ButtonPress(v) => (3, v, Value::from(0u32)),
+            MacroEvent::ButtonRelease(v) => (4, v, Value::from(0u32)),
+            MacroEvent::Consumer(v) => (5, v, Value::from(0u32)),
+            MacroEvent::Wheel(t) => (6, 0, Value::from(t)),
+            MacroEvent::PointerMove { x, y } => (7, 0, Value::from((x, y))),
+            MacroEvent::WaitForRelease => (8, 0, Value::from(0u32)),
+            MacroEvent::RepeatWhilePressed => (9, 0, Value::from(0u32)),
+        }
+    }
+
+    /// Decode one `(type, value, data)` triple; None for unknown types.
+    fn from_dbus(kind: u32, value: u32, data: &Value<'_>) -> Option<Self> {
+        let mut data = data;
+        while let Value::Value(inner) = data {
+            data = inner.as_ref();
+        }
+        Some(match (kind, data) {
+            (0, _) => MacroEvent::KeyPress(value),
+            (1, _) => MacroEvent::KeyRelease(value),
+            (2, _) => MacroEvent::Wait(value),
+            (3, _) => MacroEvent::ButtonPress(value),
+            (4, _) => MacroEvent::ButtonRelease(value),
+            (5, _) => MacroEvent::Consumer(value),
+            (6, Value::I32(t)) => MacroEvent::Wheel(*t),
+            (7, Value::Structure(s)) => match s.fields() {
+                [Value::I32(x), Value::I32(y)] => MacroEvent::PointerMove { x: *x, y: *y },
+                _ => return None,
+            },
+            (8, _) => MacroEvent::WaitForRelease,
+            (9, _) => MacroEvent::RepeatWhilePressed,
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for MacroEvent {
+    /// Formats the event in the syntax `ratbagctl button set-macro` accepts.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MacroEvent::KeyPress(k) => write!(f, "{}:1", k),
+            MacroEvent::KeyRelease(k) => write!(f, "{}:0", k),
+            MacroEvent::Wait(ms) => write!(f, "wait:{}", ms),
+            MacroEvent::ButtonPress(b) => write!(f, "button:{}:1", b),
+            MacroEvent::ButtonRelease(b) => write!(f, "button:{}:0", b),
+            MacroEvent::Consumer(u) => write!(f, "consumer:{}", u),
+            MacroEvent::Wheel(t) => write!(f, "wheel:{}", t),
+            MacroEvent::PointerMove { x, y } => write!(f, "move:{}:{}", x, y),
+            MacroEvent::WaitForRelease => write!(f, "wait-release"),
+            MacroEvent::RepeatWhilePressed => write!(f, "repeat"),
+        }
+    }
+}
 
 /// A client that talks to the `ratbagd` daemon over the system DBus.
 pub struct RatbagClient {
@@ -66,6 +162,57 @@ impl RatbagClient {
         Ok(())
     }
 
+    /// Commit every device with pending changes; returns each dirty
+    /// device's path and error message, empty on success.
+    pub async fn commit_all(&self, rollback: bool) -> Result<Vec<(String, String)>> {
+        let reply = self
+            .conn
+            .call_method(
+                Some(BUS_NAME),
+                MANAGER_PATH,
+                Some(MANAGER_IFACE),
+                "CommitAll",
+                &(rollback,),
+            )
+            .await
+            .context("CommitAll call failed")?;
+        let results: HashMap<OwnedObjectPath, String> = reply.body().deserialize()?;
+        let mut results: Vec<_> = results
+            .into_iter()
+            .map(|(path, error)| (path.to_string(), error))
+            .collect();
+        results.sort();
+        Ok(results)
+    }
+
+    /// Apply one lighting setup to every device and commit them all.
+    pub async fn set_lighting_all(
+        &self,
+        mode: u32,
+        colors: &[(u32, u32, u32)],
+        brightness: u32,
+    ) -> Result<Vec<LightingOutcome>> {
+        for &(r, g, b) in colors {
+            validate_rgb(r, g, b)?;
+        }
+        let reply = self
+            .conn
+            .call_method(
+                Some(BUS_NAME),
+                MANAGER_PATH,
+                Some(MANAGER_IFACE),
+                "SetLightingAll",
+                &(mode, colors, brightness),
+            )
+            .await
+            .context("SetLightingAll call failed")?;
+        let results: Vec<(OwnedObjectPath, _, _)> = reply.body().deserialize()?;
+        Ok(results
+            .into_iter()
+            .map(|(path, mapping, error)| (path.to_string(), mapping, error))
+            .collect())
+    }
+
     /// Resolve a device specifier (numeric index or sysname substring) to a
     /// full object path.
     pub async fn resolve_device(&self, spec: &str) -> Result<String> {
@@ -106,6 +253,12 @@ impl RatbagClient {
         self.get_string_property(path, DEVICE_IFACE, "FirmwareVersion").await
     }
 
+    pub async fn get_device_sensor(&self, path: &str) -> Result<SensorDescriptor> {
+        let val = self.get_property(path, DEVICE_IFACE, "Sensor").await?;
+        SensorDescriptor::try_from(val)
+            .with_context(|| format!("Malformed Sensor property at {}", path))
+    }
+
     pub async fn get_device_profiles(&self, path: &str) -> Result<Vec<String>> {
         let val = self.get_property(path, DEVICE_IFACE, "Profiles").await?;
         extract_object_path_array(val).context("Failed to parse Profiles property")
@@ -185,6 +338,20 @@ impl RatbagClient {
         self.get_vec_u32_property(path, PROFILE_IFACE, "Debounces").await
     }
 
+    /// Lift-off distance in mm, or -1 if unsupported.
+    pub async fn get_profile_lift_off_distance(&self, path: &str) -> Result<i32> {
+        self.get_i32_property(path, PROFILE_IFACE, "LiftOffDistance").await
+    }
+
+    pub async fn set_profile_lift_off_distance(&self, path: &str, value: i32) -> Result<()> {
+        self.set_property(path, PROFILE_IFACE, "LiftOffDistance", Value::from(value))
+            .await
+    }
+
+    pub async fn get_profile_lift_off_distances(&self, path: &str) -> Result<Vec<u32>> {
+        self.get_vec_u32_property(path, PROFILE_IFACE, "LiftOffDistances").await
+    }
+
     pub async fn set_profile_report_rate(&self, path: &str, rate: u32) -> Result<()> {
         self.set_property(path, PROFILE_IFACE, "ReportRate", Value::from(rate))
             .await
@@ -198,6 +365,23 @@ impl RatbagClient {
         Ok(())
     }
 
+    pub async fn call_profile_reset_to_default(&self, path: &str) -> Result<()> {
+        self.conn
+            .call_method(Some(BUS_NAME), path, Some(PROFILE_IFACE), "ResetToDefault", &())
+            .await
+            .context("ResetToDefault call failed")?;
+        Ok(())
+    }
+
+    pub async fn call_profile_copy_from(&self, path: &str, source: &str) -> Result<()> {
+        let source = zbus::zvariant::ObjectPath::try_from(source)?;
+        self.conn
+            .call_method(Some(BUS_NAME), path, Some(PROFILE_IFACE), "CopyFrom", &(source,))
+            .await
+            .context("CopyFrom call failed")?;
+        Ok(())
+    }
+
     pub async fn get_profile_resolutions(&self, path: &str) -> Result<Vec<String>> {
         let val = self.get_property(path, PROFILE_IFACE, "Resolutions").await?;
         extract_object_path_array(val).context("Failed to parse Resolutions property")
@@ -229,6 +413,10 @@ impl RatbagClient {
         self.get_bool_property(path, RESOLUTION_IFACE, "IsDefault").await
     }
 
+    pub async fn get_resolution_is_shift(&self, path: &str) -> Result<bool> {
+        self.get_bool_property(path, RESOLUTION_IFACE, "IsShift").await
+    }
+
     pub async fn get_resolution_is_disabled(&self, path: &str) -> Result<bool> {
         self.get_bool_property(path, RESOLUTION_IFACE, "IsDisabled").await
     }
@@ -249,11 +437,13 @@ impl RatbagClient {
 
     /// Get the DPI as a display string.
     ///
-    /// The DBus property is a variant: either `u32` or `(u32, u32)`.
+    /// The DBus property is a variant: either `u32` or `(u32, u32)`; a
+    /// plain 0 means the device could not report it.
     pub async fn get_resolution_dpi(&self, path: &str) -> Result<String> {
         let val = self.get_property(path, RESOLUTION_IFACE, "Resolution").await?;
         let inner: Value<'_> = val.into();
         match &inner {
+            Value::U32(0) => Ok("unknown DPI".to_string()),
             Value::U32(v) => Ok(format!("{} DPI", v)),
             Value::Structure(s) => {
                 if let [Value::U32(x), Value::U32(y)] = s.fields() {
@@ -306,6 +496,20 @@ impl RatbagClient {
         Ok(())
     }
 
+    pub async fn call_resolution_set_shift(&self, path: &str) -> Result<()> {
+        self.conn
+            .call_method(
+                Some(BUS_NAME),
+                path,
+                Some(RESOLUTION_IFACE),
+                "SetShift",
+                &(),
+            )
+            .await
+            .context("SetShift call failed")?;
+        Ok(())
+    }
+
     // -----------------------------------------------------------------------
     // Button
     // -----------------------------------------------------------------------
@@ -316,7 +520,8 @@ impl RatbagClient {
 
     /// Returns `(action_type, mapping_display_string)`.
     ///
-    /// For macro mappings (type 4) the display string shows decoded key events.
+    /// For macro mappings (type 4) the display string lists the events read
+    /// from the `MacroEvents` property.
     pub async fn get_button_mapping(&self, path: &str) -> Result<(u32, String)> {
         let val = self.get_property(path, BUTTON_IFACE, "Mapping").await?;
         let inner: Value<'_> = val.into();
@@ -328,19 +533,9 @@ impl RatbagClient {
                 }
                 let display = match unwrapped {
                     Value::U32(v) => v.to_string(),
-                    Value::Array(arr) => {
-                        // Decode macro entries: Vec<(u32, u32)> = (keycode, direction)
-                        let mut entries: Vec<String> = Vec::with_capacity(arr.len());
-                        for item in arr.iter() {
-                            if let Value::Structure(t) = item {
-                                if let [Value::U32(keycode), Value::U32(dir)] = t.fields() {
-                                    let arrow = if *dir == 1 { "↓" } else { "↑" };
-                                    entries.push(format!("{}:{}", keycode, arrow));
-                                    continue;
-                                }
-                            }
-                            return Err(anyhow!("Malformed macro mapping entry at {}", path));
-                        }
+                    Value::Array(_) => {
+                        let events = self.get_button_macro(path).await?;
+                        let entries: Vec<String> = events.iter().map(|e| e.to_string()).collect();
                         entries.join(" ")
                     }
                     _ => return Err(anyhow!("Unsupported Mapping payload type at {}", path)),
@@ -355,6 +550,10 @@ impl RatbagClient {
         self.get_vec_u32_property(path, BUTTON_IFACE, "ActionTypes").await
     }
 
+    pub async fn get_button_layer(&self, path: &str) -> Result<u32> {
+        self.get_u32_property(path, BUTTON_IFACE, "Layer").await
+    }
+
     pub async fn set_button_mapping(
         &self,
         path: &str,
@@ -366,19 +565,66 @@ impl RatbagClient {
             .await
     }
 
-    /// Set a macro mapping (action type 4) with a list of (keycode, direction) pairs.
-    pub async fn set_button_macro_mapping(
-        &self,
-        path: &str,
-        events: &[(u32, u32)],
-    ) -> Result<()> {
-        for &(keycode, direction) in events {
-            anyhow::ensure!(keycode <= u16::MAX as u32, "Invalid keycode {} (max 65535)", keycode);
-            anyhow::ensure!(direction <= 1, "Invalid macro direction {} (expected 0 or 1)", direction);
+    /// Read the macro bound to a button from the `MacroEvents` property.
+    pub async fn get_button_macro(&self, path: &str) -> Result<Vec<MacroEvent>> {
+        let val = self.get_property(path, BUTTON_IFACE, "MacroEvents").await?;
+        let inner: Value<'_> = val.into();
+        let Value::Array(arr) = inner else {
+            return Err(anyhow!("Malformed MacroEvents property at {}", path));
+        };
+        let mut events = Vec::with_capacity(arr.len());
+        for item in arr.iter() {
+            let event = match item {
+                Value::Structure(t) => match t.fields() {
+                    [Value::U32(kind), Value::U32(value), data] => {
+                        MacroEvent::from_dbus(*kind, *value, data)
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+            events.push(event.ok_or_else(|| anyhow!("Malformed macro event at {}", path))?);
         }
-        let arr: Vec<(u32, u32)> = events.to_vec();
-        let mapping = (4u32, Value::from(arr));
-        self.set_property(path, BUTTON_IFACE, "Mapping", Value::from(mapping))
+        Ok(events)
+    }
+
+    /// Bind a macro to a button through the `MacroEvents` property, which
+    /// also switches its action type to macro (4).
+    pub async fn set_button_macro_mapping(&self, path: &str, events: &[MacroEvent]) -> Result<()> {
+        for event in events {
+            if let MacroEvent::KeyPress(k) | MacroEvent::KeyRelease(k) = event {
+                anyhow::ensure!(*k <= u16::MAX as u32, "Invalid keycode {} (max 65535)", k);
+            }
+        }
+        let arr: Vec<(u32, u32, Value<'_>)> = events.iter().map(|e| e.to_dbus()).collect();
+        self.set_property(path, BUTTON_IFACE, "MacroEvents", Value::from(arr))
+            .await
+    }
+
+    pub async fn get_button_macro_name(&self, path: &str) -> Result<String> {
+        self.get_string_property(path, BUTTON_IFACE, "MacroName").await
+    }
+
+    pub async fn set_button_macro_name(&self, path: &str, name: &str) -> Result<()> {
+        self.set_property(path, BUTTON_IFACE, "MacroName", Value::from(name))
+            .await
+    }
+
+    pub async fn get_button_macro_group(&self, path: &str) -> Result<String> {
+        self.get_string_property(path, BUTTON_IFACE, "MacroGroup").await
+    }
+
+    pub async fn set_button_macro_group(&self, path: &str, group: &str) -> Result<()> {
+        self.set_property(path, BUTTON_IFACE, "MacroGroup", Value::from(group))
+            .await
+    }
+
+    pub async fn get_button_macro_loop_count(&self, path: &str) -> Result<u32> {
+        self.get_u32_property(path, BUTTON_IFACE, "MacroLoopCount").await
+    }
+
+    pub async fn set_button_macro_loop_count(&self, path: &str, count: u32) -> Result<()> {
+        self.set_property(path, BUTTON_IFACE, "MacroLoopCount", Value::from(count))
             .await
     }
 
@@ -476,6 +722,141 @@ impl RatbagClient {
             .await
     }
 
+    /// Device-specific effects as `(id, name, [(param, min, max)])`.
+    pub async fn get_led_effects(&self, path: &str) -> Result<Vec<LedEffect>> {
+        let val = self.get_property(path, LED_IFACE, "Effects").await?;
+        Vec::<LedEffect>::try_from(val)
+            .with_context(|| format!("Malformed Effects property at {}", path))
+    }
+
+    pub async fn get_led_effect(&self, path: &str) -> Result<(u32, HashMap<String, u32>)> {
+        let val = self.get_property(path, LED_IFACE, "Effect").await?;
+        <(u32, HashMap<String, u32>)>::try_from(val)
+            .with_context(|| format!("Malformed Effect property at {}", path))
+    }
+
+    pub async fn set_led_effect(
+        &self,
+        path: &str,
+        id: u32,
+        params: HashMap<String, u32>,
+    ) -> Result<()> {
+        self.set_property(path, LED_IFACE, "Effect", Value::from((id, params)))
+            .await
+    }
+
+    // -----------------------------------------------------------------------
+    // Receiver
+    // -----------------------------------------------------------------------
+
+    /// List the receiver's paired devices as `(slot, wpid, name)`.
+    pub async fn list_paired(&self, path: &str) -> Result<Vec<(u32, u32, String)>> {
+        let reply = self
+            .conn
+            .call_method(Some(BUS_NAME), path, Some(RECEIVER_IFACE), "ListPaired", &())
+            .await
+            .context("ListPaired call failed")?;
+        let devices: Vec<(u32, u32, String)> = reply.body().deserialize()?;
+        Ok(devices)
+    }
+
+    /// Open the receiver's pairing window and wait for it to close.
+    ///
+    /// Returns the `(status, slot, wpid)` carried by `PairingFinished`.
+    pub async fn pair(&self, path: &str, timeout: u32) -> Result<(u32, u32, u32)> {
+        /* Subscribe before starting so the signal cannot slip past. */
+        let rule = MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .interface(RECEIVER_IFACE)?
+            .member("PairingFinished")?
+            .path(path)?
+            .build();
+        let mut stream = MessageStream::for_match_rule(rule, &self.conn, None)
+            .await
+            .context("Cannot subscribe to PairingFinished")?;
+
+        self.conn
+            .call_method(Some(BUS_NAME), path, Some(RECEIVER_IFACE), "StartPairing", &(timeout,))
+            .await
+            .context("StartPairing call failed")?;
+
+        /* The daemon gives the receiver a few seconds past the window to
+         * report back; allow for that plus bus latency. */
+        let wait = Duration::from_secs(u64::from(timeout) + 10);
+        let msg = tokio::time::timeout(wait, stream.next())
+            .await
+            .map_err(|_| anyhow!("No PairingFinished signal after {}s", wait.as_secs()))?
+            .context("Signal stream closed before pairing finished")??;
+        let result: (u32, u32, u32) = msg.body().deserialize()?;
+        Ok(result)
+    }
+
+    /// Unpair the device in receiver slot `slot`.
+    pub async fn unpair(&self, path: &str, slot: u32) -> Result<()> {
+        self.conn
+            .call_method(Some(BUS_NAME), path, Some(RECEIVER_IFACE), "Unpair", &(slot,))
+            .await
+            .context("Unpair call failed")?;
+        Ok(())
+    }
+
+    // -----------------------------------------------------------------------
+    // Power
+    // -----------------------------------------------------------------------
+
+    /// Read the battery from the device now; returns the level in percent,
+    /// or -1 if the device is asleep and has never been read.
+    pub async fn refresh_battery(&self, path: &str) -> Result<i32> {
+        let reply = self
+            .conn
+            .call_method(Some(BUS_NAME), path, Some(POWER_IFACE), "RefreshBattery", &())
+            .await
+            .context("RefreshBattery call failed")?;
+        let level: i32 = reply.body().deserialize()?;
+        Ok(level)
+    }
+
+    /// Battery level in percent, or -1 if it has not been read.
+    pub async fn get_battery_level(&self, path: &str) -> Result<i32> {
+        self.get_i32_property(path, POWER_IFACE, "BatteryLevel").await
+    }
+
+    pub async fn get_is_charging(&self, path: &str) -> Result<bool> {
+        self.get_bool_property(path, POWER_IFACE, "IsCharging").await
+    }
+
+    pub async fn get_is_asleep(&self, path: &str) -> Result<bool> {
+        self.get_bool_property(path, POWER_IFACE, "IsAsleep").await
+    }
+
+    /// Sleep timeout in seconds, or -1 if the device cannot report it.
+    pub async fn get_sleep_timeout(&self, path: &str) -> Result<i32> {
+        self.get_i32_property(path, POWER_IFACE, "SleepTimeout").await
+    }
+
+    pub async fn get_sleep_timeouts(&self, path: &str) -> Result<Vec<u32>> {
+        self.get_vec_u32_property(path, POWER_IFACE, "SleepTimeouts").await
+    }
+
+    pub async fn set_sleep_timeout(&self, path: &str, seconds: i32) -> Result<()> {
+        self.set_property(path, POWER_IFACE, "SleepTimeout", Value::from(seconds))
+            .await
+    }
+
+    /// Low-battery alert threshold in percent, or -1 if the device has none.
+    pub async fn get_low_battery_alert(&self, path: &str) -> Result<i32> {
+        self.get_i32_property(path, POWER_IFACE, "LowBatteryAlert").await
+    }
+
+    pub async fn get_low_battery_alerts(&self, path: &str) -> Result<Vec<u32>> {
+        self.get_vec_u32_property(path, POWER_IFACE, "LowBatteryAlerts").await
+    }
+
+    pub async fn set_low_battery_alert(&self, path: &str, percent: i32) -> Result<()> {
+        self.set_property(path, POWER_IFACE, "LowBatteryAlert", Value::from(percent))
+            .await
+    }
+
     // -----------------------------------------------------------------------
     // Generic helpers
     // -----------------------------------------------------------------------
@@ -2,10 +2,12 @@
  * modify profiles/resolutions/buttons/LEDs, and exercise dev-hook test devices. */
 mod dbus_client;
 
+use std::collections::HashMap;
+
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 
-use dbus_client::RatbagClient;
+use dbus_client::{MacroEvent, RatbagClient};
 
 /// ratbagctl — configure gaming mice via the ratbagd DBus daemon.
 #[derive(Parser)]
@@ -29,7 +31,14 @@ enum Commands {
     /// Commit pending changes to hardware.
     Commit {
         /// Device index or sysname.
-        device: String,
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        device: Option<String>,
+        /// Commit every device with pending changes.
+        #[arg(long)]
+        all: bool,
+        /// With --all, undo the devices that committed if any other fails.
+        #[arg(long, requires = "all")]
+        rollback: bool,
     },
 
     /// Profile commands.
@@ -48,6 +57,14 @@ enum Commands {
     #[command(subcommand)]
     Led(LedCmd),
 
+    /// Wireless receiver pairing commands.
+    #[command(subcommand)]
+    Receiver(ReceiverCmd),
+
+    /// Battery and sleep timeout commands.
+    #[command(subcommand)]
+    Power(PowerCmd),
+
     /// Dev-hooks test commands (requires daemon built with dev-hooks).
     #[command(subcommand)]
     Test(TestCmd),
@@ -125,6 +142,34 @@ enum ProfileCmd {
         /// New debounce time in ms (omit to read current + supported values).
         ms: Option<i32>,
     },
+    /// Get or set the sensor lift-off distance in mm.
+    Lod {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+        /// New distance in mm (omit to read current + supported values).
+        mm: Option<i32>,
+    },
+    /// Restore a profile to its factory defaults.
+    Reset {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+    },
+    /// Copy resolutions, buttons, LEDs, rate and debounce to another profile.
+    Copy {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index to copy from.
+        src: u32,
+        /// Profile index to copy to.
+        dst: u32,
+        /// Copy to this device instead, which must be the same model.
+        #[arg(long)]
+        to: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -165,6 +210,15 @@ enum ResolutionCmd {
         /// Resolution index to make default.
         resolution: u32,
     },
+    /// Set the shift ("sniper") resolution used while DPI shift is held.
+    Shift {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+        /// Resolution index to use as the shift resolution.
+        resolution: u32,
+    },
     /// Enable a resolution slot.
     Enable {
         /// Device index or sysname.
@@ -241,9 +295,12 @@ enum ButtonCmd {
     },
     /// Set button to a macro (action type 4).
     ///
-    /// Events are specified as KEYCODE:DIRECTION pairs separated by spaces,
-    /// where DIRECTION is 1 for press and 0 for release.
-    /// Example: "30:1 30:0" (press and release KEY_A).
+    /// Events are separated by spaces. KEYCODE:DIRECTION presses (1) or
+    /// releases (0) a key; button:N:DIRECTION does the same for mouse
+    /// button N. wait:MS pauses, wheel:TICKS scrolls, consumer:USAGE sends a
+    /// consumer-control key, move:X:Y moves the pointer, wait-release waits
+    /// for the button to be released and repeat loops while it is held.
+    /// Example: "30:1 wait:50 30:0" (press KEY_A, wait 50 ms, release it).
     #[command(name = "set-macro")]
     SetMacro {
         /// Device index or sysname.
@@ -252,7 +309,16 @@ enum ButtonCmd {
         profile: u32,
         /// Button index.
         button: u32,
-        /// Macro events as "KEYCODE:DIR KEYCODE:DIR …".
+        /// Macro name, on devices that store one.
+        #[arg(long)]
+        name: Option<String>,
+        /// Macro group, on devices that store one.
+        #[arg(long)]
+        group: Option<String>,
+        /// Times the macro plays per press, on devices that support it.
+        #[arg(long)]
+        loop_count: Option<u32>,
+        /// Macro events as "KEYCODE:DIR wait:MS KEYCODE:DIR …".
         events: Vec<String>,
     },
     /// Disable a button (action type 0).
@@ -352,6 +418,76 @@ enum LedCmd {
         /// Duration in milliseconds (0-10000).
         ms: u32,
     },
+    /// Get or set a device-specific LED effect.
+    Effect {
+        /// Device index or sysname.
+        device: String,
+        /// Profile index.
+        profile: u32,
+        /// LED index.
+        led: u32,
+        /// Effect name (omit to list the effects the LED offers).
+        name: Option<String>,
+        /// Effect parameters as NAME=VALUE, e.g. speed=50.
+        params: Vec<String>,
+    },
+    /// Apply one lighting setup to every LED of every device and commit.
+    Sync {
+        /// Mode: off, solid, cycle, wave, starlight, breathing, tricolor.
+        mode: String,
+        /// Up to three hex RGB colors (primary, secondary, tertiary).
+        colors: Vec<String>,
+        /// Brightness value 0-255.
+        #[arg(long, default_value_t = 255)]
+        brightness: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReceiverCmd {
+    /// List the devices paired with the receiver.
+    List {
+        /// Device index or sysname of a device on the receiver.
+        device: String,
+    },
+    /// Open the pairing window and wait for a device to pair.
+    Pair {
+        /// Device index or sysname of a device on the receiver.
+        device: String,
+        /// Pairing window in seconds (1-255).
+        #[arg(long, default_value_t = 30)]
+        timeout: u32,
+    },
+    /// Unpair the device in a receiver slot.
+    Unpair {
+        /// Device index or sysname of a device on the receiver.
+        device: String,
+        /// Receiver slot (1-6, from `ratbagctl receiver list`).
+        slot: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum PowerCmd {
+    /// Read the battery and show the power settings.
+    Info {
+        /// Device index or sysname.
+        device: String,
+    },
+    /// Get or set the idle time before the device sleeps.
+    Sleep {
+        /// Device index or sysname.
+        device: String,
+        /// Timeout in seconds (omit to read current + supported values).
+        seconds: Option<u32>,
+    },
+    /// Get or set the battery level at which the device warns the user.
+    Alert {
+        /// Device index or sysname.
+        device: String,
+        /// Threshold in percent, 0 = off (omit to read current + supported values).
+        percent: Option<u32>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -376,7 +512,14 @@ async fn main() -> Result<()> {
     match cli.command {
         Commands::List => cmd_list(&client).await,
         Commands::Info { device } => cmd_info(&client, &device).await,
-        Commands::Commit { device } => cmd_commit(&client, &device).await,
+        Commands::Commit {
+            device,
+            all,
+            rollback,
+        } => match device {
+            Some(device) if !all => cmd_commit(&client, &device).await,
+            _ => cmd_commit_all(&client, rollback).await,
+        },
         Commands::Profile(sub) => match sub {
             ProfileCmd::List { device } => cmd_profile_list(&client, &device).await,
             ProfileCmd::Info { device, profile } => {
@@ -411,6 +554,20 @@ async fn main() -> Result<()> {
                 profile,
                 ms,
             } => cmd_profile_debounce(&client, &device, profile, ms).await,
+            ProfileCmd::Lod {
+                device,
+                profile,
+                mm,
+            } => cmd_profile_lod(&client, &device, profile, mm).await,
+            ProfileCmd::Reset { device, profile } => {
+                cmd_profile_reset(&client, &device, profile).await
+            }
+            ProfileCmd::Copy {
+                device,
+                src,
+                dst,
+                to,
+            } => cmd_profile_copy(&client, &device, src, dst, to.as_deref()).await,
         },
         Commands::Resolution(sub) => match sub {
             ResolutionCmd::List { device, profile } => {
@@ -432,6 +589,11 @@ async fn main() -> Result<()> {
                 profile,
                 resolution,
             } => cmd_resolution_default(&client, &device, profile, resolution).await,
+            ResolutionCmd::Shift {
+                device,
+                profile,
+                resolution,
+            } => cmd_resolution_shift(&client, &device, profile, resolution).await,
             ResolutionCmd::Enable {
                 device,
                 profile,
@@ -474,8 +636,14 @@ async fn main() -> Result<()> {
                 device,
                 profile,
                 button,
+                name,
+                group,
+                loop_count,
                 events,
-            } => cmd_button_set_macro(&client, &device, profile, button, &events).await,
+            } => {
+                let metadata = MacroMetadataArgs { name, group, loop_count };
+                cmd_button_set_macro(&client, &device, profile, button, &events, &metadata).await
+            }
             ButtonCmd::Disable {
                 device,
                 profile,
@@ -525,6 +693,36 @@ async fn main() -> Result<()> {
                 led,
                 ms,
             } => cmd_led_duration(&client, &device, profile, led, ms).await,
+            LedCmd::Effect {
+                device,
+                profile,
+                led,
+                name,
+                params,
+            } => cmd_led_effect(&client, &device, profile, led, name.as_deref(), &params).await,
+            LedCmd::Sync {
+                mode,
+                colors,
+                brightness,
+            } => cmd_led_sync(&client, &mode, &colors, brightness).await,
+        },
+        Commands::Receiver(sub) => match sub {
+            ReceiverCmd::List { device } => cmd_receiver_list(&client, &device).await,
+            ReceiverCmd::Pair { device, timeout } => {
+                cmd_receiver_pair(&client, &device, timeout).await
+            }
+            ReceiverCmd::Unpair { device, slot } => {
+                cmd_receiver_unpair(&client, &device, slot).await
+            }
+        },
+        Commands::Power(sub) => match sub {
+            PowerCmd::Info { device } => cmd_power_info(&client, &device).await,
+            PowerCmd::Sleep { device, seconds } => {
+                cmd_power_sleep(&client, &device, seconds).await
+            }
+            PowerCmd::Alert { device, percent } => {
+                cmd_power_alert(&client, &device, percent).await
+            }
         },
         Commands::Test(sub) => match sub {
             TestCmd::LoadDevice { json_file } => cmd_test_load_device(&client, &json_file).await,
@@ -590,6 +788,25 @@ async fn cmd_info(client: &RatbagClient, device: &str) -> Result<()> {
     if !fw.is_empty() {
         println!("Firmware:  {}", fw);
     }
+    if let Ok((sensor, min, max, step, separate_xy, lods)) = client.get_device_sensor(&path).await
+        && max > 0
+    {
+        let mut line = format!("{}-{} DPI", min, max);
+        if step > 0 {
+            line.push_str(&format!(" in steps of {}", step));
+        }
+        if separate_xy {
+            line.push_str(", separate X/Y");
+        }
+        if !lods.is_empty() {
+            let lods: Vec<String> = lods.iter().map(u32::to_string).collect();
+            line.push_str(&format!(", lift-off {} mm", lods.join("/")));
+        }
+        if !sensor.is_empty() {
+            line = format!("{} ({})", sensor, line);
+        }
+        println!("Sensor:    {}", line);
+    }
     println!("Profiles:  {}", profiles.len());
     for profile_path in &profiles {
         let idx = client.get_profile_index(profile_path).await?;
@@ -602,10 +819,10 @@ async fn cmd_info(client: &RatbagClient, device: &str) -> Result<()> {
             format!(" \"{}\"", pname)
         };
         println!(
-            "  Profile {}{}: rate={}Hz{}",
+            "  Profile {}{}: rate={}{}",
             idx,
             name_display,
-            rate,
+            report_rate_display(rate),
             if active { " [active]" } else { "" }
         );
     }
@@ -622,6 +839,25 @@ async fn cmd_commit(client: &RatbagClient, device: &str) -> Result<()> {
     Ok(())
 }
 
+async fn cmd_commit_all(client: &RatbagClient, rollback: bool) -> Result<()> {
+    let results = client.commit_all(rollback).await?;
+    if results.is_empty() {
+        println!("No pending changes.");
+    }
+    let mut failed = 0;
+    for (path, error) in &results {
+        let name = client.get_device_name(path).await.unwrap_or_else(|_| path.clone());
+        if error.is_empty() {
+            println!("{}: committed", name);
+        } else {
+            failed += 1;
+            println!("{}: {}", name, error);
+        }
+    }
+    anyhow::ensure!(failed == 0, "{} of {} device(s) not committed", failed, results.len());
+    Ok(())
+}
+
 async fn cmd_profile_list(client: &RatbagClient, device: &str) -> Result<()> {
     let dev_path = client.resolve_device(device).await?;
     let profiles = client.get_device_profiles(&dev_path).await?;
@@ -638,8 +874,8 @@ async fn cmd_profile_list(client: &RatbagClient, device: &str) -> Result<()> {
             format!(" \"{}\"", pname)
         };
         println!(
-            "Profile {}{}: rate={}Hz enabled={} active={}{}",
-            idx, name_display, rate, enabled, active,
+            "Profile {}{}: rate={} enabled={} active={}{}",
+            idx, name_display, report_rate_display(rate), enabled, active,
             if dirty { " [dirty]" } else { "" }
         );
     }
@@ -659,6 +895,7 @@ async fn cmd_profile_info(client: &RatbagClient, device: &str, profile: u32) ->
     let angle = client.get_profile_angle_snapping(&profile_path).await?;
     let debounce = client.get_profile_debounce(&profile_path).await?;
     let debounces = client.get_profile_debounces(&profile_path).await.unwrap_or_default();
+    let lod = client.get_profile_lift_off_distance(&profile_path).await.unwrap_or(-1);
 
     println!("Profile {}:", idx);
     if !pname.is_empty() {
@@ -667,7 +904,7 @@ async fn cmd_profile_info(client: &RatbagClient, device: &str, profile: u32) ->
     println!("  Active:         {}", active);
     println!("  Enabled:        {}", !disabled);
     println!("  Dirty:          {}", dirty);
-    println!("  Report rate:    {} Hz", rate);
+    println!("  Report rate:    {}", report_rate_display(rate));
     println!("  Supported rates: {:?}", rates);
     if angle >= 0 {
         println!(
@@ -681,6 +918,9 @@ async fn cmd_profile_info(client: &RatbagClient, device: &str, profile: u32) ->
     if !debounces.is_empty() {
         println!("  Supported debounces: {:?}", debounces);
     }
+    if lod >= 0 {
+        println!("  Lift-off:       {} mm", lod);
+    }
 
     let resolutions = client.get_profile_resolutions(&profile_path).await?;
     for res_path in &resolutions {
@@ -872,6 +1112,71 @@ async fn cmd_profile_debounce(
     Ok(())
 }
 
+async fn cmd_profile_lod(
+    client: &RatbagClient,
+    device: &str,
+    profile: u32,
+    mm: Option<i32>,
+) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    let profile_path = format!("{}/p{}", dev_path, profile);
+    match mm {
+        Some(val) => {
+            client.set_profile_lift_off_distance(&profile_path, val).await?;
+            auto_commit(client, &profile_path).await?;
+            println!("Profile {} lift-off distance set to {} mm.", profile, val);
+        }
+        None => {
+            let lod = client.get_profile_lift_off_distance(&profile_path).await?;
+            let lods = client
+                .get_profile_lift_off_distances(&profile_path)
+                .await
+                .unwrap_or_default();
+            if lod < 0 {
+                println!("Lift-off distance is not supported on this device.");
+            } else {
+                println!("Current: {} mm", lod);
+                if !lods.is_empty() {
+                    println!("Supported: {:?}", lods);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn cmd_profile_reset(client: &RatbagClient, device: &str, profile: u32) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    let profile_path = format!("{}/p{}", dev_path, profile);
+    client.call_profile_reset_to_default(&profile_path).await?;
+    /* Drivers without a ROM copy leave the reset profile dirty. */
+    if client.get_profile_is_dirty(&profile_path).await.unwrap_or(false) {
+        auto_commit(client, &profile_path).await?;
+    }
+    println!("Profile {} reset to factory defaults.", profile);
+    Ok(())
+}
+
+async fn cmd_profile_copy(
+    client: &RatbagClient,
+    device: &str,
+    src: u32,
+    dst: u32,
+    to: Option<&str>,
+) -> Result<()> {
+    let src_dev = client.resolve_device(device).await?;
+    let dst_dev = match to {
+        Some(to) => client.resolve_device(to).await?,
+        None => src_dev.clone(),
+    };
+    let src_path = format!("{}/p{}", src_dev, src);
+    let dst_path = format!("{}/p{}", dst_dev, dst);
+    client.call_profile_copy_from(&dst_path, &src_path).await?;
+    auto_commit(client, &dst_path).await?;
+    println!("Profile {} copied to profile {}.", src, dst);
+    Ok(())
+}
+
 async fn cmd_resolution_list(client: &RatbagClient, device: &str, profile: u32) -> Result<()> {
     let dev_path = client.resolve_device(device).await?;
     let profile_path = format!("{}/p{}", dev_path, profile);
@@ -882,6 +1187,7 @@ async fn cmd_resolution_list(client: &RatbagClient, device: &str, profile: u32)
         let active = client.get_resolution_is_active(res_path).await?;
         let default = client.get_resolution_is_default(res_path).await?;
         let disabled = client.get_resolution_is_disabled(res_path).await?;
+        let shift = client.get_resolution_is_shift(res_path).await.unwrap_or(false);
         let caps = client
             .get_resolution_capabilities(res_path)
             .await
@@ -900,6 +1206,9 @@ async fn cmd_resolution_list(client: &RatbagClient, device: &str, profile: u32)
         if disabled {
             flags.push("[disabled]");
         }
+        if shift {
+            flags.push("[shift]");
+        }
         let flags_str = if flags.is_empty() {
             String::new()
         } else {
@@ -978,6 +1287,20 @@ async fn cmd_resolution_default(
     Ok(())
 }
 
+async fn cmd_resolution_shift(
+    client: &RatbagClient,
+    device: &str,
+    profile: u32,
+    resolution: u32,
+) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    let res_path = format!("{}/p{}/r{}", dev_path, profile, resolution);
+    client.call_resolution_set_shift(&res_path).await?;
+    auto_commit(client, &res_path).await?;
+    println!("Resolution {} set as shift resolution.", resolution);
+    Ok(())
+}
+
 async fn cmd_resolution_enable_disable(
     client: &RatbagClient,
     device: &str,
@@ -1006,11 +1329,13 @@ async fn cmd_button_list(client: &RatbagClient, device: &str, profile: u32) -> R
     for btn_path in &buttons {
         let idx = client.get_button_index(btn_path).await?;
         let (action_type, mapping_val) = client.get_button_mapping(btn_path).await?;
+        let layer = client.get_button_layer(btn_path).await.unwrap_or(0);
         println!(
-            "Button {}: type={} value={}",
+            "Button {}: type={} value={}{}",
             idx,
             action_type_name(action_type),
-            mapping_val
+            mapping_val,
+            if layer > 0 { " [shift layer]" } else { "" }
         );
     }
     Ok(())
@@ -1026,6 +1351,7 @@ async fn cmd_button_get(
     let btn_path = format!("{}/p{}/b{}", dev_path, profile, button);
     let (action_type, mapping_val) = client.get_button_mapping(&btn_path).await?;
     let action_types = client.get_button_action_types(&btn_path).await?;
+    let layer = client.get_button_layer(&btn_path).await.unwrap_or(0);
     println!("Button {}:", button);
     println!(
         "  Action type: {} ({})",
@@ -1033,6 +1359,21 @@ async fn cmd_button_get(
         action_type
     );
     println!("  Value:       {}", mapping_val);
+    println!("  Layer:       {}", if layer > 0 { "shift" } else { "base" });
+    if action_type == 4 {
+        let name = client.get_button_macro_name(&btn_path).await.unwrap_or_default();
+        let group = client.get_button_macro_group(&btn_path).await.unwrap_or_default();
+        let loops = client.get_button_macro_loop_count(&btn_path).await.unwrap_or(0);
+        if !name.is_empty() {
+            println!("  Macro name:  {}", name);
+        }
+        if !group.is_empty() {
+            println!("  Macro group: {}", group);
+        }
+        if loops > 0 {
+            println!("  Loop count:  {}", loops);
+        }
+    }
     println!(
         "  Supported:   {:?}",
         action_types
@@ -1066,12 +1407,20 @@ async fn cmd_button_set(
     Ok(())
 }
 
+/* Optional macro metadata flags of `button set-macro`. */
+struct MacroMetadataArgs {
+    name: Option<String>,
+    group: Option<String>,
+    loop_count: Option<u32>,
+}
+
 async fn cmd_button_set_macro(
     client: &RatbagClient,
     device: &str,
     profile: u32,
     button: u32,
     events: &[String],
+    metadata: &MacroMetadataArgs,
 ) -> Result<()> {
     let parsed = parse_macro_events(events)?;
     let dev_path = client.resolve_device(device).await?;
@@ -1079,6 +1428,15 @@ async fn cmd_button_set_macro(
     client
         .set_button_macro_mapping(&btn_path, &parsed)
         .await?;
+    if let Some(name) = &metadata.name {
+        client.set_button_macro_name(&btn_path, name).await?;
+    }
+    if let Some(group) = &metadata.group {
+        client.set_button_macro_group(&btn_path, group).await?;
+    }
+    if let Some(count) = metadata.loop_count {
+        client.set_button_macro_loop_count(&btn_path, count).await?;
+    }
     auto_commit(client, &btn_path).await?;
     println!("Button {} set to macro ({} events).", button, parsed.len());
     Ok(())
@@ -1212,6 +1570,224 @@ async fn cmd_led_duration(
     Ok(())
 }
 
+async fn cmd_led_effect(
+    client: &RatbagClient,
+    device: &str,
+    profile: u32,
+    led: u32,
+    name: Option<&str>,
+    params: &[String],
+) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    let led_path = format!("{}/p{}/l{}", dev_path, profile, led);
+    let effects = client.get_led_effects(&led_path).await?;
+    if effects.is_empty() {
+        anyhow::bail!("LED {} has no device-specific effects", led);
+    }
+
+    let Some(name) = name else {
+        let (current, values) = client.get_led_effect(&led_path).await?;
+        for (id, effect_name, schema) in &effects {
+            let marker = if *id == current { "*" } else { " " };
+            let schema: Vec<String> = schema
+                .iter()
+                .map(|(param, min, max)| {
+                    let value = values.get(param).filter(|_| *id == current);
+                    match value {
+                        Some(v) => format!("{}={} ({}-{})", param, v, min, max),
+                        None => format!("{} ({}-{})", param, min, max),
+                    }
+                })
+                .collect();
+            println!("{} {} {}", marker, effect_name, schema.join(" "));
+        }
+        return Ok(());
+    };
+
+    let (id, _, _) = effects
+        .iter()
+        .find(|(_, effect_name, _)| effect_name == name)
+        .with_context(|| format!("Unknown effect '{}'", name))?;
+    let mut values = HashMap::new();
+    for param in params {
+        let (key, value) = param
+            .split_once('=')
+            .with_context(|| format!("Expected NAME=VALUE, got '{}'", param))?;
+        let value: u32 = value
+            .parse()
+            .with_context(|| format!("Invalid value for {}: '{}'", key, value))?;
+        values.insert(key.to_string(), value);
+    }
+    client.set_led_effect(&led_path, *id, values).await?;
+    auto_commit(client, &led_path).await?;
+    println!("LED {} effect set to {}.", led, name);
+    Ok(())
+}
+
+async fn cmd_led_sync(
+    client: &RatbagClient,
+    mode: &str,
+    colors: &[String],
+    brightness: u32,
+) -> Result<()> {
+    let mode_val = parse_led_mode(mode)?;
+    anyhow::ensure!(colors.len() <= 3, "At most 3 colors are supported");
+    anyhow::ensure!(
+        brightness <= 255,
+        "Brightness out of range: {} (expected 0..=255)",
+        brightness
+    );
+    let colors = colors
+        .iter()
+        .map(|c| parse_hex_color(c))
+        .collect::<Result<Vec<_>>>()?;
+
+    let results = client.set_lighting_all(mode_val, &colors, brightness).await?;
+    if results.is_empty() {
+        println!("No devices found.");
+    }
+    let mut failed = 0;
+    for (path, mapping, error) in &results {
+        let name = client.get_device_name(path).await.unwrap_or_else(|_| path.clone());
+        let leds: Vec<String> = mapping
+            .iter()
+            .map(|(profile, led, m)| {
+                format!("profile {} LED {}={}", profile, led, led_mode_name(*m))
+            })
+            .collect();
+        let leds = if leds.is_empty() {
+            "no matching LEDs".to_string()
+        } else {
+            leds.join(", ")
+        };
+        if error.is_empty() {
+            println!("{}: {}", name, leds);
+        } else {
+            failed += 1;
+            println!("{}: {} (commit failed: {})", name, leds, error);
+        }
+    }
+    anyhow::ensure!(failed == 0, "{} of {} device(s) failed to commit", failed, results.len());
+    Ok(())
+}
+
+async fn cmd_receiver_list(client: &RatbagClient, device: &str) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    let devices = client.list_paired(&dev_path).await?;
+    if devices.is_empty() {
+        println!("No devices paired.");
+        return Ok(());
+    }
+    for (slot, wpid, name) in &devices {
+        let name_display = if name.is_empty() { "(unnamed)" } else { name };
+        println!("Slot {}: {} (wpid {:04x})", slot, name_display, wpid);
+    }
+    Ok(())
+}
+
+async fn cmd_receiver_pair(client: &RatbagClient, device: &str, timeout: u32) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    println!("Pairing window open for {}s; switch the new device on now.", timeout);
+    let (status, slot, wpid) = client.pair(&dev_path, timeout).await?;
+    match status {
+        0 => println!("Paired device {:04x} in slot {}.", wpid, slot),
+        1 => anyhow::bail!("Pairing timed out without a new device"),
+        2 => anyhow::bail!("The receiver does not support this device"),
+        3 => anyhow::bail!("The receiver has no free pairing slot"),
+        _ => anyhow::bail!("Pairing failed"),
+    }
+    Ok(())
+}
+
+async fn cmd_receiver_unpair(client: &RatbagClient, device: &str, slot: u32) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    client.unpair(&dev_path, slot).await?;
+    println!("Unpaired receiver slot {}.", slot);
+    Ok(())
+}
+
+async fn cmd_power_info(client: &RatbagClient, device: &str) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    match client.refresh_battery(&dev_path).await {
+        /* Asleep: the level, if any, is the last reading. */
+        Ok(level) if client.get_is_asleep(&dev_path).await.unwrap_or(false) => {
+            if level >= 0 {
+                println!("Battery:        {}% (last reading, device asleep)", level);
+            } else {
+                println!("Battery:        unknown (device asleep)");
+            }
+        }
+        Ok(level) => {
+            let charging = client.get_is_charging(&dev_path).await.unwrap_or(false);
+            println!(
+                "Battery:        {}%{}",
+                level,
+                if charging { " (charging)" } else { "" }
+            );
+        }
+        /* Out of range: fall back to the last reading. */
+        Err(e) => match client.get_battery_level(&dev_path).await? {
+            level if level >= 0 => println!("Battery:        {}% (last reading)", level),
+            _ => println!("Battery:        unknown ({:#})", e),
+        },
+    }
+    cmd_power_sleep(client, device, None).await?;
+    if client.get_low_battery_alert(&dev_path).await? >= 0 {
+        cmd_power_alert(client, device, None).await?;
+    }
+    Ok(())
+}
+
+async fn cmd_power_sleep(client: &RatbagClient, device: &str, seconds: Option<u32>) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    match seconds {
+        Some(val) => {
+            let val = i32::try_from(val).context("Sleep timeout out of range")?;
+            client.set_sleep_timeout(&dev_path, val).await?;
+            auto_commit(client, &dev_path).await?;
+            println!("Sleep timeout set to {} s.", val);
+        }
+        None => {
+            let timeout = client.get_sleep_timeout(&dev_path).await?;
+            let timeouts = client.get_sleep_timeouts(&dev_path).await.unwrap_or_default();
+            match timeout {
+                t if t < 0 => println!("Sleep timeout:  unknown"),
+                0 => println!("Sleep timeout:  never"),
+                t => println!("Sleep timeout:  {} s", t),
+            }
+            if !timeouts.is_empty() {
+                println!("Supported timeouts: {:?}", timeouts);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn cmd_power_alert(client: &RatbagClient, device: &str, percent: Option<u32>) -> Result<()> {
+    let dev_path = client.resolve_device(device).await?;
+    match percent {
+        Some(val) => {
+            let val = i32::try_from(val).context("Low-battery alert out of range")?;
+            client.set_low_battery_alert(&dev_path, val).await?;
+            auto_commit(client, &dev_path).await?;
+            println!("Low-battery alert set to {}%.", val);
+        }
+        None => {
+            let alert = client.get_low_battery_alert(&dev_path).await?;
+            let alerts = client.get_low_battery_alerts(&dev_path).await.unwrap_or_default();
+            match alert {
+                a if a < 0 => println!("Low-battery alert: not supported"),
+                0 => println!("Low-battery alert: off"),
+                a => println!("Low-battery alert: {}%", a),
+            }
+            if !alerts.is_empty() {
+                println!("Supported alerts: {:?}", alerts);
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn cmd_test_load_device(client: &RatbagClient, json_file: &str) -> Result<()> {
     let json = std::fs::read_to_string(json_file)
         .with_context(|| format!("Cannot read file '{}'", json_file))?;
@@ -1241,6 +1817,15 @@ fn action_type_name(t: u32) -> &'static str {
     }
 }
 
+/* A report rate of 0 means the device could not report it. */
+fn report_rate_display(rate: u32) -> String {
+    if rate == 0 {
+        "unknown".to_string()
+    } else {
+        format!("{} Hz", rate)
+    }
+}
+
 fn led_mode_name(m: u32) -> &'static str {
     match m {
         0 => "off",
@@ -1292,30 +1877,59 @@ fn parse_hex_color(s: &str) -> Result<(u32, u32, u32)> {
 
 /// Parse macro events from CLI arguments.
 ///
-/// Each argument is "KEYCODE:DIRECTION" where DIRECTION is 1 (press) or 0 (release).
-/// Example: `["30:1", "30:0"]` = press KEY_A then release KEY_A.
-fn parse_macro_events(events: &[String]) -> Result<Vec<(u32, u32)>> {
+/// Key events are "KEYCODE:DIRECTION" where DIRECTION is 1 (press) or 0
+/// (release); the other forms are listed in the `set-macro` help.
+/// Example: `["30:1", "wait:50", "30:0"]` = press KEY_A, wait, release it.
+fn parse_macro_events(events: &[String]) -> Result<Vec<MacroEvent>> {
     let mut parsed = Vec::with_capacity(events.len());
     for ev in events {
         let parts: Vec<&str> = ev.split(':').collect();
-        anyhow::ensure!(
-            parts.len() == 2,
-            "Invalid macro event '{}'. Expected KEYCODE:DIRECTION (e.g. 30:1)",
-            ev
-        );
-        let keycode: u32 = parts[0]
-            .parse()
-            .with_context(|| format!("Invalid keycode in '{}'", ev))?;
-        let direction: u32 = parts[1]
-            .parse()
-            .with_context(|| format!("Invalid direction in '{}'", ev))?;
-        anyhow::ensure!(
-            direction <= 1,
-            "Direction must be 0 (release) or 1 (press), got {} in '{}'",
-            direction,
-            ev
-        );
-        parsed.push((keycode, direction));
+        let number = |s: &str| -> Result<u32> {
+            s.parse().with_context(|| format!("Invalid number in '{}'", ev))
+        };
+        let signed = |s: &str| -> Result<i32> {
+            s.parse().with_context(|| format!("Invalid number in '{}'", ev))
+        };
+        let pressed = |s: &str| -> Result<bool> {
+            match number(s)? {
+                0 => Ok(false),
+                1 => Ok(true),
+                d => Err(anyhow::anyhow!(
+                    "Direction must be 0 (release) or 1 (press), got {} in '{}'",
+                    d,
+                    ev
+                )),
+            }
+        };
+        let event = match parts.as_slice() {
+            ["wait", ms] => MacroEvent::Wait(number(ms)?),
+            ["button", n, dir] => {
+                if pressed(dir)? {
+                    MacroEvent::ButtonPress(number(n)?)
+                } else {
+                    MacroEvent::ButtonRelease(number(n)?)
+                }
+            }
+            ["consumer", usage] => MacroEvent::Consumer(number(usage)?),
+            ["wheel", ticks] => MacroEvent::Wheel(signed(ticks)?),
+            ["move", x, y] => MacroEvent::PointerMove { x: signed(x)?, y: signed(y)? },
+            ["wait-release"] => MacroEvent::WaitForRelease,
+            ["repeat"] => MacroEvent::RepeatWhilePressed,
+            [key, dir] => {
+                if pressed(dir)? {
+                    MacroEvent::KeyPress(number(key)?)
+                } else {
+                    MacroEvent::KeyRelease(number(key)?)
+                }
+            }
+            _ => anyhow::bail!(
+                "Invalid macro event '{}'. Expected KEYCODE:DIRECTION (e.g. 30:1) or \
+                 wait:MS, button:N:DIR, wheel:TICKS, consumer:USAGE, move:X:Y, \
+                 wait-release, repeat",
+                ev
+            ),
+        };
+        parsed.push(event);
     }
     Ok(parsed)
 }
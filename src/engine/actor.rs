@@ -13,16 +13,69 @@ use anyhow::{Context, Result};
 use tokio::sync::{mpsc, oneshot, RwLock};
 use tracing::{debug, info, warn};
 
-use crate::engine::device::DeviceInfo;
+use crate::engine::device::{
+    BatteryStatus, Color, DeviceInfo, LedInfo, PairedDevice, PairingOutcome,
+};
 use crate::hal::{DeviceDriver, DeviceIo};
 
 /* Commands that DBus interface objects can send to the device actor. */
 #[derive(Debug)]
 pub enum ActorMessage {
-    /* Commit all pending changes to hardware and report success/failure. */
+    /* Commit all pending changes to hardware and report the commit's
+     * generation or the failure. */
     Commit {
+        reply: oneshot::Sender<Result<u64, String>>,
+    },
+    /* Make a profile active and run it on the device without writing
+     * pending edits, sparing the stored profiles where the driver can,
+     * and report success/failure. */
+    SwitchProfile {
+        profile: u32,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    /* Rewrite what the commit of `generation` changed with the settings
+     * it replaced and report success/failure.  Refused once a later
+     * change superseded that commit. */
+    Rollback {
+        generation: u64,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    /* Restore a profile to its factory defaults and report success/failure. */
+    ResetProfile {
+        profile: u32,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    /* Show a transient custom colour frame and report success/failure. */
+    SetLedFrame {
+        colors: Vec<(u32, Color)>,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    /* Show an LED's settings without persisting them and report
+     * success/failure. */
+    PreviewLed {
+        led: Box<LedInfo>,
         reply: oneshot::Sender<Result<(), String>>,
     },
+    /* List the receiver's occupied pairing slots. */
+    PairedDevices {
+        reply: oneshot::Sender<Result<Vec<PairedDevice>, String>>,
+    },
+    /* Run a receiver pairing window and report how it ended.  The actor
+     * is busy until the window closes. */
+    PairDevice {
+        timeout: u8,
+        reply: oneshot::Sender<Result<PairingOutcome, String>>,
+    },
+    /* Unpair one receiver slot and report success/failure. */
+    UnpairDevice {
+        slot: u8,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    /* Read the battery charge, store it in the shared state and report
+     * it (None if the device is asleep) or the failure. */
+    ReadBattery {
+        reply: oneshot::Sender<Result<Option<BatteryStatus>, String>>,
+    },
     /* Gracefully shut down the actor (e.g., on device removal). */
     Shutdown,
 }
@@ -40,8 +93,9 @@ impl ActorHandle {
     }
 
     /* Request the actor to commit pending changes to hardware.
-     * Returns `Ok(())` on success, or an error string on failure. */
-    pub async fn commit(&self) -> Result<(), String> {
+     * Returns the generation of the commit on success, to pass to
+     * `rollback`, or an error string on failure. */
+    pub async fn commit(&self) -> Result<u64, String> {
         let (reply_tx, reply_rx) = oneshot::channel();
 
         self.tx
@@ -53,6 +107,164 @@ impl ActorHandle {
             .await
             .map_err(|_| "Device actor dropped the reply channel".to_string())?
     }
+
+    /* Request the actor to make `profile` active on the device.
+     * Returns `Ok(())` on success, or an error string on failure. */
+    pub async fn switch_profile(&self, profile: u32) -> Result<(), String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::SwitchProfile {
+                profile,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Request the actor to undo the commit of `generation`, which must
+     * still be its last hardware change.
+     * Returns `Ok(())` on success, or an error string on failure. */
+    pub async fn rollback(&self, generation: u64) -> Result<(), String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::Rollback {
+                generation,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Request the actor to restore a profile to its factory defaults.
+     * Returns `Ok(())` on success, or an error string on failure. */
+    pub async fn reset_profile(&self, profile: u32) -> Result<(), String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::ResetProfile {
+                profile,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Request the actor to show a custom colour on each listed LED.
+     * Returns `Ok(())` on success, or an error string on failure. */
+    pub async fn set_led_frame(&self, colors: Vec<(u32, Color)>) -> Result<(), String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::SetLedFrame {
+                colors,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Request the actor to show an LED's settings without persisting
+     * them.  Returns `Ok(())` on success, or an error string on failure. */
+    pub async fn preview_led(&self, led: LedInfo) -> Result<(), String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::PreviewLed {
+                led: Box::new(led),
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Request the list of devices paired with the receiver.
+     * Returns the occupied slots, or an error string on failure. */
+    pub async fn paired_devices(&self) -> Result<Vec<PairedDevice>, String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::PairedDevices { reply: reply_tx })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Request a pairing window of `timeout` seconds.  Resolves once the
+     * window closes, with the outcome or an error string on failure. */
+    pub async fn pair_device(&self, timeout: u8) -> Result<PairingOutcome, String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::PairDevice {
+                timeout,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Request the receiver to unpair the device in `slot`.
+     * Returns `Ok(())` on success, or an error string on failure. */
+    pub async fn unpair_device(&self, slot: u8) -> Result<(), String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::UnpairDevice {
+                slot,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
+
+    /* Request a fresh battery reading; `None` means the device is
+     * asleep.  The shared device state is updated before the reply
+     * arrives. */
+    pub async fn read_battery(&self) -> Result<Option<BatteryStatus>, String> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ActorMessage::ReadBattery { reply: reply_tx })
+            .await
+            .map_err(|_| "Device actor is no longer running".to_string())?;
+
+        reply_rx
+            .await
+            .map_err(|_| "Device actor dropped the reply channel".to_string())?
+    }
 }
 
 /* Upper bound on reports drained per idle wakeup, so a flood of input
@@ -75,6 +287,14 @@ struct DeviceActor {
      * the shared device state, so a future consumer can emit D-Bus
      * signals.  `None` disables notification. */
     notify_tx: Option<mpsc::UnboundedSender<()>>,
+    /* The settings last written to (or loaded from) the hardware. */
+    committed: DeviceInfo,
+    /* The settings the last commit replaced, dirty where that commit
+     * wrote, until it is rolled back or superseded. */
+    rollback: Option<DeviceInfo>,
+    /* Number of commits written so far; a rollback names the commit it
+     * undoes by this count. */
+    generation: u64,
 }
 
 impl DeviceActor {
@@ -112,6 +332,36 @@ impl DeviceActor {
                 Wakeup::Message(Some(ActorMessage::Commit { reply })) => {
                     self.handle_commit(reply).await;
                 }
+                Wakeup::Message(Some(ActorMessage::SwitchProfile { profile, reply })) => {
+                    self.handle_switch_profile(profile, reply).await;
+                }
+                Wakeup::Message(Some(ActorMessage::Rollback { generation, reply })) => {
+                    self.handle_rollback(generation, reply).await;
+                }
+                Wakeup::Message(Some(ActorMessage::ResetProfile { profile, reply })) => {
+                    self.handle_reset_profile(profile, reply).await;
+                }
+                Wakeup::Message(Some(ActorMessage::SetLedFrame { colors, reply })) => {
+                    self.handle_set_led_frame(colors, reply).await;
+                }
+                Wakeup::Message(Some(ActorMessage::PreviewLed { led, reply })) => {
+                    self.handle_preview_led(*led, reply).await;
+                }
+                Wakeup::Message(Some(ActorMessage::PairedDevices { reply })) => {
+                    let result = self.driver.paired_devices(&mut self.io).await;
+                    self.finish_receiver_request(result, reply).await;
+                }
+                Wakeup::Message(Some(ActorMessage::PairDevice { timeout, reply })) => {
+                    let result = self.driver.pair_device(&mut self.io, timeout).await;
+                    self.finish_receiver_request(result, reply).await;
+                }
+                Wakeup::Message(Some(ActorMessage::UnpairDevice { slot, reply })) => {
+                    let result = self.driver.unpair_device(&mut self.io, slot).await;
+                    self.finish_receiver_request(result, reply).await;
+                }
+                Wakeup::Message(Some(ActorMessage::ReadBattery { reply })) => {
+                    self.handle_read_battery(reply).await;
+                }
                 Wakeup::Message(Some(ActorMessage::Shutdown)) => {
                     info!(
                         "Device actor shutting down for {}",
@@ -140,7 +390,7 @@ impl DeviceActor {
     }
 
     /* Commit pending changes to hardware and reply to the requester. */
-    async fn handle_commit(&mut self, reply: oneshot::Sender<Result<(), String>>) {
+    async fn handle_commit(&mut self, reply: oneshot::Sender<Result<u64, String>>) {
         /* Clone a snapshot of the device state and release the
          * lock immediately.  This prevents write-starvation:
          * if the commit takes a long time (wireless retries,
@@ -149,13 +399,7 @@ impl DeviceActor {
          * The ~1.6 µs clone cost is negligible compared to the
          * multi-millisecond hardware I/O that follows. */
         let snapshot = self.info.read().await.clone();
-        let result = self.driver.commit(&mut self.io, &snapshot).await;
-
-        if result.is_ok() {
-            /* Clear dirty flags under a brief write-lock. */
-            let mut info = self.info.write().await;
-            *info = info.with_cleared_dirty_flags();
-        }
+        let result = self.commit_snapshot(snapshot).await;
 
         /* Process any unsolicited hardware events (e.g. profile
          * switch notifications) that arrived during the commit's
@@ -168,6 +412,243 @@ impl DeviceActor {
         let _ = reply.send(response);
     }
 
+    /* Write a snapshot of the device state and, on success, clear the
+     * dirty flags, remember what the commit replaced and return the
+     * commit's generation. */
+    async fn commit_snapshot(&mut self, snapshot: DeviceInfo) -> Result<u64> {
+        self.driver.commit(&mut self.io, &snapshot).await?;
+
+        /* Clear dirty flags under a brief write-lock. */
+        let mut info = self.info.write().await;
+        *info = info.with_cleared_dirty_flags();
+
+        self.rollback = Some(self.committed.with_dirty_flags_from(&snapshot));
+        self.committed = snapshot.with_cleared_dirty_flags();
+        self.generation += 1;
+        Ok(self.generation)
+    }
+
+    /* Make a profile active and reply to the requester.  The driver's
+     * live switch is tried first.  Drivers without one get a commit of
+     * the committed settings with only the active profile changed; as
+     * that rewrites the stored profiles, it is refused while edits are
+     * pending rather than writing them along.  Dirty flags are left as
+     * they were either way. */
+    async fn handle_switch_profile(
+        &mut self,
+        profile: u32,
+        reply: oneshot::Sender<Result<(), String>>,
+    ) {
+        let (snapshot, pending) = {
+            let info = self.info.read().await;
+            if info.find_profile(profile).is_none() {
+                let _ = reply.send(Err(format!("Profile {profile} not found")));
+                return;
+            }
+            (info.with_active_profile(profile), info.is_dirty())
+        };
+
+        let result = match self.driver.switch_profile(&mut self.io, &snapshot).await {
+            Ok(()) => {
+                /* The last commit is no longer the last hardware change. */
+                self.rollback = None;
+                Ok(())
+            }
+            Err(e) if pending => Err(e.context(format!(
+                "Profile {profile} not selected: uncommitted changes are pending"
+            ))),
+            Err(e) => {
+                debug!("Live profile switch unavailable, committing the selection: {e:#}");
+                self.commit_profile_selection(profile).await
+            }
+        };
+
+        if result.is_ok() {
+            let mut info = self.info.write().await;
+            for p in info.profiles.iter_mut().chain(self.committed.profiles.iter_mut()) {
+                p.is_active = p.index == profile;
+            }
+        }
+
+        let events = self.io.drain_events();
+        self.handle_unsolicited_reports(events).await;
+
+        let response = result.map_err(|e| format!("{e:#}"));
+        let _ = reply.send(response);
+    }
+
+    /* Commit the committed settings with `profile` made active, so the
+     * selection is the only change written. */
+    async fn commit_profile_selection(&mut self, profile: u32) -> Result<()> {
+        let selection = self.committed.with_active_profile(profile);
+        self.driver.commit(&mut self.io, &selection).await?;
+
+        self.rollback = Some(self.committed.with_dirty_flags_from(&selection));
+        self.committed = selection.with_cleared_dirty_flags();
+        self.generation += 1;
+        Ok(())
+    }
+
+    /* Restore one profile to its factory defaults and reply to the
+     * requester.  Like a commit, the driver works on a snapshot so the
+     * lock is not held across hardware I/O; only the reset profile is
+     * copied back, leaving concurrent edits to other profiles intact. */
+    async fn handle_reset_profile(
+        &mut self,
+        profile: u32,
+        reply: oneshot::Sender<Result<(), String>>,
+    ) {
+        let mut snapshot = self.info.read().await.clone();
+        let result = self
+            .driver
+            .reset_profile(&mut self.io, &mut snapshot, profile)
+            .await;
+
+        if result.is_ok()
+            && let Some(reset) = snapshot.find_profile(profile)
+        {
+            let mut info = self.info.write().await;
+            if let Some(slot) = info.find_profile_mut(profile) {
+                *slot = reset.clone();
+            }
+            if let Some(slot) = self.committed.find_profile_mut(profile) {
+                *slot = reset.clone();
+            }
+            /* The last commit is no longer the last hardware change. */
+            self.rollback = None;
+        }
+
+        let events = self.io.drain_events();
+        self.handle_unsolicited_reports(events).await;
+
+        let response = result.map_err(|e| format!("{e:#}"));
+        let _ = reply.send(response);
+    }
+
+    /* Write back the settings the commit of `generation` replaced and
+     * reply to the requester.  Only the rolled-back profiles and power
+     * settings are restored in the shared state; other pending edits are
+     * kept.  A commit that is no longer the last hardware change is left
+     * alone, so a rollback never undoes another client's commit.  A
+     * failed rollback may be retried. */
+    async fn handle_rollback(
+        &mut self,
+        generation: u64,
+        reply: oneshot::Sender<Result<(), String>>,
+    ) {
+        if generation != self.generation {
+            let _ = reply.send(Err(format!(
+                "Commit {generation} was superseded by a later commit"
+            )));
+            return;
+        }
+        let Some(previous) = self.rollback.take() else {
+            let _ = reply.send(Err("No commit to roll back".to_string()));
+            return;
+        };
+        let result = self.driver.commit(&mut self.io, &previous).await;
+
+        if result.is_ok() {
+            let restored = previous.with_cleared_dirty_flags();
+            let mut info = self.info.write().await;
+            for profile in restored.profiles.iter() {
+                if previous.find_profile(profile.index).is_some_and(|p| p.is_dirty)
+                    && let Some(slot) = info.find_profile_mut(profile.index)
+                {
+                    *slot = profile.clone();
+                }
+            }
+            /* Only the settings are restored; the battery reading stays. */
+            if let (Some(power), Some(restored)) = (info.power.as_mut(), restored.power.as_ref())
+                && previous.power.as_ref().is_some_and(|p| p.is_dirty)
+            {
+                power.sleep_timeout = restored.sleep_timeout;
+                power.low_battery_alert = restored.low_battery_alert;
+                power.is_dirty = false;
+            }
+            self.committed = restored;
+        } else {
+            self.rollback = Some(previous);
+        }
+
+        let events = self.io.drain_events();
+        self.handle_unsolicited_reports(events).await;
+
+        let response = result.map_err(|e| format!("{e:#}"));
+        let _ = reply.send(response);
+    }
+
+    /* Show a custom colour frame and reply to the requester.  Frames
+     * never touch the shared device state, so no lock is taken. */
+    async fn handle_set_led_frame(
+        &mut self,
+        colors: Vec<(u32, Color)>,
+        reply: oneshot::Sender<Result<(), String>>,
+    ) {
+        let result = self.driver.set_led_frame(&mut self.io, &colors).await;
+
+        let events = self.io.drain_events();
+        self.handle_unsolicited_reports(events).await;
+
+        let response = result.map_err(|e| format!("{e:#}"));
+        let _ = reply.send(response);
+    }
+
+    /* Preview an LED and reply to the requester.  The settings come
+     * with the message, so no lock is taken. */
+    async fn handle_preview_led(
+        &mut self,
+        led: LedInfo,
+        reply: oneshot::Sender<Result<(), String>>,
+    ) {
+        let result = self.driver.preview_led(&mut self.io, &led).await;
+
+        let events = self.io.drain_events();
+        self.handle_unsolicited_reports(events).await;
+
+        let response = result.map_err(|e| format!("{e:#}"));
+        let _ = reply.send(response);
+    }
+
+    /* Read the battery and record the reading in the shared state. */
+    async fn handle_read_battery(
+        &mut self,
+        reply: oneshot::Sender<Result<Option<BatteryStatus>, String>>,
+    ) {
+        let result = self.driver.read_battery(&mut self.io).await;
+
+        if let Ok(status) = &result
+            && let Some(power) = self.info.write().await.power.as_mut()
+        {
+            /* An asleep device keeps its last reading. */
+            power.is_asleep = status.is_none();
+            if let Some(status) = status {
+                power.battery = Some(*status);
+            }
+        }
+
+        let events = self.io.drain_events();
+        self.handle_unsolicited_reports(events).await;
+
+        let response = result.map_err(|e| format!("{e:#}"));
+        let _ = reply.send(response);
+    }
+
+    /* Reply to a receiver pairing request.  The receiver commands
+     * never touch the shared device state; only events that arrived
+     * alongside them are processed. */
+    async fn finish_receiver_request<T>(
+        &mut self,
+        result: Result<T>,
+        reply: oneshot::Sender<Result<T, String>>,
+    ) {
+        let events = self.io.drain_events();
+        self.handle_unsolicited_reports(events).await;
+
+        let response = result.map_err(|e| format!("{e:#}"));
+        let _ = reply.send(response);
+    }
+
     /* Drain reports queued on the idle fd and feed the HID++ ones to
      * the driver.  Never blocks: uses the non-blocking read so command
      * messages regain control as soon as the queue is empty. */
@@ -177,14 +658,10 @@ impl DeviceActor {
 
         for _ in 0..MAX_IDLE_DRAIN {
             match self.io.try_read_report(&mut buf) {
-                Ok(Some(n))
-                    if n > 0
-                        && (buf[0] == crate::hal::HIDPP_SHORT_REPORT_ID
-                            || buf[0] == crate::hal::HIDPP_LONG_REPORT_ID) =>
-                {
+                Ok(Some(n)) if n > 0 && self.driver.is_event_report(&buf[..n]) => {
                     reports.push(buf[..n].to_vec());
                 }
-                /* Non-HID++ noise (motion/keyboard input): discard. */
+                /* Noise the driver has no use for (motion/keyboard input): discard. */
                 Ok(Some(_)) => continue,
                 /* Queue drained. */
                 Ok(None) => break,
@@ -319,12 +796,16 @@ pub async fn spawn_device_actor(
     /* Create the message channel and spawn the actor */
     let (tx, rx) = mpsc::channel(16);
 
+    let committed = info.read().await.clone();
     let actor = DeviceActor {
         driver,
         io,
         info,
         rx,
         notify_tx,
+        committed,
+        rollback: None,
+        generation: 0,
     };
 
     tokio::spawn(async move {
@@ -333,3 +814,203 @@ pub async fn spawn_device_actor(
 
     Ok(ActorHandle { tx })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::engine::device_database::{DeviceEntry, DriverConfig};
+
+    /* A driver whose "hardware" is the list of snapshots it was asked to
+     * write.  With `fail` set, every commit fails instead; with
+     * `live_switch` set, profile switches skip the commit. */
+    struct RecordingDriver {
+        writes: Arc<Mutex<Vec<DeviceInfo>>>,
+        fail: bool,
+        live_switch: bool,
+    }
+
+    #[async_trait]
+    impl DeviceDriver for RecordingDriver {
+        fn name(&self) -> &str {
+            "Recording"
+        }
+
+        async fn probe(&mut self, _io: &mut DeviceIo) -> Result<()> {
+            Ok(())
+        }
+
+        async fn load_profiles(&mut self, _io: &mut DeviceIo, _info: &mut DeviceInfo) -> Result<()> {
+            Ok(())
+        }
+
+        async fn commit(&mut self, _io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+            if self.fail {
+                anyhow::bail!("device unplugged");
+            }
+            self.writes.lock().unwrap().push(info.clone());
+            Ok(())
+        }
+
+        async fn switch_profile(&mut self, _io: &mut DeviceIo, _info: &DeviceInfo) -> Result<()> {
+            if !self.live_switch {
+                anyhow::bail!("no live switch");
+            }
+            Ok(())
+        }
+    }
+
+    type Writes = Arc<Mutex<Vec<DeviceInfo>>>;
+
+    fn spawn_test_actor(fail: bool) -> (ActorHandle, Arc<RwLock<DeviceInfo>>, Writes) {
+        spawn_recording_actor(fail, false)
+    }
+
+    /* Spawn an actor over a two-profile device driven by a recording
+     * driver; returns its handle, the shared state and the writes. */
+    fn spawn_recording_actor(
+        fail: bool,
+        live_switch: bool,
+    ) -> (ActorHandle, Arc<RwLock<DeviceInfo>>, Writes) {
+        let entry = DeviceEntry {
+            name: "Test Mouse".into(),
+            driver: "test".into(),
+            device_type: "mouse".into(),
+            matches: Vec::new(),
+            driver_config: Some(DriverConfig {
+                profiles: Some(2),
+                ..DriverConfig::default()
+            }),
+        };
+        let device = DeviceInfo::from_entry("test0", "Test Mouse", 0x03, 0x046d, 0xc000, &entry);
+        let info = Arc::new(RwLock::new(device.clone()));
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let (io, _handle) = DeviceIo::with_mock(Vec::new());
+        let (tx, rx) = mpsc::channel(16);
+
+        let actor = DeviceActor {
+            driver: Box::new(RecordingDriver {
+                writes: Arc::clone(&writes),
+                fail,
+                live_switch,
+            }),
+            io,
+            info: Arc::clone(&info),
+            rx,
+            notify_tx: None,
+            committed: device,
+            rollback: None,
+            generation: 0,
+        };
+        tokio::spawn(actor.run());
+        (ActorHandle { tx }, info, writes)
+    }
+
+    async fn set_report_rate(info: &RwLock<DeviceInfo>, profile: u32, rate: u32) {
+        let mut info = info.write().await;
+        *info = info.with_profile_report_rate(profile, rate);
+    }
+
+    /* CommitAll's path: a device commits, another one fails, and the
+     * first is rolled back on the hardware and in the shared state, while
+     * an edit made in between survives. */
+    #[tokio::test]
+    async fn rollback_undoes_a_commit_after_another_device_failed() {
+        let (good, good_info, writes) = spawn_test_actor(false);
+        let (bad, bad_info, _) = spawn_test_actor(true);
+        set_report_rate(&good_info, 0, 500).await;
+        set_report_rate(&bad_info, 0, 500).await;
+
+        let generation = good.commit().await.unwrap();
+        assert!(bad.commit().await.is_err());
+        set_report_rate(&good_info, 1, 250).await;
+        good.rollback(generation).await.unwrap();
+
+        {
+            let writes = writes.lock().unwrap();
+            assert_eq!(writes.len(), 2);
+            assert_eq!(writes[0].profiles[0].report_rate, 500);
+            assert_eq!(writes[1].profiles[0].report_rate, 1000);
+        }
+        let info = good_info.read().await;
+        assert_eq!(info.profiles[0].report_rate, 1000);
+        assert!(!info.profiles[0].is_dirty);
+        assert_eq!(info.profiles[1].report_rate, 250);
+        assert!(info.profiles[1].is_dirty);
+        drop(info);
+        assert!(good.rollback(generation).await.is_err(), "a commit rolls back once");
+    }
+
+    /* A commit from another client between a commit and its rollback
+     * supersedes it: the rollback is refused and writes nothing. */
+    #[tokio::test]
+    async fn rollback_of_a_superseded_commit_is_refused() {
+        let (actor, info, writes) = spawn_test_actor(false);
+        set_report_rate(&info, 0, 500).await;
+        let first = actor.commit().await.unwrap();
+        set_report_rate(&info, 0, 250).await;
+        let second = actor.commit().await.unwrap();
+
+        assert!(actor.rollback(first).await.is_err());
+        assert_eq!(writes.lock().unwrap().len(), 2);
+        assert_eq!(info.read().await.profiles[0].report_rate, 250);
+
+        actor.rollback(second).await.unwrap();
+        assert_eq!(info.read().await.profiles[0].report_rate, 500);
+    }
+
+    /* Without a live switch, a profile switch commits the selection and
+     * nothing else. */
+    #[tokio::test]
+    async fn profile_switch_fallback_commits_only_the_selection() {
+        let (actor, info, writes) = spawn_test_actor(false);
+        actor.switch_profile(1).await.unwrap();
+
+        {
+            let writes = writes.lock().unwrap();
+            assert_eq!(writes.len(), 1);
+            assert!(!writes[0].profiles[0].is_active);
+            assert!(writes[0].profiles[1].is_active);
+            assert!(!writes[0].profiles[0].is_dirty);
+        }
+        let info = info.read().await;
+        assert!(info.profiles[1].is_active);
+        assert!(!info.is_dirty());
+    }
+
+    /* Pending edits would be written along with the selection, so the
+     * switch is refused and neither the device nor the state changes. */
+    #[tokio::test]
+    async fn profile_switch_fallback_refuses_pending_edits() {
+        let (actor, info, writes) = spawn_test_actor(false);
+        set_report_rate(&info, 0, 500).await;
+
+        assert!(actor.switch_profile(1).await.is_err());
+        assert!(writes.lock().unwrap().is_empty());
+        let info = info.read().await;
+        assert!(info.profiles[0].is_active);
+        assert!(!info.profiles[1].is_active);
+        assert_eq!(info.profiles[0].report_rate, 500);
+        assert!(info.profiles[0].is_dirty);
+    }
+
+    /* A live switch changes the device after the last commit, so that
+     * commit can no longer be rolled back over it. */
+    #[tokio::test]
+    async fn live_profile_switch_invalidates_the_rollback() {
+        let (actor, info, writes) = spawn_recording_actor(false, true);
+        set_report_rate(&info, 0, 500).await;
+        let generation = actor.commit().await.unwrap();
+
+        actor.switch_profile(1).await.unwrap();
+        assert!(actor.rollback(generation).await.is_err());
+        assert_eq!(writes.lock().unwrap().len(), 1);
+        let info = info.read().await;
+        assert_eq!(info.profiles[0].report_rate, 500);
+        assert!(!info.profiles[0].is_active);
+        assert!(info.profiles[1].is_active);
+    }
+}
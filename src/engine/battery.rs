@@ -0,0 +1,332 @@
+/* Battery monitoring policy: how often wireless devices are polled for their battery, and which
+ * readings are worth a desktop notification.
+ *
+ * The settings live in an optional INI file:
+ *
+ *     [battery]
+ *     poll-interval = 300
+ *     low = 20, 5
+ *     charging = true
+ *     debounce = 60
+ *
+ * `poll-interval` is the number of seconds between battery reads.  `low` lists the charge levels
+ * in percent at which to warn; each one warns once per discharge.  `charging` turns on a
+ * notification when charging starts or stops, at most one per `debounce` seconds so a loose
+ * cable cannot flood the desktop.  Without the file the battery is still polled, but nothing is
+ * notified. */
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use configparser::ini::Ini;
+
+use crate::engine::device::BatteryStatus;
+
+const SECTION: &str = "battery";
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(300);
+const DEFAULT_LOW_LEVELS: [u32; 2] = [20, 5];
+const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(60);
+
+/* A warned level is armed again once the charge climbs this far above
+ * it, so a reading that wobbles around a threshold warns only once. */
+const REARM_MARGIN: u32 = 5;
+
+/* Battery polling and notification settings. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatteryConfig {
+    pub poll_interval: Duration,
+    /* Notification thresholds in percent, highest first; empty when
+     * notifications are off. */
+    pub low_levels: Vec<u32>,
+    /* Notify when charging starts or stops. */
+    pub charging: bool,
+    /* Minimum time between two charging notifications of one device. */
+    pub debounce: Duration,
+}
+
+impl Default for BatteryConfig {
+    /* Polling only: what the daemon does without a configuration file. */
+    fn default() -> Self {
+        Self {
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            low_levels: Vec::new(),
+            charging: false,
+            debounce: DEFAULT_DEBOUNCE,
+        }
+    }
+}
+
+impl BatteryConfig {
+    /* Parse the settings from the text of a configuration file.  Keys
+     * left out take their defaults, with notifications on. */
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut ini = Ini::new();
+        ini.read(text.to_string())
+            .map_err(|e| format!("INI parse error: {}", e))?;
+
+        let seconds = |key: &str, default: Duration| -> Result<Duration, String> {
+            match ini.get(SECTION, key) {
+                None => Ok(default),
+                Some(value) => value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|&s| s > 0)
+                    .map(Duration::from_secs)
+                    .ok_or_else(|| format!("invalid {} '{}'", key, value)),
+            }
+        };
+        let poll_interval = seconds("poll-interval", DEFAULT_POLL_INTERVAL)?;
+        let debounce = seconds("debounce", DEFAULT_DEBOUNCE)?;
+
+        let mut low_levels = match ini.get(SECTION, "low") {
+            None => DEFAULT_LOW_LEVELS.to_vec(),
+            Some(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|level| !level.is_empty())
+                .map(|level| {
+                    level
+                        .parse()
+                        .ok()
+                        .filter(|&l| (1..=100).contains(&l))
+                        .ok_or_else(|| format!("invalid low level '{}'", level))
+                })
+                .collect::<Result<Vec<u32>, String>>()?,
+        };
+        low_levels.sort_unstable_by(|a, b| b.cmp(a));
+        low_levels.dedup();
+
+        let charging = ini
+            .getboolcoerce(SECTION, "charging")
+            .map_err(|e| format!("invalid charging: {}", e))?
+            .unwrap_or(true);
+
+        Ok(Self {
+            poll_interval,
+            low_levels,
+            charging,
+            debounce,
+        })
+    }
+
+    /* Load the settings file at `path`. */
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&text)
+    }
+
+    /* True when any reading can lead to a notification. */
+    pub fn notifies(&self) -> bool {
+        !self.low_levels.is_empty() || self.charging
+    }
+}
+
+/* Location of the settings file: `$RATBAGD_BATTERY_CONFIG` if set,
+ * otherwise `ratbagd/battery.ini` in the user's configuration directory. */
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("RATBAGD_BATTERY_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    Some(super::rules::user_config_dir()?.join("ratbagd").join("battery.ini"))
+}
+
+/* A battery change worth telling the user about. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryAlert {
+    /* The charge fell to or below the `threshold` level. */
+    Low { level: u32, threshold: u32 },
+    ChargingStarted { level: u32 },
+    ChargingStopped { level: u32 },
+}
+
+/* Notification state of one device, fed with every battery reading. */
+#[derive(Debug, Clone, Default)]
+pub struct BatteryWatch {
+    last: Option<BatteryStatus>,
+    /* Lowest level warned about since the battery last charged. */
+    warned: Option<u32>,
+    /* When the last charging notification was shown. */
+    last_charging_alert: Option<Instant>,
+}
+
+impl BatteryWatch {
+    /* Record a reading taken at `now` and return the notification it
+     * calls for, if any.  A low-battery warning wins over a charging
+     * change in the same reading. */
+    pub fn update(
+        &mut self,
+        config: &BatteryConfig,
+        status: BatteryStatus,
+        now: Instant,
+    ) -> Option<BatteryAlert> {
+        let previous = self.last.replace(status);
+
+        if status.is_charging {
+            self.warned = None;
+        } else if let Some(warned) = self.warned {
+            /* Re-arm every warned level the charge has climbed well above. */
+            self.warned = config
+                .low_levels
+                .iter()
+                .copied()
+                .filter(|&l| l >= warned && status.level < l + REARM_MARGIN)
+                .min();
+        }
+
+        if !status.is_charging
+            && let Some(threshold) = config
+                .low_levels
+                .iter()
+                .copied()
+                .filter(|&l| status.level <= l)
+                .min()
+            && self.warned.is_none_or(|warned| threshold < warned)
+        {
+            self.warned = Some(threshold);
+            return Some(BatteryAlert::Low {
+                level: status.level,
+                threshold,
+            });
+        }
+
+        let previous = previous?;
+        if !config.charging || previous.is_charging == status.is_charging {
+            return None;
+        }
+        if self
+            .last_charging_alert
+            .is_some_and(|at| now.duration_since(at) < config.debounce)
+        {
+            return None;
+        }
+        self.last_charging_alert = Some(now);
+        Some(if status.is_charging {
+            BatteryAlert::ChargingStarted {
+                level: status.level,
+            }
+        } else {
+            BatteryAlert::ChargingStopped {
+                level: status.level,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(level: u32, is_charging: bool) -> BatteryStatus {
+        BatteryStatus { level, is_charging }
+    }
+
+    fn config() -> BatteryConfig {
+        BatteryConfig::parse("[battery]\nlow = 5, 20\n").unwrap()
+    }
+
+    #[test]
+    fn test_config_defaults_and_parse() {
+        let config = config();
+        assert_eq!(config.low_levels, vec![20, 5]);
+        assert!(config.charging);
+        assert_eq!(config.poll_interval, DEFAULT_POLL_INTERVAL);
+        assert!(config.notifies());
+        assert!(!BatteryConfig::default().notifies());
+
+        let config =
+            BatteryConfig::parse("[battery]\nlow =\ncharging = no\npoll-interval = 60\n").unwrap();
+        assert!(!config.notifies());
+        assert_eq!(config.poll_interval, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_config_rejects_bad_values() {
+        assert!(BatteryConfig::parse("[battery]\nlow = 0\n").is_err());
+        assert!(BatteryConfig::parse("[battery]\nlow = ten\n").is_err());
+        assert!(BatteryConfig::parse("[battery]\npoll-interval = 0\n").is_err());
+        assert!(BatteryConfig::parse("[battery]\ncharging = maybe\n").is_err());
+    }
+
+    #[test]
+    fn test_low_levels_warn_once_per_discharge() {
+        let config = config();
+        let now = Instant::now();
+        let mut watch = BatteryWatch::default();
+
+        assert_eq!(watch.update(&config, reading(50, false), now), None);
+        assert_eq!(
+            watch.update(&config, reading(20, false), now),
+            Some(BatteryAlert::Low { level: 20, threshold: 20 })
+        );
+        /* Wobbling around the threshold does not warn again. */
+        assert_eq!(watch.update(&config, reading(21, false), now), None);
+        assert_eq!(watch.update(&config, reading(19, false), now), None);
+        assert_eq!(
+            watch.update(&config, reading(4, false), now),
+            Some(BatteryAlert::Low { level: 4, threshold: 5 })
+        );
+        assert_eq!(watch.update(&config, reading(3, false), now), None);
+    }
+
+    #[test]
+    fn test_first_reading_below_threshold_warns() {
+        let mut watch = BatteryWatch::default();
+        assert_eq!(
+            watch.update(&config(), reading(12, false), Instant::now()),
+            Some(BatteryAlert::Low { level: 12, threshold: 20 })
+        );
+    }
+
+    #[test]
+    fn test_charging_rearms_low_levels() {
+        let config = config();
+        let now = Instant::now();
+        let mut watch = BatteryWatch::default();
+
+        watch.update(&config, reading(18, false), now);
+        assert_eq!(
+            watch.update(&config, reading(18, true), now),
+            Some(BatteryAlert::ChargingStarted { level: 18 })
+        );
+        /* Unplugged too early: the warning wins over the charging stop. */
+        assert_eq!(
+            watch.update(&config, reading(19, false), now + Duration::from_secs(5)),
+            Some(BatteryAlert::Low { level: 19, threshold: 20 })
+        );
+    }
+
+    #[test]
+    fn test_charging_changes_are_debounced() {
+        let config = config();
+        let start = Instant::now();
+        let mut watch = BatteryWatch::default();
+
+        /* The first reading only establishes the charging state. */
+        assert_eq!(watch.update(&config, reading(60, true), start), None);
+        assert_eq!(
+            watch.update(&config, reading(60, false), start),
+            Some(BatteryAlert::ChargingStopped { level: 60 })
+        );
+        assert_eq!(watch.update(&config, reading(60, true), start + Duration::from_secs(10)), None);
+        assert_eq!(
+            watch.update(&config, reading(61, false), start + DEFAULT_DEBOUNCE),
+            Some(BatteryAlert::ChargingStopped { level: 61 })
+        );
+    }
+
+    #[test]
+    fn test_recovered_charge_rearms_threshold() {
+        let config = config();
+        let now = Instant::now();
+        let mut watch = BatteryWatch::default();
+
+        watch.update(&config, reading(20, false), now);
+        /* E.g. a new set of batteries, swapped in without charging. */
+        assert_eq!(watch.update(&config, reading(90, false), now), None);
+        assert_eq!(
+            watch.update(&config, reading(20, false), now),
+            Some(BatteryAlert::Low { level: 20, threshold: 20 })
+        );
+    }
+}
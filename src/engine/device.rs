@@ -1,5 +1,7 @@
 /* Canonical device state shared across DBus objects and drivers: device/profile/resolution/button
  * and LED structures plus enums for actions, DPI, and LED modes. */
+use std::collections::BTreeMap;
+
 /// Button action types exposed over DBus.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[repr(u32)]
@@ -98,7 +100,8 @@ impl Color {
  * Discriminant values 0–3 match the C daemon's ratbag_led_mode enum
  * (Off=0, On=1, Cycle=2, Breathing=3) so that existing clients like
  * Piper work without translation.  Values 4+ are Rust-only extensions
- * for hardware modes not present in the C codebase. */
+ * for hardware modes not present in the C codebase.  Unknown marks an LED
+ * whose state the device cannot report; clients cannot set it. */
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum LedMode {
@@ -109,6 +112,7 @@ pub enum LedMode {
     ColorWave = 4,
     Starlight = 5,
     TriColor = 6,
+    Unknown = 1000,
 }
 
 impl LedMode {
@@ -125,6 +129,243 @@ impl LedMode {
             _ => None,
         }
     }
+
+    /* Parse a `.device` file `LedModes=` entry, case-insensitively.
+     * "ON" is the C spelling of Solid. */
+    pub fn from_name(name: &str) -> Option<LedMode> {
+        match name.to_ascii_uppercase().as_str() {
+            "OFF" => Some(LedMode::Off),
+            "ON" | "SOLID" => Some(LedMode::Solid),
+            "CYCLE" => Some(LedMode::Cycle),
+            "BREATHING" => Some(LedMode::Breathing),
+            "COLORWAVE" => Some(LedMode::ColorWave),
+            "STARLIGHT" => Some(LedMode::Starlight),
+            "TRICOLOR" => Some(LedMode::TriColor),
+            _ => None,
+        }
+    }
+
+    /* The mode closest to this one among `supported`, or None if the LED
+     * offers nothing comparable.  Animated modes degrade towards a
+     * breathing or static colour. */
+    pub fn nearest(self, supported: &[LedMode]) -> Option<LedMode> {
+        let fallbacks: &[LedMode] = match self {
+            LedMode::Off => &[],
+            LedMode::Solid => &[LedMode::Breathing],
+            LedMode::Breathing => &[LedMode::Solid],
+            LedMode::Cycle => &[LedMode::ColorWave, LedMode::Breathing, LedMode::Solid],
+            LedMode::ColorWave => &[LedMode::Cycle, LedMode::Breathing, LedMode::Solid],
+            LedMode::Starlight => &[LedMode::Breathing, LedMode::Solid],
+            LedMode::TriColor => &[LedMode::Solid],
+            LedMode::Unknown => &[],
+        };
+        std::iter::once(self)
+            .chain(fallbacks.iter().copied())
+            .find(|m| supported.contains(m))
+    }
+}
+
+/* One step of a button macro, the common model every driver decodes its
+ * onboard macro format into and encodes back from.  Key events carry HID
+ * keyboard usages (modifiers are 0xE0–0xE7), `Consumer` a HID consumer-page
+ * usage sent as a press/release pair, and button events 1-based mouse
+ * button numbers.  Wheel ticks are positive for scrolling up.
+ *
+ * The DBus type codes returned by `kind()` are shared by the typed
+ * `MacroEvents` property and the legacy `(type, value)` pairs of the
+ * Button `Mapping` property: 0–2 keep the historic press/release/wait
+ * meaning, so old clients see the events they always did. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroEvent {
+    KeyPress(u32),
+    KeyRelease(u32),
+    Wait(u32),
+    ButtonPress(u32),
+    ButtonRelease(u32),
+    Consumer(u32),
+    Wheel(i32),
+    PointerMove { x: i32, y: i32 },
+    /* Pause until the bound button is released. */
+    WaitForRelease,
+    /* Restart the macro from the top while the bound button is held. */
+    RepeatWhilePressed,
+}
+
+impl MacroEvent {
+    pub const KEY_PRESS: u32 = 0;
+    pub const KEY_RELEASE: u32 = 1;
+    pub const WAIT: u32 = 2;
+    pub const BUTTON_PRESS: u32 = 3;
+    pub const BUTTON_RELEASE: u32 = 4;
+    pub const CONSUMER: u32 = 5;
+    pub const WHEEL: u32 = 6;
+    pub const POINTER_MOVE: u32 = 7;
+    pub const WAIT_FOR_RELEASE: u32 = 8;
+    pub const REPEAT_WHILE_PRESSED: u32 = 9;
+
+    /* DBus type code of this event. */
+    pub fn kind(self) -> u32 {
+        match self {
+            MacroEvent::KeyPress(_) => Self::KEY_PRESS,
+            MacroEvent::KeyRelease(_) => Self::KEY_RELEASE,
+            MacroEvent::Wait(_) => Self::WAIT,
+            MacroEvent::ButtonPress(_) => Self::BUTTON_PRESS,
+            MacroEvent::ButtonRelease(_) => Self::BUTTON_RELEASE,
+            MacroEvent::Consumer(_) => Self::CONSUMER,
+            MacroEvent::Wheel(_) => Self::WHEEL,
+            MacroEvent::PointerMove { .. } => Self::POINTER_MOVE,
+            MacroEvent::WaitForRelease => Self::WAIT_FOR_RELEASE,
+            MacroEvent::RepeatWhilePressed => Self::REPEAT_WHILE_PRESSED,
+        }
+    }
+
+    /* Build an event from a legacy `(type, value)` pair.  Wheel and pointer
+     * events carry signed payloads the pair format cannot hold, so they
+     * (and unknown type codes) yield None. */
+    pub fn from_pair(kind: u32, value: u32) -> Option<MacroEvent> {
+        match kind {
+            Self::KEY_PRESS => Some(MacroEvent::KeyPress(value)),
+            Self::KEY_RELEASE => Some(MacroEvent::KeyRelease(value)),
+            Self::WAIT => Some(MacroEvent::Wait(value)),
+            Self::BUTTON_PRESS => Some(MacroEvent::ButtonPress(value)),
+            Self::BUTTON_RELEASE => Some(MacroEvent::ButtonRelease(value)),
+            Self::CONSUMER => Some(MacroEvent::Consumer(value)),
+            Self::WAIT_FOR_RELEASE => Some(MacroEvent::WaitForRelease),
+            Self::REPEAT_WHILE_PRESSED => Some(MacroEvent::RepeatWhilePressed),
+            _ => None,
+        }
+    }
+
+    /* The legacy `(type, value)` pair for this event, if it has one. */
+    pub fn to_pair(self) -> Option<(u32, u32)> {
+        match self {
+            MacroEvent::KeyPress(v)
+            | MacroEvent::KeyRelease(v)
+            | MacroEvent::Wait(v)
+            | MacroEvent::ButtonPress(v)
+            | MacroEvent::ButtonRelease(v)
+            | MacroEvent::Consumer(v) => Some((self.kind(), v)),
+            MacroEvent::WaitForRelease | MacroEvent::RepeatWhilePressed => Some((self.kind(), 0)),
+            MacroEvent::Wheel(_) | MacroEvent::PointerMove { .. } => None,
+        }
+    }
+}
+
+/* Descriptive fields some devices store next to a macro's events
+ * (Roccat keeps all three in its macro report).  Drivers without them
+ * leave this at its default. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MacroMetadata {
+    pub name: String,
+    /* Macro set the macro is filed under in vendor software. */
+    pub group: String,
+    /* How many times the macro plays per press; 0 = device default. */
+    pub loop_count: u32,
+}
+
+/* One occupied pairing slot on a wireless receiver. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairedDevice {
+    /* Receiver slot, 1-based (the HID++ device index). */
+    pub slot: u8,
+    /* Wireless product ID the device paired with. */
+    pub wpid: u16,
+    /* Name stored by the receiver at pairing time; may be empty. */
+    pub name: String,
+}
+
+/* How a receiver pairing window ended. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingOutcome {
+    Paired { slot: u8, wpid: u16 },
+    TimedOut,
+    Unsupported,
+    TooManyDevices,
+    Failed,
+}
+
+impl PairingOutcome {
+    /* Status code carried by the Receiver `PairingFinished` DBus signal. */
+    pub fn status(self) -> u32 {
+        match self {
+            PairingOutcome::Paired { .. } => 0,
+            PairingOutcome::TimedOut => 1,
+            PairingOutcome::Unsupported => 2,
+            PairingOutcome::TooManyDevices => 3,
+            PairingOutcome::Failed => 4,
+        }
+    }
+}
+
+/* Battery charge as read from the device. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryStatus {
+    /* Charge in percent, 0-100. */
+    pub level: u32,
+    pub is_charging: bool,
+}
+
+/* Battery and power-saving state of a wireless device. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PowerInfo {
+    /* Last battery reading; None until the driver has read one. */
+    pub battery: Option<BatteryStatus>,
+    /* Idle time in seconds before the device goes to sleep; None if the
+     * device cannot report it and it has not been set. */
+    pub sleep_timeout: Option<u32>,
+    /* Sleep timeouts the device accepts, in seconds.  Empty if the
+     * timeout cannot be changed. */
+    pub sleep_timeouts: Vec<u32>,
+    /* Battery level in percent at which the device warns the user; None
+     * if the device has no such alert. */
+    pub low_battery_alert: Option<u32>,
+    /* Alert thresholds the device accepts, in percent (0 = off). */
+    pub low_battery_alerts: Vec<u32>,
+    /* True when the last battery read found the device asleep; the
+     * previous reading, if any, is kept. */
+    pub is_asleep: bool,
+    /* Set when the sleep timeout or low-battery alert changed since the
+     * last commit. */
+    pub is_dirty: bool,
+}
+
+/* Capabilities of the motion sensor, shared by every profile. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SensorInfo {
+    /* Sensor part, e.g. "PMW3360"; empty if the driver cannot tell. */
+    pub name: String,
+    pub dpi_min: u32,
+    pub dpi_max: u32,
+    /* Spacing of the settable values between min and max; 0 if they
+     * are not evenly spaced. */
+    pub dpi_step: u32,
+    /* True if X and Y resolutions can be set independently. */
+    pub separate_xy: bool,
+    /* Lift-off distances the sensor can be set to, in millimetres.
+     * Empty if it is not configurable. */
+    pub lift_off_distances: Vec<u32>,
+    /* True if angle snapping and the lift-off distance are one setting
+     * of the whole device: setting them on a profile sets them on all. */
+    pub device_wide_settings: bool,
+}
+
+impl SensorInfo {
+    /* Derive the DPI limits from a list of supported values, in any
+     * order and possibly with repeats, as the device reported it. */
+    pub fn from_dpi_list(dpi_list: &[u32]) -> Option<Self> {
+        let mut dpi_list = dpi_list.to_vec();
+        dpi_list.sort_unstable();
+        dpi_list.dedup();
+        let (&dpi_min, &dpi_max) = (dpi_list.first()?, dpi_list.last()?);
+        let step = dpi_list.get(1).map_or(0, |&second| second - dpi_min);
+        let evenly_spaced = dpi_list.windows(2).all(|w| w[1] - w[0] == step);
+        Some(Self {
+            dpi_min,
+            dpi_max,
+            dpi_step: if evenly_spaced { step } else { 0 },
+            ..Self::default()
+        })
+    }
 }
 
 /* Resolution value, either unified or per-axis. */
@@ -148,6 +389,15 @@ pub struct DeviceInfo {
     pub firmware_version: String,
     /* Device type exposed over DBus: 0=unspecified, 1=other, 2=mouse, 3=keyboard */
     pub device_type: u32,
+    /* Set by drivers that reach the device through a wireless receiver
+     * whose pairing slots they can manage; exposes the Receiver interface. */
+    pub has_receiver: bool,
+    /* Set by drivers of battery-powered devices; exposes the Power
+     * interface. */
+    pub power: Option<PowerInfo>,
+    /* Sensor limits; None if neither the driver nor the device file
+     * describes them. */
+    pub sensor: Option<SensorInfo>,
     pub profiles: Vec<ProfileInfo>,
     pub driver_config: crate::engine::device_database::DriverConfig,
 }
@@ -185,6 +435,23 @@ impl DeviceInfo {
             .as_ref()
             .and_then(|c| c.leds)
             .unwrap_or(0) as usize;
+        /* LED modes come from the `.device` file when it lists them; drivers
+         * that can query the hardware (or know their fixed set) overwrite
+         * them at load time.  Without either, no mode is offered. */
+        let mut led_modes: Vec<LedMode> = Vec::new();
+        for mode in entry
+            .driver_config
+            .as_ref()
+            .map(|c| c.led_modes.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|name| LedMode::from_name(name))
+        {
+            if !led_modes.contains(&mode) {
+                led_modes.push(mode);
+            }
+        }
+
         let num_dpis = entry
             .driver_config
             .as_ref()
@@ -192,12 +459,17 @@ impl DeviceInfo {
             .unwrap_or(1) as usize;
 
         /* Build DPI list from the range specification if available */
-        let dpi_list: Vec<u32> = entry
-            .driver_config
-            .as_ref()
-            .and_then(|c| c.dpi_range.as_ref())
+        let dpi_range = entry.driver_config.as_ref().and_then(|c| c.dpi_range.as_ref());
+        let dpi_list: Vec<u32> = dpi_range
             .map(|r| (r.min..=r.max).step_by(r.step as usize).collect())
             .unwrap_or_else(|| vec![800, 1600]);
+        /* Drivers that know the sensor replace this at load time. */
+        let sensor = dpi_range.map(|r| SensorInfo {
+            dpi_min: r.min,
+            dpi_max: r.max,
+            dpi_step: r.step,
+            ..SensorInfo::default()
+        });
 
         let profiles: Vec<ProfileInfo> = (0..num_profiles as u32)
             .map(|idx| ProfileInfo {
@@ -211,6 +483,8 @@ impl DeviceInfo {
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: Vec::new(),
+                lift_off_distance: -1,
+                lift_off_distances: Vec::new(),
                 capabilities: Vec::new(),
                 resolutions: (0..num_dpis as u32)
                     .map(|ri| ResolutionInfo {
@@ -221,6 +495,7 @@ impl DeviceInfo {
                         is_active: ri == 0,
                         is_default: ri == 0,
                         is_disabled: false,
+                        is_shift: false,
                     })
                     .collect(),
                 buttons: (0..num_buttons as u32)
@@ -230,27 +505,23 @@ impl DeviceInfo {
                         action_types: vec![0, 1, 2, 3, 4],
                         mapping_value: bi,
                         macro_entries: Vec::new(),
+                        macro_metadata: MacroMetadata::default(),
+                        layer: BUTTON_LAYER_BASE,
                     })
                     .collect(),
                 leds: (0..num_leds as u32)
                     .map(|li| LedInfo {
                         index: li,
                         mode: LedMode::Off,
-                        modes: vec![
-                            LedMode::Off,
-                            LedMode::Solid,
-                            LedMode::Cycle,
-                            LedMode::ColorWave,
-                            LedMode::Starlight,
-                            LedMode::Breathing,
-                            LedMode::TriColor,
-                        ],
+                        modes: led_modes.clone(),
                         color: Color::default(),
                         secondary_color: Color::default(),
                         tertiary_color: Color::default(),
                         color_depth: 1,
                         effect_duration: 0,
                         brightness: 255,
+                        effects: Vec::new(),
+                        effect: None,
                     })
                     .collect(),
             })
@@ -270,6 +541,9 @@ impl DeviceInfo {
             model,
             firmware_version: String::new(),
             device_type,
+            has_receiver: false,
+            power: None,
+            sensor,
             profiles,
             driver_config: entry.driver_config.clone().unwrap_or_default(),
         }
@@ -337,6 +611,20 @@ impl DeviceInfo {
         next
     }
 
+    pub fn with_shift_resolution(&self, profile_id: u32, resolution_id: u32) -> Self {
+        let mut next = self.clone();
+        if let Some(profile) = next.find_profile_mut(profile_id) {
+            for res in &mut profile.resolutions {
+                res.is_shift = false;
+            }
+            if let Some(res) = profile.find_resolution_mut(resolution_id) {
+                res.is_shift = true;
+                profile.is_dirty = true;
+            }
+        }
+        next
+    }
+
     pub fn with_profile_name(&self, profile_id: u32, name: String) -> Self {
         let mut next = self.clone();
         if let Some(profile) = next.find_profile_mut(profile_id) {
@@ -360,6 +648,9 @@ impl DeviceInfo {
         if let Some(profile) = next.find_profile_mut(profile_id) {
             profile.angle_snapping = value;
             profile.is_dirty = true;
+            for profile in next.profiles_sharing_sensor_settings() {
+                profile.angle_snapping = value;
+            }
         }
         next
     }
@@ -373,6 +664,26 @@ impl DeviceInfo {
         next
     }
 
+    pub fn with_profile_lift_off_distance(&self, profile_id: u32, value: i32) -> Self {
+        let mut next = self.clone();
+        if let Some(profile) = next.find_profile_mut(profile_id) {
+            profile.lift_off_distance = value;
+            profile.is_dirty = true;
+            for profile in next.profiles_sharing_sensor_settings() {
+                profile.lift_off_distance = value;
+            }
+        }
+        next
+    }
+
+    /* Every profile when the sensor settings are device-wide, none
+     * otherwise.  The other profiles only mirror the value, so they are
+     * not marked dirty. */
+    fn profiles_sharing_sensor_settings(&mut self) -> impl Iterator<Item = &mut ProfileInfo> {
+        let shared = self.sensor.as_ref().is_some_and(|s| s.device_wide_settings);
+        self.profiles.iter_mut().filter(move |_| shared)
+    }
+
     pub fn with_profile_report_rate(&self, profile_id: u32, rate: u32) -> Self {
         let mut next = self.clone();
         if let Some(profile) = next.find_profile_mut(profile_id) {
@@ -400,7 +711,7 @@ impl DeviceInfo {
         button_id: u32,
         action_type: ActionType,
         mapping_value: u32,
-        macro_entries: Vec<(u32, u32)>,
+        macro_entries: Vec<MacroEvent>,
     ) -> Self {
         let mut next = self.clone();
         if let Some(profile) = next.find_profile_mut(profile_id) {
@@ -414,17 +725,72 @@ impl DeviceInfo {
         next
     }
 
+    pub fn with_button_macro_metadata(
+        &self,
+        profile_id: u32,
+        button_id: u32,
+        metadata: MacroMetadata,
+    ) -> Self {
+        let mut next = self.clone();
+        if let Some(profile) = next.find_profile_mut(profile_id)
+            && let Some(button) = profile.find_button_mut(button_id)
+        {
+            button.macro_metadata = metadata;
+            profile.is_dirty = true;
+        }
+        next
+    }
+
     pub fn with_led_mode(&self, profile_id: u32, led_id: u32, mode: LedMode) -> Self {
         let mut next = self.clone();
         if let Some(profile) = next.find_profile_mut(profile_id) {
             if let Some(led) = profile.find_led_mut(led_id) {
                 led.mode = mode;
+                led.effect = None;
                 profile.is_dirty = true;
             }
         }
         next
     }
 
+    /* Apply one lighting setup to every LED of every profile, each LED
+     * showing the nearest mode it supports.  `colors` fill the primary,
+     * secondary and tertiary colours in order; LEDs with no comparable
+     * mode are left untouched. */
+    pub fn with_lighting_all(&self, mode: LedMode, colors: &[Color], brightness: u32) -> Self {
+        let mut next = self.clone();
+        for profile in &mut next.profiles {
+            for led in &mut profile.leds {
+                let Some(nearest) = mode.nearest(&led.modes) else {
+                    continue;
+                };
+                led.mode = nearest;
+                led.effect = None;
+                for (slot, color) in
+                    [&mut led.color, &mut led.secondary_color, &mut led.tertiary_color]
+                        .into_iter()
+                        .zip(colors)
+                {
+                    *slot = *color;
+                }
+                led.brightness = brightness.min(255);
+                profile.is_dirty = true;
+            }
+        }
+        next
+    }
+
+    pub fn with_led_effect(&self, profile_id: u32, led_id: u32, effect: LedEffectState) -> Self {
+        let mut next = self.clone();
+        if let Some(profile) = next.find_profile_mut(profile_id)
+            && let Some(led) = profile.find_led_mut(led_id)
+        {
+            led.effect = Some(effect);
+            profile.is_dirty = true;
+        }
+        next
+    }
+
     pub fn with_led_color(&self, profile_id: u32, led_id: u32, color: Color) -> Self {
         let mut next = self.clone();
         if let Some(profile) = next.find_profile_mut(profile_id) {
@@ -480,11 +846,132 @@ impl DeviceInfo {
         next
     }
 
+    /* Copy the resolutions, buttons, LEDs, report rate and debounce of
+     * `source` onto a profile, one setting at a time through the
+     * transitions above, leaving it dirty as if each had been written
+     * separately.  Items are matched by index; ones the target lacks are
+     * skipped.  Name, enabled state and sensor settings stay as they are. */
+    pub fn with_profile_copied_from(&self, profile_id: u32, source: &ProfileInfo) -> Self {
+        let mut next = self
+            .with_profile_report_rate(profile_id, source.report_rate)
+            .with_profile_debounce(profile_id, source.debounce);
+
+        for res in &source.resolutions {
+            next = next
+                .with_resolution_dpi(profile_id, res.index, res.dpi)
+                .with_resolution_disabled(profile_id, res.index, res.is_disabled);
+            if res.is_active {
+                next = next.with_active_resolution(profile_id, res.index);
+            }
+            if res.is_default {
+                next = next.with_default_resolution(profile_id, res.index);
+            }
+            if res.is_shift {
+                next = next.with_shift_resolution(profile_id, res.index);
+            }
+        }
+
+        for button in &source.buttons {
+            next = next
+                .with_button_mapping(
+                    profile_id,
+                    button.index,
+                    button.action_type,
+                    button.mapping_value,
+                    button.macro_entries.clone(),
+                )
+                .with_button_macro_metadata(
+                    profile_id,
+                    button.index,
+                    button.macro_metadata.clone(),
+                );
+        }
+
+        for led in &source.leds {
+            next = next
+                .with_led_mode(profile_id, led.index, led.mode)
+                .with_led_color(profile_id, led.index, led.color)
+                .with_led_secondary_color(profile_id, led.index, led.secondary_color)
+                .with_led_tertiary_color(profile_id, led.index, led.tertiary_color)
+                .with_led_effect_duration(profile_id, led.index, led.effect_duration)
+                .with_led_brightness(profile_id, led.index, led.brightness);
+            if let Some(effect) = &led.effect {
+                next = next.with_led_effect(profile_id, led.index, effect.clone());
+            }
+        }
+        next
+    }
+
+    /* Reset a profile to a driver-neutral baseline: unnamed and enabled,
+     * every resolution slot enabled with the first one active/default,
+     * and each button mapped back to its own logical (1-based) button.
+     * DPI values, LEDs and sensor settings are left as they are since
+     * the engine has no knowledge of their factory values.  Used by
+     * drivers that have no ROM copy of their profiles. */
+    pub fn with_profile_defaults(&self, profile_id: u32) -> Self {
+        let mut next = self.clone();
+        if let Some(profile) = next.find_profile_mut(profile_id) {
+            profile.name.clear();
+            profile.is_enabled = true;
+            for res in &mut profile.resolutions {
+                res.is_disabled = false;
+                res.is_active = res.index == 0;
+                res.is_default = res.index == 0;
+            }
+            for button in &mut profile.buttons {
+                button.action_type = ActionType::Button;
+                button.mapping_value = button.index + 1;
+                button.macro_entries.clear();
+            }
+            profile.is_dirty = true;
+        }
+        next
+    }
+
+    pub fn with_sleep_timeout(&self, seconds: u32) -> Self {
+        let mut next = self.clone();
+        if let Some(power) = next.power.as_mut() {
+            power.sleep_timeout = Some(seconds);
+            power.is_dirty = true;
+        }
+        next
+    }
+
+    pub fn with_low_battery_alert(&self, percent: u32) -> Self {
+        let mut next = self.clone();
+        if let Some(power) = next.power.as_mut() {
+            power.low_battery_alert = Some(percent);
+            power.is_dirty = true;
+        }
+        next
+    }
+
     pub fn with_cleared_dirty_flags(&self) -> Self {
         let mut next = self.clone();
         for profile in &mut next.profiles {
             profile.is_dirty = false;
         }
+        if let Some(power) = next.power.as_mut() {
+            power.is_dirty = false;
+        }
+        next
+    }
+
+    /* Whether any profile or the power settings await a commit. */
+    pub fn is_dirty(&self) -> bool {
+        self.profiles.iter().any(|p| p.is_dirty) || self.power.as_ref().is_some_and(|p| p.is_dirty)
+    }
+
+    /* Copy the dirty flags of `other` onto this state, matching profiles
+     * by index.  Used to rewrite exactly what a commit touched. */
+    pub fn with_dirty_flags_from(&self, other: &DeviceInfo) -> Self {
+        let mut next = self.clone();
+        for profile in &mut next.profiles {
+            profile.is_dirty = other.find_profile(profile.index).is_some_and(|p| p.is_dirty);
+        }
+        if let Some(power) = next.power.as_mut() {
+            power.is_dirty = other.power.as_ref().is_some_and(|p| p.is_dirty);
+        }
         next
     }
 }
@@ -496,10 +983,19 @@ pub const RATBAG_PROFILE_CAP_DISABLE: u32 = 102;
 
 /* Resolution capability constants matching libratbag's `ratbag_resolution_capability` enum.
  * SEPARATE_XY gates whether a (u32,u32) DPI tuple is accepted over DBus;
- * DISABLE gates whether the is_disabled property can be toggled. */
+ * DISABLE gates whether the is_disabled property can be toggled.
+ * SHIFT is a ratbagd extension: the slot can be chosen as the shift
+ * resolution with SetShift.  A slot flagged is_shift without it is a
+ * dedicated shift-only value (G600 DPI shift). */
 pub const RATBAG_RESOLUTION_CAP_INDIVIDUAL_REPORT_RATE: u32 = 1;
 pub const RATBAG_RESOLUTION_CAP_SEPARATE_XY_RESOLUTION: u32 = 2;
 pub const RATBAG_RESOLUTION_CAP_DISABLE: u32 = 3;
+pub const RATBAG_RESOLUTION_CAP_SHIFT: u32 = 4;
+
+/* Button layers. Most devices only have the base layer; devices with a
+ * hold-to-shift mode (G600 G-Shift) expose a second set of bindings. */
+pub const BUTTON_LAYER_BASE: u32 = 0;
+pub const BUTTON_LAYER_SHIFT: u32 = 1;
 
 /// Minimum and maximum allowed report rates (Hz) for sanity-clamping.
 pub const REPORT_RATE_MIN: u32 = 125;
@@ -518,6 +1014,9 @@ pub struct ProfileInfo {
     pub angle_snapping: i32,
     pub debounce: i32,
     pub debounces: Vec<u32>,
+    /* Sensor lift-off distance in millimetres, -1 if unsupported. */
+    pub lift_off_distance: i32,
+    pub lift_off_distances: Vec<u32>,
     pub capabilities: Vec<u32>,
     pub resolutions: Vec<ResolutionInfo>,
     pub buttons: Vec<ButtonInfo>,
@@ -609,6 +1108,17 @@ pub struct ResolutionInfo {
     pub is_active: bool,
     pub is_default: bool,
     pub is_disabled: bool,
+    /* Shift ("sniper") slot: the resolution used while a RESOLUTION_ALTERNATE
+     * button is held.  See RATBAG_RESOLUTION_CAP_SHIFT. */
+    pub is_shift: bool,
+}
+
+impl ResolutionInfo {
+    /* A shift-only slot (no CAP_SHIFT) cannot be cycled to, so it can
+     * never be the active or default resolution. */
+    pub fn is_dedicated_shift(&self) -> bool {
+        self.is_shift && !self.capabilities.contains(&RATBAG_RESOLUTION_CAP_SHIFT)
+    }
 }
 
 /// Button mapping state.
@@ -618,7 +1128,11 @@ pub struct ButtonInfo {
     pub action_type: ActionType,
     pub action_types: Vec<u32>,
     pub mapping_value: u32,
-    pub macro_entries: Vec<(u32, u32)>,
+    pub macro_entries: Vec<MacroEvent>,
+    pub macro_metadata: MacroMetadata,
+    /* Binding layer: BUTTON_LAYER_BASE, or BUTTON_LAYER_SHIFT for bindings
+     * that only apply while the device's shift mode (e.g. G-Shift) is held. */
+    pub layer: u32,
 }
 
 /// LED state.
@@ -633,4 +1147,49 @@ pub struct LedInfo {
     pub color_depth: u32,
     pub effect_duration: u32,
     pub brightness: u32,
+    /* Hardware-native effects the driver offers beyond `modes`; empty if
+     * the LED only speaks the generic modes. */
+    pub effects: Vec<LedEffect>,
+    /* Selected native effect.  None means the LED follows `mode`; setting
+     * the mode clears it. */
+    pub effect: Option<LedEffectState>,
+}
+
+/* A vendor LED effect as offered by the driver. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedEffect {
+    /* Driver-defined id, stable across restarts (usually the hardware
+     * effect code). */
+    pub id: u32,
+    pub name: &'static str,
+    pub params: Vec<LedEffectParam>,
+}
+
+/* One parameter an effect takes and the values it accepts. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedEffectParam {
+    pub name: &'static str,
+    pub min: u32,
+    pub max: u32,
+}
+
+impl LedEffectParam {
+    /* Well-known parameter names; drivers may add their own. */
+    pub const SPEED: &'static str = "speed";
+    pub const DIRECTION: &'static str = "direction";
+    pub const COLORS: &'static str = "colors";
+}
+
+/* A selected effect with a value for each of its parameters. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LedEffectState {
+    pub id: u32,
+    pub params: BTreeMap<String, u32>,
+}
+
+impl LedEffectState {
+    /* Value of `name`, or `default` if the effect was selected without it. */
+    pub fn param(&self, name: &str, default: u32) -> u32 {
+        self.params.get(name).copied().unwrap_or(default)
+    }
 }
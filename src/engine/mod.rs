@@ -1,6 +1,8 @@
 pub mod actor;
+pub mod battery;
 pub mod device;
 pub mod device_database;
+pub mod rules;
 pub mod test_device;
 
 pub use device_database::load_device_database;
@@ -0,0 +1,211 @@
+/* Profile switching rules: maps the focused application (an app id or window class reported
+ * through Manager.SetFocusedApplication) to the profile each device should run.
+ *
+ * Rules live in an INI file, one section per rule:
+ *
+ *     [browsing]
+ *     match = firefox, org.gnome.Nautilus
+ *     profile = 0
+ *
+ *     [shooter]
+ *     match = steam_app_730
+ *     device = usb:046d:c539:0
+ *     profile = 2
+ *
+ *     [fallback]
+ *     match = *
+ *     profile = 0
+ *
+ * `match` lists app ids or window classes, compared case-insensitively; `*` matches any
+ * application no other rule names.  `device` is optional and selects devices by model
+ * string or by a substring of their name; without it the rule applies to every device.
+ * When several rules fit a device, the first section in alphabetical order wins. */
+use std::path::{Path, PathBuf};
+
+use configparser::ini::Ini;
+
+/* App id that matches any application without a rule of its own. */
+const ANY_APP: &str = "*";
+
+/* One rule section of the rules file. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileRule {
+    /* Section name, used in log messages. */
+    pub name: String,
+    /* Lowercased app ids / window classes, possibly `*`. */
+    pub apps: Vec<String>,
+    /* Model string or name substring; None applies to every device. */
+    pub device: Option<String>,
+    pub profile: u32,
+}
+
+impl ProfileRule {
+    fn matches_device(&self, model: &str, name: &str) -> bool {
+        match &self.device {
+            None => true,
+            Some(device) => {
+                model.eq_ignore_ascii_case(device)
+                    || name.to_lowercase().contains(&device.to_lowercase())
+            }
+        }
+    }
+}
+
+/* The parsed rules file; empty when no rules are configured. */
+#[derive(Debug, Clone, Default)]
+pub struct ProfileRules {
+    rules: Vec<ProfileRule>,
+}
+
+impl ProfileRules {
+    /* Parse the rules from the text of a rules file. */
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut ini = Ini::new();
+        ini.read(text.to_string())
+            .map_err(|e| format!("INI parse error: {}", e))?;
+
+        let mut sections = ini.sections();
+        sections.sort();
+
+        let mut rules = Vec::new();
+        for section in sections {
+            let apps: Vec<String> = ini
+                .get(&section, "match")
+                .ok_or_else(|| format!("rule [{}] has no match key", section))?
+                .split(',')
+                .map(|app| app.trim().to_lowercase())
+                .filter(|app| !app.is_empty())
+                .collect();
+            if apps.is_empty() {
+                return Err(format!("rule [{}] matches no application", section));
+            }
+
+            let profile = ini
+                .get(&section, "profile")
+                .ok_or_else(|| format!("rule [{}] has no profile key", section))?;
+            let profile = profile
+                .trim()
+                .parse()
+                .map_err(|_| format!("rule [{}] has invalid profile '{}'", section, profile))?;
+
+            let device = ini
+                .get(&section, "device")
+                .map(|d| d.trim().to_string())
+                .filter(|d| !d.is_empty());
+
+            rules.push(ProfileRule {
+                name: section,
+                apps,
+                device,
+                profile,
+            });
+        }
+
+        Ok(Self { rules })
+    }
+
+    /* Load the rules file at `path`. */
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&text)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /* The rule that decides the profile of the device `model`/`name`
+     * while `app` has focus.  A rule naming the app beats a `*` rule. */
+    pub fn rule_for(&self, app: &str, model: &str, name: &str) -> Option<&ProfileRule> {
+        let app = app.trim().to_lowercase();
+        let fits = |rule: &&ProfileRule, wanted: &str| {
+            rule.apps.iter().any(|a| a == wanted) && rule.matches_device(model, name)
+        };
+        self.rules
+            .iter()
+            .find(|rule| fits(rule, &app))
+            .or_else(|| self.rules.iter().find(|rule| fits(rule, ANY_APP)))
+    }
+}
+
+/* Location of the rules file: `$RATBAGD_PROFILE_RULES` if set, otherwise
+ * `ratbagd/profile-rules.ini` in the user's configuration directory. */
+pub fn default_rules_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("RATBAGD_PROFILE_RULES") {
+        return Some(PathBuf::from(path));
+    }
+    Some(user_config_dir()?.join("ratbagd").join("profile-rules.ini"))
+}
+
+/* The user's configuration directory: `$XDG_CONFIG_HOME`, or `~/.config`. */
+pub fn user_config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = "
+[browsing]
+match = Firefox, org.gnome.Nautilus
+profile = 0
+
+[shooter]
+match = steam_app_730
+device = usb:046d:c539:0
+profile = 2
+
+[zz-fallback]
+match = *
+profile = 1
+";
+
+    #[test]
+    fn test_rule_matches_app_case_insensitively() {
+        let rules = ProfileRules::parse(RULES).unwrap();
+        assert_eq!(rules.len(), 3);
+        let rule = rules.rule_for("firefox", "usb:046d:c539:0", "G Pro").unwrap();
+        assert_eq!(rule.name, "browsing");
+        assert_eq!(rule.profile, 0);
+    }
+
+    #[test]
+    fn test_rule_device_filter() {
+        let rules = ProfileRules::parse(RULES).unwrap();
+        let mouse = rules.rule_for("steam_app_730", "usb:046d:c539:0", "G Pro").unwrap();
+        assert_eq!(mouse.profile, 2);
+        /* Another device falls through to the wildcard rule. */
+        let other = rules.rule_for("steam_app_730", "usb:1038:1836:0", "Rival").unwrap();
+        assert_eq!(other.name, "zz-fallback");
+    }
+
+    #[test]
+    fn test_rule_device_name_substring() {
+        let rules =
+            ProfileRules::parse("[r]\nmatch = foot\ndevice = g pro\nprofile = 3\n").unwrap();
+        assert!(rules.rule_for("foot", "usb:046d:c539:0", "Logitech G Pro").is_some());
+        assert!(rules.rule_for("foot", "usb:046d:c539:0", "Rival 3").is_none());
+    }
+
+    #[test]
+    fn test_rules_without_wildcard_leave_other_apps_alone() {
+        let rules = ProfileRules::parse("[r]\nmatch = foot\nprofile = 1\n").unwrap();
+        assert!(rules.rule_for("firefox", "usb:046d:c539:0", "G Pro").is_none());
+    }
+
+    #[test]
+    fn test_rules_reject_incomplete_sections() {
+        assert!(ProfileRules::parse("[r]\nprofile = 1\n").is_err());
+        assert!(ProfileRules::parse("[r]\nmatch = foot\n").is_err());
+        assert!(ProfileRules::parse("[r]\nmatch = foot\nprofile = two\n").is_err());
+        assert!(ProfileRules::parse("[r]\nmatch = ,\nprofile = 1\n").is_err());
+    }
+}
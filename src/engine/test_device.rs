@@ -11,8 +11,8 @@ pub mod spec {
     use serde::Deserialize;
 
     use crate::engine::device::{
-        ActionType, ButtonInfo, Color, DeviceInfo, Dpi, LedInfo, LedMode, ProfileInfo,
-        ResolutionInfo,
+        ActionType, BUTTON_LAYER_BASE, ButtonInfo, Color, DeviceInfo, Dpi, LedInfo, LedMode,
+        MacroMetadata, ProfileInfo, ResolutionInfo,
     };
     use crate::engine::device_database::DriverConfig;
 
@@ -201,6 +201,7 @@ pub mod spec {
                             is_active: r.is_active,
                             is_default: r.is_default,
                             is_disabled: r.is_disabled,
+                            is_shift: false,
                         }
                     })
                     .collect();
@@ -224,6 +225,8 @@ pub mod spec {
                             action_types: vec![0, 1, 2, 3, 4],
                             mapping_value: b.button,
                             macro_entries: Vec::new(),
+                            macro_metadata: MacroMetadata::default(),
+                            layer: BUTTON_LAYER_BASE,
                         }
                     })
                     .collect();
@@ -265,6 +268,8 @@ pub mod spec {
                             color_depth: 1,
                             effect_duration: l.duration,
                             brightness: l.brightness,
+                            effects: Vec::new(),
+                            effect: None,
                         }
                     })
                     .collect();
@@ -280,6 +285,8 @@ pub mod spec {
                     angle_snapping: -1,
                     debounce: -1,
                     debounces: Vec::new(),
+                    lift_off_distance: -1,
+                    lift_off_distances: Vec::new(),
                     capabilities: Vec::new(),
                     resolutions,
                     buttons,
@@ -294,6 +301,9 @@ pub mod spec {
             model: "test:0000:0000:0".to_string(),
             firmware_version: String::new(),
             device_type: 2, /* mouse */
+            has_receiver: false,
+            power: None,
+            sensor: None,
             profiles,
             driver_config: DriverConfig::default(),
         }
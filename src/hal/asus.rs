@@ -14,9 +14,12 @@
  * Reads:  GET_PROFILE_DATA (active profile, dpi preset, firmware),
  *         GET_BUTTON_DATA (17 bindings per group), GET_SETTINGS (DPI
  *         presets + rate/debounce/snapping, optionally per-axis XY),
- *         GET_LED_DATA (up to 3 LEDs: mode, brightness, RGB).
+ *         GET_LED_DATA (up to 3 LEDs: mode, brightness, RGB),
+ *         GET_BATTERY (wireless only: charge, sleep timeout, low-battery
+ *         alert).
  * Writes: SET_BUTTON, SET_SETTING, SET_LED, SET_PROFILE, SAVE (persist
- *         the currently selected profile to flash).
+ *         the currently selected profile to flash), SET_BATTERY (sleep
+ *         timeout + low-battery alert, applied immediately).
  *
  * Per-device quirks (Quirks= in the .device file):
  *   DOUBLE_DPI        wire DPI value is half the real DPI
@@ -25,13 +28,20 @@
  *   SEPARATE_XY_DPI   extra GET_SETTINGS query for per-axis DPI
  *   SEPARATE_LEDS     one GET_LED_DATA query per LED index
  *   BUTTONS_SECONDARY second button group (indices 17-33)
+ *   BATTERY_V2        GET_BATTERY fields sit one byte later
+ *   LIFT_OFF_DISTANCE settings carry a lift-off distance after snapping
  */
 
+use std::collections::BTreeMap;
+
 use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
 use tracing::{debug, warn};
 
-use crate::engine::device::{ActionType, Color, DeviceInfo, Dpi, LedMode, ProfileInfo};
+use crate::engine::device::{
+    ActionType, BatteryStatus, Color, DeviceInfo, Dpi, LedEffect, LedEffectState, LedInfo,
+    LedMode, PowerInfo, ProfileInfo, SensorInfo,
+};
 use crate::hal::{DeviceDriver, DeviceIo, DriverError};
 
 // ────────────────────────────── Constants ──────────────────────────────────
@@ -59,11 +69,14 @@ const ASUS_CMD_SET_SETTING:      u16 = 0x3151; /* dpi / rate / button response /
 const ASUS_CMD_SET_BUTTON:       u16 = 0x2151; /* set single button */
 const ASUS_CMD_SET_PROFILE:      u16 = 0x0250; /* switch profile */
 const ASUS_CMD_SAVE:             u16 = 0x0350; /* save settings */
+const ASUS_CMD_GET_BATTERY:      u16 = 0x0712; /* battery, sleep timeout, low-battery alert */
+const ASUS_CMD_SET_BATTERY:      u16 = 0x3751; /* sleep timeout / low-battery alert */
 
 /* Field selectors for ASUS_CMD_SET_SETTING (added to dpi_count). */
 const ASUS_FIELD_RATE:     u8 = 0;
 const ASUS_FIELD_RESPONSE: u8 = 1;
 const ASUS_FIELD_SNAPPING: u8 = 2;
+const ASUS_FIELD_LOD:      u8 = 3;
 
 /* Button action type bytes from the hardware. */
 const ASUS_ACTION_TYPE_KEY:      u8 = 0; /* keyboard key */
@@ -79,16 +92,22 @@ const ASUS_MAX_NUM_LED_MODES:    usize = 7;
 /* Quirk bitmasks. */
 const ASUS_QUIRK_DOUBLE_DPI:        u32 = 1 << 0;
 const ASUS_QUIRK_STRIX_PROFILE:     u32 = 1 << 1;
-#[allow(dead_code)]
-const ASUS_QUIRK_BATTERY_V2:        u32 = 1 << 2; /* unused in probe/commit, reserved */
+const ASUS_QUIRK_BATTERY_V2:        u32 = 1 << 2;
 const ASUS_QUIRK_RAW_BRIGHTNESS:    u32 = 1 << 3;
 const ASUS_QUIRK_SEPARATE_XY_DPI:   u32 = 1 << 4;
 const ASUS_QUIRK_SEPARATE_LEDS:     u32 = 1 << 5;
 const ASUS_QUIRK_BUTTONS_SECONDARY: u32 = 1 << 6;
+const ASUS_QUIRK_LIFT_OFF_DISTANCE: u32 = 1 << 7;
 
 /* Fixed hardware capability lists. */
 static ASUS_POLLING_RATES:  &[u32] = &[125, 250, 500, 1000];
 static ASUS_DEBOUNCE_TIMES: &[u32] = &[4, 8, 12, 16, 20, 24, 28, 32];
+static ASUS_LIFT_OFF_DISTANCES: &[u32] = &[1, 2]; /* mm, indexed by the hardware value */
+
+/* Wireless power settings, indexed by the hardware value.  A sleep
+ * timeout of 0 s means "never sleep"; an alert of 0 % means "no alert". */
+static ASUS_SLEEP_TIMEOUTS:      &[u32] = &[60, 120, 180, 300, 600, 0];
+static ASUS_LOW_BATTERY_ALERTS:  &[u32] = &[0, 10, 15, 20, 25];
 
 /* Default button-mapping (ASUS hardware code for each button slot).
  * Values that stay -1 after init_from_config mean "unused slot". */
@@ -116,6 +135,18 @@ const ASUS_DEFAULT_LED_MODES: [LedMode; ASUS_MAX_NUM_LED_MODES] = [
     LedMode::Solid,
 ];
 
+/* Names of the hardware modes, exposed as native effects with the mode
+ * index as the effect id. */
+const ASUS_LED_EFFECT_NAMES: [&str; ASUS_MAX_NUM_LED_MODES] = [
+    "static",
+    "breathing",
+    "color-cycle",
+    "wave",
+    "reactive",
+    "custom",
+    "battery",
+];
+
 // ─────────────────────────── Button tables ─────────────────────────────────
 
 /* Ratbag-side action kind for an entry in the ASUS button table. */
@@ -301,6 +332,7 @@ fn parse_quirks(quirk_strings: &[String]) -> u32 {
             "SEPARATE_XY_DPI"   => q |= ASUS_QUIRK_SEPARATE_XY_DPI,
             "SEPARATE_LEDS"     => q |= ASUS_QUIRK_SEPARATE_LEDS,
             "BUTTONS_SECONDARY" => q |= ASUS_QUIRK_BUTTONS_SECONDARY,
+            "LIFT_OFF_DISTANCE" => q |= ASUS_QUIRK_LIFT_OFF_DISTANCE,
             other => warn!("ASUS: unknown quirk string: {}", other),
         }
     }
@@ -431,20 +463,10 @@ fn debounce_index(ms: u32) -> Option<u8> {
 
 /// Parse a LED mode string from a `.device` file `LedModes=` field.
 fn parse_led_mode_str(s: &str) -> LedMode {
-    if s.eq_ignore_ascii_case("ON") || s.eq_ignore_ascii_case("SOLID") {
-        LedMode::Solid
-    } else if s.eq_ignore_ascii_case("BREATHING") {
-        LedMode::Breathing
-    } else if s.eq_ignore_ascii_case("CYCLE") {
-        LedMode::Cycle
-    } else if s.eq_ignore_ascii_case("OFF") {
-        LedMode::Off
-    } else if s.eq_ignore_ascii_case("COLORWAVE") {
-        LedMode::ColorWave
-    } else {
+    LedMode::from_name(s).unwrap_or_else(|| {
         warn!("ASUS: unknown LED mode string: {}", s);
         LedMode::Solid
-    }
+    })
 }
 
 // ─────────────────────────── Packet types ──────────────────────────────────
@@ -534,12 +556,14 @@ struct AsusDpi2Data {
     rate_idx:     u16,
     response_idx: u16,
     snapping:     u16,
+    lod_idx:      u16, /* only meaningful with LIFT_OFF_DISTANCE */
 }
 
 impl AsusDpi2Data {
     /* Wire layout (matches `_asus_dpi2_data` overlaid on response at raw[0]):
      *   raw[0..3]=pad, raw[4..5]=dpi[0], raw[6..7]=dpi[1],
-     *   raw[8..9]=rate, raw[10..11]=response, raw[12..13]=snapping
+     *   raw[8..9]=rate, raw[10..11]=response, raw[12..13]=snapping,
+     *   raw[14..15]=lift-off distance
      * result(i) = raw[i+2], so raw[4] = result(2), raw[5] = result(3), ...
      */
     fn from_response(resp: &AsusResponse) -> Self {
@@ -551,6 +575,7 @@ impl AsusDpi2Data {
             rate_idx:     u16::from_le_bytes([resp.result(6),  resp.result(7)]),
             response_idx: u16::from_le_bytes([resp.result(8),  resp.result(9)]),
             snapping:     u16::from_le_bytes([resp.result(10), resp.result(11)]),
+            lod_idx:      u16::from_le_bytes([resp.result(12), resp.result(13)]),
         }
     }
 }
@@ -561,12 +586,14 @@ struct AsusDpi4Data {
     rate_idx:     u16,
     response_idx: u16,
     snapping:     u16,
+    lod_idx:      u16, /* only meaningful with LIFT_OFF_DISTANCE */
 }
 
 impl AsusDpi4Data {
     /* Wire layout (matches `_asus_dpi4_data` overlaid on response at raw[0]):
      *   raw[0..3]=pad, raw[4..5]=dpi[0], …, raw[10..11]=dpi[3],
-     *   raw[12..13]=rate, raw[14..15]=response, raw[16..17]=snapping
+     *   raw[12..13]=rate, raw[14..15]=response, raw[16..17]=snapping,
+     *   raw[18..19]=lift-off distance
      */
     fn from_response(resp: &AsusResponse) -> Self {
         Self {
@@ -579,6 +606,7 @@ impl AsusDpi4Data {
             rate_idx:     u16::from_le_bytes([resp.result(10), resp.result(11)]),
             response_idx: u16::from_le_bytes([resp.result(12), resp.result(13)]),
             snapping:     u16::from_le_bytes([resp.result(14), resp.result(15)]),
+            lod_idx:      u16::from_le_bytes([resp.result(16), resp.result(17)]),
         }
     }
 }
@@ -606,6 +634,33 @@ impl AsusDpiXyData {
     }
 }
 
+/* Parsed GET_BATTERY data (wireless devices only). */
+struct AsusBatteryData {
+    battery:           BatteryStatus,
+    sleep_timeout:     Option<u32>, /* None: index outside ASUS_SLEEP_TIMEOUTS */
+    low_battery_alert: Option<u32>, /* None: index outside ASUS_LOW_BATTERY_ALERTS */
+}
+
+impl AsusBatteryData {
+    /* Wire layout (offset by one byte with BATTERY_V2):
+     *   raw[0..3]=pad, raw[4]=level (percent), raw[5]=charging flag,
+     *   raw[6]=sleep timeout index, raw[7]=low-battery alert index
+     */
+    fn from_response(resp: &AsusResponse, quirks: u32) -> Self {
+        let base = if quirks & ASUS_QUIRK_BATTERY_V2 != 0 { 3 } else { 2 };
+        Self {
+            battery: BatteryStatus {
+                level:       u32::from(resp.result(base)).min(100),
+                is_charging: resp.result(base + 1) != 0,
+            },
+            sleep_timeout:     ASUS_SLEEP_TIMEOUTS.get(resp.result(base + 2) as usize).copied(),
+            low_battery_alert: ASUS_LOW_BATTERY_ALERTS
+                .get(resp.result(base + 3) as usize)
+                .copied(),
+        }
+    }
+}
+
 /* Parsed LED entry for a single LED. */
 #[derive(Clone, Copy, Default)]
 struct AsusLedEntry {
@@ -920,6 +975,17 @@ impl AsusDriver {
         Ok(())
     }
 
+    async fn set_lift_off_distance(&self, io: &mut DeviceIo, mm: u32, dpi_count: u8) -> Result<()> {
+        let Some(idx) = ASUS_LIFT_OFF_DISTANCES.iter().position(|&v| v == mm) else {
+            bail!("ASUS: unsupported lift-off distance {} mm", mm);
+        };
+        let mut req = AsusRequest::new(ASUS_CMD_SET_SETTING);
+        req.set_param(0, dpi_count + ASUS_FIELD_LOD);
+        req.set_param(2, idx as u8);
+        self.query(io, &req).await?;
+        Ok(())
+    }
+
     async fn get_led_data(&self, io: &mut DeviceIo, led_index: u8) -> Result<AsusLedData> {
         let mut req = AsusRequest::new(ASUS_CMD_GET_LED_DATA);
         req.set_param(0, led_index);
@@ -946,6 +1012,108 @@ impl AsusDriver {
         Ok(())
     }
 
+    /* Send one LED's settings to the selected profile.  SET_LED only
+     * changes the live state; the profile reaches flash with SAVE. */
+    async fn write_led(&self, io: &mut DeviceIo, led: &LedInfo) -> Result<()> {
+        /* A native effect names the hardware mode directly; otherwise
+         * find the first mode index mapped to the generic mode. */
+        let asus_mode = match &led.effect {
+            Some(state) => state.id as u8,
+            None => self
+                .led_modes
+                .iter()
+                .position(|&m| m == led.mode)
+                .unwrap_or(0) as u8,
+        };
+
+        let asus_brightness = brightness_to_asus(led.brightness, self.quirks);
+        let rgb = led.color.to_rgb();
+
+        self.set_led(
+            io,
+            led.index as u8,
+            asus_mode,
+            asus_brightness,
+            rgb.r, rgb.g, rgb.b,
+        )
+        .await
+    }
+
+    async fn get_battery_data(&self, io: &mut DeviceIo) -> Result<AsusBatteryData> {
+        let req = AsusRequest::new(ASUS_CMD_GET_BATTERY);
+        let resp = self.query(io, &req).await?;
+        Ok(AsusBatteryData::from_response(&resp, self.quirks))
+    }
+
+    async fn set_power_settings(
+        &self,
+        io:         &mut DeviceIo,
+        sleep_idx:  u8,
+        alert_idx:  u8,
+    ) -> Result<()> {
+        let mut req = AsusRequest::new(ASUS_CMD_SET_BATTERY);
+        req.set_param(0, sleep_idx);
+        req.set_param(1, alert_idx);
+        self.query(io, &req).await?;
+        Ok(())
+    }
+
+    /* ─── Power settings ────────────────────────────────────────────────── */
+
+    /* Build the power state of a wireless device.  A sleeping device gets
+     * the static capability lists and is flagged asleep; its battery is
+     * read again on the next RefreshBattery. */
+    async fn load_power(&self, io: &mut DeviceIo) -> PowerInfo {
+        let mut power = PowerInfo {
+            sleep_timeouts:     ASUS_SLEEP_TIMEOUTS.to_vec(),
+            low_battery_alerts: ASUS_LOW_BATTERY_ALERTS.to_vec(),
+            is_asleep:          !self.is_ready,
+            ..PowerInfo::default()
+        };
+        if !self.is_ready {
+            return power;
+        }
+
+        match self.get_battery_data(io).await {
+            Ok(data) => {
+                power.battery           = Some(data.battery);
+                power.sleep_timeout     = data.sleep_timeout;
+                power.low_battery_alert = data.low_battery_alert;
+            }
+            Err(e) if is_asleep_error(&e) => {
+                debug!("ASUS: device fell asleep before the battery read: {}", e);
+                power.is_asleep = true;
+            }
+            Err(e) => warn!("ASUS: reading the battery failed: {:#}", e),
+        }
+        power
+    }
+
+    /* Write the sleep timeout and low-battery alert.  Both travel in one
+     * request, so a value that was never read (the device was asleep at
+     * load time) is taken from the device first. */
+    async fn save_power(&self, io: &mut DeviceIo, power: &PowerInfo) -> Result<()> {
+        let (mut sleep_timeout, mut alert) = (power.sleep_timeout, power.low_battery_alert);
+        if sleep_timeout.is_none() || alert.is_none() {
+            let current = self.get_battery_data(io).await?;
+            sleep_timeout = sleep_timeout.or(current.sleep_timeout);
+            alert = alert.or(current.low_battery_alert);
+        }
+
+        let sleep_idx = sleep_timeout
+            .and_then(|t| ASUS_SLEEP_TIMEOUTS.iter().position(|&v| v == t))
+            .context("ASUS: unsupported sleep timeout")?;
+        let alert_idx = alert
+            .and_then(|a| ASUS_LOW_BATTERY_ALERTS.iter().position(|&v| v == a))
+            .context("ASUS: unsupported low-battery alert")?;
+
+        debug!(
+            "ASUS: setting sleep timeout {:?} s, low-battery alert {:?}%",
+            sleep_timeout, alert
+        );
+        self.set_power_settings(io, sleep_idx as u8, alert_idx as u8).await
+    }
+
     /* ─── Profile loading ───────────────────────────────────────────────── */
 
     async fn load_single_profile(
@@ -1052,13 +1220,18 @@ impl AsusDriver {
 
         /* Destructure the variant-specific data into a common shape so we
          * can handle Dpi2 and Dpi4 with a single code path. */
-        let (dpis, rate_idx, response_idx, snapping): (&[u16], u16, u16, u16) = match res_data {
-            AsusResolutionResult::Dpi2(ref d) => (&d.dpi, d.rate_idx, d.response_idx, d.snapping),
-            AsusResolutionResult::Dpi4(ref d) => (&d.dpi, d.rate_idx, d.response_idx, d.snapping),
-            AsusResolutionResult::Xy(_) => {
-                bail!("ASUS: unexpected XY response for non-XY DPI query");
-            }
-        };
+        let (dpis, rate_idx, response_idx, snapping, lod_idx): (&[u16], u16, u16, u16, u16) =
+            match res_data {
+                AsusResolutionResult::Dpi2(ref d) => {
+                    (&d.dpi, d.rate_idx, d.response_idx, d.snapping, d.lod_idx)
+                }
+                AsusResolutionResult::Dpi4(ref d) => {
+                    (&d.dpi, d.rate_idx, d.response_idx, d.snapping, d.lod_idx)
+                }
+                AsusResolutionResult::Xy(_) => {
+                    bail!("ASUS: unexpected XY response for non-XY DPI query");
+                }
+            };
 
         profile.report_rate = ASUS_POLLING_RATES
             .get(rate_idx as usize)
@@ -1068,6 +1241,12 @@ impl AsusDriver {
             profile.debounce = ASUS_DEBOUNCE_TIMES[response_idx as usize] as i32;
         }
         profile.angle_snapping = snapping as i32;
+        if self.has_quirk(ASUS_QUIRK_LIFT_OFF_DISTANCE) {
+            profile.lift_off_distances = ASUS_LIFT_OFF_DISTANCES.to_vec();
+            profile.lift_off_distance = ASUS_LIFT_OFF_DISTANCES
+                .get(lod_idx as usize)
+                .map_or(-1, |&mm| mm as i32);
+        }
         for res in &mut profile.resolutions {
             let i = res.index as usize;
             res.dpi = self.build_dpi(
@@ -1106,6 +1285,14 @@ impl AsusDriver {
 
             let mode_idx = entry.mode as usize;
             led.mode = self.led_modes.get(mode_idx).copied().unwrap_or(LedMode::Solid);
+            /* Only a hardware mode the generic mode would not write back
+             * (wave, reactive, ...) is reported as a native effect. */
+            let generic_idx = self.led_modes.iter().position(|&m| m == led.mode);
+            led.effect = (mode_idx < ASUS_MAX_NUM_LED_MODES && generic_idx != Some(mode_idx))
+                .then(|| LedEffectState {
+                    id: u32::from(entry.mode),
+                    params: BTreeMap::new(),
+                });
             led.brightness = brightness_to_ratbag(entry.brightness, self.quirks);
             led.color = Color {
                 red:   entry.r as u32,
@@ -1259,6 +1446,9 @@ impl AsusDriver {
         if profile.debounce > 0 {
             self.set_button_response(io, profile.debounce as u32, dpi_count).await?;
         }
+        if self.has_quirk(ASUS_QUIRK_LIFT_OFF_DISTANCE) && profile.lift_off_distance > 0 {
+            self.set_lift_off_distance(io, profile.lift_off_distance as u32, dpi_count).await?;
+        }
 
         /* ── DPI presets ─────────────────────────────────────────────────── */
         let mut clamp_warned = false;
@@ -1283,24 +1473,7 @@ impl AsusDriver {
 
         /* ── LEDs ────────────────────────────────────────────────────────── */
         for led in &profile.leds {
-            /* Find ASUS mode index by scanning led_modes for a match. */
-            let asus_mode = self
-                .led_modes
-                .iter()
-                .position(|&m| m == led.mode)
-                .unwrap_or(0) as u8;
-
-            let asus_brightness = brightness_to_asus(led.brightness, self.quirks);
-            let rgb = led.color.to_rgb();
-
-            self.set_led(
-                io,
-                led.index as u8,
-                asus_mode,
-                asus_brightness,
-                rgb.r, rgb.g, rgb.b,
-            )
-            .await?;
+            self.write_led(io, led).await?;
         }
 
         Ok(())
@@ -1401,20 +1574,49 @@ impl DeviceDriver for AsusDriver {
         self.init_from_config(&info.driver_config);
 
         /* Fill static per-profile capability lists that don't need hardware I/O. */
-        let led_modes_vec: Vec<LedMode> = self.led_modes.to_vec();
+        /* Several hardware indices may map to the same mode; offer each once. */
+        let mut led_modes_vec: Vec<LedMode> = Vec::new();
+        for mode in self.led_modes {
+            if !led_modes_vec.contains(&mode) {
+                led_modes_vec.push(mode);
+            }
+        }
+        /* The device file's DpiRange describes the sensor; without one, fall
+         * back to what the wire encoding can express.  Either way the
+         * one-byte encoding caps the usable maximum. */
+        let mut sensor = info.sensor.take().unwrap_or_else(|| SensorInfo {
+            dpi_min: dpi_from_stored(0, self.quirks),
+            dpi_step: dpi_from_stored(1, self.quirks) - dpi_from_stored(0, self.quirks),
+            ..SensorInfo::default()
+        });
+        sensor.dpi_max = if sensor.dpi_max == 0 {
+            max_wire_dpi(self.quirks)
+        } else {
+            sensor.dpi_max.min(max_wire_dpi(self.quirks))
+        };
+        sensor.separate_xy = self.has_quirk(ASUS_QUIRK_SEPARATE_XY_DPI);
+        if self.has_quirk(ASUS_QUIRK_LIFT_OFF_DISTANCE) {
+            sensor.lift_off_distances = ASUS_LIFT_OFF_DISTANCES.to_vec();
+        }
+        info.sensor = Some(sensor);
+
         for profile in &mut info.profiles {
             profile.report_rates = ASUS_POLLING_RATES.to_vec();
             profile.debounces    = ASUS_DEBOUNCE_TIMES.to_vec();
             for led in &mut profile.leds {
                 led.color_depth = 1; /* RGB 888, matching the other RGB drivers */
                 led.modes = led_modes_vec.clone();
+                led.effects = ASUS_LED_EFFECT_NAMES
+                    .iter()
+                    .enumerate()
+                    .map(|(id, &name)| LedEffect { id: id as u32, name, params: Vec::new() })
+                    .collect();
             }
         }
 
         match self.load_all_profiles(io, info).await {
             Ok(()) => {
                 self.is_ready = true;
-                Ok(())
             }
             Err(e) if is_asleep_error(&e) => {
                 warn!("ASUS: device asleep, exposing skeleton profiles: {}", e);
@@ -1426,10 +1628,14 @@ impl DeviceDriver for AsusDriver {
                 {
                     first.is_active = true;
                 }
-                Ok(())
             }
-            Err(e) => Err(e.context("ASUS: loading profiles failed")),
+            Err(e) => return Err(e.context("ASUS: loading profiles failed")),
         }
+
+        if info.driver_config.wireless {
+            info.power = Some(self.load_power(io).await);
+        }
+        Ok(())
     }
 
     async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
@@ -1459,7 +1665,14 @@ impl DeviceDriver for AsusDriver {
             );
         }
 
-        match self.save_all_profiles(io, info).await {
+        let mut result = self.save_all_profiles(io, info).await;
+        if result.is_ok()
+            && let Some(power) = info.power.as_ref().filter(|p| p.is_dirty)
+        {
+            result = self.save_power(io, power).await;
+        }
+
+        match result {
             Ok(()) => Ok(()),
             Err(e) => {
                 /* If the device fell asleep mid-commit, remember it so the
@@ -1471,6 +1684,26 @@ impl DeviceDriver for AsusDriver {
             }
         }
     }
+
+    /* SET_LED without the SAVE that commit sends: the LED changes on
+     * the selected profile and the stored setting comes back on the next
+     * profile load or power cycle. */
+    async fn preview_led(&mut self, io: &mut DeviceIo, led: &LedInfo) -> Result<()> {
+        self.write_led(io, led).await.context("ASUS: LED preview failed")
+    }
+
+    async fn read_battery(&mut self, io: &mut DeviceIo) -> Result<Option<BatteryStatus>> {
+        match self.get_battery_data(io).await {
+            Ok(data) => Ok(Some(data.battery)),
+            /* A sleeping mouse is a normal state for a wireless device,
+             * not a failure: report it and keep the last reading. */
+            Err(e) if is_asleep_error(&e) => {
+                debug!("ASUS: battery not readable, device asleep: {}", e);
+                Ok(None)
+            }
+            Err(e) => Err(e.context("ASUS: reading the battery failed")),
+        }
+    }
 }
 
 // ──────────────────────────── Unit tests ───────────────────────────────────
@@ -1949,6 +2182,8 @@ mod tests {
         assert_eq!(p.angle_snapping, 1);
 
         assert_eq!(p.leds[0].mode, LedMode::Breathing);
+        /* Breathing is a generic mode, so no native effect is reported. */
+        assert!(p.leds[0].effect.is_none());
         assert_eq!(p.leds[0].brightness, 128);
         assert_eq!(p.leds[0].color, Color { red: 0x11, green: 0x22, blue: 0x33 });
         assert_eq!(p.leds[1].mode, LedMode::Solid);
@@ -2122,4 +2357,237 @@ mod tests {
         assert!(drv.commit(&mut io, &info).await.is_err());
         assert!(!drv.is_ready, "driver must remember the device fell asleep");
     }
+
+    #[tokio::test]
+    async fn lift_off_distance_round_trips_with_quirk() {
+        let mut info = make_info(1, 0, 0, 2, &["LIFT_OFF_DISTANCE"], &[], &[]);
+
+        let (mut io, _handle) = DeviceIo::with_mock(vec![
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_PROFILE_DATA, &[]), ok_resp(&[])),
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_BUTTON_DATA, &[(0, 0)]), ok_resp(&[])),
+            /* 2-DPI layout: lift-off distance index 1 -> 2 mm. */
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_SETTINGS, &[(0, 0)]), ok_resp(&[(12, 1)])),
+        ]);
+        let mut drv = AsusDriver::new();
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+
+        assert_eq!(info.profiles[0].lift_off_distance, 2);
+        assert_eq!(info.profiles[0].lift_off_distances, ASUS_LIFT_OFF_DISTANCES);
+
+        let info = info.with_profile_lift_off_distance(0, 1);
+        let script = (0..8).map(|_| MockExchange::reply(ok_resp(&[]))).collect();
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        drv.commit(&mut io, &info).await.unwrap();
+
+        /* Field selector = dpi_count(2) + LOD(3), value = index 0 (1 mm). */
+        let lod_write = req_bytes(ASUS_CMD_SET_SETTING, &[(0, 5), (2, 0)]);
+        assert!(handle.writes().contains(&lod_write));
+    }
+
+    #[tokio::test]
+    async fn lift_off_distance_unsupported_without_quirk() {
+        let mut info = make_info(1, 0, 0, 2, &[], &[], &[]);
+        let (mut io, _handle) = DeviceIo::with_mock(vec![
+            MockExchange::reply(ok_resp(&[])),
+            MockExchange::reply(ok_resp(&[])),
+            MockExchange::reply(ok_resp(&[(12, 1)])),
+        ]);
+        let mut drv = AsusDriver::new();
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+
+        assert_eq!(info.profiles[0].lift_off_distance, -1);
+        assert!(info.profiles[0].lift_off_distances.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sensor_limits_follow_wire_encoding() {
+        async fn load(info: &mut DeviceInfo) {
+            let script = (0..4).map(|_| MockExchange::reply(ok_resp(&[]))).collect();
+            let (mut io, _handle) = DeviceIo::with_mock(script);
+            AsusDriver::new().load_profiles(&mut io, info).await.unwrap();
+        }
+
+        /* No DpiRange in the device file: the wire encoding is the limit. */
+        let mut info = make_info(1, 0, 0, 2, &["DOUBLE_DPI", "LIFT_OFF_DISTANCE"], &[], &[]);
+        load(&mut info).await;
+        let sensor = info.sensor.unwrap();
+        assert_eq!((sensor.dpi_min, sensor.dpi_max, sensor.dpi_step), (100, 25600, 100));
+        assert!(!sensor.separate_xy);
+        assert_eq!(sensor.lift_off_distances, ASUS_LIFT_OFF_DISTANCES);
+
+        /* A sensor range beyond the wire limit is capped. */
+        let mut info = make_info(1, 0, 0, 2, &["SEPARATE_XY_DPI"], &[], &[]);
+        info.sensor =
+            Some(SensorInfo { dpi_min: 100, dpi_max: 36000, dpi_step: 50, ..Default::default() });
+        load(&mut info).await;
+        let sensor = info.sensor.unwrap();
+        assert_eq!((sensor.dpi_min, sensor.dpi_max, sensor.dpi_step), (100, 12800, 50));
+        assert!(sensor.separate_xy);
+        assert!(sensor.lift_off_distances.is_empty());
+    }
+
+    #[tokio::test]
+    async fn led_effect_selects_hardware_mode() {
+        let mut info = make_info(1, 0, 1, 2, &[], &[], &[]);
+
+        let (mut io, _handle) = DeviceIo::with_mock(vec![
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_PROFILE_DATA, &[]), ok_resp(&[])),
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_BUTTON_DATA, &[(0, 0)]), ok_resp(&[])),
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_SETTINGS, &[(0, 0)]), ok_resp(&[])),
+            /* LED 0 in hardware mode 3 (wave), which has no generic LedMode. */
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_LED_DATA, &[(0, 0)]), ok_resp(&[(2, 3)])),
+        ]);
+        let mut drv = AsusDriver::new();
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+
+        let led = &info.profiles[0].leds[0];
+        assert_eq!(led.mode, LedMode::Solid);
+        assert_eq!(led.effects.len(), ASUS_MAX_NUM_LED_MODES);
+        assert_eq!(led.effects[3].name, "wave");
+        assert_eq!(led.effect.as_ref().map(|e| e.id), Some(3));
+
+        /* Reactive (4) is not reachable through the generic modes. */
+        let reactive = LedEffectState { id: 4, params: BTreeMap::new() };
+        let info = info.with_led_effect(0, 0, reactive);
+        let script = (0..8).map(|_| MockExchange::reply(ok_resp(&[]))).collect();
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        drv.commit(&mut io, &info).await.unwrap();
+
+        let led_write = req_bytes(ASUS_CMD_SET_LED, &[(0, 0), (2, 4)]);
+        assert!(handle.writes().contains(&led_write));
+    }
+
+    #[tokio::test]
+    async fn led_preview_skips_the_save_command() {
+        let info = make_info(1, 0, 1, 2, &[], &[], &[]);
+        let mut led = info.profiles[0].leds[0].clone();
+        led.effect = Some(LedEffectState { id: 1, params: BTreeMap::new() });
+
+        let (mut io, handle) = DeviceIo::with_mock(vec![MockExchange::reply(ok_resp(&[]))]);
+        let mut drv = AsusDriver::new();
+        drv.preview_led(&mut io, &led).await.unwrap();
+
+        assert!(handle.script_exhausted());
+        let writes = handle.writes();
+        assert_eq!(writes.len(), 1);
+        assert_eq!(&writes[0][..4], &req_bytes(ASUS_CMD_SET_LED, &[(2, 1)])[..4]);
+        assert!(!writes.contains(&req_bytes(ASUS_CMD_SAVE, &[])));
+    }
+
+    /* ── Wireless power settings ─────────────────────────────────────────── */
+
+    #[test]
+    fn battery_data_honours_battery_v2_offset() {
+        /* level 80 %, charging, sleep index 3 (300 s), alert index 2 (15 %). */
+        let v1 = AsusBatteryData::from_response(
+            &AsusResponse { buf: ok_resp(&[(2, 80), (3, 1), (4, 3), (5, 2)]).try_into().unwrap() },
+            0,
+        );
+        assert_eq!(v1.battery, BatteryStatus { level: 80, is_charging: true });
+        assert_eq!(v1.sleep_timeout, Some(300));
+        assert_eq!(v1.low_battery_alert, Some(15));
+
+        let v2 = AsusBatteryData::from_response(
+            &AsusResponse { buf: ok_resp(&[(3, 55), (4, 0), (5, 5), (6, 9)]).try_into().unwrap() },
+            ASUS_QUIRK_BATTERY_V2,
+        );
+        assert_eq!(v2.battery, BatteryStatus { level: 55, is_charging: false });
+        assert_eq!(v2.sleep_timeout, Some(0), "index 5 means never sleep");
+        assert_eq!(v2.low_battery_alert, None, "out-of-range index is unknown");
+    }
+
+    #[tokio::test]
+    async fn load_profiles_reads_wireless_power_state() {
+        let mut info = make_info(1, 0, 0, 2, &[], &[], &[]);
+        info.driver_config.wireless = true;
+
+        let (mut io, handle) = DeviceIo::with_mock(vec![
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_PROFILE_DATA, &[]), ok_resp(&[])),
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_BUTTON_DATA, &[(0, 0)]), ok_resp(&[])),
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_SETTINGS, &[(0, 0)]), ok_resp(&[])),
+            MockExchange::expect_reply(
+                req_bytes(ASUS_CMD_GET_BATTERY, &[]),
+                ok_resp(&[(2, 42), (3, 0), (4, 1), (5, 1)]),
+            ),
+        ]);
+        let mut drv = AsusDriver::new();
+
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+
+        assert!(handle.script_exhausted());
+        let power = info.power.expect("wireless device must expose power state");
+        assert_eq!(power.battery, Some(BatteryStatus { level: 42, is_charging: false }));
+        assert_eq!(power.sleep_timeout, Some(120));
+        assert_eq!(power.low_battery_alert, Some(10));
+        assert_eq!(power.sleep_timeouts, ASUS_SLEEP_TIMEOUTS);
+        assert_eq!(power.low_battery_alerts, ASUS_LOW_BATTERY_ALERTS);
+        assert!(!power.is_asleep);
+    }
+
+    #[tokio::test]
+    async fn load_profiles_flags_sleeping_wireless_device_asleep() {
+        let mut info = make_info(1, 0, 0, 2, &[], &[], &[]);
+        info.driver_config.wireless = true;
+
+        /* The device is asleep from the first query on; no battery read
+         * is attempted once that is known. */
+        let (mut io, handle) =
+            DeviceIo::with_mock(vec![MockExchange::reply(asleep_resp())]);
+        let mut drv = AsusDriver::new();
+
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+
+        assert!(handle.script_exhausted());
+        let power = info.power.unwrap();
+        assert!(power.is_asleep);
+        assert_eq!(power.battery, None);
+    }
+
+    #[tokio::test]
+    async fn read_battery_treats_sleep_as_state_not_failure() {
+        let (mut io, _handle) = DeviceIo::with_mock(vec![
+            MockExchange::expect_reply(req_bytes(ASUS_CMD_GET_BATTERY, &[]), asleep_resp()),
+            MockExchange::expect_reply(
+                req_bytes(ASUS_CMD_GET_BATTERY, &[]),
+                ok_resp(&[(2, 90), (3, 1)]),
+            ),
+        ]);
+        let mut drv = AsusDriver::new();
+
+        assert_eq!(drv.read_battery(&mut io).await.unwrap(), None);
+        assert_eq!(
+            drv.read_battery(&mut io).await.unwrap(),
+            Some(BatteryStatus { level: 90, is_charging: true })
+        );
+    }
+
+    #[tokio::test]
+    async fn commit_writes_dirty_power_settings() {
+        let mut info = make_info(1, 0, 0, 2, &[], &[], &[]);
+        info.power = Some(PowerInfo {
+            sleep_timeouts: ASUS_SLEEP_TIMEOUTS.to_vec(),
+            low_battery_alerts: ASUS_LOW_BATTERY_ALERTS.to_vec(),
+            ..PowerInfo::default()
+        });
+        /* Only the sleep timeout is known; the alert is read back first
+         * so the combined write keeps it. */
+        let info = info.with_sleep_timeout(600);
+
+        let (mut io, handle) = DeviceIo::with_mock(vec![
+            MockExchange::expect_reply(
+                req_bytes(ASUS_CMD_GET_BATTERY, &[]),
+                ok_resp(&[(2, 70), (4, 0), (5, 4)]),
+            ),
+            MockExchange::expect_reply(
+                req_bytes(ASUS_CMD_SET_BATTERY, &[(0, 4), (1, 4)]),
+                ok_resp(&[]),
+            ),
+        ]);
+        let mut drv = AsusDriver::new();
+        drv.init_from_config(&info.driver_config);
+        drv.is_ready = true;
+
+        drv.commit(&mut io, &info).await.unwrap();
+        assert!(handle.script_exhausted());
+    }
 }
@@ -107,7 +107,8 @@ pub fn build_led_payload(led: &crate::engine::device::LedInfo) -> [u8; LED_PAYLO
     let brightness = (led.brightness.min(255) * 100 / 255) as u8;
 
     match led.mode {
-        LedMode::Off => {
+        /* HID++ always reads its LEDs back, so Unknown never reaches here. */
+        LedMode::Off | LedMode::Unknown => {
             payload[0] = LED_HW_MODE_OFF;
         }
         LedMode::Solid => {
@@ -606,6 +607,8 @@ mod tests {
             color_depth: 1,
             effect_duration: 0,
             brightness: 255,
+            effects: Vec::new(),
+            effect: None,
         }
     }
 
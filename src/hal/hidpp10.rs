@@ -4,13 +4,16 @@
 /* It uses register-based commands with short (7-byte) reports. */
 /* Based on the HID++ 1.0 documentation provided by Nestor Lopez Casado. */
 
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use tracing::{debug, info, warn};
 
 use crate::engine::device::{
-    ActionType, Color, DeviceInfo, Dpi, LedMode, ProfileInfo, RgbColor,
-    special_action,
+    self, ActionType, BatteryStatus, ButtonInfo, Color, DeviceInfo, Dpi, LedMode, PairedDevice,
+    PairingOutcome, PowerInfo, ProfileInfo, RgbColor, special_action,
 };
 use crate::hal::DeviceIo;
 
@@ -63,21 +66,40 @@ const PROFILE_TYPE_ADDRESS: u8 = 0x01;
 const PROFILE_TYPE_FACTORY: u8 = 0xFF;
 
 /* Pairing information sub-types for register 0xB5 */
-#[allow(dead_code)]
 const PAIRING_INFO_DEVICE: u8 = 0x20;
-#[allow(dead_code)]
 const PAIRING_INFO_DEVICE_NAME: u8 = 0x40;
 #[allow(dead_code)]
 const PAIRING_INFO_EXTENDED: u8 = 0x30;
 
 /* Device connection/disconnection commands for register 0xB2 */
-#[allow(dead_code)]
 const CONNECT_OPEN_LOCK: u8 = 1;
-#[allow(dead_code)]
 const CONNECT_CLOSE_LOCK: u8 = 2;
-#[allow(dead_code)]
 const CONNECT_DISCONNECT: u8 = 3;
 
+/* Receiver notifications.  0x41 announces a device (re)connecting in the
+ * slot given by the device index: params = [flags, wpid LSB, wpid MSB].
+ * 0x4A reports the pairing lock: address bit 0 = lock open, params[0] =
+ * error code once it closes.  Both need the wireless notification flag
+ * (register 0x00, byte 1 bit 0) set on the receiver. */
+const NOTIF_DEVICE_CONNECTION: u8 = 0x41;
+const NOTIF_PAIRING_LOCK: u8 = 0x4A;
+const NOTIF_FLAG_WIRELESS: u8 = 0x01;
+const PAIRING_LOCK_OPEN: u8 = 0x01;
+
+/* Pairing lock error codes (0x4A params[0]). */
+const PAIRING_ERR_NONE: u8 = 0x00;
+const PAIRING_ERR_TIMEOUT: u8 = 0x01;
+const PAIRING_ERR_UNSUPPORTED: u8 = 0x02;
+const PAIRING_ERR_TOO_MANY_DEVICES: u8 = 0x03;
+const PAIRING_ERR_SEQUENCE_TIMEOUT: u8 = 0x06;
+
+/* Unifying and Nano receivers have at most six pairing slots. */
+const RECEIVER_MAX_SLOTS: u8 = 6;
+
+/* Extra time past the requested pairing window to wait for the
+ * receiver's lock-closed notification before closing it ourselves. */
+const PAIRING_GRACE: Duration = Duration::from_secs(5);
+
 /* Firmware info sub-items for register 0xF1 */
 #[allow(dead_code)]
 const FW_INFO_NAME_AND_VERSION: u8 = 0x01;
@@ -117,7 +139,6 @@ const MAX_MACRO_EVENTS: usize = 256;
 
 /* The receiver's own HID++ device index, used for pairing commands.
  * This happens to be the same value as DEVICE_IDX_CORDED (0xFF). */
-#[allow(dead_code)]
 const HIDPP_RECEIVER_IDX: u8 = 0xFF;
 
 /* ------------------------------------------------------------------ */
@@ -177,6 +198,19 @@ impl BatteryLevel {
             _ => Self::Unknown,
         }
     }
+
+    /* Register 0x07 only reports coarse levels; approximate each with a
+     * percentage so it fits the generic battery model.  None when the
+     * device does not know its level. */
+    fn approx_percent(self) -> Option<u32> {
+        match self {
+            Self::Unknown => None,
+            Self::Critical | Self::CriticalLegacy => Some(5),
+            Self::Low | Self::LowLegacy => Some(20),
+            Self::Good | Self::GoodLegacy => Some(50),
+            Self::FullLegacy => Some(90),
+        }
+    }
 }
 
 /* Battery charge state shared by registers 0x07 and 0x0D. */
@@ -208,6 +242,15 @@ impl BatteryChargeState {
             _ => Self::Unknown,
         }
     }
+
+    /* True while current flows into the battery; a completed charge
+     * or a charging error does not count. */
+    fn is_charging(self) -> bool {
+        matches!(
+            self,
+            Self::Charging | Self::ChargingFast | Self::ChargingSlow | Self::ToppingCharge
+        )
+    }
 }
 
 /* LED hardware status per LED, from register 0x51. Each LED occupies a
@@ -246,13 +289,22 @@ impl LedStatus {
 
 /* Return type for battery status queries (register 0x07). */
 #[derive(Debug, Clone, Copy)]
-#[allow(dead_code)]
 pub struct BatteryStatusInfo {
     pub level: BatteryLevel,
     pub charge_state: BatteryChargeState,
     pub low_threshold_percent: u8,
 }
 
+impl BatteryStatusInfo {
+    /* The reading in the generic battery model; None if the level is unknown. */
+    fn to_battery_status(self) -> Option<BatteryStatus> {
+        Some(BatteryStatus {
+            level: self.level.approx_percent()?,
+            is_charging: self.charge_state.is_charging(),
+        })
+    }
+}
+
 /* Return type for battery mileage queries (register 0x0D). */
 #[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
@@ -800,6 +852,152 @@ fn parse_macro_event(data: &[u8], pos: usize) -> Option<(MacroEvent, usize)> {
     Some((event, size))
 }
 
+/* Translate a parsed onboard macro into the engine's macro model.  Modifier
+ * instructions carry a modifier bitmask, expanded into one key event per
+ * modifier usage (0xE0–0xE7).  Firmware flow control without an engine
+ * counterpart (no-ops, jumps, unconditional repeats, END) is dropped. */
+fn macro_events_from_onboard(events: &[MacroEvent]) -> Vec<device::MacroEvent> {
+    let modifiers = |mask: u8| {
+        (0..8u32)
+            .filter(move |bit| mask & (1 << bit) != 0)
+            .map(|bit| 0xE0 + bit)
+    };
+    let mut out = Vec::with_capacity(events.len());
+    for event in events {
+        match *event {
+            MacroEvent::KeyPress { key } => out.push(device::MacroEvent::KeyPress(u32::from(key))),
+            MacroEvent::KeyRelease { key } => {
+                out.push(device::MacroEvent::KeyRelease(u32::from(key)));
+            }
+            MacroEvent::ModPress { key } => {
+                out.extend(modifiers(key).map(device::MacroEvent::KeyPress));
+            }
+            MacroEvent::ModRelease { key } => {
+                out.extend(modifiers(key).map(device::MacroEvent::KeyRelease));
+            }
+            MacroEvent::MouseWheel { value } => {
+                out.push(device::MacroEvent::Wheel(i32::from(value)));
+            }
+            MacroEvent::MouseButtonPress { button } if button != 0 => {
+                out.push(device::MacroEvent::ButtonPress(u32::from(button)));
+            }
+            MacroEvent::MouseButtonRelease { button } if button != 0 => {
+                out.push(device::MacroEvent::ButtonRelease(u32::from(button)));
+            }
+            MacroEvent::ConsumerControl { usage } => {
+                out.push(device::MacroEvent::Consumer(u32::from(usage)));
+            }
+            MacroEvent::Delay { time_ms } => out.push(device::MacroEvent::Wait(u32::from(time_ms))),
+            MacroEvent::MousePointerMove { x_rel, y_rel } => {
+                out.push(device::MacroEvent::PointerMove {
+                    x: i32::from(x_rel),
+                    y: i32::from(y_rel as i16),
+                });
+            }
+            MacroEvent::WaitForButtonRelease => out.push(device::MacroEvent::WaitForRelease),
+            MacroEvent::RepeatUntilButtonRelease => {
+                out.push(device::MacroEvent::RepeatWhilePressed);
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/* Parse macro bytecode up to its END marker (or the first undecodable
+ * instruction).  Jumps are kept as events, not followed. */
+fn parse_macro(code: &[u8]) -> Vec<MacroEvent> {
+    let mut events = Vec::new();
+    let mut pos = 0;
+    while let Some((event, size)) = parse_macro_event(code, pos) {
+        events.push(event);
+        if matches!(event, MacroEvent::End) { break; }
+        pos += size;
+    }
+    events
+}
+
+/* ------------------------------------------------------------------ */
+/*  Macro serialization                                                */
+/* ------------------------------------------------------------------ */
+
+/* Encode engine macro events as bytecode terminated by MACRO_END, the
+ * inverse of `macro_events_from_onboard`: modifier usages (0xE0–0xE7)
+ * become single-bit MOD_PRESS/MOD_RELEASE instructions.  Values wider than
+ * an instruction's field are clamped; keys outside the 8-bit HID range
+ * and mouse buttons beyond 16 are skipped. */
+fn serialize_macro(events: &[device::MacroEvent]) -> Vec<u8> {
+    let mut code = Vec::new();
+    for event in events {
+        match *event {
+            device::MacroEvent::KeyPress(key) | device::MacroEvent::KeyRelease(key) => {
+                let press = matches!(event, device::MacroEvent::KeyPress(_));
+                match key {
+                    0xE0..=0xE7 => {
+                        let tag = if press { MACRO_MOD_PRESS } else { MACRO_MOD_RELEASE };
+                        code.extend_from_slice(&[tag, 1 << (key - 0xE0)]);
+                    }
+                    0x01..=0xFF => {
+                        let tag = if press { MACRO_KEY_PRESS } else { MACRO_KEY_RELEASE };
+                        code.extend_from_slice(&[tag, key as u8]);
+                    }
+                    _ => warn!("HID++ 1.0: skipping macro key 0x{key:X} outside the HID key range"),
+                }
+            }
+            device::MacroEvent::ButtonPress(button) | device::MacroEvent::ButtonRelease(button) => {
+                if !(1..=16).contains(&button) {
+                    warn!("HID++ 1.0: skipping macro mouse button {button} outside 1-16");
+                    continue;
+                }
+                let tag = if matches!(event, device::MacroEvent::ButtonPress(_)) {
+                    MACRO_MOUSE_BUTTON_PRESS
+                } else {
+                    MACRO_MOUSE_BUTTON_RELEASE
+                };
+                code.push(tag);
+                code.extend_from_slice(&(1u16 << (button - 1)).to_le_bytes());
+            }
+            device::MacroEvent::Wait(ms) => {
+                code.push(MACRO_DELAY);
+                code.extend_from_slice(&(ms.min(u32::from(u16::MAX)) as u16).to_be_bytes());
+            }
+            device::MacroEvent::Consumer(usage) => {
+                code.push(MACRO_CONSUMER_CONTROL);
+                code.extend_from_slice(&(usage.min(u32::from(u16::MAX)) as u16).to_be_bytes());
+            }
+            device::MacroEvent::Wheel(ticks) => {
+                let ticks = ticks.clamp(i32::from(i8::MIN), i32::from(i8::MAX)) as i8;
+                code.extend_from_slice(&[MACRO_MOUSE_WHEEL, ticks as u8]);
+            }
+            device::MacroEvent::PointerMove { x, y } => {
+                let clamp = |v: i32| v.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16;
+                code.push(MACRO_MOUSE_POINTER_MOVE);
+                code.extend_from_slice(&clamp(x).to_be_bytes());
+                code.extend_from_slice(&clamp(y).to_be_bytes());
+            }
+            device::MacroEvent::WaitForRelease => code.push(MACRO_WAIT_FOR_BUTTON_RELEASE),
+            device::MacroEvent::RepeatWhilePressed => code.push(MACRO_REPEAT_UNTIL_BUTTON_RELEASE),
+        }
+    }
+    code.push(MACRO_END);
+    code
+}
+
+/* Translate the error code of the pairing lock's closing notification.
+ * `connected` is the last (slot, wpid) announced while the lock was open;
+ * a lock that closes cleanly without one simply ran out of time. */
+fn pairing_outcome(error: u8, connected: Option<(u8, u16)>) -> PairingOutcome {
+    match (error, connected) {
+        (PAIRING_ERR_NONE, Some((slot, wpid))) => PairingOutcome::Paired { slot, wpid },
+        (PAIRING_ERR_NONE | PAIRING_ERR_TIMEOUT | PAIRING_ERR_SEQUENCE_TIMEOUT, _) => {
+            PairingOutcome::TimedOut
+        }
+        (PAIRING_ERR_UNSUPPORTED, _) => PairingOutcome::Unsupported,
+        (PAIRING_ERR_TOO_MANY_DEVICES, _) => PairingOutcome::TooManyDevices,
+        _ => PairingOutcome::Failed,
+    }
+}
+
 /* ------------------------------------------------------------------ */
 /*  Driver                                                             */
 /* ------------------------------------------------------------------ */
@@ -813,6 +1011,9 @@ pub struct Hidpp10Driver {
     dpi_table_is_range: bool,
     onboard_profiles: Vec<Hidpp10Profile>,
     profile_count: usize,
+    /* Flash pages holding each macro, keyed by (profile index, button
+     * index).  Used to find free pages when a macro is rewritten. */
+    macro_pages: BTreeMap<(u32, u32), Vec<u8>>,
 }
 
 /* Outcome of a protocol probe at one device index. */
@@ -837,6 +1038,7 @@ impl Hidpp10Driver {
             dpi_table_is_range: false,
             onboard_profiles: Vec::new(),
             profile_count: 1,
+            macro_pages: BTreeMap::new(),
         }
     }
 
@@ -1140,6 +1342,127 @@ impl Hidpp10Driver {
         Ok(())
     }
 
+    /* Seal a page with its CRC-CCITT trailer and flash it.  The RAM
+     * buffer (page 0) holds half a page, so the data goes up in two HOT
+     * uploads, each copied into place after the page is erased. */
+    async fn write_page(
+        &self, io: &mut DeviceIo, page: u8, data: &mut [u8; PAGE_SIZE],
+    ) -> Result<()> {
+        let crc = hidpp::compute_ccitt_crc(&data[..PAGE_SIZE - 2]);
+        data[PAGE_SIZE - 2..].copy_from_slice(&crc.to_be_bytes());
+
+        let half = PAGE_SIZE / 2;
+        self.send_hot_payload(io, 0x00, 0x0000, &data[..half]).await?;
+        self.erase_memory(io, page).await?;
+        self.write_flash(io, 0x00, 0x0000, page, 0x0000, half as u16).await?;
+        self.send_hot_payload(io, 0x00, 0x0000, &data[half..]).await?;
+        self.write_flash(io, 0x00, 0x0000, page, half as u16, half as u16).await?;
+        Ok(())
+    }
+
+    /* ---- Macro writing to flash ------------------------------------ */
+
+    /* Pick a flash page for a macro: `preferred` (the button's previous
+     * page) when nothing else occupies it, otherwise the lowest free page.
+     * Macros only go above the highest profile page, so the RAM buffer,
+     * the profile directory and the profiles themselves are never hit. */
+    fn allocate_macro_page(&self, preferred: Option<u8>) -> Option<u8> {
+        let mut in_use: BTreeSet<u8> = self.macro_pages.values().flatten().copied().collect();
+        in_use.extend(self.onboard_profiles.iter().map(|p| p.page));
+        let first = self.onboard_profiles.iter().map(|p| p.page).max().unwrap_or(0).max(1) + 1;
+        let mut range = first..=MAX_PAGE_NUMBER;
+        preferred
+            .filter(|p| range.contains(p) && !in_use.contains(p))
+            .or_else(|| range.find(|p| !in_use.contains(p)))
+    }
+
+    /* Write a button's macro to a page of its own and record the
+     * allocation.  The button's previous pages are released first, so a
+     * rewritten macro reuses its own page when no other macro shares it.
+     * Returns the binding that points the button at the new macro, along
+     * with the macro as the firmware will read it back. */
+    async fn store_macro(
+        &mut self,
+        io: &mut DeviceIo,
+        profile: u32,
+        button: &ButtonInfo,
+    ) -> Result<(Hidpp10ButtonBinding, Vec<MacroEvent>)> {
+        let code = serialize_macro(&button.macro_entries);
+        if code.len() > PAGE_SIZE - 2 {
+            anyhow::bail!(
+                "macro for button {} needs {} bytes, a page holds {}",
+                button.index,
+                code.len(),
+                PAGE_SIZE - 2
+            );
+        }
+        let mut data = [0xFFu8; PAGE_SIZE];
+        data[..code.len()].copy_from_slice(&code);
+
+        let key = (profile, button.index);
+        let previous = self.macro_pages.remove(&key);
+        let preferred = previous.as_ref().and_then(|p| p.first().copied());
+        let Some(page) = self.allocate_macro_page(preferred) else {
+            if let Some(prev) = previous {
+                self.macro_pages.insert(key, prev);
+            }
+            anyhow::bail!("no free flash page for the macro on button {}", button.index);
+        };
+
+        if let Err(e) = self.write_page(io, page, &mut data).await {
+            if let Some(prev) = previous {
+                self.macro_pages.insert(key, prev);
+            }
+            return Err(e);
+        }
+
+        debug!("HID++ 1.0: profile {profile} button {}: macro → page 0x{page:02X}", button.index);
+        self.macro_pages.insert(key, vec![page]);
+        Ok((Hidpp10ButtonBinding::Macro { page, offset: 0 }, parse_macro(&code)))
+    }
+
+    /* Write the macros of `profile` whose events differ from what flash
+     * holds, pointing the buttons of `onboard` at the new pages.  A macro
+     * that fails to write leaves its button's binding untouched. */
+    async fn store_changed_macros(
+        &mut self,
+        io: &mut DeviceIo,
+        profile: &ProfileInfo,
+        onboard: &mut Hidpp10Profile,
+    ) {
+        for button in &profile.buttons {
+            let j = button.index as usize;
+            if button.action_type != ActionType::Macro
+                || button.macro_entries.is_empty()
+                || j >= onboard.buttons.len()
+            {
+                continue;
+            }
+            let stored = onboard.macros.get(j).and_then(Option::as_ref).map(|events| {
+                macro_events_from_onboard(events)
+            });
+            if matches!(onboard.buttons[j], Hidpp10ButtonBinding::Macro { .. })
+                && stored.as_ref() == Some(&button.macro_entries)
+            {
+                continue;
+            }
+
+            match self.store_macro(io, profile.index, button).await {
+                Ok((binding, events)) => {
+                    onboard.buttons[j] = binding;
+                    if onboard.macros.len() <= j {
+                        onboard.macros.resize(j + 1, None);
+                    }
+                    onboard.macros[j] = Some(events);
+                }
+                Err(e) => warn!(
+                    "HID++ 1.0: profile {} button {j}: cannot write macro: {e:#}",
+                    profile.index
+                ),
+            }
+        }
+    }
+
     /* ---- Macro reading from flash ---------------------------------- */
 
     /* Read a complete macro starting at (page, byte_offset) in flash.
@@ -1147,16 +1470,19 @@ impl Hidpp10Driver {
      * instructions that redirect to a different page/offset, which this
      * method follows transparently (matching the C implementation). The
      * method accumulates events into a Vec until it hits MACRO_END or
-     * exceeds MAX_MACRO_EVENTS, preventing runaway reads. */
+     * exceeds MAX_MACRO_EVENTS, preventing runaway reads.  Also returns
+     * every page the macro spans, so the commit path knows which pages
+     * are occupied. */
     async fn read_macro(
-        &self, io: &mut DeviceIo, start_page: u8, start_byte_offset: u8,
-    ) -> Result<Vec<MacroEvent>> {
+        &self, io: &mut DeviceIo, start_page: u8, start_byte_offset: u16,
+    ) -> Result<(Vec<MacroEvent>, Vec<u8>)> {
         let mut events = Vec::new();
         let mut page = start_page;
+        let mut pages = vec![page];
         let mut byte_offset = start_byte_offset;
         /* Pre-fetch the 16-byte chunk that contains `byte_offset`. */
-        let mut chunk = self.read_memory(io, page, u16::from(byte_offset & 0xFE)).await?;
-        let mut chunk_base = u16::from(byte_offset & 0xFE);
+        let mut chunk_base = byte_offset & !1;
+        let mut chunk = self.read_memory(io, page, chunk_base).await?;
 
         /* Flatten the chunk into a working buffer that can span two
          * consecutive 16-byte reads when an instruction straddles a
@@ -1164,7 +1490,7 @@ impl Hidpp10Driver {
         let mut buf = [0u8; 32];
         buf[..16].copy_from_slice(&chunk);
         let mut buf_len: usize = 16;
-        let mut buf_offset = usize::from(byte_offset) - chunk_base as usize;
+        let mut buf_offset = usize::from(byte_offset - chunk_base);
 
         loop {
             if events.len() >= MAX_MACRO_EVENTS {
@@ -1176,7 +1502,7 @@ impl Hidpp10Driver {
              * instruction (5 bytes). If not, fetch the next chunk. */
             if buf_offset + 5 > buf_len && buf_len < 32 {
                 let next_addr = chunk_base + 16;
-                if next_addr <= 0xFF {
+                if usize::from(next_addr) < PAGE_SIZE {
                     let next_chunk = self.read_memory(io, page, next_addr).await?;
                     buf[16..32].copy_from_slice(&next_chunk);
                     buf_len = 32;
@@ -1197,12 +1523,15 @@ impl Hidpp10Driver {
                 MacroEvent::Jump { page: jp, offset: jo } => {
                     /* Follow the jump transparently — do not store it. */
                     page = jp;
-                    byte_offset = jo.wrapping_mul(2);
-                    chunk_base = u16::from(byte_offset & 0xFE);
+                    if !pages.contains(&page) {
+                        pages.push(page);
+                    }
+                    byte_offset = u16::from(jo) * 2;
+                    chunk_base = byte_offset & !1;
                     chunk = self.read_memory(io, page, chunk_base).await?;
                     buf[..16].copy_from_slice(&chunk);
                     buf_len = 16;
-                    buf_offset = usize::from(byte_offset) - chunk_base as usize;
+                    buf_offset = usize::from(byte_offset - chunk_base);
                     continue;
                 }
                 _ => {
@@ -1222,7 +1551,7 @@ impl Hidpp10Driver {
             /* If we've exhausted the current buffer entirely, fetch next. */
             if buf_offset >= buf_len {
                 let next_addr = chunk_base + buf_len as u16;
-                if next_addr > 0xFF { break; }
+                if usize::from(next_addr) >= PAGE_SIZE { break; }
                 chunk = self.read_memory(io, page, next_addr).await?;
                 buf[..16].copy_from_slice(&chunk);
                 buf_len = 16;
@@ -1230,7 +1559,7 @@ impl Hidpp10Driver {
                 chunk_base = next_addr;
             }
         }
-        Ok(events)
+        Ok((events, pages))
     }
 
     /* ---- Register 0x00: HID++ Notifications ----------------------- */
@@ -1271,7 +1600,6 @@ impl Hidpp10Driver {
 
     /* ---- Register 0x07: Battery Status ---------------------------- */
 
-    #[allow(dead_code)]
     async fn get_battery_status(&self, io: &mut DeviceIo) -> Result<BatteryStatusInfo> {
         let p = self.get_register(io, REG_BATTERY_STATUS, [0, 0, 0]).await?;
         let mut threshold = p[2];
@@ -1364,84 +1692,93 @@ impl Hidpp10Driver {
         Ok(p[0])
     }
 
-    /* ---- Register 0xB2: Device Connection / Disconnection --------- */
+    /* ---- Receiver register access -------------------------------- */
 
-    #[allow(dead_code)]
-    async fn open_pairing_lock(&self, io: &mut DeviceIo, timeout: u8) -> Result<()> {
-        let request = hidpp::build_short_report(
-            HIDPP_RECEIVER_IDX, SUB_ID_SET_REGISTER, REG_DEVICE_CONNECTION,
-            [CONNECT_OPEN_LOCK, 0xFF, timeout],
-        );
-        io.request(&request, 20, 3, move |buf| {
-            let report = HidppReport::parse(buf)?;
-            if report.is_error() { return None; }
-            match report {
-                HidppReport::Short { device_index, sub_id, address, .. }
+    /* Access a register of the receiver itself (index 0xFF) and return
+     * the reply parameters (3 bytes short, 16 bytes long).  Unlike the
+     * per-device helpers, a HID++ 1.0 error reply fails the request at
+     * once: empty pairing slots answer every query with an error, and a
+     * slot scan would otherwise stall for the whole read deadline. */
+    async fn receiver_request(
+        &self,
+        io: &mut DeviceIo,
+        sub_id: u8,
+        register: u8,
+        params: [u8; 3],
+    ) -> Result<Vec<u8>> {
+        let request = hidpp::build_short_report(HIDPP_RECEIVER_IDX, sub_id, register, params);
+        let reply = io.request(&request, 20, 3, move |buf| {
+            match HidppReport::parse(buf)? {
+                HidppReport::Short { device_index, sub_id: sid, address, params }
                     if device_index == HIDPP_RECEIVER_IDX
-                        && sub_id == SUB_ID_SET_REGISTER
-                        && address == REG_DEVICE_CONNECTION => Some(()),
+                        && sid == hidpp::HIDPP10_ERROR
+                        && address == sub_id
+                        && params[0] == register => Some(Err(params[1])),
+                HidppReport::Short { device_index, sub_id: sid, address, params }
+                    if device_index == HIDPP_RECEIVER_IDX
+                        && sid == sub_id
+                        && address == register => Some(Ok(params.to_vec())),
+                HidppReport::Long { device_index, sub_id: sid, address, params }
+                    if device_index == HIDPP_RECEIVER_IDX
+                        && sid == sub_id
+                        && address == register => Some(Ok(params.to_vec())),
                 _ => None,
             }
-        }).await.context("HID++ 1.0 open pairing lock failed")
+        }).await?;
+        reply.map_err(|error| crate::hal::DriverError::ProtocolError { sub_id, error }.into())
+    }
+
+    /* ---- Register 0x00: Receiver Notifications -------------------- */
+
+    /* Make sure the receiver reports connection and pairing-lock events. */
+    async fn enable_wireless_notifications(&self, io: &mut DeviceIo) -> Result<()> {
+        let p = self
+            .receiver_request(io, SUB_ID_GET_REGISTER, REG_HIDPP_NOTIFICATIONS, [0, 0, 0])
+            .await
+            .context("HID++ 1.0 read receiver notifications failed")?;
+        if p[1] & NOTIF_FLAG_WIRELESS == 0 {
+            self.receiver_request(
+                io, SUB_ID_SET_REGISTER, REG_HIDPP_NOTIFICATIONS,
+                [p[0], p[1] | NOTIF_FLAG_WIRELESS, p[2]],
+            ).await.context("HID++ 1.0 enable receiver notifications failed")?;
+        }
+        Ok(())
+    }
+
+    /* ---- Register 0xB2: Device Connection / Disconnection --------- */
+
+    async fn open_pairing_lock(&self, io: &mut DeviceIo, timeout: u8) -> Result<()> {
+        self.receiver_request(
+            io, SUB_ID_SET_REGISTER, REG_DEVICE_CONNECTION,
+            [CONNECT_OPEN_LOCK, 0xFF, timeout],
+        ).await.context("HID++ 1.0 open pairing lock failed")?;
+        Ok(())
     }
 
-    #[allow(dead_code)]
     async fn close_pairing_lock(&self, io: &mut DeviceIo) -> Result<()> {
-        let request = hidpp::build_short_report(
-            HIDPP_RECEIVER_IDX, SUB_ID_SET_REGISTER, REG_DEVICE_CONNECTION,
+        self.receiver_request(
+            io, SUB_ID_SET_REGISTER, REG_DEVICE_CONNECTION,
             [CONNECT_CLOSE_LOCK, 0xFF, 0x00],
-        );
-        io.request(&request, 20, 3, move |buf| {
-            let report = HidppReport::parse(buf)?;
-            if report.is_error() { return None; }
-            match report {
-                HidppReport::Short { device_index, sub_id, address, .. }
-                    if device_index == HIDPP_RECEIVER_IDX
-                        && sub_id == SUB_ID_SET_REGISTER
-                        && address == REG_DEVICE_CONNECTION => Some(()),
-                _ => None,
-            }
-        }).await.context("HID++ 1.0 close pairing lock failed")
+        ).await.context("HID++ 1.0 close pairing lock failed")?;
+        Ok(())
     }
 
-    #[allow(dead_code)]
     async fn disconnect_device(&self, io: &mut DeviceIo, device_idx: u8) -> Result<()> {
-        let request = hidpp::build_short_report(
-            HIDPP_RECEIVER_IDX, SUB_ID_SET_REGISTER, REG_DEVICE_CONNECTION,
+        self.receiver_request(
+            io, SUB_ID_SET_REGISTER, REG_DEVICE_CONNECTION,
             [CONNECT_DISCONNECT, device_idx, 0x00],
-        );
-        io.request(&request, 20, 3, move |buf| {
-            let report = HidppReport::parse(buf)?;
-            if report.is_error() { return None; }
-            match report {
-                HidppReport::Short { device_index, sub_id, address, .. }
-                    if device_index == HIDPP_RECEIVER_IDX
-                        && sub_id == SUB_ID_SET_REGISTER
-                        && address == REG_DEVICE_CONNECTION => Some(()),
-                _ => None,
-            }
-        }).await.context("HID++ 1.0 disconnect device failed")
+        ).await.context("HID++ 1.0 disconnect device failed")?;
+        Ok(())
     }
 
     /* ---- Register 0xB5: Pairing Information ----------------------- */
 
-    #[allow(dead_code)]
-    async fn get_pairing_information(&self, io: &mut DeviceIo) -> Result<PairingInfo> {
-        let request = hidpp::build_short_report(
-            HIDPP_RECEIVER_IDX, SUB_ID_GET_LONG_REGISTER, REG_PAIRING_INFORMATION,
-            [PAIRING_INFO_DEVICE + self.device_index - 1, 0x00, 0x00],
-        );
-        let resp = io.request(&request, 20, 3, move |buf| {
-            let report = HidppReport::parse(buf)?;
-            if report.is_error() { return None; }
-            match report {
-                HidppReport::Long { device_index, sub_id, address, params }
-                    if device_index == HIDPP_RECEIVER_IDX
-                        && sub_id == SUB_ID_GET_LONG_REGISTER
-                        && address == REG_PAIRING_INFORMATION => Some(params),
-                _ => None,
-            }
-        }).await.context("HID++ 1.0 get pairing info failed")?;
+    async fn get_pairing_information(&self, io: &mut DeviceIo, slot: u8) -> Result<PairingInfo> {
+        let resp = self.receiver_request(
+            io, SUB_ID_GET_LONG_REGISTER, REG_PAIRING_INFORMATION,
+            [PAIRING_INFO_DEVICE + slot - 1, 0x00, 0x00],
+        ).await.context("HID++ 1.0 get pairing info failed")?;
+        anyhow::ensure!(resp.len() >= 8, "HID++ 1.0 pairing info reply too short");
         Ok(PairingInfo {
             report_interval: resp[2],
             wpid: u16::from_be_bytes([resp[3], resp[4]]),
@@ -1449,45 +1786,24 @@ impl Hidpp10Driver {
         })
     }
 
-    #[allow(dead_code)]
-    async fn get_pairing_device_name(&self, io: &mut DeviceIo) -> Result<String> {
-        let request = hidpp::build_short_report(
-            HIDPP_RECEIVER_IDX, SUB_ID_GET_LONG_REGISTER, REG_PAIRING_INFORMATION,
-            [PAIRING_INFO_DEVICE_NAME + self.device_index - 1, 0x00, 0x00],
-        );
-        let resp = io.request(&request, 20, 3, move |buf| {
-            let report = HidppReport::parse(buf)?;
-            if report.is_error() { return None; }
-            match report {
-                HidppReport::Long { device_index, sub_id, address, params }
-                    if device_index == HIDPP_RECEIVER_IDX
-                        && sub_id == SUB_ID_GET_LONG_REGISTER
-                        && address == REG_PAIRING_INFORMATION => Some(params),
-                _ => None,
-            }
-        }).await.context("HID++ 1.0 get pairing device name failed")?;
+    async fn get_pairing_device_name(&self, io: &mut DeviceIo, slot: u8) -> Result<String> {
+        let resp = self.receiver_request(
+            io, SUB_ID_GET_LONG_REGISTER, REG_PAIRING_INFORMATION,
+            [PAIRING_INFO_DEVICE_NAME + slot - 1, 0x00, 0x00],
+        ).await.context("HID++ 1.0 get pairing device name failed")?;
+        anyhow::ensure!(resp.len() >= 2, "HID++ 1.0 pairing name reply too short");
         let name_len = resp[1] as usize;
         let end = (2 + name_len).min(resp.len());
         Ok(String::from_utf8_lossy(&resp[2..end]).into_owned())
     }
 
     #[allow(dead_code)]
-    async fn get_extended_pairing_info(&self, io: &mut DeviceIo) -> Result<u32> {
-        let request = hidpp::build_short_report(
-            HIDPP_RECEIVER_IDX, SUB_ID_GET_LONG_REGISTER, REG_PAIRING_INFORMATION,
-            [PAIRING_INFO_EXTENDED + self.device_index - 1, 0x00, 0x00],
-        );
-        let resp = io.request(&request, 20, 3, move |buf| {
-            let report = HidppReport::parse(buf)?;
-            if report.is_error() { return None; }
-            match report {
-                HidppReport::Long { device_index, sub_id, address, params }
-                    if device_index == HIDPP_RECEIVER_IDX
-                        && sub_id == SUB_ID_GET_LONG_REGISTER
-                        && address == REG_PAIRING_INFORMATION => Some(params),
-                _ => None,
-            }
-        }).await.context("HID++ 1.0 get extended pairing info failed")?;
+    async fn get_extended_pairing_info(&self, io: &mut DeviceIo, slot: u8) -> Result<u32> {
+        let resp = self.receiver_request(
+            io, SUB_ID_GET_LONG_REGISTER, REG_PAIRING_INFORMATION,
+            [PAIRING_INFO_EXTENDED + slot - 1, 0x00, 0x00],
+        ).await.context("HID++ 1.0 get extended pairing info failed")?;
+        anyhow::ensure!(resp.len() >= 5, "HID++ 1.0 extended pairing reply too short");
         Ok(u32::from_be_bytes([resp[1], resp[2], resp[3], resp[4]]))
     }
 
@@ -1695,23 +2011,13 @@ impl Hidpp10Driver {
             bytes[base + 2] = ((0b111u8 << index) >> 2) & 0b111;
             index += 1;
         }
-        let crc = hidpp::compute_ccitt_crc(&bytes[..PAGE_SIZE - 2]);
-        bytes[PAGE_SIZE - 2] = (crc >> 8) as u8;
-        bytes[PAGE_SIZE - 1] = (crc & 0xFF) as u8;
-
-        let half = PAGE_SIZE / 2;
-        self.send_hot_payload(io, 0x00, 0x0000, &bytes[..half]).await?;
-        self.erase_memory(io, 0x01).await?;
-        self.write_flash(io, 0x00, 0x0000, 0x01, 0x0000, half as u16).await?;
-        self.send_hot_payload(io, 0x00, 0x0000, &bytes[half..]).await?;
-        self.write_flash(io, 0x00, 0x0000, 0x01, half as u16, half as u16).await?;
-        Ok(())
+        self.write_page(io, 0x01, &mut bytes).await
     }
 
     /* ---- Read individual onboard profiles from flash --------------- */
 
     async fn read_onboard_profile(
-        &self, io: &mut DeviceIo, profile_idx: usize,
+        &mut self, io: &mut DeviceIo, profile_idx: usize,
     ) -> Result<Hidpp10Profile> {
         let mut profile = if profile_idx < self.onboard_profiles.len() {
             self.onboard_profiles[profile_idx].clone()
@@ -1786,17 +2092,18 @@ impl Hidpp10Driver {
          * Vec is indexed by button number; entries that are not macros
          * are stored as None. */
         profile.macros = Vec::with_capacity(profile.buttons.len());
-        for binding in &profile.buttons {
+        for (j, binding) in profile.buttons.iter().enumerate() {
             match *binding {
                 Hidpp10ButtonBinding::Macro { page, offset } => {
-                    let byte_offset = u8::from(offset).wrapping_mul(2);
+                    let byte_offset = u16::from(offset) * 2;
                     match self.read_macro(io, page, byte_offset).await {
-                        Ok(events) => {
+                        Ok((events, pages)) => {
                             debug!(
                                 "HID++ 1.0: read macro at ({page:02X},{offset:02X}): {} events",
                                 events.len()
                             );
                             profile.macros.push(Some(events));
+                            self.macro_pages.insert((profile_idx as u32, j as u32), pages);
                         }
                         Err(e) => {
                             warn!("HID++ 1.0: failed to read macro at ({page:02X},{offset:02X}): {e}");
@@ -1872,21 +2179,12 @@ impl Hidpp10Driver {
             Hidpp10ProfileType::Unknown => return Ok(()),
         }
 
-        let crc = hidpp::compute_ccitt_crc(&page_data[..PAGE_SIZE - 2]);
-        page_data[PAGE_SIZE - 2] = (crc >> 8) as u8;
-        page_data[PAGE_SIZE - 1] = (crc & 0xFF) as u8;
-
         /* Atomic write: factory profile → upload → erase → flash → restore. */
         self.set_register(
             io, REG_CURRENT_PROFILE, [PROFILE_TYPE_FACTORY, 0x00, 0x00],
         ).await.context("Failed to switch to factory profile for write")?;
 
-        let half = PAGE_SIZE / 2;
-        self.send_hot_payload(io, 0x00, 0x0000, &page_data[..half]).await?;
-        self.erase_memory(io, profile.page).await?;
-        self.write_flash(io, 0x00, 0x0000, profile.page, 0x0000, half as u16).await?;
-        self.send_hot_payload(io, 0x00, 0x0000, &page_data[half..]).await?;
-        self.write_flash(io, 0x00, 0x0000, profile.page, half as u16, half as u16).await?;
+        self.write_page(io, profile.page, &mut page_data).await?;
 
         self.set_register(
             io, REG_CURRENT_PROFILE,
@@ -2003,6 +2301,9 @@ impl Hidpp10Driver {
                     }
                     Hidpp10ButtonBinding::Macro { .. } => {
                         btn.action_type = ActionType::Macro;
+                        if let Some(Some(events)) = onboard.macros.get(j) {
+                            btn.macro_entries = macro_events_from_onboard(events);
+                        }
                     }
                     Hidpp10ButtonBinding::Unknown { .. } => {
                         btn.action_type = ActionType::Unknown;
@@ -2071,8 +2372,13 @@ impl super::DeviceDriver for Hidpp10Driver {
     }
 
     async fn load_profiles(&mut self, io: &mut DeviceIo, info: &mut DeviceInfo) -> Result<()> {
+        /* Anything not answering at the corded index sits behind a
+         * receiver whose pairing slots we can manage. */
+        info.has_receiver = self.device_index != DEVICE_IDX_CORDED;
+
         /* Read onboard profiles from flash if the device supports them. */
         if self.profile_type != Hidpp10ProfileType::Unknown {
+            self.macro_pages.clear();
             self.read_profile_directory(io).await?;
             let count = self.onboard_profiles.len();
             let mut loaded = Vec::with_capacity(count);
@@ -2102,6 +2408,11 @@ impl super::DeviceDriver for Hidpp10Driver {
         /* Supplement with live register values. */
         for profile in &mut info.profiles {
             profile.is_active = profile.index == active_idx;
+            /* Register 0x57 holds a single colour: there are no effects
+             * to choose from, so Solid is the only mode. */
+            for led in &mut profile.leds {
+                led.modes = vec![LedMode::Solid];
+            }
             if let Err(e) = self.read_resolution(io, profile).await {
                 warn!("Failed to read DPI for profile {}: {}", profile.index, e);
             }
@@ -2113,6 +2424,23 @@ impl super::DeviceDriver for Hidpp10Driver {
             }
         }
 
+        /* Wireless devices report their battery through register 0x07;
+         * corded ones and receivers without a battery answer with an
+         * unknown level or not at all. */
+        if info.has_receiver {
+            match self.get_battery_status(io).await {
+                Ok(status) if status.level != BatteryLevel::Unknown => {
+                    info.power = Some(PowerInfo {
+                        battery: status.to_battery_status(),
+                        low_battery_alert: Some(u32::from(status.low_threshold_percent)),
+                        ..PowerInfo::default()
+                    });
+                }
+                Ok(_) => debug!("HID++ 1.0: device reports no battery level"),
+                Err(e) => debug!("HID++ 1.0: battery status unavailable: {e:#}"),
+            }
+        }
+
         debug!(
             "HID++ 1.0: loaded {} profiles, active = {active_idx}",
             info.profiles.len()
@@ -2138,10 +2466,12 @@ impl super::DeviceDriver for Hidpp10Driver {
             if self.profile_type != Hidpp10ProfileType::Unknown
                 && (idx as usize) < self.onboard_profiles.len()
             {
-                let op = self.onboard_profiles[idx as usize].clone();
+                let mut op = self.onboard_profiles[idx as usize].clone();
+                self.store_changed_macros(io, profile, &mut op).await;
                 if let Err(e) = self.write_onboard_profile(io, idx as usize, &op).await {
                     warn!("Failed to write onboard profile {idx}: {e}");
                 }
+                self.onboard_profiles[idx as usize] = op;
             }
 
             self.set_register(io, REG_CURRENT_PROFILE, [idx, 0x00, 0x00])
@@ -2151,4 +2481,396 @@ impl super::DeviceDriver for Hidpp10Driver {
         }
         Ok(())
     }
+
+    async fn paired_devices(&mut self, io: &mut DeviceIo) -> Result<Vec<PairedDevice>> {
+        let mut devices = Vec::new();
+        for slot in 1..=RECEIVER_MAX_SLOTS {
+            let pairing = match self.get_pairing_information(io, slot).await {
+                Ok(pairing) => pairing,
+                Err(e) => {
+                    debug!("HID++ 1.0: receiver slot {slot} is empty: {e:#}");
+                    continue;
+                }
+            };
+            let name = self.get_pairing_device_name(io, slot).await.unwrap_or_else(|e| {
+                warn!("HID++ 1.0: failed to read the name in receiver slot {slot}: {e:#}");
+                String::new()
+            });
+            devices.push(PairedDevice {
+                slot,
+                wpid: pairing.wpid,
+                name,
+            });
+        }
+        Ok(devices)
+    }
+
+    async fn pair_device(&mut self, io: &mut DeviceIo, timeout: u8) -> Result<PairingOutcome> {
+        self.enable_wireless_notifications(io).await?;
+        self.open_pairing_lock(io, timeout).await?;
+        info!("HID++ 1.0: receiver pairing window open for {timeout}s");
+
+        /* Remember the last device announced while the lock is open; the
+         * lock-closed notification then says whether pairing succeeded. */
+        let mut connected = None;
+        let window = Duration::from_secs(u64::from(timeout)) + PAIRING_GRACE;
+        let closed = io.wait_report(window, |buf| match HidppReport::parse(buf)? {
+            HidppReport::Short { device_index, sub_id, params, .. }
+                if sub_id == NOTIF_DEVICE_CONNECTION
+                    && (1..=RECEIVER_MAX_SLOTS).contains(&device_index) =>
+            {
+                connected = Some((device_index, u16::from_be_bytes([params[2], params[1]])));
+                None
+            }
+            HidppReport::Short { device_index, sub_id, address, params }
+                if device_index == HIDPP_RECEIVER_IDX
+                    && sub_id == NOTIF_PAIRING_LOCK
+                    && address & PAIRING_LOCK_OPEN == 0 => Some(params[0]),
+            _ => None,
+        }).await;
+
+        let outcome = match closed {
+            Ok(error) => pairing_outcome(error, connected),
+            Err(crate::hal::DriverError::Timeout { .. }) => {
+                warn!("HID++ 1.0: receiver never reported the pairing lock closing");
+                self.close_pairing_lock(io).await?;
+                PairingOutcome::TimedOut
+            }
+            Err(e) => return Err(e.into()),
+        };
+        info!("HID++ 1.0: pairing window closed: {outcome:?}");
+        Ok(outcome)
+    }
+
+    async fn unpair_device(&mut self, io: &mut DeviceIo, slot: u8) -> Result<()> {
+        anyhow::ensure!(
+            (1..=RECEIVER_MAX_SLOTS).contains(&slot),
+            "Receiver slot {slot} out of range (1..={RECEIVER_MAX_SLOTS})"
+        );
+        self.disconnect_device(io, slot).await?;
+        info!("HID++ 1.0: unpaired receiver slot {slot}");
+        Ok(())
+    }
+
+    async fn read_battery(&mut self, io: &mut DeviceIo) -> Result<Option<BatteryStatus>> {
+        match self.get_battery_status(io).await {
+            Ok(status) => Ok(status.to_battery_status()),
+            /* The receiver answers for a sleeping device with an error
+             * the register request skips, so the read times out: report
+             * the device asleep and keep the last reading. */
+            Err(e) if e.chain().any(|cause| {
+                matches!(cause.downcast_ref(), Some(crate::hal::DriverError::Timeout { .. }))
+            }) => {
+                debug!("HID++ 1.0: battery not readable, device asleep: {e:#}");
+                Ok(None)
+            }
+            Err(e) => Err(e.context("HID++ 1.0: reading the battery failed")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::DeviceDriver;
+    use crate::hal::mock::MockExchange;
+
+    /* Short report from the receiver itself. */
+    fn receiver_short(sub_id: u8, address: u8, params: [u8; 3]) -> Vec<u8> {
+        hidpp::build_short_report(HIDPP_RECEIVER_IDX, sub_id, address, params).to_vec()
+    }
+
+    #[test]
+    fn pairing_outcome_maps_lock_errors() {
+        assert_eq!(
+            pairing_outcome(PAIRING_ERR_NONE, Some((2, 0x402D))),
+            PairingOutcome::Paired { slot: 2, wpid: 0x402D }
+        );
+        assert_eq!(pairing_outcome(PAIRING_ERR_NONE, None), PairingOutcome::TimedOut);
+        assert_eq!(
+            pairing_outcome(PAIRING_ERR_SEQUENCE_TIMEOUT, Some((2, 0x402D))),
+            PairingOutcome::TimedOut
+        );
+        assert_eq!(
+            pairing_outcome(PAIRING_ERR_TOO_MANY_DEVICES, None),
+            PairingOutcome::TooManyDevices
+        );
+        assert_eq!(pairing_outcome(0x7F, None), PairingOutcome::Failed);
+    }
+
+    #[tokio::test]
+    async fn pairing_reports_the_announced_device() {
+        let script = vec![
+            MockExchange::reply(receiver_short(
+                SUB_ID_GET_REGISTER, REG_HIDPP_NOTIFICATIONS, [0; 3],
+            )),
+            MockExchange::reply(receiver_short(
+                SUB_ID_SET_REGISTER, REG_HIDPP_NOTIFICATIONS, [0; 3],
+            )),
+            MockExchange::burst(vec![
+                receiver_short(SUB_ID_SET_REGISTER, REG_DEVICE_CONNECTION, [0; 3]),
+                hidpp::build_short_report(2, NOTIF_DEVICE_CONNECTION, 0x04, [0x00, 0x2D, 0x40])
+                    .to_vec(),
+                receiver_short(NOTIF_PAIRING_LOCK, 0x00, [PAIRING_ERR_NONE, 0, 0]),
+            ]),
+        ];
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        let mut driver = Hidpp10Driver::new();
+
+        let outcome = driver.pair_device(&mut io, 30).await.unwrap();
+        assert_eq!(outcome, PairingOutcome::Paired { slot: 2, wpid: 0x402D });
+        assert!(handle.script_exhausted());
+
+        let writes = handle.writes();
+        /* Wireless notifications enabled, then the lock opened for 30 s. */
+        assert_eq!(writes[1][5] & NOTIF_FLAG_WIRELESS, NOTIF_FLAG_WIRELESS);
+        assert_eq!(&writes[2][4..7], &[CONNECT_OPEN_LOCK, 0xFF, 30]);
+        /* The connection notification stays queued for the actor. */
+        assert_eq!(io.drain_events().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn paired_devices_skip_empty_slots() {
+        let mut info = [0u8; 16];
+        info[0] = PAIRING_INFO_DEVICE;
+        info[3..5].copy_from_slice(&0x4013u16.to_be_bytes());
+        let mut name = [0u8; 16];
+        name[0] = PAIRING_INFO_DEVICE_NAME;
+        name[1] = 4;
+        name[2..6].copy_from_slice(b"G700");
+        let empty = receiver_short(
+            hidpp::HIDPP10_ERROR,
+            SUB_ID_GET_LONG_REGISTER,
+            [REG_PAIRING_INFORMATION, 0x03, 0x00],
+        );
+
+        let mut script = vec![
+            MockExchange::reply(
+                hidpp::build_long_report(
+                    HIDPP_RECEIVER_IDX, SUB_ID_GET_LONG_REGISTER, REG_PAIRING_INFORMATION, info,
+                )
+                .to_vec(),
+            ),
+            MockExchange::reply(
+                hidpp::build_long_report(
+                    HIDPP_RECEIVER_IDX, SUB_ID_GET_LONG_REGISTER, REG_PAIRING_INFORMATION, name,
+                )
+                .to_vec(),
+            ),
+        ];
+        for _ in 2..=RECEIVER_MAX_SLOTS {
+            script.push(MockExchange::reply(empty.clone()));
+        }
+        let (mut io, handle) = DeviceIo::with_mock(script);
+
+        let devices = Hidpp10Driver::new().paired_devices(&mut io).await.unwrap();
+        assert!(handle.script_exhausted());
+        assert_eq!(
+            devices,
+            vec![PairedDevice { slot: 1, wpid: 0x4013, name: "G700".into() }]
+        );
+        /* Slot 6 was queried through sub-address 0x25. */
+        assert_eq!(handle.writes().last().unwrap()[4], PAIRING_INFO_DEVICE + 5);
+    }
+
+    #[test]
+    fn onboard_macros_map_to_engine_events() {
+        let onboard = [
+            MacroEvent::ModPress { key: 0x02 },
+            MacroEvent::KeyPress { key: 0x04 },
+            MacroEvent::Delay { time_ms: 20 },
+            MacroEvent::KeyRelease { key: 0x04 },
+            MacroEvent::ModRelease { key: 0x02 },
+            MacroEvent::MouseButtonPress { button: 4 },
+            MacroEvent::MouseWheel { value: -1 },
+            MacroEvent::ConsumerControl { usage: 0xE2 },
+            MacroEvent::MousePointerMove { x_rel: -5, y_rel: 0xFFFE },
+            MacroEvent::Noop,
+            MacroEvent::RepeatUntilButtonRelease,
+            MacroEvent::End,
+        ];
+        assert_eq!(
+            macro_events_from_onboard(&onboard),
+            vec![
+                device::MacroEvent::KeyPress(0xE1),
+                device::MacroEvent::KeyPress(0x04),
+                device::MacroEvent::Wait(20),
+                device::MacroEvent::KeyRelease(0x04),
+                device::MacroEvent::KeyRelease(0xE1),
+                device::MacroEvent::ButtonPress(4),
+                device::MacroEvent::Wheel(-1),
+                device::MacroEvent::Consumer(0xE2),
+                device::MacroEvent::PointerMove { x: -5, y: -2 },
+                device::MacroEvent::RepeatWhilePressed,
+            ]
+        );
+    }
+
+    #[test]
+    fn macro_serialization_round_trips() {
+        let events = vec![
+            device::MacroEvent::KeyPress(0xE1),
+            device::MacroEvent::KeyPress(0x04),
+            device::MacroEvent::Wait(300),
+            device::MacroEvent::KeyRelease(0x04),
+            device::MacroEvent::KeyRelease(0xE1),
+            device::MacroEvent::ButtonPress(2),
+            device::MacroEvent::ButtonRelease(2),
+            device::MacroEvent::Wheel(-3),
+            device::MacroEvent::Consumer(0xE9),
+            device::MacroEvent::PointerMove { x: 12, y: -7 },
+            device::MacroEvent::WaitForRelease,
+        ];
+        let code = serialize_macro(&events);
+        assert_eq!(&code[..4], &[MACRO_MOD_PRESS, 0x02, MACRO_KEY_PRESS, 0x04]);
+        assert_eq!(code.last(), Some(&MACRO_END));
+
+        let parsed = parse_macro(&code);
+        assert!(matches!(parsed.last(), Some(MacroEvent::End)));
+        assert_eq!(macro_events_from_onboard(&parsed), events);
+    }
+
+    #[test]
+    fn macro_allocation_skips_profile_and_occupied_pages() {
+        let mut driver = Hidpp10Driver::new();
+        driver.onboard_profiles = vec![
+            Hidpp10Profile { page: 2, ..Default::default() },
+            Hidpp10Profile { page: 3, ..Default::default() },
+        ];
+        driver.macro_pages.insert((0, 4), vec![4]);
+        driver.macro_pages.insert((1, 6), vec![5, 6]);
+
+        assert_eq!(driver.allocate_macro_page(None), Some(7));
+        assert_eq!(driver.allocate_macro_page(Some(5)), Some(7));
+        assert_eq!(driver.allocate_macro_page(Some(3)), Some(7));
+        assert_eq!(driver.allocate_macro_page(Some(9)), Some(9));
+
+        for page in 7..=MAX_PAGE_NUMBER {
+            driver.macro_pages.insert((2, u32::from(page)), vec![page]);
+        }
+        assert_eq!(driver.allocate_macro_page(None), None);
+    }
+
+    /* Replies for one `write_page`: for each half, the HOT control reset,
+     * the 17 chunk acknowledgements of a 256-byte upload, then the erase
+     * (first half only) and the flash copy. */
+    fn script_page_write(script: &mut Vec<MockExchange>, dev: u8) {
+        for half in 0..2 {
+            script.push(MockExchange::reply(
+                hidpp::build_short_report(dev, SUB_ID_SET_REGISTER, CMD_HOT_CONTROL, [0; 3])
+                    .to_vec(),
+            ));
+            for index in 0..17u8 {
+                let mut params = [0u8; 16];
+                params[0] = index;
+                script.push(MockExchange::reply(
+                    hidpp::build_long_report(dev, HOT_NOTIFICATION, 0x00, params).to_vec(),
+                ));
+            }
+            let memory_ack = hidpp::build_long_report(
+                dev, SUB_ID_SET_LONG_REGISTER, REG_MEMORY_MANAGEMENT, [0; 16],
+            );
+            if half == 0 {
+                script.push(MockExchange::reply(memory_ack.to_vec()));
+            }
+            script.push(MockExchange::reply(memory_ack.to_vec()));
+        }
+    }
+
+    #[tokio::test]
+    async fn changed_macro_is_written_to_its_own_page_and_bound() {
+        let mut driver = Hidpp10Driver::new();
+        driver.device_index = DEVICE_IDX_CORDED;
+        driver.profile_type = Hidpp10ProfileType::G700;
+        driver.onboard_profiles = vec![Hidpp10Profile { page: 2, ..Default::default() }];
+        driver.macro_pages.insert((0, 3), vec![3]);
+        driver.macro_pages.insert((0, 4), vec![4]);
+
+        let mut script = Vec::new();
+        script_page_write(&mut script, DEVICE_IDX_CORDED);
+        let (mut io, handle) = DeviceIo::with_mock(script);
+
+        let events = vec![device::MacroEvent::KeyPress(0x05), device::MacroEvent::KeyRelease(0x05)];
+        let mut profile = ProfileInfo { index: 0, ..Default::default() };
+        for index in 0..5 {
+            profile.buttons.push(ButtonInfo { index, ..Default::default() });
+        }
+        /* Button 3 is unchanged; button 4 gets a new macro. */
+        profile.buttons[3].action_type = ActionType::Macro;
+        profile.buttons[3].macro_entries = vec![device::MacroEvent::Wait(10)];
+        profile.buttons[4].action_type = ActionType::Macro;
+        profile.buttons[4].macro_entries = events.clone();
+
+        let mut onboard = Hidpp10Profile {
+            page: 2,
+            buttons: vec![Hidpp10ButtonBinding::Disabled; 5],
+            macros: vec![None; 5],
+            ..Default::default()
+        };
+        onboard.buttons[3] = Hidpp10ButtonBinding::Macro { page: 3, offset: 0 };
+        onboard.macros[3] = Some(vec![MacroEvent::Delay { time_ms: 10 }, MacroEvent::End]);
+
+        driver.store_changed_macros(&mut io, &profile, &mut onboard).await;
+        assert!(handle.script_exhausted());
+
+        /* Page 4 belonged to button 4 alone, so the macro reuses it. */
+        assert!(matches!(onboard.buttons[4], Hidpp10ButtonBinding::Macro { page: 4, offset: 0 }));
+        assert_eq!(macro_events_from_onboard(onboard.macros[4].as_ref().unwrap()), events);
+        assert_eq!(driver.macro_pages.get(&(0, 4)), Some(&vec![4]));
+
+        /* The first HOT chunk carries the bytecode; the erase hit page 4. */
+        let writes = handle.writes();
+        assert_eq!(
+            &writes[1][13..18],
+            &[MACRO_KEY_PRESS, 0x05, MACRO_KEY_RELEASE, 0x05, MACRO_END]
+        );
+        assert_eq!(writes[18][4 + 6], 4);
+    }
+
+    #[tokio::test]
+    async fn battery_status_maps_to_approximate_percent() {
+        let battery = |params| {
+            MockExchange::reply(
+                hidpp::build_short_report(
+                    DEVICE_IDX_RECEIVER, SUB_ID_GET_REGISTER, REG_BATTERY_STATUS, params,
+                )
+                .to_vec(),
+            )
+        };
+        let script = vec![
+            battery([0x03, 0x21, 0x02]),
+            battery([0x07, 0x22, 0x00]),
+            battery([0x00, 0x00, 0x00]),
+        ];
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        let mut driver = Hidpp10Driver::new();
+
+        let status = driver.get_battery_status(&mut io).await.unwrap();
+        assert_eq!(status.low_threshold_percent, 10);
+        assert_eq!(
+            status.to_battery_status(),
+            Some(BatteryStatus { level: 20, is_charging: true })
+        );
+        /* A completed charge no longer counts as charging. */
+        assert_eq!(
+            driver.read_battery(&mut io).await.unwrap(),
+            Some(BatteryStatus { level: 90, is_charging: false })
+        );
+        /* An unknown level is no reading at all. */
+        assert_eq!(driver.read_battery(&mut io).await.unwrap(), None);
+        assert!(handle.script_exhausted());
+    }
+
+    /* start_paused: the read deadlines of every retry elapse instantly. */
+    #[tokio::test(start_paused = true)]
+    async fn battery_read_of_sleeping_device_is_not_an_error() {
+        let script = (0..3)
+            .map(|_| MockExchange { expect: None, reply: crate::hal::mock::MockReply::Silence })
+            .collect();
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        let mut driver = Hidpp10Driver::new();
+
+        assert_eq!(driver.read_battery(&mut io).await.unwrap(), None);
+        assert!(handle.script_exhausted());
+    }
 }
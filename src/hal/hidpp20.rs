@@ -4,13 +4,18 @@
 /* Logitech gaming mice. Each capability is exposed as a numbered "feature" */
 /* that must be discovered at probe time via the Root feature (0x0000). */
 
+use std::collections::{BTreeMap, BTreeSet};
+
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use thiserror::Error;
 use tokio::time::{Duration, sleep};
 use tracing::{debug, info, trace, warn};
 
-use crate::engine::device::{Color, DeviceInfo, Dpi, LedMode, ProfileInfo, RgbColor};
+use crate::engine::device::{
+    Color, DeviceInfo, Dpi, LedMode, MacroEvent, ProfileInfo, RATBAG_RESOLUTION_CAP_SHIFT, RgbColor,
+    SensorInfo,
+};
 use crate::hal::{DeviceIo, DriverError};
 
 use super::hidpp::{
@@ -40,9 +45,29 @@ const RATE_FN_GET_REPORT_RATE: u8 = 0x01;
  *            SET_ZONE_EFFECT=0x30, GET_ZONE_EFFECT=0xE0.
  * The address byte encodes (function << 4 | sw_id), so we store the function
  * number in the upper nibble position: 0x30 → fn 3, 0xE0 → fn 14. */
+const LED_FN_GET_INFO: u8 = 0x00;
+const LED_FN_GET_ZONE_INFO: u8 = 0x01;
+const LED_FN_GET_ZONE_EFFECT_INFO: u8 = 0x02;
 const LED_FN_GET_ZONE_EFFECT: u8 = 0x0E;
 const LED_FN_SET_ZONE_EFFECT: u8 = 0x03;
 
+/* RGB Effects (0x8071) function IDs.  getInfo takes (cluster, effect)
+ * indices where 0xFF selects the device-level or cluster-level record. */
+const RGB_FN_GET_INFO: u8 = 0x00;
+const RGB_FN_SET_CLUSTER_EFFECT: u8 = 0x01;
+const RGB_FN_SET_MULTI_LED_PATTERN: u8 = 0x02;
+const RGB_INFO_ALL: u8 = 0xFF;
+
+/* Upper bounds on the zone/cluster and per-zone effect counts reported by
+ * 0x8070/0x8071, so a garbage reply cannot trigger hundreds of requests. */
+const RGB_MAX_CLUSTERS: u8 = 16;
+const RGB_MAX_EFFECTS: u8 = 32;
+
+/* Persistence byte of a setRgbClusterEffect request: 0x01 stores the
+ * effect in the device, 0x00 shows it until the next change. */
+const RGB_PERSIST: u8 = 0x01;
+const RGB_VOLATILE: u8 = 0x00;
+
 /* Onboard Profiles (0x8100) function IDs.
  * C defines: GET_PROFILES_DESCR=0x00, SET_ONBOARD_MODE=0x10,
  * GET_ONBOARD_MODE=0x20, SET_CURRENT_PROFILE=0x30,
@@ -92,6 +117,7 @@ const ONBOARD_MODE_HOST: u8 = 0x02;
  * sector; the offsets below mirror it field-for-field. */
 const EEPROM_REPORT_INTERVAL_OFFSET: usize = 0;
 const EEPROM_DEFAULT_DPI_OFFSET: usize = 1;
+const EEPROM_SWITCHED_DPI_OFFSET: usize = 2;
 const EEPROM_DPI_OFFSET: usize = 3;
 const EEPROM_DPI_COUNT: usize = 5;
 const EEPROM_BUTTON_OFFSET: usize = 32;
@@ -101,6 +127,17 @@ const EEPROM_LED_OFFSET: usize = 208;
 const EEPROM_LED_SIZE: usize = 11;
 const EEPROM_LED_COUNT: usize = 2;
 
+/* Modes the EEPROM LED record (and the 0x8070 payload) can encode.  Used
+ * until the lighting-zone enumeration narrows it down per LED. */
+const EEPROM_LED_MODES: &[LedMode] = &[
+    LedMode::Off,
+    LedMode::Solid,
+    LedMode::Cycle,
+    LedMode::ColorWave,
+    LedMode::Starlight,
+    LedMode::Breathing,
+];
+
 /* Minimum sector length that fits every slot `EepromProfile` manages.  The
  * LED region ends last (208 + 2 × 11 = 230); the CRC trailer is owned by
  * the caller and sits beyond this, so real sectors (typically 256 bytes)
@@ -108,6 +145,32 @@ const EEPROM_LED_COUNT: usize = 2;
  * rejected rather than decoded into a partial profile. */
 const EEPROM_PROFILE_MIN_LEN: usize = EEPROM_LED_OFFSET + EEPROM_LED_COUNT * EEPROM_LED_SIZE;
 
+/* Onboard macro bytecode opcodes (C enum hidpp20_macro_type).  The opcode's
+ * top three bits encode the instruction length: 0x00–0x1F are one byte,
+ * 0x20–0x5F three bytes, 0x60–0x7F five bytes; 0xFF terminates the macro. */
+const MACRO_OP_WAIT_FOR_RELEASE: u8 = 0x02;
+const MACRO_OP_REPEAT_UNTIL_RELEASE: u8 = 0x03;
+const MACRO_OP_KEY_PRESS: u8 = 0x20;
+const MACRO_OP_KEY_RELEASE: u8 = 0x21;
+const MACRO_OP_MODIFIER_PRESS: u8 = 0x22;
+const MACRO_OP_MODIFIER_RELEASE: u8 = 0x23;
+const MACRO_OP_WHEEL: u8 = 0x24;
+const MACRO_OP_BUTTON_PRESS: u8 = 0x40;
+const MACRO_OP_BUTTON_RELEASE: u8 = 0x41;
+const MACRO_OP_CONSUMER: u8 = 0x42;
+const MACRO_OP_DELAY: u8 = 0x43;
+const MACRO_OP_JUMP: u8 = 0x44;
+const MACRO_OP_POINTER_MOVE: u8 = 0x60;
+const MACRO_OP_END: u8 = 0xFF;
+
+/* HID usage of the first keyboard modifier (Left Control).  The eight
+ * modifier bits in a key instruction map to usages 0xE0..=0xE7. */
+const HID_USAGE_MODIFIER_BASE: u32 = 0xE0;
+
+/* Upper bound on jumps followed while decoding one macro, so a corrupted
+ * sector whose jumps form a cycle cannot stall the load. */
+const MACRO_MAX_JUMPS: usize = 16;
+
 /* ---------------------------------------------------------------------- */
 /* Driver error topology                                                  */
 /* ---------------------------------------------------------------------- */
@@ -160,6 +223,25 @@ pub enum HidppDriverError {
     #[error(transparent)]
     Transport(DriverError),
 
+    /* Macro bytecode could not be decoded: an unknown opcode, an
+     * instruction running past the sector body, or a jump chain that never
+     * reaches an end marker. */
+    #[error("sector 0x{sector:04X}: malformed macro at offset {offset}")]
+    MalformedMacro { sector: u16, offset: usize },
+
+    /* Encoded macro does not fit into a single EEPROM sector. */
+    #[error("macro too long: needs {needed} bytes, sector holds {available}")]
+    MacroTooLong { needed: usize, available: usize },
+
+    /* The LED (0x8071 cluster) does not advertise an effect for the
+     * requested mode, or no such LED exists. */
+    #[error("LED {led} does not support mode {mode:?}")]
+    UnsupportedLedMode { led: u32, mode: LedMode },
+
+    /* Every user sector past the profile sectors already holds a macro. */
+    #[error("no free EEPROM sector left for the macro on profile {profile} button {button}")]
+    MacroSpaceExhausted { profile: u32, button: u32 },
+
     /* Protocol version probe failed at every candidate device index. */
     #[error("HID++ 2.0 probe failed (tried indices {0:02X?})")]
     ProbeFailed(Vec<u8>),
@@ -276,6 +358,20 @@ impl Hidpp20ButtonBinding {
         buf
     }
 
+    /* Macro bindings use the C `macro` arm of the binding union:
+     * [type, page, 0x00, offset], where page is the sector number. */
+    pub fn for_macro(page: u8) -> Self {
+        Self {
+            button_type: BUTTON_TYPE_MACRO,
+            subtype: page,
+            control_id_or_macro_id: [0, 0],
+        }
+    }
+
+    pub fn macro_address(self) -> (u8, u8) {
+        (self.subtype, self.control_id_or_macro_id[1])
+    }
+
     pub fn to_action(self) -> crate::engine::device::ActionType {
         use crate::engine::device::ActionType;
         match self.button_type {
@@ -354,6 +450,7 @@ impl Hidpp20ButtonBinding {
  * Sector layout (mirrors the C packed `hidpp20_profile` union):
  *   [0]        report interval in ms (0 = unset)
  *   [1]        default DPI slot index
+ *   [2]        switched DPI slot index, used while DPI shift is held
  *   [3..13]    5 × DPI value, little-endian u16 (0 / 0xFFFF = disabled slot)
  *   [32..]     buttons, 4 bytes each (`Hidpp20ButtonBinding`)
  *   [208..]    LEDs, 11 bytes each (`EEPROM_LED_COUNT` entries)
@@ -367,6 +464,7 @@ impl Hidpp20ButtonBinding {
 struct EepromProfile {
     report_interval: u8,
     default_dpi_index: u8,
+    switched_dpi_index: u8,
     dpis: Vec<u16>,
     buttons: Vec<Hidpp20ButtonBinding>,
     leds: Vec<[u8; EEPROM_LED_SIZE]>,
@@ -392,6 +490,7 @@ impl EepromProfile {
 
         let report_interval = data[EEPROM_REPORT_INTERVAL_OFFSET];
         let default_dpi_index = data[EEPROM_DEFAULT_DPI_OFFSET];
+        let switched_dpi_index = data[EEPROM_SWITCHED_DPI_OFFSET];
 
         let dpis = (0..EEPROM_DPI_COUNT)
             .map(|i| {
@@ -421,6 +520,7 @@ impl EepromProfile {
         Ok(Self {
             report_interval,
             default_dpi_index,
+            switched_dpi_index,
             dpis,
             buttons,
             leds,
@@ -435,6 +535,7 @@ impl EepromProfile {
 
         data[EEPROM_REPORT_INTERVAL_OFFSET] = self.report_interval;
         data[EEPROM_DEFAULT_DPI_OFFSET] = self.default_dpi_index;
+        data[EEPROM_SWITCHED_DPI_OFFSET] = self.switched_dpi_index;
         for (i, &dpi) in self.dpis.iter().enumerate().take(EEPROM_DPI_COUNT) {
             let off = EEPROM_DPI_OFFSET + i * 2;
             data[off..off + 2].copy_from_slice(&dpi.to_le_bytes());
@@ -451,6 +552,250 @@ impl EepromProfile {
     }
 }
 
+/* ---------------------------------------------------------------------- */
+/* Onboard macro bytecode                                                 */
+/* ---------------------------------------------------------------------- */
+
+/* Where decoding one sector's worth of macro bytecode stopped. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MacroTail {
+    End,
+    Jump { page: u8, offset: u8 },
+}
+
+/* Instruction length in bytes, derived from the opcode range. */
+fn macro_instruction_len(op: u8) -> Option<usize> {
+    match op {
+        0x00..=0x1F | MACRO_OP_END => Some(1),
+        0x20..=0x5F => Some(3),
+        0x60..=0x7F => Some(5),
+        _ => None,
+    }
+}
+
+/* Emit one event per modifier bit set in a key instruction's mask. */
+fn push_modifier_events(entries: &mut Vec<MacroEvent>, event: fn(u32) -> MacroEvent, mask: u8) {
+    for bit in 0..8u32 {
+        if mask & (1 << bit) != 0 {
+            entries.push(event(HID_USAGE_MODIFIER_BASE + bit));
+        }
+    }
+}
+
+/* Mouse button number (1-based) of the lowest bit set in a button
+ * instruction's big-endian button mask. */
+fn macro_button_from_mask(mask: u16) -> Option<u32> {
+    (mask != 0).then(|| mask.trailing_zeros() + 1)
+}
+
+/* Decode macro bytecode from a sector body (CRC trailer excluded) starting
+ * at `offset`, appending events to `entries`.  Decoding stops at an END
+ * marker or a jump, which the caller follows.  Instructions ratbag has no
+ * event for (no-ops, unconditional repeats, conditional jumps) are
+ * skipped. */
+fn decode_macro(
+    sector: u16,
+    body: &[u8],
+    offset: usize,
+    entries: &mut Vec<MacroEvent>,
+) -> Result<MacroTail, HidppDriverError> {
+    let malformed = |offset| HidppDriverError::MalformedMacro { sector, offset };
+    let mut pos = offset;
+    loop {
+        let op = *body.get(pos).ok_or_else(|| malformed(pos))?;
+        let len = macro_instruction_len(op).ok_or_else(|| malformed(pos))?;
+        let args = body.get(pos + 1..pos + len).ok_or_else(|| malformed(pos))?;
+        match op {
+            MACRO_OP_END => return Ok(MacroTail::End),
+            MACRO_OP_JUMP => {
+                return Ok(MacroTail::Jump {
+                    page: args[0],
+                    offset: args[1],
+                });
+            }
+            MACRO_OP_KEY_PRESS | MACRO_OP_MODIFIER_PRESS => {
+                push_modifier_events(entries, MacroEvent::KeyPress, args[0]);
+                if args[1] != 0 {
+                    entries.push(MacroEvent::KeyPress(u32::from(args[1])));
+                }
+            }
+            MACRO_OP_KEY_RELEASE | MACRO_OP_MODIFIER_RELEASE => {
+                if args[1] != 0 {
+                    entries.push(MacroEvent::KeyRelease(u32::from(args[1])));
+                }
+                push_modifier_events(entries, MacroEvent::KeyRelease, args[0]);
+            }
+            MACRO_OP_DELAY => {
+                let ms = u16::from_be_bytes([args[0], args[1]]);
+                entries.push(MacroEvent::Wait(u32::from(ms)));
+            }
+            MACRO_OP_WAIT_FOR_RELEASE => entries.push(MacroEvent::WaitForRelease),
+            MACRO_OP_REPEAT_UNTIL_RELEASE => entries.push(MacroEvent::RepeatWhilePressed),
+            MACRO_OP_WHEEL => entries.push(MacroEvent::Wheel(i32::from(args[0] as i8))),
+            MACRO_OP_BUTTON_PRESS | MACRO_OP_BUTTON_RELEASE => {
+                if let Some(button) = macro_button_from_mask(u16::from_be_bytes([args[0], args[1]]))
+                {
+                    entries.push(if op == MACRO_OP_BUTTON_PRESS {
+                        MacroEvent::ButtonPress(button)
+                    } else {
+                        MacroEvent::ButtonRelease(button)
+                    });
+                }
+            }
+            MACRO_OP_CONSUMER => {
+                let usage = u16::from_be_bytes([args[0], args[1]]);
+                entries.push(MacroEvent::Consumer(u32::from(usage)));
+            }
+            MACRO_OP_POINTER_MOVE => {
+                let x = i16::from_be_bytes([args[0], args[1]]);
+                let y = i16::from_be_bytes([args[2], args[3]]);
+                entries.push(MacroEvent::PointerMove {
+                    x: i32::from(x),
+                    y: i32::from(y),
+                });
+            }
+            _ => trace!("HID++ 2.0: sector 0x{sector:04X}: skipping macro opcode 0x{op:02X}"),
+        }
+        pos += len;
+    }
+}
+
+/* Encode macro events into a complete macro sector: bytecode, END marker,
+ * 0xFF padding and the sealed CRC trailer.  Modifier usages (0xE0–0xE7) go
+ * into the key instruction's modifier mask, every other usage into its key
+ * byte; out-of-range values are clamped to the instruction's field width.
+ * Macros are written into a single sector, so no jumps are emitted; one
+ * that does not fit is rejected. */
+fn encode_macro(entries: &[MacroEvent], sector_size: u16) -> Result<Vec<u8>, HidppDriverError> {
+    let mut code = Vec::new();
+    for &event in entries {
+        match event {
+            MacroEvent::KeyPress(value) | MacroEvent::KeyRelease(value) => {
+                let op = if matches!(event, MacroEvent::KeyPress(_)) {
+                    MACRO_OP_KEY_PRESS
+                } else {
+                    MACRO_OP_KEY_RELEASE
+                };
+                let (modifier, key) = match value {
+                    HID_USAGE_MODIFIER_BASE..=0xE7 => (1u8 << (value - HID_USAGE_MODIFIER_BASE), 0),
+                    0x01..=0xFF => (0, value as u8),
+                    _ => {
+                        warn!(
+                            "HID++ 2.0: skipping macro key 0x{value:X} outside the HID key range"
+                        );
+                        continue;
+                    }
+                };
+                code.extend_from_slice(&[op, modifier, key]);
+            }
+            MacroEvent::Wait(ms) => {
+                let ms = ms.min(u32::from(u16::MAX)) as u16;
+                code.push(MACRO_OP_DELAY);
+                code.extend_from_slice(&ms.to_be_bytes());
+            }
+            MacroEvent::ButtonPress(button) | MacroEvent::ButtonRelease(button) => {
+                if !(1..=16).contains(&button) {
+                    warn!("HID++ 2.0: skipping macro mouse button {button} outside 1-16");
+                    continue;
+                }
+                let op = if matches!(event, MacroEvent::ButtonPress(_)) {
+                    MACRO_OP_BUTTON_PRESS
+                } else {
+                    MACRO_OP_BUTTON_RELEASE
+                };
+                code.push(op);
+                code.extend_from_slice(&(1u16 << (button - 1)).to_be_bytes());
+            }
+            MacroEvent::Consumer(usage) => {
+                let usage = usage.min(u32::from(u16::MAX)) as u16;
+                code.push(MACRO_OP_CONSUMER);
+                code.extend_from_slice(&usage.to_be_bytes());
+            }
+            MacroEvent::Wheel(ticks) => {
+                let ticks = ticks.clamp(i32::from(i8::MIN), i32::from(i8::MAX)) as i8;
+                code.extend_from_slice(&[MACRO_OP_WHEEL, ticks as u8, 0]);
+            }
+            MacroEvent::PointerMove { x, y } => {
+                let clamp = |v: i32| v.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16;
+                code.push(MACRO_OP_POINTER_MOVE);
+                code.extend_from_slice(&clamp(x).to_be_bytes());
+                code.extend_from_slice(&clamp(y).to_be_bytes());
+            }
+            MacroEvent::WaitForRelease => code.push(MACRO_OP_WAIT_FOR_RELEASE),
+            MacroEvent::RepeatWhilePressed => code.push(MACRO_OP_REPEAT_UNTIL_RELEASE),
+        }
+    }
+    code.push(MACRO_OP_END);
+
+    let available = (sector_size as usize).saturating_sub(2);
+    if code.len() > available {
+        return Err(HidppDriverError::MacroTooLong {
+            needed: code.len(),
+            available,
+        });
+    }
+
+    let mut data = vec![0xFFu8; sector_size as usize];
+    data[..code.len()].copy_from_slice(&code);
+    Hidpp20Driver::seal_sector_crc(&mut data);
+    Ok(data)
+}
+
+/* ---------------------------------------------------------------------- */
+/* Lighting zones (0x8070 / 0x8071)                                      */
+/* ---------------------------------------------------------------------- */
+
+/* Map a lighting effect ID to the ratbag LED mode it implements.  0x8071
+ * reuses the 0x8070 effect numbering, so the hardware mode bytes apply. */
+fn led_mode_from_effect_id(id: u16) -> Option<LedMode> {
+    match u8::try_from(id).ok()? {
+        LED_HW_MODE_OFF => Some(LedMode::Off),
+        LED_HW_MODE_FIXED => Some(LedMode::Solid),
+        LED_HW_MODE_CYCLE => Some(LedMode::Cycle),
+        LED_HW_MODE_COLOR_WAVE => Some(LedMode::ColorWave),
+        LED_HW_MODE_STARLIGHT => Some(LedMode::Starlight),
+        LED_HW_MODE_BREATHING => Some(LedMode::Breathing),
+        _ => None,
+    }
+}
+
+/* One lighting zone (a 0x8070 zone or 0x8071 cluster) and the effects it
+ * advertises.  Each zone is exposed as its own LED, with the LED index
+ * equal to the zone's position in the enumeration. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct LedZone {
+    cluster: u8,
+    /* (effect index, effect ID) in enumeration order.  setRgbClusterEffect
+     * addresses effects by their per-cluster index, not by ID. */
+    effects: Vec<(u8, u16)>,
+}
+
+impl LedZone {
+    /* LED modes this zone can show, in effect enumeration order.  Effects
+     * ratbag has no mode for (ripple, boot/demo animations) are left out. */
+    fn modes(&self) -> Vec<LedMode> {
+        let mut modes = Vec::new();
+        for mode in self
+            .effects
+            .iter()
+            .filter_map(|&(_, id)| led_mode_from_effect_id(id))
+        {
+            if !modes.contains(&mode) {
+                modes.push(mode);
+            }
+        }
+        modes
+    }
+
+    /* Per-cluster index of the effect implementing `mode`. */
+    fn effect_index(&self, mode: LedMode) -> Option<u8> {
+        self.effects
+            .iter()
+            .find(|&&(_, id)| led_mode_from_effect_id(id) == Some(mode))
+            .map(|&(index, _)| index)
+    }
+}
+
 /* ---------------------------------------------------------------------- */
 /* HID++ 2.0 special-action translation tables                            */
 /*                                                                        */
@@ -588,6 +933,7 @@ pub struct Hidpp20OnboardProfilesInfo {
     pub profile_count: u8,
     pub profile_count_oob: u8,
     pub button_count: u8,
+    pub sector_count: u8,
     pub sector_size: [u8; 2], /* Big Endian u16 */
 }
 
@@ -600,7 +946,7 @@ impl Hidpp20OnboardProfilesInfo {
          *   [3] profile_count
          *   [4] profile_count_oob
          *   [5] button_count
-         *   [6] sector_count      – unused
+         *   [6] sector_count
          *   [7..9] sector_size    (BE u16)
          *   [9] mechanical_layout – unused
          *   [10..16] reserved     – unused
@@ -608,18 +954,28 @@ impl Hidpp20OnboardProfilesInfo {
         let profile_count = buf[3];
         let profile_count_oob = buf[4];
         let button_count = buf[5];
+        let sector_count = buf[6];
         let mut sector_size = [0u8; 2];
         sector_size.copy_from_slice(&buf[7..9]);
         Self {
             profile_count,
             profile_count_oob,
             button_count,
+            sector_count,
             sector_size,
         }
     }
     pub fn sector_size(&self) -> u16 {
         u16::from_be_bytes(self.sector_size)
     }
+
+    /* User sectors available for macros: everything after the directory
+     * (0x0000) and the profile sectors (1..=profile_count), up to the
+     * descriptor's sector count. */
+    pub fn macro_sectors(&self) -> std::ops::Range<u16> {
+        let first = u16::from(self.profile_count) + 1;
+        first..u16::from(self.sector_count).max(first)
+    }
 }
 
 pub struct Hidpp20Driver {
@@ -632,6 +988,14 @@ pub struct Hidpp20Driver {
     /* Set when any onboard-profile sector CRC check fails; triggers a full
      * rewrite/rebuild attempt on the next commit. */
     needs_eeprom_repair: bool,
+    /* User sectors occupied by onboard macros, keyed by (profile, button).
+     * Filled from the macro bindings at load time and updated on commit, so
+     * new macros are placed in free sectors and the sectors of buttons that
+     * stopped being macros are released for reuse. */
+    macro_sectors: BTreeMap<(u32, u32), Vec<u16>>,
+    /* Lighting zones enumerated from 0x8071 at load time; empty when the
+     * device lacks the feature, in which case LEDs go through 0x8070. */
+    rgb_zones: Vec<LedZone>,
 }
 
 impl Hidpp20Driver {
@@ -642,6 +1006,8 @@ impl Hidpp20Driver {
             cached_onboard_info: None,
             cached_report_rate_hz: 0,
             needs_eeprom_repair: false,
+            macro_sectors: BTreeMap::new(),
+            rgb_zones: Vec::new(),
         }
     }
 
@@ -986,6 +1352,46 @@ impl Hidpp20Driver {
         Ok(())
     }
 
+    /* Store the CRC-CCITT of everything but the last two bytes into the
+     * sector trailer (big-endian), the inverse of `verify_sector_crc`. */
+    fn seal_sector_crc(data: &mut [u8]) {
+        if data.len() < 2 {
+            return;
+        }
+        let crc_offset = data.len() - 2;
+        let crc = hidpp::compute_ccitt_crc(&data[..crc_offset]);
+        data[crc_offset..].copy_from_slice(&crc.to_be_bytes());
+    }
+
+    /* Build the profile directory sector (0x0000).
+     * Format: 4 bytes per profile [0x00, i+1, enabled, 0x00],
+     * followed by [0xFF, 0xFF, 0x00, 0x00], rest padded 0xFF,
+     * then CRC-CCITT in the last two bytes. */
+    fn build_profile_directory(profiles: &[ProfileInfo], sector_size: u16) -> Vec<u8> {
+        let mut dir = vec![0xFFu8; sector_size as usize];
+        let mut pos = 0usize;
+        for profile in profiles {
+            if pos + 4 > dir.len().saturating_sub(2) {
+                break;
+            }
+            dir[pos] = 0x00;
+            dir[pos + 1] = (profile.index + 1) as u8;
+            dir[pos + 2] = u8::from(profile.is_enabled);
+            dir[pos + 3] = 0x00;
+            pos += 4;
+        }
+        /* End-of-directory marker */
+        if pos + 4 <= dir.len().saturating_sub(2) {
+            dir[pos] = 0xFF;
+            dir[pos + 1] = 0xFF;
+            dir[pos + 2] = 0x00;
+            dir[pos + 3] = 0x00;
+        }
+        /* CRC over the whole sector minus the last 2 bytes */
+        Self::seal_sector_crc(&mut dir);
+        dir
+    }
+
     async fn read_sector(
         &self,
         io: &mut DeviceIo,
@@ -1102,6 +1508,110 @@ impl Hidpp20Driver {
         Ok(())
     }
 
+    /* Read one macro starting at `page`/`offset`, following jumps across
+     * sectors.  `base` selects the user or ROM sector space the binding was
+     * read from.  Returns the decoded entries together with every sector the
+     * macro spans, so the commit path knows which sectors are occupied. */
+    async fn read_macro(
+        &self,
+        io: &mut DeviceIo,
+        idx: u8,
+        base: u16,
+        page: u8,
+        offset: u8,
+        sector_size: u16,
+    ) -> Result<(Vec<MacroEvent>, Vec<u16>), HidppDriverError> {
+        let mut entries = Vec::new();
+        let mut sectors = Vec::new();
+        let mut data = Vec::new();
+        let mut loaded: Option<u16> = None;
+        let (mut page, mut offset) = (page, offset);
+
+        for _ in 0..=MACRO_MAX_JUMPS {
+            let sector = base | u16::from(page);
+            if loaded != Some(sector) {
+                data = self.read_sector(io, idx, sector, 0, sector_size).await?;
+                Self::verify_sector_crc(sector, &data)?;
+                loaded = Some(sector);
+                if !sectors.contains(&sector) {
+                    sectors.push(sector);
+                }
+            }
+
+            let body = &data[..data.len() - 2];
+            match decode_macro(sector, body, offset as usize, &mut entries)? {
+                MacroTail::End => return Ok((entries, sectors)),
+                MacroTail::Jump { page: p, offset: o } => {
+                    page = p;
+                    offset = o;
+                }
+            }
+        }
+
+        Err(HidppDriverError::MalformedMacro {
+            sector: base | u16::from(page),
+            offset: offset as usize,
+        })
+    }
+
+    /* Pick a user sector for a macro: `preferred` (the button's previous
+     * sector) when no other macro occupies it, otherwise the lowest free
+     * sector in the descriptor's macro range. */
+    fn allocate_macro_sector(
+        &self,
+        desc: &Hidpp20OnboardProfilesInfo,
+        preferred: Option<u16>,
+    ) -> Option<u16> {
+        let in_use: BTreeSet<u16> = self.macro_sectors.values().flatten().copied().collect();
+        let mut range = desc.macro_sectors();
+        preferred
+            .filter(|s| range.contains(s) && !in_use.contains(s))
+            .or_else(|| range.find(|s| !in_use.contains(s)))
+    }
+
+    /* Write a button's macro into a user sector and record the allocation.
+     * The button's previous sectors are released first, so a rewritten
+     * macro reuses its own sector and any jump-chained tail is freed.
+     * Returns the page to store in the button binding. */
+    async fn store_macro(
+        &mut self,
+        io: &mut DeviceIo,
+        idx: u8,
+        desc: &Hidpp20OnboardProfilesInfo,
+        profile: u32,
+        button: &crate::engine::device::ButtonInfo,
+    ) -> Result<u8, HidppDriverError> {
+        let key = (profile, button.index);
+        let data = encode_macro(&button.macro_entries, desc.sector_size())?;
+
+        let previous = self.macro_sectors.remove(&key);
+        let preferred = previous.as_ref().and_then(|s| s.first().copied());
+        let Some(sector) = self.allocate_macro_sector(desc, preferred) else {
+            if let Some(prev) = previous {
+                self.macro_sectors.insert(key, prev);
+            }
+            return Err(HidppDriverError::MacroSpaceExhausted {
+                profile,
+                button: button.index,
+            });
+        };
+
+        if let Err(e) = self.write_sector(io, idx, sector, 0, &data).await {
+            if let Some(prev) = previous {
+                self.macro_sectors.insert(key, prev);
+            }
+            return Err(e);
+        }
+
+        debug!(
+            "HID++ 2.0: profile {profile} button {}: macro → sector 0x{sector:04X}",
+            button.index
+        );
+        self.macro_sectors.insert(key, vec![sector]);
+        /* The macro range ends at the u8 sector_count, so this never truncates. */
+        Ok(sector as u8)
+    }
+
     /* Read DPI sensor information using feature 0x2201. */
     async fn read_dpi_info(
         &self,
@@ -1200,43 +1710,217 @@ impl Hidpp20Driver {
         Ok(())
     }
 
-    /* Write LED zone effect to the device using feature 0x8070. */
-    /* TriColor mode is routed through feature 0x8071 (RGB Effects) instead. */
-    async fn write_led_info(
+    /* Enumerate the lighting zones of 0x8070 or 0x8071 (`page`) and the
+     * effects each one advertises.
+     *
+     * 0x8071 multiplexes everything through getInfo: (0xFF, 0xFF) returns
+     * the cluster count in byte 2, (cluster, 0xFF) the location (bytes 2-3,
+     * BE) and effect count (byte 4).  0x8070 has dedicated functions whose
+     * records lack the 0xFF selector byte: getInfo's zone count is byte 0,
+     * getZoneInfo's location bytes 1-2 and effect count byte 3.  Both
+     * return the effect ID in bytes 2-3 (BE) of the per-effect record. */
+    async fn enumerate_led_zones(
         &self,
         io: &mut DeviceIo,
-        profile: &ProfileInfo,
-    ) -> Result<(), HidppDriverError> {
-        for led in &profile.leds {
-            let zone_index = led.index as u8;
+        page: u16,
+    ) -> Result<Vec<LedZone>, HidppDriverError> {
+        let idx = self.features.require(page)?;
+        let rgb = page == PAGE_RGB_EFFECTS;
+
+        let (zone_count, zone_fn, effect_fn) = if rgb {
+            let device = self
+                .feature_request(io, idx, RGB_FN_GET_INFO, &[RGB_INFO_ALL, RGB_INFO_ALL])
+                .await?;
+            (device[2], RGB_FN_GET_INFO, RGB_FN_GET_INFO)
+        } else {
+            let device = self.feature_request(io, idx, LED_FN_GET_INFO, &[]).await?;
+            (device[0], LED_FN_GET_ZONE_INFO, LED_FN_GET_ZONE_EFFECT_INFO)
+        };
+        let location_offset = if rgb { 2 } else { 1 };
 
-            if led.mode == LedMode::TriColor {
-                /* TriColor uses 0x8071 RGB Effects with the multi-LED cluster pattern command. */
-                let idx = self.features.require(PAGE_RGB_EFFECTS)?;
-                let led_payload = hidpp::build_led_payload(led);
-                let mut bytes = [0u8; 16];
-                bytes[0] = zone_index;
-                bytes[1..12].copy_from_slice(&led_payload);
-                bytes[12] = 0x01; /* persist */
-                /* Function 0x02 = setMultiLEDRGBClusterPattern on 0x8071. Note: C passes 13 bytes */
-                self.feature_request(io, idx, 0x02, &bytes[0..13]).await?;
-            } else {
-                let idx = self.features.require(PAGE_COLOR_LED_EFFECTS)?;
-                let led_payload = hidpp::build_led_payload(led);
-                let mut bytes = [0u8; 16];
-                bytes[0] = zone_index;
-                bytes[1..12].copy_from_slice(&led_payload);
-                bytes[12] = 0x01; /* persist */
-                self.feature_request(io, idx, LED_FN_SET_ZONE_EFFECT, &bytes[0..13])
+        let zone_count = zone_count.min(RGB_MAX_CLUSTERS);
+        let mut zones = Vec::with_capacity(zone_count as usize);
+        for cluster in 0..zone_count {
+            let info = self
+                .feature_request(io, idx, zone_fn, &[cluster, RGB_INFO_ALL])
+                .await?;
+            let location =
+                u16::from_be_bytes([info[location_offset], info[location_offset + 1]]);
+            let effect_count = info[location_offset + 2].min(RGB_MAX_EFFECTS);
+
+            let mut effects = Vec::with_capacity(effect_count as usize);
+            for effect in 0..effect_count {
+                let info = self
+                    .feature_request(io, idx, effect_fn, &[cluster, effect])
                     .await?;
+                effects.push((effect, u16::from_be_bytes([info[2], info[3]])));
+            }
+
+            debug!(
+                "HID++ 2.0: 0x{page:04X} zone {cluster} (location 0x{location:04X}) \
+                 effects {effects:04X?}"
+            );
+            zones.push(LedZone { cluster, effects });
+        }
+
+        Ok(zones)
+    }
+
+    /* Make a profile's LEDs match the enumerated lighting zones: one LED per
+     * zone carrying the modes that zone advertises.  LEDs read from the
+     * profile keep their state; missing ones are added switched off, and
+     * LEDs without a zone are dropped. */
+    fn apply_led_zones(profile: &mut ProfileInfo, zones: &[LedZone]) {
+        profile.leds.retain(|l| (l.index as usize) < zones.len());
+        for (i, zone) in zones.iter().enumerate() {
+            let modes = zone.modes();
+            match profile.leds.iter_mut().find(|l| l.index as usize == i) {
+                Some(led) => led.modes = modes,
+                None => profile.leds.push(crate::engine::device::LedInfo {
+                    index: i as u32,
+                    mode: LedMode::Off,
+                    modes,
+                    color: Color::default(),
+                    secondary_color: Color::default(),
+                    tertiary_color: Color::default(),
+                    color_depth: 1,
+                    effect_duration: 0,
+                    brightness: 255,
+                    effects: Vec::new(),
+                    effect: None,
+                }),
             }
+        }
+        profile.leds.sort_by_key(|l| l.index);
+    }
+
+    /* Send one setRgbClusterEffect: the 0x8070 payload minus its mode
+     * byte carries the effect parameters, addressed by the zone's own
+     * index for the effect implementing `led.mode`. */
+    async fn write_rgb_zone(
+        &self,
+        io: &mut DeviceIo,
+        led: &crate::engine::device::LedInfo,
+        persist: u8,
+    ) -> Result<(), HidppDriverError> {
+        let idx = self.features.require(PAGE_RGB_EFFECTS)?;
+        let unsupported = || HidppDriverError::UnsupportedLedMode {
+            led: led.index,
+            mode: led.mode,
+        };
+        let zone = self
+            .rgb_zones
+            .get(led.index as usize)
+            .ok_or_else(unsupported)?;
+        let effect = zone.effect_index(led.mode).ok_or_else(unsupported)?;
+
+        let led_payload = hidpp::build_led_payload(led);
+        let mut bytes = [0u8; 16];
+        bytes[0] = zone.cluster;
+        bytes[1] = effect;
+        bytes[2..12].copy_from_slice(&led_payload[1..]);
+        bytes[12] = persist;
+        self.feature_request(io, idx, RGB_FN_SET_CLUSTER_EFFECT, &bytes[0..13])
+            .await?;
+        Ok(())
+    }
 
+    /* Write every LED zone effect of a profile, persisting it. */
+    async fn write_led_info(
+        &self,
+        io: &mut DeviceIo,
+        profile: &ProfileInfo,
+    ) -> Result<(), HidppDriverError> {
+        for led in &profile.leds {
+            self.write_led(io, led, RGB_PERSIST).await?;
             debug!(
-                "HID++ 2.0: committed LED zone {zone_index} mode={:?}",
-                led.mode
+                "HID++ 2.0: committed LED zone {} mode={:?}",
+                led.index, led.mode
             );
         }
+        Ok(())
+    }
+
+    /* Write one LED zone effect using feature 0x8070, or 0x8071 when its
+     * zones were enumerated.  TriColor mode is always routed through the
+     * 0x8071 multi-LED pattern command.  `persist` is RGB_PERSIST or
+     * RGB_VOLATILE; both features share the flag byte. */
+    async fn write_led(
+        &self,
+        io: &mut DeviceIo,
+        led: &crate::engine::device::LedInfo,
+        persist: u8,
+    ) -> Result<(), HidppDriverError> {
+        let zone_index = led.index as u8;
+
+        if led.mode == LedMode::TriColor {
+            /* TriColor uses 0x8071 RGB Effects with the multi-LED cluster pattern command. */
+            let idx = self.features.require(PAGE_RGB_EFFECTS)?;
+            let led_payload = hidpp::build_led_payload(led);
+            let mut bytes = [0u8; 16];
+            bytes[0] = zone_index;
+            bytes[1..12].copy_from_slice(&led_payload);
+            bytes[12] = persist;
+            /* Note: C passes 13 bytes */
+            self.feature_request(io, idx, RGB_FN_SET_MULTI_LED_PATTERN, &bytes[0..13])
+                .await?;
+        } else if !self.rgb_zones.is_empty() {
+            self.write_rgb_zone(io, led, persist).await?;
+        } else {
+            let idx = self.features.require(PAGE_COLOR_LED_EFFECTS)?;
+            let led_payload = hidpp::build_led_payload(led);
+            let mut bytes = [0u8; 16];
+            bytes[0] = zone_index;
+            bytes[1..12].copy_from_slice(&led_payload);
+            bytes[12] = persist;
+            self.feature_request(io, idx, LED_FN_SET_ZONE_EFFECT, &bytes[0..13])
+                .await?;
+        }
+        Ok(())
+    }
+
+    /* Make `active` the onboard profile the firmware runs, along with its
+     * active resolution.  The C driver calls
+     * hidpp20_onboard_profiles_set_current_profile() (function 0x03 with
+     * parameters[1] = 1-based sector) and
+     * hidpp20_onboard_profiles_set_current_dpi_index() (function 0x0C),
+     * both as short reports; some firmware silently drops long reports
+     * here.  A failed DPI index is only logged. */
+    async fn set_current_profile(
+        &self,
+        io: &mut DeviceIo,
+        idx: u8,
+        active: &ProfileInfo,
+    ) -> Result<(), HidppDriverError> {
+        let sector = (active.index + 1) as u8; /* 0-based → 1-based */
+        self.short_feature_request_with_params(
+            io,
+            idx,
+            PROFILES_FN_SET_CURRENT_PROFILE,
+            &[0x00, sector],
+        )
+        .await?;
+        debug!(
+            "HID++ 2.0: set current profile = {} (sector {sector})",
+            active.index
+        );
 
+        if let Some(res) = active.resolutions.iter().find(|r| r.is_active) {
+            let dpi_idx = res.index as u8;
+            if let Err(e) = self
+                .short_feature_request_with_params(
+                    io,
+                    idx,
+                    PROFILES_FN_SET_CURRENT_DPI_INDEX,
+                    &[dpi_idx],
+                )
+                .await
+            {
+                warn!("HID++ 2.0: failed to set DPI index to {dpi_idx}: {e}");
+            } else {
+                debug!("HID++ 2.0: set current DPI index = {dpi_idx}");
+            }
+        }
         Ok(())
     }
 
@@ -1364,13 +2048,15 @@ impl Hidpp20Driver {
         let mut led = crate::engine::device::LedInfo {
             index: led_index as u32,
             mode: LedMode::Off,
-            modes: Vec::new(),
+            modes: EEPROM_LED_MODES.to_vec(),
             color: Color::default(),
             secondary_color: Color::default(),
             tertiary_color: Color::default(),
             color_depth: 0,
             effect_duration: 0,
             brightness: 0,
+            effects: Vec::new(),
+            effect: None,
         };
 
         if led_bytes.len() < 11 {
@@ -1497,6 +2183,97 @@ impl Hidpp20Driver {
 
         buf
     }
+
+    /* Apply a decoded EEPROM profile sector to a `ProfileInfo`: report
+     * rate, DPI slots, button bindings and LEDs.  Shared by the load path
+     * and the ROM reset path so both decode sectors identically. */
+    fn apply_eeprom_profile(p: &mut ProfileInfo, eeprom: &EepromProfile) {
+        let i = p.index;
+
+        /* --- Report rate (byte 0): stored as ms-interval, convert to Hz --- */
+        if eeprom.report_interval > 0 {
+            p.report_rate = 1000 / (eeprom.report_interval as u32);
+            debug!(
+                "HID++ 2.0: profile {i} EEPROM report rate = {} Hz (interval {}ms)",
+                p.report_rate, eeprom.report_interval
+            );
+        }
+
+        /* --- DPI slots --- */
+        /* A raw value of 0 or 0xFFFF means the resolution slot is     */
+        /* disabled, but the slot must still appear on DBus with       */
+        /* IsDisabled = true (Piper shows all slots so users can       */
+        /* re-enable them), matching the C daemon's behaviour.         */
+        let default_dpi_idx = eeprom.default_dpi_index as usize;
+        let switched_dpi_idx = eeprom.switched_dpi_index as usize;
+        if !eeprom.dpis.is_empty() {
+            debug!(
+                "HID++ 2.0: profile {i} EEPROM DPIs: {:?} (default idx {}, switched idx {})",
+                eeprom.dpis, default_dpi_idx, switched_dpi_idx
+            );
+
+            /* Rebuild the resolutions list to match the EEPROM entries. */
+            p.resolutions.clear();
+            for (r_idx, &raw) in eeprom.dpis.iter().enumerate() {
+                let disabled = raw == 0 || raw == 0xFFFF;
+                p.resolutions.push(crate::engine::device::ResolutionInfo {
+                    index: r_idx as u32,
+                    dpi: crate::engine::device::Dpi::Unified(if disabled {
+                        0
+                    } else {
+                        u32::from(raw)
+                    }),
+                    dpi_list: Vec::new(), /* filled later by read_dpi_info */
+                    capabilities: vec![RATBAG_RESOLUTION_CAP_SHIFT],
+                    is_active: !disabled && r_idx == default_dpi_idx,
+                    is_default: !disabled && r_idx == default_dpi_idx,
+                    is_disabled: disabled,
+                    is_shift: r_idx == switched_dpi_idx,
+                });
+            }
+        }
+
+        /* --- Buttons --- */
+        for (b_idx, binding) in eeprom.buttons.iter().enumerate() {
+            p.buttons[b_idx].action_type = binding.to_action();
+
+            /* EEPROM mouse buttons are stored as a big-endian bit mask
+             * (matching the C hidpp20_buttons_to_cpu / buttons_from_cpu).
+             * ffs(mask) gives the 1-based button ordinal. */
+            let raw_id = u16::from_be_bytes(binding.control_id_or_macro_id);
+            let mapping_value = match (binding.button_type, binding.subtype) {
+                (BUTTON_TYPE_HID, BUTTON_SUBTYPE_MOUSE) => {
+                    if raw_id > 0 {
+                        u32::from(raw_id.trailing_zeros()) + 1
+                    } else {
+                        0
+                    }
+                }
+                /* Translate the raw HID++ special opcode to the
+                 * canonical special_action constant for DBus. */
+                (BUTTON_TYPE_SPECIAL, _) => hidpp20_raw_to_special(raw_id as u8),
+                _ => u32::from(raw_id),
+            };
+            p.buttons[b_idx].mapping_value = mapping_value;
+
+            debug!(
+                "HID++ 2.0: profile {i} button {b_idx}: \
+                 type=0x{:02X} sub=0x{:02X} raw=[{:02X},{:02X}] \
+                 → action={:?} mapping={mapping_value}",
+                binding.button_type,
+                binding.subtype,
+                binding.control_id_or_macro_id[0],
+                binding.control_id_or_macro_id[1],
+                p.buttons[b_idx].action_type
+            );
+        }
+
+        /* --- LEDs --- */
+        p.leds.clear();
+        for (led_idx, led_bytes) in eeprom.leds.iter().enumerate() {
+            p.leds.push(Self::parse_eeprom_led(led_bytes, led_idx));
+        }
+    }
 }
 
 #[async_trait]
@@ -1567,6 +2344,7 @@ impl super::DeviceDriver for Hidpp20Driver {
 
             let desc = Hidpp20OnboardProfilesInfo::from_bytes(&desc_data);
             self.cached_onboard_info = Some(desc);
+            self.macro_sectors.clear();
 
             /* Use profile_count directly from the descriptor, matching the
              * C driver at hidpp20.c:2289.  The profile_count_oob field is the
@@ -1833,87 +2611,38 @@ impl super::DeviceDriver for Hidpp20Driver {
 
                 let p = &mut info.profiles[i];
                 p.is_enabled = enabled;
+                Self::apply_eeprom_profile(p, &eeprom);
 
-                /* --- Report rate (byte 0): stored as ms-interval, convert to Hz --- */
-                if eeprom.report_interval > 0 {
-                    p.report_rate = 1000 / (eeprom.report_interval as u32);
-                    debug!(
-                        "HID++ 2.0: profile {i} EEPROM report rate = {} Hz (interval {}ms)",
-                        p.report_rate, eeprom.report_interval
-                    );
-                }
-
-                /* --- DPI slots --- */
-                /* A raw value of 0 or 0xFFFF means the resolution slot is     */
-                /* disabled, but the slot must still appear on DBus with       */
-                /* IsDisabled = true (Piper shows all slots so users can       */
-                /* re-enable them), matching the C daemon's behaviour.         */
-                let default_dpi_idx = eeprom.default_dpi_index as usize;
-                if !eeprom.dpis.is_empty() {
-                    debug!(
-                        "HID++ 2.0: profile {i} EEPROM DPIs: {:?} (default idx {})",
-                        eeprom.dpis, default_dpi_idx
-                    );
-
-                    /* Rebuild the resolutions list to match the EEPROM entries. */
-                    p.resolutions.clear();
-                    for (r_idx, &raw) in eeprom.dpis.iter().enumerate() {
-                        let disabled = raw == 0 || raw == 0xFFFF;
-                        p.resolutions.push(crate::engine::device::ResolutionInfo {
-                            index: r_idx as u32,
-                            dpi: crate::engine::device::Dpi::Unified(if disabled {
-                                0
-                            } else {
-                                u32::from(raw)
-                            }),
-                            dpi_list: Vec::new(), /* filled later by read_dpi_info */
-                            capabilities: Vec::new(),
-                            is_active: !disabled && r_idx == default_dpi_idx,
-                            is_default: !disabled && r_idx == default_dpi_idx,
-                            is_disabled: disabled,
-                        });
-                    }
-                }
-
-                /* --- Buttons --- */
+                /* Decode the onboard macros of macro-bound buttons.  Sectors
+                 * of user macros are recorded so commit knows which ones are
+                 * taken; ROM macros live in read-only space. */
+                let base = if use_rom {
+                    ROM_PROFILES_BASE
+                } else {
+                    USER_PROFILES_BASE
+                };
                 for (b_idx, binding) in eeprom.buttons.iter().enumerate() {
-                    p.buttons[b_idx].action_type = binding.to_action();
-
-                    /* EEPROM mouse buttons are stored as a big-endian bit mask
-                     * (matching the C hidpp20_buttons_to_cpu / buttons_from_cpu).
-                     * ffs(mask) gives the 1-based button ordinal. */
-                    let raw_id = u16::from_be_bytes(binding.control_id_or_macro_id);
-                    let mapping_value = match (binding.button_type, binding.subtype) {
-                        (BUTTON_TYPE_HID, BUTTON_SUBTYPE_MOUSE) => {
-                            if raw_id > 0 {
-                                u32::from(raw_id.trailing_zeros()) + 1
-                            } else {
-                                0
+                    if binding.button_type != BUTTON_TYPE_MACRO {
+                        continue;
+                    }
+                    let (page, offset) = binding.macro_address();
+                    match self
+                        .read_macro(io, idx, base, page, offset, sector_size)
+                        .await
+                    {
+                        Ok((entries, sectors)) => {
+                            let p = &mut info.profiles[i];
+                            p.buttons[b_idx].macro_entries = entries;
+                            if !use_rom {
+                                self.macro_sectors
+                                    .insert((p.index, p.buttons[b_idx].index), sectors);
                             }
                         }
-                        /* Translate the raw HID++ special opcode to the
-                         * canonical special_action constant for DBus. */
-                        (BUTTON_TYPE_SPECIAL, _) => hidpp20_raw_to_special(raw_id as u8),
-                        _ => u32::from(raw_id),
-                    };
-                    p.buttons[b_idx].mapping_value = mapping_value;
-
-                    debug!(
-                        "HID++ 2.0: profile {i} button {b_idx}: \
-                         type=0x{:02X} sub=0x{:02X} raw=[{:02X},{:02X}] \
-                         → action={:?} mapping={mapping_value}",
-                        binding.button_type,
-                        binding.subtype,
-                        binding.control_id_or_macro_id[0],
-                        binding.control_id_or_macro_id[1],
-                        p.buttons[b_idx].action_type
-                    );
-                }
-
-                /* --- LEDs --- */
-                p.leds.clear();
-                for (led_idx, led_bytes) in eeprom.leds.iter().enumerate() {
-                    p.leds.push(Self::parse_eeprom_led(led_bytes, led_idx));
+                        Err(e) => warn!(
+                            "HID++ 2.0: profile {i} button {b_idx}: cannot read macro \
+                             at page {page} offset {offset}: {e}"
+                        ),
+                    }
                 }
             }
         } else {
@@ -2067,12 +2796,50 @@ impl super::DeviceDriver for Hidpp20Driver {
             }
         }
 
-        info!("HID++ 2.0: loaded {} profiles", info.profiles.len());
-        Ok(())
-    }
+        /* The DPI list came from the sensor (0x2201 getSensorDpiList), so
+         * its bounds are the sensor's own limits.  Per-axis DPI (0x2202)
+         * is not supported by this driver. */
+        if self.features.adjustable_dpi.is_some()
+            && let Some(sensor) = info
+                .profiles
+                .first()
+                .and_then(|p| p.resolutions.first())
+                .and_then(|r| SensorInfo::from_dpi_list(&r.dpi_list))
+        {
+            info.sensor = Some(sensor);
+        }
 
-    async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
-        /* When onboard profiles (0x8100) are present the firmware reads all
+        /* Each lighting zone becomes its own LED advertising only the
+         * effects the zone implements.  0x8071 zones additionally route
+         * LED writes through setRgbClusterEffect. */
+        let zone_page = if self.features.rgb_effects.is_some() {
+            Some(PAGE_RGB_EFFECTS)
+        } else if self.features.color_led_effects.is_some() {
+            Some(PAGE_COLOR_LED_EFFECTS)
+        } else {
+            None
+        };
+        if let Some(page) = zone_page {
+            match self.enumerate_led_zones(io, page).await {
+                Ok(zones) => {
+                    info!("HID++ 2.0: {} lighting zones (0x{page:04X})", zones.len());
+                    for profile in &mut info.profiles {
+                        Self::apply_led_zones(profile, &zones);
+                    }
+                    if page == PAGE_RGB_EFFECTS {
+                        self.rgb_zones = zones;
+                    }
+                }
+                Err(e) => warn!("HID++ 2.0: failed to enumerate lighting zones: {e}"),
+            }
+        }
+
+        info!("HID++ 2.0: loaded {} profiles", info.profiles.len());
+        Ok(())
+    }
+
+    async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+        /* When onboard profiles (0x8100) are present the firmware reads all
          * per-profile settings (DPI, report rate, LEDs) from the EEPROM
          * sectors.  We must NOT call the live feature set commands
          * (setSensorDPI 0x2201, setReportRate 0x8060, setZoneEffect 0x8070)
@@ -2151,6 +2918,23 @@ impl super::DeviceDriver for Hidpp20Driver {
 
                 let force_repair = self.needs_eeprom_repair;
 
+                /* Release the sectors of buttons that are no longer macros
+                 * before any allocation, so this commit can reuse them. */
+                for profile in info.profiles.iter().filter(|p| p.is_dirty || force_repair) {
+                    for btn in &profile.buttons {
+                        if btn.action_type == crate::engine::device::ActionType::Macro {
+                            continue;
+                        }
+                        if let Some(freed) = self.macro_sectors.remove(&(profile.index, btn.index))
+                        {
+                            debug!(
+                                "HID++ 2.0: profile {} button {}: freed macro sectors {freed:04X?}",
+                                profile.index, btn.index
+                            );
+                        }
+                    }
+                }
+
                 /* Switch to host mode before writing EEPROM. Firmware rejects
                  * memWrite calls while in onboard mode (INVALID_ARGUMENT). */
                 if let Err(e) = self
@@ -2230,6 +3014,12 @@ impl super::DeviceDriver for Hidpp20Driver {
                         eeprom.default_dpi_index = def_idx as u8;
                     }
 
+                    /* Switched-DPI index (byte 2).  Left as read when no
+                     * slot is flagged, e.g. an out-of-range index. */
+                    if let Some(shift_idx) = profile.resolutions.iter().position(|r| r.is_shift) {
+                        eeprom.switched_dpi_index = shift_idx as u8;
+                    }
+
                     /* 3. DPI list. */
                     for (i, res) in profile.resolutions.iter().enumerate().take(EEPROM_DPI_COUNT) {
                         if let Dpi::Unified(val) = res.dpi {
@@ -2239,14 +3029,35 @@ impl super::DeviceDriver for Hidpp20Driver {
                         }
                     }
 
-                    /* 4. Buttons. */
+                    /* 4. Buttons.  A macro binding points at the sector its
+                     * bytecode is written to; a macro without entries (one
+                     * that failed to decode at load) keeps its stored binding. */
                     for btn in &profile.buttons {
                         let b_idx = btn.index as usize;
-                        if b_idx < eeprom.buttons.len() {
+                        if b_idx >= eeprom.buttons.len() {
+                            continue;
+                        }
+                        if btn.action_type != crate::engine::device::ActionType::Macro {
                             eeprom.buttons[b_idx] = Hidpp20ButtonBinding::from_action(
                                 btn.action_type,
                                 btn.mapping_value,
                             );
+                            continue;
+                        }
+                        if btn.macro_entries.is_empty() {
+                            continue;
+                        }
+                        match self.store_macro(io, idx, &desc, profile.index, btn).await {
+                            Ok(page) => {
+                                eeprom.buttons[b_idx] = Hidpp20ButtonBinding::for_macro(page);
+                            }
+                            Err(e) => {
+                                warn!(
+                                    "HID++ 2.0: cannot store macro for profile {} button {}: {e}",
+                                    profile.index, btn.index
+                                );
+                                last_err = Some(e);
+                            }
                         }
                     }
 
@@ -2268,11 +3079,7 @@ impl super::DeviceDriver for Hidpp20Driver {
                     }
 
                     /* 6. Recompute CRC (last 2 bytes, BE) */
-                    let crc_offset = profile_data.len() - 2;
-                    let crc = hidpp::compute_ccitt_crc(&profile_data[..crc_offset]);
-                    let crc_bytes = crc.to_be_bytes();
-                    profile_data[crc_offset] = crc_bytes[0];
-                    profile_data[crc_offset + 1] = crc_bytes[1];
+                    Self::seal_sector_crc(&mut profile_data);
 
                     /* 7. Write sector */
                     match self.write_sector(io, idx, addr, 0, &profile_data).await {
@@ -2294,37 +3101,9 @@ impl super::DeviceDriver for Hidpp20Driver {
                 }
 
                 /* After writing profile sectors, rebuild the directory (sector
-                 * 0x0000) — mirrors C's hidpp20_onboard_profiles_write_dict.
-                 * Format: 4 bytes per profile [0x00, i+1, enabled, 0x00],
-                 * followed by [0xFF, 0xFF, 0x00, 0x00], rest padded 0xFF,
-                 * then CRC-CCITT in the last two bytes. */
+                 * 0x0000) — mirrors C's hidpp20_onboard_profiles_write_dict. */
                 if any_written {
-                    let mut dir = vec![0xFFu8; sector_size as usize];
-                    let mut pos = 0usize;
-                    for profile in &info.profiles {
-                        if pos + 4 > dir.len().saturating_sub(2) {
-                            break;
-                        }
-                        dir[pos] = 0x00;
-                        dir[pos + 1] = (profile.index + 1) as u8;
-                        dir[pos + 2] = u8::from(profile.is_enabled);
-                        dir[pos + 3] = 0x00;
-                        pos += 4;
-                    }
-                    /* End-of-directory marker */
-                    if pos + 4 <= dir.len().saturating_sub(2) {
-                        dir[pos] = 0xFF;
-                        dir[pos + 1] = 0xFF;
-                        dir[pos + 2] = 0x00;
-                        dir[pos + 3] = 0x00;
-                    }
-                    /* CRC over the whole sector minus the last 2 bytes */
-                    let dir_crc_off = dir.len() - 2;
-                    let dir_crc = hidpp::compute_ccitt_crc(&dir[..dir_crc_off]);
-                    let dir_crc_bytes = dir_crc.to_be_bytes();
-                    dir[dir_crc_off] = dir_crc_bytes[0];
-                    dir[dir_crc_off + 1] = dir_crc_bytes[1];
-
+                    let dir = Self::build_profile_directory(&info.profiles, sector_size);
                     if let Err(e) = self.write_sector(io, idx, 0x0000, 0, &dir).await {
                         warn!("HID++ 2.0: failed to write profile directory: {e}");
                         last_err = Some(e);
@@ -2350,60 +3129,241 @@ impl super::DeviceDriver for Hidpp20Driver {
                 /* Successful rewrite clears the repair flag. */
                 self.needs_eeprom_repair = false;
 
-                /* Tell the hardware which profile is now active.  The C driver
-                 * calls hidpp20_onboard_profiles_set_current_profile() which
-                 * uses function 0x03 with parameters[1] = 1-based sector.
-                 * Without this, the device stays on whichever profile the
-                 * firmware last selected and Piper's profile switching has no
-                 * effect on the actual hardware output. */
-                if let Some(active) = info.profiles.iter().find(|p| p.is_active) {
-                    let sector = (active.index + 1) as u8; /* 0-based → 1-based */
-                    /* C driver uses REPORT_ID_SHORT for this command.
-                     * Some firmware silently drops long reports here. */
-                    if let Err(e) = self
-                        .short_feature_request_with_params(
-                            io,
-                            idx,
-                            PROFILES_FN_SET_CURRENT_PROFILE,
-                            &[0x00, sector],
-                        )
-                        .await
-                    {
+                /* Tell the hardware which profile is now active.  Without
+                 * this, the device stays on whichever profile the firmware
+                 * last selected and Piper's profile switching has no effect
+                 * on the actual hardware output. */
+                if let Some(active) = info.profiles.iter().find(|p| p.is_active)
+                    && let Err(e) = self.set_current_profile(io, idx, active).await
+                {
+                    warn!(
+                        "HID++ 2.0: failed to set current profile to {}: {e}",
+                        active.index
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /* Restore a profile from its factory ROM copy.
+     *
+     * The ROM sector matching the profile (ROM_PROFILES_BASE | n, reusing
+     * the first ROM profile past profile_count_oob exactly like the load
+     * path) is copied over the user sector, its CRC re-sealed, and the
+     * directory rewritten with the profile enabled.  `info` is then updated
+     * from the ROM bytes so D-Bus reflects what the device now holds.
+     *
+     * Host-managed devices (no 0x8100, or no writable EEPROM) have no ROM
+     * copy and fall back to the generic default profile. */
+    async fn reset_profile(
+        &mut self,
+        io: &mut DeviceIo,
+        info: &mut DeviceInfo,
+        profile_id: u32,
+    ) -> Result<()> {
+        let (Some(idx), Some(desc)) = (self.features.onboard_profiles, self.cached_onboard_info)
+        else {
+            *info = info.with_profile_defaults(profile_id);
+            return Ok(());
+        };
+        let sector_size = desc.sector_size();
+        if sector_size == 0 {
+            *info = info.with_profile_defaults(profile_id);
+            return Ok(());
+        }
+
+        let button_count = info
+            .find_profile(profile_id)
+            .map(|p| p.buttons.len())
+            .with_context(|| format!("profile {profile_id} not found"))?;
+
+        let num_rom = desc.profile_count_oob as usize;
+        let rom_idx = if num_rom > 0 && (profile_id as usize) < num_rom {
+            profile_id as u16
+        } else {
+            0
+        };
+        let rom_addr = ROM_PROFILES_BASE | (rom_idx + 1);
+        let user_addr = USER_PROFILES_BASE | (profile_id as u16 + 1);
+
+        let mut sector = self
+            .read_sector(io, idx, rom_addr, 0, sector_size)
+            .await
+            .with_context(|| format!("reading ROM profile sector 0x{rom_addr:04X}"))?;
+        /* Decode before touching flash: a ROM sector that cannot hold the
+         * profile layout must not be copied over the user data. */
+        let eeprom = EepromProfile::from_bytes(&sector, button_count)?;
+        Self::seal_sector_crc(&mut sector);
+
+        let mut profiles = info.profiles.clone();
+        if let Some(p) = profiles.iter_mut().find(|p| p.index == profile_id) {
+            p.is_enabled = true;
+        }
+        let dir = Self::build_profile_directory(&profiles, sector_size);
+
+        /* Firmware rejects memWrite while in onboard mode. */
+        if let Err(e) = self
+            .feature_request(io, idx, PROFILES_FN_SET_MODE, &[ONBOARD_MODE_HOST])
+            .await
+        {
+            warn!("Failed to switch to host mode: {e:#}");
+        }
+
+        let mut result = self.write_sector(io, idx, user_addr, 0, &sector).await;
+        if result.is_ok() {
+            result = self.write_sector(io, idx, USER_PROFILES_BASE, 0, &dir).await;
+        }
+
+        if let Err(e) = self
+            .feature_request(io, idx, PROFILES_FN_SET_MODE, &[ONBOARD_MODE_ONBOARD])
+            .await
+        {
+            warn!("Failed to switch back to onboard mode: {e:#}");
+        }
+
+        result.with_context(|| {
+            format!("restoring profile {profile_id} from ROM sector 0x{rom_addr:04X}")
+        })?;
+
+        info!(
+            "HID++ 2.0: profile {profile_id} restored from ROM sector 0x{rom_addr:04X} \
+             → sector 0x{user_addr:04X}"
+        );
+
+        if let Some(p) = info.find_profile_mut(profile_id) {
+            /* The ROM bytes carry no sensor range or LED mode list; keep
+             * the ones discovered at load time. */
+            let dpi_list = p.resolutions.first().map(|r| r.dpi_list.clone());
+            let led_modes: Vec<_> = p.leds.iter().map(|l| (l.index, l.modes.clone())).collect();
+
+            Self::apply_eeprom_profile(p, &eeprom);
+            p.is_enabled = true;
+            p.is_dirty = false;
+            if let Some(list) = dpi_list {
+                for res in &mut p.resolutions {
+                    res.dpi_list = list.clone();
+                }
+            }
+            for led in &mut p.leds {
+                if let Some((_, modes)) = led_modes.iter().find(|(i, _)| *i == led.index) {
+                    led.modes = modes.clone();
+                }
+            }
+        }
+
+        /* The profile's user macros went with the overwritten sector; the
+         * restored bindings reference the ROM's own macros. */
+        self.macro_sectors.retain(|&(p, _), _| p != profile_id);
+        for (b_idx, binding) in eeprom.buttons.iter().enumerate() {
+            let entries = if binding.button_type == BUTTON_TYPE_MACRO {
+                let (page, offset) = binding.macro_address();
+                match self
+                    .read_macro(io, idx, ROM_PROFILES_BASE, page, offset, sector_size)
+                    .await
+                {
+                    Ok((entries, _)) => entries,
+                    Err(e) => {
                         warn!(
-                            "HID++ 2.0: failed to set current profile to {} (sector {sector}): {e}",
-                            active.index
-                        );
-                    } else {
-                        debug!(
-                            "HID++ 2.0: set current profile = {} (sector {sector})",
-                            active.index
+                            "HID++ 2.0: profile {profile_id} button {b_idx}: cannot read \
+                             ROM macro at page {page} offset {offset}: {e}"
                         );
+                        Vec::new()
                     }
+                }
+            } else {
+                Vec::new()
+            };
+            if let Some(button) = info
+                .find_profile_mut(profile_id)
+                .and_then(|p| p.buttons.get_mut(b_idx))
+            {
+                button.macro_entries = entries;
+            }
+        }
 
-                    /* Also set the active DPI index within the profile.
-                     * C: hidpp20_onboard_profiles_set_current_dpi_index()
-                     * uses function 0x0C with parameters[0] = resolution index. */
-                    if let Some(res) = active.resolutions.iter().find(|r| r.is_active) {
-                        let dpi_idx = res.index as u8;
-                        /* C driver uses REPORT_ID_SHORT for this command too. */
-                        if let Err(e) = self
-                            .short_feature_request_with_params(
-                                io,
-                                idx,
-                                PROFILES_FN_SET_CURRENT_DPI_INDEX,
-                                &[dpi_idx],
-                            )
-                            .await
-                        {
-                            warn!("HID++ 2.0: failed to set DPI index to {dpi_idx}: {e}");
-                        } else {
-                            debug!("HID++ 2.0: set current DPI index = {dpi_idx}");
-                        }
-                    }
+        Ok(())
+    }
+
+    /* Show a custom colour on each listed 0x8071 zone using the zone's
+     * static effect without persisting it, so the stored lighting comes
+     * back on the next commit or power cycle.  Every zone is checked
+     * before the first request so a bad index sends nothing. */
+    async fn set_led_frame(&mut self, io: &mut DeviceIo, colors: &[(u32, Color)]) -> Result<()> {
+        if self.rgb_zones.is_empty() {
+            return Err(HidppDriverError::UnsupportedFeature(PAGE_RGB_EFFECTS).into());
+        }
+
+        let frame: Vec<_> = colors
+            .iter()
+            .map(|&(index, color)| crate::engine::device::LedInfo {
+                index,
+                mode: LedMode::Solid,
+                modes: Vec::new(),
+                color,
+                secondary_color: Color::default(),
+                tertiary_color: Color::default(),
+                color_depth: 1,
+                effect_duration: 0,
+                brightness: 255,
+                effects: Vec::new(),
+                effect: None,
+            })
+            .collect();
+        for led in &frame {
+            let supported = self
+                .rgb_zones
+                .get(led.index as usize)
+                .and_then(|z| z.effect_index(LedMode::Solid))
+                .is_some();
+            if !supported {
+                return Err(HidppDriverError::UnsupportedLedMode {
+                    led: led.index,
+                    mode: LedMode::Solid,
                 }
+                .into());
             }
         }
 
+        for led in &frame {
+            self.write_rgb_zone(io, led, RGB_VOLATILE).await?;
+        }
+        debug!("HID++ 2.0: showed custom colour frame on {} zones", frame.len());
+        Ok(())
+    }
+
+    /* Select the active profile with SET_CURRENT_PROFILE.  Only
+     * devices with writable onboard profiles can do this; host-managed
+     * ones need the live feature writes of a commit. */
+    async fn switch_profile(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()> {
+        let (Some(idx), Some(desc)) = (self.features.onboard_profiles, self.cached_onboard_info)
+        else {
+            anyhow::bail!("HID++ 2.0: no onboard profiles to select from");
+        };
+        anyhow::ensure!(
+            desc.sector_size() != 0,
+            "HID++ 2.0: onboard profiles are unavailable (sector_size=0)"
+        );
+        let active = info
+            .profiles
+            .iter()
+            .find(|p| p.is_active)
+            .context("HID++ 2.0: no active profile to switch to")?;
+        self.set_current_profile(io, idx, active).await?;
+        Ok(())
+    }
+
+    /* Show an LED through the live 0x8070/0x8071 zone commands with the
+     * persist flag cleared: no EEPROM sector is written, so the stored
+     * lighting comes back on the next commit or power cycle. */
+    async fn preview_led(
+        &mut self,
+        io: &mut DeviceIo,
+        led: &crate::engine::device::LedInfo,
+    ) -> Result<()> {
+        self.write_led(io, led, RGB_VOLATILE).await?;
+        debug!("HID++ 2.0: previewed LED zone {} mode={:?}", led.index, led.mode);
         Ok(())
     }
 
@@ -2512,6 +3472,7 @@ impl super::DeviceDriver for Hidpp20Driver {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hal::mock::MockExchange;
 
     /* Probe against a scripted fake receiver that answers the version
      * ping with a HID++ 1.0 RESOURCE_ERROR (device asleep).  The probe
@@ -2613,6 +3574,7 @@ mod tests {
         let mut data = vec![0u8; 256];
         data[EEPROM_REPORT_INTERVAL_OFFSET] = 1; /* 1 ms → 1000 Hz */
         data[EEPROM_DEFAULT_DPI_OFFSET] = 2;
+        data[EEPROM_SWITCHED_DPI_OFFSET] = 1;
         /* 5 DPI slots: 400, 800, 1600, disabled (0), disabled (0xFFFF). */
         for (i, raw) in [400u16, 800, 1600, 0, 0xFFFF].into_iter().enumerate() {
             let off = EEPROM_DPI_OFFSET + i * 2;
@@ -2639,11 +3601,33 @@ mod tests {
         data
     }
 
+    #[test]
+    fn sensor_limits_from_dpi_list() {
+        /* 200, step 50, up to 4000: a range. */
+        let range = [0x00, 0xc8, 0xe0, 0x32, 0x0f, 0xa0, 0x00, 0x00];
+        let sensor = SensorInfo::from_dpi_list(&parse_dpi_list(&range)).unwrap();
+        assert_eq!((sensor.dpi_min, sensor.dpi_max, sensor.dpi_step), (200, 4000, 50));
+
+        /* 400, 800, 1600: discrete values, no common step. */
+        let discrete = [0x01, 0x90, 0x03, 0x20, 0x06, 0x40, 0x00, 0x00];
+        let sensor = SensorInfo::from_dpi_list(&parse_dpi_list(&discrete)).unwrap();
+        assert_eq!((sensor.dpi_min, sensor.dpi_max, sensor.dpi_step), (400, 1600, 0));
+
+        /* An unsorted list with repeats gives the same limits. */
+        let sensor = SensorInfo::from_dpi_list(&[1600, 400, 800, 400]).unwrap();
+        assert_eq!((sensor.dpi_min, sensor.dpi_max, sensor.dpi_step), (400, 1600, 0));
+        let sensor = SensorInfo::from_dpi_list(&[300, 200, 100]).unwrap();
+        assert_eq!((sensor.dpi_min, sensor.dpi_max, sensor.dpi_step), (100, 300, 100));
+
+        assert!(SensorInfo::from_dpi_list(&[]).is_none());
+    }
+
     #[test]
     fn eeprom_profile_decodes_each_field() {
         let eeprom = EepromProfile::from_bytes(&sample_sector(), 8).unwrap();
         assert_eq!(eeprom.report_interval, 1);
         assert_eq!(eeprom.default_dpi_index, 2);
+        assert_eq!(eeprom.switched_dpi_index, 1);
         assert_eq!(eeprom.dpis, vec![400, 800, 1600, 0, 0xFFFF]);
         assert_eq!(eeprom.buttons.len(), 8);
         assert_eq!(eeprom.buttons[0], Hidpp20ButtonBinding::from_bytes(&[0x80, 0, 0xAA, 0xBB]));
@@ -2762,4 +3746,593 @@ mod tests {
             }
         ));
     }
+
+    /* ------------------------------------------------------------------ */
+    /* Profile reset from ROM                                             */
+    /* ------------------------------------------------------------------ */
+
+    const TEST_FEATURE_IDX: u8 = 0x0E;
+
+    /* Long 0x8100 response echoing `function` with the given payload. */
+    fn onboard_reply(function: u8, params: &[u8]) -> Vec<u8> {
+        hidpp::build_hidpp20_request(DEVICE_IDX_CORDED, TEST_FEATURE_IDX, function, SW_ID, params)
+            .to_vec()
+    }
+
+    /* Script the memWrite sequence for one full sector. */
+    fn script_sector_write(script: &mut Vec<MockExchange>, sector_size: usize) {
+        script.push(MockExchange::reply(onboard_reply(PROFILES_FN_MEMORY_ADDR_WRITE, &[])));
+        for _ in 0..sector_size.div_ceil(16) {
+            script.push(MockExchange::reply(onboard_reply(PROFILES_FN_MEMORY_WRITE, &[])));
+        }
+        script.push(MockExchange::reply(onboard_reply(PROFILES_FN_MEMORY_WRITE_END, &[])));
+    }
+
+    #[tokio::test]
+    async fn reset_profile_copies_rom_sector_over_user_sector() {
+        let mut rom = sample_sector();
+        /* A stale CRC in the source must be re-sealed on the copy. */
+        rom[254] = 0x00;
+        rom[255] = 0x00;
+        let sector_size = rom.len();
+
+        let mut script = Vec::new();
+        for chunk in rom.chunks(16) {
+            script.push(MockExchange::reply(onboard_reply(PROFILES_FN_MEMORY_READ, chunk)));
+        }
+        script.push(MockExchange::reply(onboard_reply(PROFILES_FN_SET_MODE, &[])));
+        script_sector_write(&mut script, sector_size); /* user profile sector */
+        script_sector_write(&mut script, sector_size); /* directory */
+        script.push(MockExchange::reply(onboard_reply(PROFILES_FN_SET_MODE, &[])));
+        let (mut io, handle) = DeviceIo::with_mock(script);
+
+        let mut driver = Hidpp20Driver::new();
+        driver.device_index = DEVICE_IDX_CORDED;
+        driver.features.insert(PAGE_ONBOARD_PROFILES, TEST_FEATURE_IDX);
+        let mut desc = [0u8; 16];
+        desc[3] = 1; /* profile_count */
+        desc[4] = 1; /* profile_count_oob */
+        desc[5] = 3; /* button_count */
+        desc[7..9].copy_from_slice(&(sector_size as u16).to_be_bytes());
+        driver.cached_onboard_info = Some(Hidpp20OnboardProfilesInfo::from_bytes(&desc));
+
+        let mut info = DeviceInfo {
+            sysname: "hidraw0".into(),
+            name: "Test".into(),
+            model: String::new(),
+            firmware_version: String::new(),
+            device_type: 2,
+            has_receiver: false,
+            power: None,
+            sensor: None,
+            profiles: vec![ProfileInfo {
+                index: 0,
+                is_enabled: false,
+                is_dirty: true,
+                report_rate: 125,
+                buttons: (0..3)
+                    .map(|i| crate::engine::device::ButtonInfo {
+                        index: i,
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+            driver_config: Default::default(),
+        };
+
+        crate::hal::DeviceDriver::reset_profile(&mut driver, &mut io, &mut info, 0)
+            .await
+            .expect("reset must succeed");
+        assert!(handle.script_exhausted());
+
+        /* The first memRead addressed ROM sector 0x0101. */
+        let writes = handle.writes();
+        assert_eq!(&writes[0][4..6], &[0x01, 0x01]);
+
+        /* memWrite start for the user sector 0x0001, then the sector
+         * body: the ROM bytes with a freshly computed CRC trailer. */
+        let start = rom.len() / 16 + 1;
+        assert_eq!(&writes[start][4..6], &[0x00, 0x01]);
+        let written: Vec<u8> = writes[start + 1..start + 1 + sector_size / 16]
+            .iter()
+            .flat_map(|w| w[4..20].to_vec())
+            .collect();
+        assert_eq!(&written[..254], &rom[..254]);
+        Hidpp20Driver::verify_sector_crc(0x0001, &written).unwrap();
+
+        /* The decoded ROM contents replace the stale profile state. */
+        let p = &info.profiles[0];
+        assert!(p.is_enabled);
+        assert!(!p.is_dirty);
+        assert_eq!(p.report_rate, 1000);
+        assert_eq!(p.resolutions.len(), EEPROM_DPI_COUNT);
+        assert!(p.resolutions[2].is_default);
+    }
+
+    #[tokio::test]
+    async fn switch_profile_selects_onboard_profile_without_writing() {
+        let script = vec![
+            MockExchange::reply(onboard_reply(PROFILES_FN_SET_CURRENT_PROFILE, &[])),
+            MockExchange::reply(onboard_reply(PROFILES_FN_SET_CURRENT_DPI_INDEX, &[])),
+        ];
+        let (mut io, handle) = DeviceIo::with_mock(script);
+
+        let mut driver = Hidpp20Driver::new();
+        driver.device_index = DEVICE_IDX_CORDED;
+        driver.features.insert(PAGE_ONBOARD_PROFILES, TEST_FEATURE_IDX);
+        driver.cached_onboard_info = Some(macro_desc());
+
+        let profile = |index, is_active| ProfileInfo {
+            index,
+            is_active,
+            resolutions: vec![crate::engine::device::ResolutionInfo {
+                index: 1,
+                is_active: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let info = DeviceInfo {
+            sysname: "hidraw0".into(),
+            name: "Test".into(),
+            model: String::new(),
+            firmware_version: String::new(),
+            device_type: 2,
+            has_receiver: false,
+            power: None,
+            sensor: None,
+            profiles: vec![profile(0, false), profile(1, true)],
+            driver_config: Default::default(),
+        };
+
+        crate::hal::DeviceDriver::switch_profile(&mut driver, &mut io, &info)
+            .await
+            .unwrap();
+        assert!(handle.script_exhausted());
+        let writes = handle.writes();
+        assert_eq!(writes.len(), 2);
+        /* Short reports: 1-based sector, then the active DPI index. */
+        assert_eq!(writes[0][0], hidpp::REPORT_ID_SHORT);
+        assert_eq!(&writes[0][4..6], &[0x00, 0x02]);
+        assert_eq!(writes[1][4], 1);
+
+        /* Host-managed devices have nothing to select. */
+        let mut driver = Hidpp20Driver::new();
+        let (mut io, _handle) = DeviceIo::with_mock(Vec::new());
+        assert!(
+            crate::hal::DeviceDriver::switch_profile(&mut driver, &mut io, &info)
+                .await
+                .is_err()
+        );
+    }
+
+    /* ------------------------------------------------------------------ */
+    /* Onboard macros                                                     */
+    /* ------------------------------------------------------------------ */
+
+    /* Descriptor with two profiles and eight user sectors (macros: 3..8). */
+    fn macro_desc() -> Hidpp20OnboardProfilesInfo {
+        let mut desc = [0u8; 16];
+        desc[3] = 2; /* profile_count */
+        desc[6] = 8; /* sector_count */
+        desc[7..9].copy_from_slice(&256u16.to_be_bytes());
+        Hidpp20OnboardProfilesInfo::from_bytes(&desc)
+    }
+
+    /* Script the memRead chunks returning one full sector. */
+    fn script_sector_read(script: &mut Vec<MockExchange>, sector: &[u8]) {
+        for chunk in sector.chunks(16) {
+            script.push(MockExchange::reply(onboard_reply(
+                PROFILES_FN_MEMORY_READ,
+                chunk,
+            )));
+        }
+    }
+
+    /* Sector holding `code` at offset 0, 0xFF padding and a valid CRC. */
+    fn macro_sector(code: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFFu8; 256];
+        data[..code.len()].copy_from_slice(code);
+        Hidpp20Driver::seal_sector_crc(&mut data);
+        data
+    }
+
+    #[test]
+    fn macro_decode_maps_keys_modifiers_and_delays() {
+        let body = [
+            MACRO_OP_KEY_PRESS, 0x02, 0x04, /* LeftShift + A down */
+            MACRO_OP_DELAY, 0x01, 0x2C, /* 300 ms */
+            0x01, /* no-op: skipped */
+            MACRO_OP_KEY_RELEASE, 0x02, 0x04, /* A + LeftShift up */
+            MACRO_OP_JUMP, 0x05, 0x10,
+        ];
+        let mut entries = Vec::new();
+        let tail = decode_macro(0x0003, &body, 0, &mut entries).unwrap();
+        assert_eq!(tail, MacroTail::Jump { page: 5, offset: 0x10 });
+        assert_eq!(
+            entries,
+            vec![
+                MacroEvent::KeyPress(0xE1),
+                MacroEvent::KeyPress(0x04),
+                MacroEvent::Wait(300),
+                MacroEvent::KeyRelease(0x04),
+                MacroEvent::KeyRelease(0xE1),
+            ]
+        );
+    }
+
+    #[test]
+    fn macro_decode_maps_mouse_consumer_and_flow_events() {
+        let body = [
+            MACRO_OP_BUTTON_PRESS, 0x00, 0x04, /* button 3 down */
+            MACRO_OP_BUTTON_RELEASE, 0x00, 0x04,
+            MACRO_OP_WHEEL, 0xFE, 0x00, /* two ticks down */
+            MACRO_OP_CONSUMER, 0x00, 0xE9, /* Volume Up */
+            MACRO_OP_POINTER_MOVE, 0x00, 0x0A, 0xFF, 0xF6,
+            MACRO_OP_WAIT_FOR_RELEASE,
+            MACRO_OP_REPEAT_UNTIL_RELEASE,
+            MACRO_OP_END,
+        ];
+        let mut entries = Vec::new();
+        let tail = decode_macro(0x0003, &body, 0, &mut entries).unwrap();
+        assert_eq!(tail, MacroTail::End);
+        assert_eq!(
+            entries,
+            vec![
+                MacroEvent::ButtonPress(3),
+                MacroEvent::ButtonRelease(3),
+                MacroEvent::Wheel(-2),
+                MacroEvent::Consumer(0xE9),
+                MacroEvent::PointerMove { x: 10, y: -10 },
+                MacroEvent::WaitForRelease,
+                MacroEvent::RepeatWhilePressed,
+            ]
+        );
+    }
+
+    #[test]
+    fn macro_decode_rejects_truncated_instruction() {
+        let body = [MACRO_OP_KEY_PRESS, 0x00, 0x04, MACRO_OP_DELAY, 0x00];
+        let err = decode_macro(0x0003, &body, 0, &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err,
+            HidppDriverError::MalformedMacro {
+                sector: 0x0003,
+                offset: 3
+            }
+        ));
+    }
+
+    #[test]
+    fn macro_encode_round_trips() {
+        let entries = vec![
+            MacroEvent::KeyPress(0xE0),
+            MacroEvent::KeyPress(0x06),
+            MacroEvent::Wait(50),
+            MacroEvent::ButtonPress(1),
+            MacroEvent::Wheel(3),
+            MacroEvent::ButtonRelease(1),
+            MacroEvent::Consumer(0xCD),
+            MacroEvent::KeyRelease(0x06),
+            MacroEvent::KeyRelease(0xE0),
+            MacroEvent::WaitForRelease,
+        ];
+        let data = encode_macro(&entries, 256).unwrap();
+        assert_eq!(data.len(), 256);
+        Hidpp20Driver::verify_sector_crc(0x0003, &data).unwrap();
+
+        let mut decoded = Vec::new();
+        let tail = decode_macro(0x0003, &data[..254], 0, &mut decoded).unwrap();
+        assert_eq!(tail, MacroTail::End);
+        assert_eq!(decoded, entries);
+    }
+
+    #[test]
+    fn macro_encode_rejects_oversized_macro() {
+        /* 3 bytes per event + END cannot fit 254 bytes with 85 events. */
+        let entries = vec![MacroEvent::KeyPress(0x04); 85];
+        let err = encode_macro(&entries, 256).unwrap_err();
+        assert!(matches!(
+            err,
+            HidppDriverError::MacroTooLong {
+                needed: 256,
+                available: 254
+            }
+        ));
+    }
+
+    #[test]
+    fn macro_allocation_skips_occupied_sectors() {
+        let desc = macro_desc();
+        assert_eq!(desc.macro_sectors(), 3..8);
+
+        let mut driver = Hidpp20Driver::new();
+        driver.macro_sectors.insert((0, 0), vec![3]);
+        driver.macro_sectors.insert((1, 2), vec![4, 5]);
+
+        assert_eq!(driver.allocate_macro_sector(&desc, None), Some(6));
+        assert_eq!(driver.allocate_macro_sector(&desc, Some(4)), Some(6));
+        assert_eq!(driver.allocate_macro_sector(&desc, Some(7)), Some(7));
+
+        driver.macro_sectors.insert((1, 3), vec![6, 7]);
+        assert_eq!(driver.allocate_macro_sector(&desc, None), None);
+    }
+
+    #[tokio::test]
+    async fn read_macro_follows_jump_across_sectors() {
+        let mut script = Vec::new();
+        script_sector_read(
+            &mut script,
+            &macro_sector(&[MACRO_OP_KEY_PRESS, 0x00, 0x04, MACRO_OP_JUMP, 0x04, 0x03]),
+        );
+        script_sector_read(
+            &mut script,
+            &macro_sector(&[0xFF, 0xFF, 0xFF, MACRO_OP_KEY_RELEASE, 0x00, 0x04, MACRO_OP_END]),
+        );
+        let (mut io, handle) = DeviceIo::with_mock(script);
+
+        let mut driver = Hidpp20Driver::new();
+        driver.device_index = DEVICE_IDX_CORDED;
+        let (entries, sectors) = driver
+            .read_macro(&mut io, TEST_FEATURE_IDX, USER_PROFILES_BASE, 3, 0, 256)
+            .await
+            .unwrap();
+        assert!(handle.script_exhausted());
+        assert_eq!(entries, vec![MacroEvent::KeyPress(0x04), MacroEvent::KeyRelease(0x04)]);
+        assert_eq!(sectors, vec![3, 4]);
+    }
+
+    #[tokio::test]
+    async fn store_macro_reuses_own_sector_and_frees_chained_tail() {
+        let mut script = Vec::new();
+        script_sector_write(&mut script, 256);
+        let (mut io, handle) = DeviceIo::with_mock(script);
+
+        let mut driver = Hidpp20Driver::new();
+        driver.device_index = DEVICE_IDX_CORDED;
+        driver.macro_sectors.insert((0, 1), vec![4, 5]);
+        let button = crate::engine::device::ButtonInfo {
+            index: 1,
+            action_type: crate::engine::device::ActionType::Macro,
+            macro_entries: vec![MacroEvent::KeyPress(0x05), MacroEvent::KeyRelease(0x05)],
+            macro_metadata: crate::engine::device::MacroMetadata::default(),
+            layer: crate::engine::device::BUTTON_LAYER_BASE,
+            ..Default::default()
+        };
+
+        let page = driver
+            .store_macro(&mut io, TEST_FEATURE_IDX, &macro_desc(), 0, &button)
+            .await
+            .unwrap();
+        assert!(handle.script_exhausted());
+        assert_eq!(page, 4);
+        assert_eq!(driver.macro_sectors.get(&(0, 1)), Some(&vec![4]));
+
+        /* memWrite start addressed sector 0x0004. */
+        assert_eq!(&handle.writes()[0][4..6], &[0x00, 0x04]);
+        assert_eq!(
+            Hidpp20ButtonBinding::for_macro(page).into_bytes(),
+            [BUTTON_TYPE_MACRO, 4, 0, 0]
+        );
+    }
+
+    /* ------------------------------------------------------------------ */
+    /* RGB Effects (0x8071) zones                                         */
+    /* ------------------------------------------------------------------ */
+
+    const TEST_RGB_IDX: u8 = 0x0F;
+
+    /* Long 0x8071 response echoing `function` with the given payload. */
+    fn rgb_reply(function: u8, params: &[u8]) -> Vec<u8> {
+        hidpp::build_hidpp20_request(DEVICE_IDX_CORDED, TEST_RGB_IDX, function, SW_ID, params)
+            .to_vec()
+    }
+
+    fn rgb_driver(zones: Vec<LedZone>) -> Hidpp20Driver {
+        let mut driver = Hidpp20Driver::new();
+        driver.device_index = DEVICE_IDX_CORDED;
+        driver.features.insert(PAGE_RGB_EFFECTS, TEST_RGB_IDX);
+        driver.rgb_zones = zones;
+        driver
+    }
+
+    fn test_led(index: u32, mode: LedMode) -> crate::engine::device::LedInfo {
+        crate::engine::device::LedInfo {
+            index,
+            mode,
+            modes: Vec::new(),
+            color: Color::from_rgb(RgbColor { r: 1, g: 2, b: 3 }),
+            secondary_color: Color::default(),
+            tertiary_color: Color::default(),
+            color_depth: 1,
+            effect_duration: 0x0400,
+            brightness: 255,
+            effects: Vec::new(),
+            effect: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn rgb_zones_enumerate_clusters_and_effects() {
+        let script = vec![
+            MockExchange::reply(rgb_reply(RGB_FN_GET_INFO, &[0xFF, 0xFF, 2])),
+            MockExchange::reply(rgb_reply(RGB_FN_GET_INFO, &[0, 0xFF, 0x00, 0x01, 3])),
+            MockExchange::reply(rgb_reply(RGB_FN_GET_INFO, &[0, 0, 0x00, 0x00])),
+            MockExchange::reply(rgb_reply(RGB_FN_GET_INFO, &[0, 1, 0x00, 0x01])),
+            MockExchange::reply(rgb_reply(RGB_FN_GET_INFO, &[0, 2, 0x00, 0x0A])),
+            MockExchange::reply(rgb_reply(RGB_FN_GET_INFO, &[1, 0xFF, 0x00, 0x02, 1])),
+            MockExchange::reply(rgb_reply(RGB_FN_GET_INFO, &[1, 0, 0x00, 0x0B])),
+        ];
+        let (mut io, handle) = DeviceIo::with_mock(script);
+
+        let zones = rgb_driver(Vec::new())
+            .enumerate_led_zones(&mut io, PAGE_RGB_EFFECTS)
+            .await
+            .unwrap();
+        assert!(handle.script_exhausted());
+        assert_eq!(zones.len(), 2);
+        assert_eq!(
+            zones[0].modes(),
+            vec![LedMode::Off, LedMode::Solid, LedMode::Breathing]
+        );
+        assert_eq!(zones[0].effect_index(LedMode::Breathing), Some(2));
+        /* Ripple (0x0B) has no ratbag mode. */
+        assert!(zones[1].modes().is_empty());
+
+        /* The per-effect queries addressed (cluster, effect). */
+        let writes = handle.writes();
+        assert_eq!(&writes[4][4..6], &[0, 2]);
+    }
+
+    #[tokio::test]
+    async fn color_led_zones_enumerate_through_dedicated_functions() {
+        let script = vec![
+            MockExchange::reply(rgb_reply(LED_FN_GET_INFO, &[1])),
+            MockExchange::reply(rgb_reply(LED_FN_GET_ZONE_INFO, &[0, 0x00, 0x01, 2])),
+            MockExchange::reply(rgb_reply(LED_FN_GET_ZONE_EFFECT_INFO, &[0, 0, 0x00, 0x01])),
+            MockExchange::reply(rgb_reply(LED_FN_GET_ZONE_EFFECT_INFO, &[0, 1, 0x00, 0x03])),
+        ];
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        let mut driver = rgb_driver(Vec::new());
+        driver.features.insert(PAGE_COLOR_LED_EFFECTS, TEST_RGB_IDX);
+
+        let zones = driver
+            .enumerate_led_zones(&mut io, PAGE_COLOR_LED_EFFECTS)
+            .await
+            .unwrap();
+        assert!(handle.script_exhausted());
+        assert_eq!(zones.len(), 1);
+        assert_eq!(zones[0].modes(), vec![LedMode::Solid, LedMode::Cycle]);
+    }
+
+    #[test]
+    fn eeprom_leds_advertise_encodable_modes() {
+        let mut data = [0u8; EEPROM_LED_SIZE];
+        data[0] = LED_HW_MODE_FIXED;
+        let led = Hidpp20Driver::parse_eeprom_led(&data, 0);
+        assert_eq!(led.modes, EEPROM_LED_MODES);
+        assert!(!led.modes.contains(&LedMode::TriColor));
+    }
+
+    #[test]
+    fn rgb_zones_reshape_profile_leds() {
+        let zones = vec![
+            LedZone {
+                cluster: 0,
+                effects: vec![(0, 0x01)],
+            },
+            LedZone {
+                cluster: 1,
+                effects: vec![(0, 0x00), (1, 0x03)],
+            },
+        ];
+        let mut profile = ProfileInfo {
+            leds: vec![test_led(0, LedMode::Solid), test_led(5, LedMode::Off)],
+            ..Default::default()
+        };
+
+        Hidpp20Driver::apply_led_zones(&mut profile, &zones);
+        let indices: Vec<u32> = profile.leds.iter().map(|l| l.index).collect();
+        assert_eq!(indices, vec![0, 1]);
+        assert_eq!(profile.leds[0].mode, LedMode::Solid);
+        assert_eq!(profile.leds[0].modes, vec![LedMode::Solid]);
+        assert_eq!(profile.leds[1].modes, vec![LedMode::Off, LedMode::Cycle]);
+    }
+
+    #[tokio::test]
+    async fn rgb_zone_write_addresses_effect_by_zone_index() {
+        let script = vec![MockExchange::reply(rgb_reply(
+            RGB_FN_SET_CLUSTER_EFFECT,
+            &[],
+        ))];
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        let driver = rgb_driver(vec![LedZone {
+            cluster: 3,
+            effects: vec![(0, 0x01), (1, 0x0A)],
+        }]);
+        let profile = ProfileInfo {
+            leds: vec![test_led(0, LedMode::Breathing)],
+            ..Default::default()
+        };
+
+        driver.write_led_info(&mut io, &profile).await.unwrap();
+        assert!(handle.script_exhausted());
+        let writes = handle.writes();
+        /* cluster, effect index, colour, period (BE), waveform, intensity… */
+        assert_eq!(&writes[0][4..11], &[3, 1, 1, 2, 3, 0x04, 0x00]);
+        assert_eq!(writes[0][4 + 12], RGB_PERSIST);
+    }
+
+    #[tokio::test]
+    async fn led_frame_is_volatile_and_validated_up_front() {
+        let script = vec![MockExchange::reply(rgb_reply(
+            RGB_FN_SET_CLUSTER_EFFECT,
+            &[],
+        ))];
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        let mut driver = rgb_driver(vec![
+            LedZone {
+                cluster: 0,
+                effects: vec![(0, 0x01)],
+            },
+            LedZone {
+                cluster: 1,
+                effects: vec![(0, 0x03)],
+            },
+        ]);
+        let red = Color::from_rgb(RgbColor { r: 255, g: 0, b: 0 });
+
+        /* Zone 1 has no static effect: nothing may be sent. */
+        let frame = [(0, red), (1, red)];
+        let err = crate::hal::DeviceDriver::set_led_frame(&mut driver, &mut io, &frame)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HidppDriverError>(),
+            Some(HidppDriverError::UnsupportedLedMode { led: 1, .. })
+        ));
+        assert!(handle.writes().is_empty());
+
+        crate::hal::DeviceDriver::set_led_frame(&mut driver, &mut io, &[(0, red)])
+            .await
+            .unwrap();
+        assert!(handle.script_exhausted());
+        let writes = handle.writes();
+        assert_eq!(&writes[0][4..7], &[0, 0, 255]);
+        assert_eq!(writes[0][4 + 12], RGB_VOLATILE);
+    }
+
+    #[tokio::test]
+    async fn led_preview_clears_the_persist_flag() {
+        let script = vec![
+            MockExchange::reply(rgb_reply(RGB_FN_SET_CLUSTER_EFFECT, &[])),
+            MockExchange::reply(rgb_reply(LED_FN_SET_ZONE_EFFECT, &[])),
+        ];
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        let mut driver = rgb_driver(vec![LedZone {
+            cluster: 3,
+            effects: vec![(0, 0x01), (1, 0x0A)],
+        }]);
+        let led = test_led(0, LedMode::Breathing);
+
+        /* 0x8071 zones address the cluster and its breathing effect. */
+        crate::hal::DeviceDriver::preview_led(&mut driver, &mut io, &led)
+            .await
+            .unwrap();
+
+        /* Without enumerated zones the 0x8070 zone effect is written. */
+        driver.rgb_zones.clear();
+        driver.features.insert(PAGE_COLOR_LED_EFFECTS, TEST_RGB_IDX);
+        crate::hal::DeviceDriver::preview_led(&mut driver, &mut io, &led)
+            .await
+            .unwrap();
+
+        assert!(handle.script_exhausted());
+        let writes = handle.writes();
+        assert_eq!(&writes[0][4..6], &[3, 1]);
+        assert_eq!(writes[0][4 + 12], RGB_VOLATILE);
+        assert_eq!(writes[1][4], 0);
+        assert_eq!(writes[1][4 + 12], RGB_VOLATILE);
+    }
 }
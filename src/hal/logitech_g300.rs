@@ -145,6 +145,8 @@ impl DeviceDriver for LogitechG300Driver {
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: Vec::new(),
+                lift_off_distance: -1,
+                lift_off_distances: Vec::new(),
                 capabilities: Vec::new(),
             };
 
@@ -154,6 +156,7 @@ impl DeviceDriver for LogitechG300Driver {
                     is_active: profile_id == active_idx as u32 && res_id == active_res as u32,
                     is_default: false,
                     is_disabled: false,
+                    is_shift: false,
                     dpi: crate::engine::device::Dpi::Unknown,
                     dpi_list: vec![],
                     capabilities: Vec::new(),
@@ -167,19 +170,24 @@ impl DeviceDriver for LogitechG300Driver {
                     action_types: vec![0, 1, 2, 3, 4],
                     mapping_value: 0,
                     macro_entries: Vec::new(),
+                    macro_metadata: crate::engine::device::MacroMetadata::default(),
+                    layer: crate::engine::device::BUTTON_LAYER_BASE,
                 });
             }
 
             profile.leds.push(crate::engine::device::LedInfo {
                 index: 0,
                 mode: crate::engine::device::LedMode::Solid,
-                modes: vec![],
+                /* The G300 LED only takes a 3-bit colour, no effects. */
+                modes: vec![crate::engine::device::LedMode::Solid],
                 color: crate::engine::device::Color::default(),
                 secondary_color: crate::engine::device::Color::default(),
                 tertiary_color: crate::engine::device::Color::default(),
                 color_depth: 1,
                 effect_duration: 0,
                 brightness: 255,
+                effects: Vec::new(),
+                effect: None,
             });
 
             info.profiles.push(profile);
@@ -3,6 +3,12 @@
  * Targets the Logitech G600 MMO Gaming Mouse, a 20-button device with
  * 3 profiles, 4 DPI levels, and one RGB LED zone.
  *
+ * Holding a button bound to SECOND_MODE switches the mouse to its G-Shift
+ * layer: a second set of 20 bindings and its own LED colour. The DPI-shift
+ * value is the resolution used while a RESOLUTION_ALTERNATE button is held.
+ * These are exposed as buttons 20-39 on BUTTON_LAYER_SHIFT, LED 1, and a
+ * fifth resolution flagged `is_shift`.
+ *
  * Reference implementation: `src/driver-logitech-g600.c`. */
 
 use anyhow::{Context, Result};
@@ -10,8 +16,8 @@ use async_trait::async_trait;
 use tracing::{debug, info, warn};
 
 use crate::engine::device::{
-    ActionType, Color, DeviceInfo, Dpi, LedMode, ProfileInfo, RgbColor,
-    special_action,
+    ActionType, BUTTON_LAYER_BASE, BUTTON_LAYER_SHIFT, Color, DeviceInfo, Dpi, LedMode,
+    ProfileInfo, RgbColor, special_action,
 };
 use crate::hal::{DeviceDriver, DeviceIo};
 
@@ -20,9 +26,13 @@ use crate::hal::{DeviceDriver, DeviceIo};
 /* ------------------------------------------------------------------ */
 
 const NUM_PROFILES: usize = 3;
-const NUM_BUTTONS: usize = 41; /* 20 standard + 20 G-Shift + 1 color buffer */
+const NUM_LAYER_BUTTONS: usize = 20;
+const NUM_BUTTONS: usize = 2 * NUM_LAYER_BUTTONS; /* standard + G-Shift */
 const NUM_DPI: usize = 4;
-const NUM_LED: usize = 1;
+const NUM_LED: usize = 2; /* main zone + G-Shift colour */
+
+/* Index of the DPI-shift resolution, exposed after the regular slots. */
+const SHIFT_RES_INDEX: usize = NUM_DPI;
 
 const DPI_MIN: u32 = 200;
 const DPI_MAX: u32 = 8200;
@@ -424,10 +434,24 @@ impl DeviceDriver for LG600Driver {
                     is_active: is_active_res,
                     is_default,
                     is_disabled: disabled,
+                    is_shift: false,
                 });
             }
 
-            /* --- Buttons (41 total) --- */
+            /* DPI shift: never active or default, raw 0 disables it. */
+            let shift_disabled = report.dpi_shift == 0;
+            resolutions.push(crate::engine::device::ResolutionInfo {
+                index: SHIFT_RES_INDEX as u32,
+                dpi: Dpi::Unified(if shift_disabled { 0 } else { raw_to_dpi(report.dpi_shift) }),
+                dpi_list: dpi_list.clone(),
+                capabilities: Vec::new(),
+                is_active: false,
+                is_default: false,
+                is_disabled: shift_disabled,
+                is_shift: true,
+            });
+
+            /* --- Buttons (20 standard + 20 G-Shift) --- */
             /* C lines 296-299: supported action types */
             let action_types = vec![
                 ActionType::None as u32,
@@ -438,15 +462,13 @@ impl DeviceDriver for LG600Driver {
 
             let mut buttons = Vec::with_capacity(NUM_BUTTONS);
             for b in 0..NUM_BUTTONS {
-                let (action_type, mapping_value) = if b < 20 {
-                    decode_button(&report.buttons[b])
-                } else if b == 20 {
-                    /* G-shift color buffer slot — not a real button. */
-                    (ActionType::None, 0)
+                /* G-Shift buttons: indices 20-39 → g_shift_buttons[0-19]. */
+                let (entry, layer) = if b < NUM_LAYER_BUTTONS {
+                    (&report.buttons[b], BUTTON_LAYER_BASE)
                 } else {
-                    /* G-shift buttons: indices 21-40 → g_shift_buttons[0-19]. */
-                    decode_button(&report.g_shift_buttons[b - 21])
+                    (&report.g_shift_buttons[b - NUM_LAYER_BUTTONS], BUTTON_LAYER_SHIFT)
                 };
+                let (action_type, mapping_value) = decode_button(entry);
 
                 buttons.push(crate::engine::device::ButtonInfo {
                     index: b as u32,
@@ -454,6 +476,8 @@ impl DeviceDriver for LG600Driver {
                     action_types: action_types.clone(),
                     mapping_value,
                     macro_entries: Vec::new(),
+                    macro_metadata: crate::engine::device::MacroMetadata::default(),
+                    layer,
                 });
             }
 
@@ -490,6 +514,24 @@ impl DeviceDriver for LG600Driver {
                 color_depth: 0,
                 effect_duration,
                 brightness: 255,
+                effects: Vec::new(),
+                effect: None,
+            };
+
+            /* --- G-Shift LED: a static colour, black means off --- */
+            let [gr, gg, gb] = report.g_shift_color;
+            let g_shift_led = crate::engine::device::LedInfo {
+                index: 1,
+                mode: if gr == 0 && gg == 0 && gb == 0 { LedMode::Off } else { LedMode::Solid },
+                modes: vec![LedMode::Off, LedMode::Solid],
+                color: Color::from_rgb(RgbColor { r: gr, g: gg, b: gb }),
+                secondary_color: Color::default(),
+                tertiary_color: Color::default(),
+                color_depth: 0,
+                effect_duration: 0,
+                brightness: 255,
+                effects: Vec::new(),
+                effect: None,
             };
 
             let profile = ProfileInfo {
@@ -503,10 +545,12 @@ impl DeviceDriver for LG600Driver {
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: Vec::new(),
+                lift_off_distance: -1,
+                lift_off_distances: Vec::new(),
                 capabilities: Vec::new(),
                 resolutions,
                 buttons,
-                leds: vec![led],
+                leds: vec![led, g_shift_led],
             };
 
             info.profiles.push(profile);
@@ -517,7 +561,7 @@ impl DeviceDriver for LG600Driver {
             );
         }
 
-        info!("G600: loaded {} profiles ({} buttons, {} DPI slots, {} LED)",
+        info!("G600: loaded {} profiles ({} buttons, {} DPI slots + shift, {} LEDs)",
               NUM_PROFILES, NUM_BUTTONS, NUM_DPI, NUM_LED);
         Ok(())
     }
@@ -552,7 +596,7 @@ impl DeviceDriver for LG600Driver {
             let mut active_res_index: u8 = 0;
             for res in &profile.resolutions {
                 let r_idx = res.index as usize;
-                if r_idx >= NUM_DPI {
+                if r_idx > SHIFT_RES_INDEX {
                     continue;
                 }
 
@@ -562,7 +606,7 @@ impl DeviceDriver for LG600Driver {
                     Dpi::Unknown => 0,
                 };
 
-                report.dpi[r_idx] = if res.is_disabled || dpi_val == 0 {
+                let raw = if res.is_disabled || dpi_val == 0 {
                     0
                 } else {
                     dpi_to_raw(dpi_val).unwrap_or_else(|| {
@@ -573,6 +617,12 @@ impl DeviceDriver for LG600Driver {
                     })
                 };
 
+                if r_idx == SHIFT_RES_INDEX {
+                    report.dpi_shift = raw;
+                    continue;
+                }
+                report.dpi[r_idx] = raw;
+
                 if res.is_default {
                     report.dpi_default = (r_idx + 1) as u8; /* 1-indexed */
                 }
@@ -586,17 +636,16 @@ impl DeviceDriver for LG600Driver {
             for btn in &profile.buttons {
                 let b = btn.index as usize;
 
-                if b < 20 {
-                    report.buttons[b] = encode_button(btn.action_type, btn.mapping_value);
-                } else if b == 20 {
-                    /* G-shift color buffer — skip encoding. */
-                } else if b <= 40 {
-                    report.g_shift_buttons[b - 21] = encode_button(btn.action_type, btn.mapping_value);
+                let entry = encode_button(btn.action_type, btn.mapping_value);
+                if b < NUM_LAYER_BUTTONS {
+                    report.buttons[b] = entry;
+                } else if b < NUM_BUTTONS {
+                    report.g_shift_buttons[b - NUM_LAYER_BUTTONS] = entry;
                 }
             }
 
             /* 4. LED: C lines 534-561 */
-            if let Some(led) = profile.leds.first() {
+            if let Some(led) = profile.find_led(0) {
                 let c = led.color.to_rgb();
                 match led.mode {
                     LedMode::Off => {
@@ -633,9 +682,16 @@ impl DeviceDriver for LG600Driver {
                         report.led_blue = c.b;
                     }
                 }
+            }
 
-                /* Copy main LED color to g-shift color: C lines 565-567. */
-                report.g_shift_color = [report.led_red, report.led_green, report.led_blue];
+            /* The C driver copies the main colour here (lines 565-567);
+             * G-Shift has its own LED object instead. */
+            if let Some(led) = profile.find_led(1) {
+                let c = led.color.to_rgb();
+                report.g_shift_color = match led.mode {
+                    LedMode::Off => [0, 0, 0],
+                    _ => [c.r, c.g, c.b],
+                };
             }
 
             /* 5. Serialize and send: C lines 569-575 */
@@ -660,3 +716,60 @@ impl DeviceDriver for LG600Driver {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::device_database::DeviceEntry;
+
+    fn make_info() -> DeviceInfo {
+        let entry = DeviceEntry {
+            name: "Logitech G600".into(),
+            driver: "logitech_g600".into(),
+            device_type: "mouse".into(),
+            matches: Vec::new(),
+            driver_config: None,
+        };
+        DeviceInfo::from_entry("test0", "Logitech G600", 0x03, 0x046d, 0xc24a, &entry)
+    }
+
+    #[tokio::test]
+    async fn g_shift_layer_led_and_dpi_shift_are_exposed_separately() {
+        let mut report = ProfileReport::new();
+        report.led_red = 0x10;
+        report.dpi = [0x10, 0x20, 0, 0];
+        report.dpi_shift = 0x08; /* 400 DPI */
+        report.buttons[0] = ButtonEntry { code: 0x01, modifier: 0, key: 0 };
+        report.buttons[19] = ButtonEntry { code: 0x17, modifier: 0, key: 0 };
+        report.g_shift_color = [0x00, 0x80, 0xff];
+        report.g_shift_buttons[0] = ButtonEntry { code: 0x03, modifier: 0, key: 0 };
+
+        let mut drv = LG600Driver::new();
+        drv.data = Some(G600Data {
+            profile_reports: [Some(report.clone()), Some(report.clone()), Some(report)],
+            active: ActiveProfileReport::default(),
+        });
+        let (mut io, _handle) = DeviceIo::with_mock(Vec::new());
+        let mut info = make_info();
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+
+        let p = &info.profiles[0];
+        assert_eq!(p.buttons.len(), NUM_BUTTONS);
+        let b19 = p.find_button(19).unwrap();
+        assert_eq!((b19.action_type, b19.mapping_value, b19.layer),
+                   (ActionType::Special, special_action::SECOND_MODE, BUTTON_LAYER_BASE));
+        let b20 = p.find_button(20).unwrap();
+        assert_eq!((b20.action_type, b20.mapping_value, b20.layer),
+                   (ActionType::Button, 3, BUTTON_LAYER_SHIFT));
+
+        let shift = p.find_resolution(SHIFT_RES_INDEX as u32).unwrap();
+        assert!(shift.is_shift && !shift.is_active && !shift.is_default && !shift.is_disabled);
+        assert!(matches!(shift.dpi, Dpi::Unified(400)));
+        assert!(p.resolutions[..NUM_DPI].iter().all(|r| !r.is_shift));
+
+        let g_shift_led = p.find_led(1).unwrap();
+        assert_eq!(g_shift_led.mode, LedMode::Solid);
+        assert_eq!(g_shift_led.color.to_rgb(), RgbColor { r: 0x00, g: 0x80, b: 0xff });
+        assert_eq!(p.find_led(0).unwrap().color.to_rgb(), RgbColor { r: 0x10, g: 0, b: 0 });
+    }
+}
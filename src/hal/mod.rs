@@ -28,7 +28,9 @@ use thiserror::Error;
 use tokio::io::unix::AsyncFd;
 use tracing::{debug, trace, warn};
 
-use crate::engine::device::DeviceInfo;
+use crate::engine::device::{
+    BatteryStatus, Color, DeviceInfo, LedInfo, PairedDevice, PairingOutcome,
+};
 
 /* Domain-specific error variants for all driver I/O operations. */
 /*                                                                 */
@@ -555,6 +557,43 @@ impl DeviceIo {
         })
     }
 
+    /* Wait for a report without sending anything first, e.g. a      */
+    /* notification the device emits some time after a command.     */
+    /*                                                              */
+    /* Matching follows `request()`: non-HID++ input reports are    */
+    /* discarded and HID++ reports the matcher rejects are buffered */
+    /* as pending events.  Fails with `Timeout` once `timeout`      */
+    /* elapses without a match.                                     */
+    pub async fn wait_report<T, F>(
+        &mut self,
+        timeout: Duration,
+        mut matcher: F,
+    ) -> Result<T, DriverError>
+    where
+        F: FnMut(&[u8]) -> Option<T>,
+    {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut buf = [0u8; 64];
+
+        loop {
+            match tokio::time::timeout_at(deadline, self.read_report(&mut buf)).await {
+                Ok(Ok(n)) => {
+                    if n == 0
+                        || (buf[0] != HIDPP_SHORT_REPORT_ID && buf[0] != HIDPP_LONG_REPORT_ID)
+                    {
+                        continue;
+                    }
+                    if let Some(result) = matcher(&buf[..n]) {
+                        return Ok(result);
+                    }
+                    self.push_pending_event(buf[..n].to_vec());
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_elapsed) => return Err(DriverError::Timeout { attempts: 1 }),
+            }
+        }
+    }
+
     /* Buffer an unsolicited HID++ report, dropping the oldest entry
      * once the cap is reached so a chatty device cannot grow the
      * buffer without bound during a long commit. */
@@ -595,6 +634,9 @@ pub(crate) mod mock {
     pub enum MockReply {
         /* Deliver these bytes on the next read. */
         Data(Vec<u8>),
+        /* Deliver each report, in order, on the following reads — a reply
+         * followed by notifications the device sends on its own. */
+        Burst(Vec<Vec<u8>>),
         /* Stay silent: the next read pends forever (timeout testing). */
         Silence,
         /* Fail the write itself with a hard I/O error. */
@@ -616,6 +658,10 @@ pub(crate) mod mock {
         pub fn expect_reply(expect: Vec<u8>, data: Vec<u8>) -> Self {
             Self { expect: Some(expect), reply: MockReply::Data(data) }
         }
+
+        pub fn burst(reports: Vec<Vec<u8>>) -> Self {
+            Self { expect: None, reply: MockReply::Burst(reports) }
+        }
     }
 
     #[derive(Default)]
@@ -661,6 +707,7 @@ pub(crate) mod mock {
             }
             match exchange.reply {
                 MockReply::Data(data) => st.queued.push_back(data),
+                MockReply::Burst(reports) => st.queued.extend(reports),
                 MockReply::Silence => { /* nothing queued: next read pends */ }
                 MockReply::WriteError => bail!("MockHid: scripted write error"),
             }
@@ -733,6 +780,15 @@ pub trait DeviceDriver: Send + Sync {
         false
     }
 
+    /* Whether an input report read while idle is one of this driver's  */
+    /* event reports and should reach `handle_event`.  Everything else  */
+    /* (motion, keyboard input) is discarded by the actor.              */
+    /*                                                                  */
+    /* The default accepts HID++ short and long reports.                */
+    fn is_event_report(&self, report: &[u8]) -> bool {
+        matches!(report.first(), Some(&HIDPP_SHORT_REPORT_ID) | Some(&HIDPP_LONG_REPORT_ID))
+    }
+
     /* Probe the device to confirm it speaks this protocol.        */
     /*                                                             */
     /* For HID++ this sends a version ping; for other protocols it */
@@ -750,6 +806,87 @@ pub trait DeviceDriver: Send + Sync {
     /* diff the `DeviceInfo` against its internal cached state.    */
     async fn commit(&mut self, io: &mut DeviceIo, info: &DeviceInfo) -> Result<()>;
 
+    /* Restore a profile to its factory defaults.                  */
+    /*                                                             */
+    /* Drivers that keep a ROM copy of their profiles write it     */
+    /* straight back to the device and update `info` to match.     */
+    /* The default resets the profile in `info` to a driver-       */
+    /* neutral baseline and leaves it dirty, so the next commit    */
+    /* writes it out like any other change.                        */
+    async fn reset_profile(
+        &mut self,
+        _io: &mut DeviceIo,
+        info: &mut DeviceInfo,
+        profile_id: u32,
+    ) -> Result<()> {
+        *info = info.with_profile_defaults(profile_id);
+        Ok(())
+    }
+
+    /* Show a transient custom colour on each listed LED, given as  */
+    /* `(led index, colour)`.  Frames bypass the profiles: nothing  */
+    /* is marked dirty and the stored lighting returns on the next  */
+    /* commit or power cycle.                                       */
+    /*                                                             */
+    /* The default rejects the frame; only drivers whose hardware  */
+    /* takes direct per-zone colours implement it.                 */
+    async fn set_led_frame(
+        &mut self,
+        _io: &mut DeviceIo,
+        _colors: &[(u32, Color)],
+    ) -> Result<()> {
+        anyhow::bail!("{} does not support custom colour frames", self.name())
+    }
+
+    /* Show `led` as configured without persisting it, so the     */
+    /* stored lighting returns on the next commit or power cycle.  */
+    /*                                                             */
+    /* The default rejects the preview; only drivers with a        */
+    /* volatile LED path that skips the flash write implement it.  */
+    async fn preview_led(&mut self, _io: &mut DeviceIo, _led: &LedInfo) -> Result<()> {
+        anyhow::bail!("{} does not support LED previews", self.name())
+    }
+
+    /* Run the active profile of `info` without rewriting any     */
+    /* stored profile, e.g. by selecting it on the device.  Used   */
+    /* for frequent automatic switches to spare the flash.         */
+    /*                                                             */
+    /* The default rejects the switch; callers then `commit` the   */
+    /* selection alone, and only while no other edit is pending.  */
+    async fn switch_profile(&mut self, _io: &mut DeviceIo, _info: &DeviceInfo) -> Result<()> {
+        anyhow::bail!("{} does not support live profile switching", self.name())
+    }
+
+    /* List the occupied pairing slots of the wireless receiver    */
+    /* the device is reached through.                              */
+    /*                                                             */
+    /* The receiver methods are only called when `load_profiles`   */
+    /* set `DeviceInfo::has_receiver`; the defaults reject them.   */
+    async fn paired_devices(&mut self, _io: &mut DeviceIo) -> Result<Vec<PairedDevice>> {
+        anyhow::bail!("{} does not manage receiver pairing", self.name())
+    }
+
+    /* Open the receiver's pairing window for `timeout` seconds and */
+    /* wait for it to close, reporting whether a device paired.     */
+    async fn pair_device(&mut self, _io: &mut DeviceIo, _timeout: u8) -> Result<PairingOutcome> {
+        anyhow::bail!("{} does not manage receiver pairing", self.name())
+    }
+
+    /* Remove the device paired in receiver slot `slot`. */
+    async fn unpair_device(&mut self, _io: &mut DeviceIo, _slot: u8) -> Result<()> {
+        anyhow::bail!("{} does not manage receiver pairing", self.name())
+    }
+
+    /* Read the current battery charge.                            */
+    /*                                                             */
+    /* Returns `None` when the device is asleep and cannot answer  */
+    /* right now; that is not an error.  Only called when          */
+    /* `load_profiles` set `DeviceInfo::power`; the default        */
+    /* rejects it.                                                 */
+    async fn read_battery(&mut self, _io: &mut DeviceIo) -> Result<Option<BatteryStatus>> {
+        anyhow::bail!("{} does not report battery status", self.name())
+    }
+
     /* Handle an unsolicited hardware event (e.g. profile switch,  */
     /* DPI change triggered by a physical button on the device).   */
     /*                                                             */
@@ -1,8 +1,11 @@
-use crate::engine::device::DeviceInfo;
+use crate::engine::device::{
+    ButtonInfo, Color, DeviceInfo, LedInfo, LedMode, MacroEvent, MacroMetadata, RgbColor,
+};
 use crate::hal::{DeviceDriver, DeviceIo, DriverError};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use tracing::debug;
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 /* Protocol constants from driver-roccat.c */
@@ -10,21 +13,57 @@ use std::time::Duration;
 const ROCCAT_PROFILE_MAX: u8 = 4;
 #[allow(dead_code)]
 const ROCCAT_BUTTON_MAX: u8 = 23;
+/* The settings report holds only these cycled slots; there is no
+ * separate shift/sniper resolution, so no slot is flagged `is_shift`. */
 #[allow(dead_code)]
 const ROCCAT_NUM_DPI: u8 = 5;
 
 const ROCCAT_REPORT_ID_CONFIGURE_PROFILE: u8 = 4;
 const ROCCAT_REPORT_ID_PROFILE: u8 = 5;
-#[allow(dead_code)]
 const ROCCAT_REPORT_ID_SETTINGS: u8 = 6;
 #[allow(dead_code)]
 const ROCCAT_REPORT_ID_KEY_MAPPING: u8 = 7;
 #[allow(dead_code)]
 const ROCCAT_REPORT_ID_MACRO: u8 = 8;
+/* Input report the mouse sends when a button changes device state:
+ * [id, 0, type, data1, data2, ...]. */
+const ROCCAT_REPORT_ID_SPECIAL: u8 = 3;
+/* data1 = new profile, 1-based. */
+const ROCCAT_SPECIAL_TYPE_PROFILE: u8 = 0x20;
+/* data1 = new resolution slot, 1-based. */
+const ROCCAT_SPECIAL_TYPE_CPI: u8 = 0xb0;
 
 const ROCCAT_MAX_RETRY_READY: usize = 10;
 #[allow(dead_code)]
 const ROCCAT_MAX_MACRO_LENGTH: usize = 500;
+/* Fixed width of the macro group and name fields. */
+const ROCCAT_MACRO_STRING_LEN: usize = 24;
+/* Keystroke action flags. */
+const ROCCAT_MACRO_KEY_PRESS: u8 = 0x01;
+const ROCCAT_MACRO_KEY_RELEASE: u8 = 0x02;
+/* Size field of the macro report, i.e. its total length (2082). */
+const ROCCAT_MACRO_REPORT_LENGTH: u16 = 0x0822;
+
+/* Lights in the settings report.  They share one effect and speed; each
+ * has its own colour. */
+const ROCCAT_NUM_LEDS: usize = 4;
+/* `light_effect_type` values. */
+const ROCCAT_LIGHT_EFFECT_ALL_OFF: u8 = 0;
+const ROCCAT_LIGHT_EFFECT_FULLY_LIGHTED: u8 = 1;
+const ROCCAT_LIGHT_EFFECT_BLINKING: u8 = 2;
+const ROCCAT_LIGHT_EFFECT_BREATHING: u8 = 3;
+const ROCCAT_LIGHT_EFFECT_HEARTBEAT: u8 = 4;
+/* `color_flow_effect`: 0 = off, 1 = all lights simultaneously, 2-5 = a
+ * directional flow. */
+const ROCCAT_COLOR_FLOW_OFF: u8 = 0;
+const ROCCAT_COLOR_FLOW_SIMULTANEOUS: u8 = 1;
+/* `light_effect_mode`: where the lights take their colour from. */
+#[cfg(test)]
+const ROCCAT_LIGHT_SOURCE_TABLE: u8 = 0;
+const ROCCAT_LIGHT_SOURCE_CUSTOM: u8 = 1;
+/* `light_effect_speed` range, 1 = slowest. */
+const ROCCAT_LIGHT_SPEED_MIN: u8 = 1;
+const ROCCAT_LIGHT_SPEED_MAX: u8 = 3;
 
 /* Each Roccat button mapping is a 3-byte stride: [action, param1, param2] */
 const ROCCAT_BUTTON_STRIDE: usize = 3;
@@ -36,6 +75,8 @@ pub struct RoccatSettingsReport {
     pub report_id: u8,
     pub report_length: u8,
     pub profile_id: u8,
+    /* Sensor sensitivity (bytes 3-5). ratbagd has no sensitivity property,
+     * so these are written back exactly as they were read. */
     pub x_y_linked: u8,
     pub x_sensitivity: u8,
     pub y_sensitivity: u8,
@@ -45,18 +86,45 @@ pub struct RoccatSettingsReport {
     pub yres: [u8; 5],
     pub padding1: u8,
     pub report_rate: u8,
-    pub padding2: [u8; 21],
+    /* Light settings (bytes 20-40). */
+    /* One bit per light; a cleared bit keeps that light dark. */
+    pub lights_enabled: u8,
+    /* Colour source: custom colours or the built-in colour table. */
+    pub light_effect_mode: u8,
+    pub color_flow_effect: u8,
+    pub light_effect_type: u8,
+    pub light_effect_speed: u8,
+    pub lights: [RoccatLight; ROCCAT_NUM_LEDS],
     pub checksum: u16,
 }
 
+/* One light's colour in the settings report. */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RoccatLight {
+    /* Entry in the built-in colour table.  The RGB bytes hold that
+     * entry's colour while the table is the colour source. */
+    pub index: u8,
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
 impl RoccatSettingsReport {
     pub fn from_bytes(buf: &[u8; 43]) -> Self {
         let mut xres = [0u8; 5];
         xres.copy_from_slice(&buf[7..12]);
         let mut yres = [0u8; 5];
         yres.copy_from_slice(&buf[13..18]);
-        let mut padding2 = [0u8; 21];
-        padding2.copy_from_slice(&buf[20..41]);
+        let mut lights = [RoccatLight::default(); ROCCAT_NUM_LEDS];
+        for (i, light) in lights.iter_mut().enumerate() {
+            let off = 25 + i * 4;
+            *light = RoccatLight {
+                index: buf[off],
+                red: buf[off + 1],
+                green: buf[off + 2],
+                blue: buf[off + 3],
+            };
+        }
         
         Self {
             report_id: buf[0],
@@ -71,7 +139,12 @@ impl RoccatSettingsReport {
             yres,
             padding1: buf[18],
             report_rate: buf[19],
-            padding2,
+            lights_enabled: buf[20],
+            light_effect_mode: buf[21],
+            color_flow_effect: buf[22],
+            light_effect_type: buf[23],
+            light_effect_speed: buf[24],
+            lights,
             checksum: u16::from_le_bytes([buf[41], buf[42]]),
         }
     }
@@ -90,7 +163,15 @@ impl RoccatSettingsReport {
         buf[13..18].copy_from_slice(&self.yres);
         buf[18] = self.padding1;
         buf[19] = self.report_rate;
-        buf[20..41].copy_from_slice(&self.padding2);
+        buf[20] = self.lights_enabled;
+        buf[21] = self.light_effect_mode;
+        buf[22] = self.color_flow_effect;
+        buf[23] = self.light_effect_type;
+        buf[24] = self.light_effect_speed;
+        for (i, light) in self.lights.iter().enumerate() {
+            let off = 25 + i * 4;
+            buf[off..off + 4].copy_from_slice(&[light.index, light.red, light.green, light.blue]);
+        }
         buf[41..43].copy_from_slice(&self.checksum.to_le_bytes());
         buf
     }
@@ -134,6 +215,7 @@ impl RoccatProfileReport {
 pub struct RoccatMacroEvent {
     pub keycode: u8,
     pub flag: u8,
+    /* Delay after this keystroke, in ms. */
     pub time: u16,
 }
 
@@ -144,10 +226,13 @@ pub struct RoccatMacro {
     pub report_length: u16,
     pub profile: u8,
     pub button_index: u8,
-    pub active: u8,
+    /* Play count per press.  The C driver calls this `active` and always
+     * writes 1. */
+    pub loop_count: u8,
     pub padding: [u8; 24],
-    pub group: [u8; 24],
-    pub name: [u8; 24],
+    /* NUL-padded macro set and macro names. */
+    pub group: [u8; ROCCAT_MACRO_STRING_LEN],
+    pub name: [u8; ROCCAT_MACRO_STRING_LEN],
     pub length: u16,
     pub keys: [RoccatMacroEvent; ROCCAT_MAX_MACRO_LENGTH],
     pub checksum: u16,
@@ -177,7 +262,7 @@ impl RoccatMacro {
             report_length: u16::from_le_bytes([buf[1], buf[2]]),
             profile: buf[3],
             button_index: buf[4],
-            active: buf[5],
+            loop_count: buf[5],
             padding,
             group,
             name,
@@ -193,7 +278,7 @@ impl RoccatMacro {
         buf[1..3].copy_from_slice(&self.report_length.to_le_bytes());
         buf[3] = self.profile;
         buf[4] = self.button_index;
-        buf[5] = self.active;
+        buf[5] = self.loop_count;
         buf[6..30].copy_from_slice(&self.padding);
         buf[30..54].copy_from_slice(&self.group);
         buf[54..78].copy_from_slice(&self.name);
@@ -211,6 +296,286 @@ impl RoccatMacro {
     }
 }
 
+/* LED modes the shared light effect can show. */
+const ROCCAT_LED_MODES: &[LedMode] = &[LedMode::Off, LedMode::Solid, LedMode::Cycle, LedMode::Breathing];
+
+/* The ratbag mode of the shared light effect.  Blinking and heartbeat have
+ * no ratbag mode; they read as Solid and Breathing and are left as they
+ * are unless the mode changes.  A colour flow over any effect is Cycle. */
+fn light_mode(settings: &RoccatSettingsReport) -> LedMode {
+    match settings.light_effect_type {
+        ROCCAT_LIGHT_EFFECT_ALL_OFF => LedMode::Off,
+        _ if settings.color_flow_effect != ROCCAT_COLOR_FLOW_OFF => LedMode::Cycle,
+        ROCCAT_LIGHT_EFFECT_BREATHING | ROCCAT_LIGHT_EFFECT_HEARTBEAT => LedMode::Breathing,
+        _ => LedMode::Solid,
+    }
+}
+
+/* Effect speed as a duration: speed 1 (slowest) is 3000 ms, 3 is 1000 ms. */
+fn light_duration(settings: &RoccatSettingsReport) -> u32 {
+    let speed = settings.light_effect_speed.clamp(ROCCAT_LIGHT_SPEED_MIN, ROCCAT_LIGHT_SPEED_MAX);
+    u32::from(ROCCAT_LIGHT_SPEED_MAX + 1 - speed) * 1000
+}
+
+/* True unless light `index` is switched off by its enable bit. */
+fn light_enabled(settings: &RoccatSettingsReport, index: usize) -> bool {
+    settings.lights_enabled & (1 << index) != 0
+}
+
+/* Decode light `index` of the settings report.  A light whose enable bit
+ * is cleared is Off whatever the shared effect. */
+fn decode_light(settings: &RoccatSettingsReport, index: usize) -> LedInfo {
+    let light = settings.lights[index];
+    LedInfo {
+        index: index as u32,
+        mode: if light_enabled(settings, index) { light_mode(settings) } else { LedMode::Off },
+        modes: ROCCAT_LED_MODES.to_vec(),
+        color: Color::from_rgb(RgbColor { r: light.red, g: light.green, b: light.blue }),
+        secondary_color: Color::default(),
+        tertiary_color: Color::default(),
+        color_depth: 1,
+        effect_duration: light_duration(settings),
+        brightness: 255,
+        effects: Vec::new(),
+        effect: None,
+    }
+}
+
+/* Write the LEDs back into the settings report, touching only what
+ * differs from the report as read.
+ *
+ * Colours are per light; a new colour switches the colour source to
+ * custom colours, or it would not show.  Turning one LED off or on flips
+ * its enable bit.  Effect and speed are shared, so the first LED that
+ * is on with a mode (or duration) differing from the report's sets it
+ * for all of them. */
+fn apply_lights(settings: &mut RoccatSettingsReport, leds: &[LedInfo]) {
+    let read = *settings;
+    for led in leds {
+        let index = led.index as usize;
+        if index >= ROCCAT_NUM_LEDS {
+            continue;
+        }
+        let current = decode_light(&read, index);
+
+        if led.color.to_rgb() != current.color.to_rgb() {
+            let c = led.color.to_rgb();
+            let light = &mut settings.lights[index];
+            (light.red, light.green, light.blue) = (c.r, c.g, c.b);
+            settings.light_effect_mode = ROCCAT_LIGHT_SOURCE_CUSTOM;
+        }
+
+        if led.mode != current.mode {
+            if led.mode == LedMode::Off {
+                settings.lights_enabled &= !(1 << index);
+            } else {
+                settings.lights_enabled |= 1 << index;
+            }
+        }
+    }
+
+    let current = light_mode(&read);
+    if let Some(led) = leds.iter().find(|l| l.mode != LedMode::Off && l.mode != current) {
+        let effect = settings.light_effect_type;
+        match led.mode {
+            LedMode::Solid => {
+                settings.color_flow_effect = ROCCAT_COLOR_FLOW_OFF;
+                if effect != ROCCAT_LIGHT_EFFECT_BLINKING {
+                    settings.light_effect_type = ROCCAT_LIGHT_EFFECT_FULLY_LIGHTED;
+                }
+            }
+            LedMode::Breathing => {
+                settings.color_flow_effect = ROCCAT_COLOR_FLOW_OFF;
+                if effect != ROCCAT_LIGHT_EFFECT_HEARTBEAT {
+                    settings.light_effect_type = ROCCAT_LIGHT_EFFECT_BREATHING;
+                }
+            }
+            LedMode::Cycle => {
+                if settings.color_flow_effect == ROCCAT_COLOR_FLOW_OFF {
+                    settings.color_flow_effect = ROCCAT_COLOR_FLOW_SIMULTANEOUS;
+                }
+                if effect == ROCCAT_LIGHT_EFFECT_ALL_OFF {
+                    settings.light_effect_type = ROCCAT_LIGHT_EFFECT_FULLY_LIGHTED;
+                }
+            }
+            other => tracing::warn!("Roccat: unsupported LED mode {:?}, keeping effect", other),
+        }
+    }
+    /* Lights that read as Off only because the effect was off keep
+     * their enable bits; once the effect comes on, the bits must follow
+     * the LED modes or those lights would light up too. */
+    if read.light_effect_type == ROCCAT_LIGHT_EFFECT_ALL_OFF
+        && settings.light_effect_type != ROCCAT_LIGHT_EFFECT_ALL_OFF
+    {
+        for led in leds.iter().filter(|l| (l.index as usize) < ROCCAT_NUM_LEDS) {
+            if led.mode == LedMode::Off {
+                settings.lights_enabled &= !(1 << led.index);
+            } else {
+                settings.lights_enabled |= 1 << led.index;
+            }
+        }
+    }
+
+    let duration = light_duration(&read);
+    if let Some(led) = leds.iter().find(|l| l.mode != LedMode::Off && l.effect_duration != duration) {
+        let secs = (led.effect_duration.saturating_add(500) / 1000)
+            .clamp(u32::from(ROCCAT_LIGHT_SPEED_MIN), u32::from(ROCCAT_LIGHT_SPEED_MAX)) as u8;
+        settings.light_effect_speed = ROCCAT_LIGHT_SPEED_MAX + 1 - secs;
+    }
+}
+
+/* Decode a NUL-padded macro string field. */
+fn macro_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/* Encode a macro string field, truncating on a character boundary so
+ * at least one NUL terminator remains. */
+fn fill_macro_string(field: &mut [u8; ROCCAT_MACRO_STRING_LEN], value: &str) {
+    let mut end = value.len().min(ROCCAT_MACRO_STRING_LEN - 1);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    *field = [0; ROCCAT_MACRO_STRING_LEN];
+    field[..end].copy_from_slice(&value.as_bytes()[..end]);
+}
+
+/* Translate a macro report into engine events and metadata.  Each
+ * keystroke's delay becomes a Wait after it; zero delays are omitted so
+ * the events encode back to the same keystrokes. */
+fn decode_macro(report: &RoccatMacro) -> (Vec<MacroEvent>, MacroMetadata) {
+    let mut events = Vec::new();
+    for key in report.keys.iter().take(usize::from(report.length)) {
+        let keycode = u32::from(key.keycode);
+        if key.flag & ROCCAT_MACRO_KEY_PRESS != 0 {
+            events.push(MacroEvent::KeyPress(keycode));
+        } else if key.flag & ROCCAT_MACRO_KEY_RELEASE != 0 {
+            events.push(MacroEvent::KeyRelease(keycode));
+        }
+        if key.time > 0 {
+            events.push(MacroEvent::Wait(u32::from(key.time)));
+        }
+    }
+    let metadata = MacroMetadata {
+        name: macro_string(&report.name),
+        group: macro_string(&report.group),
+        loop_count: u32::from(report.loop_count),
+    };
+    (events, metadata)
+}
+
+/* Build the macro report for a button.  `base` is the report read from
+ * the device, if any, so bytes ratbag does not manage survive.  Waits
+ * add to the preceding keystroke's delay; a leading wait and non-key
+ * events have no place in the format and are dropped. */
+fn encode_macro(base: Option<&RoccatMacro>, profile: u8, button: &ButtonInfo) -> RoccatMacro {
+    let mut report = base.copied().unwrap_or(RoccatMacro {
+        report_id: ROCCAT_REPORT_ID_MACRO,
+        report_length: ROCCAT_MACRO_REPORT_LENGTH,
+        profile,
+        button_index: button.index as u8,
+        loop_count: 1,
+        padding: [0; 24],
+        group: [0; ROCCAT_MACRO_STRING_LEN],
+        name: [0; ROCCAT_MACRO_STRING_LEN],
+        length: 0,
+        keys: [RoccatMacroEvent { keycode: 0, flag: 0, time: 0 }; ROCCAT_MAX_MACRO_LENGTH],
+        checksum: 0,
+    });
+
+    let metadata = &button.macro_metadata;
+    if metadata.loop_count > 0 {
+        report.loop_count = metadata.loop_count.min(u32::from(u8::MAX)) as u8;
+    } else if report.loop_count == 0 {
+        report.loop_count = 1;
+    }
+    /* The C driver files every macro under "g0". */
+    let group = if metadata.group.is_empty() { "g0" } else { metadata.group.as_str() };
+    fill_macro_string(&mut report.group, group);
+    fill_macro_string(&mut report.name, &metadata.name);
+
+    report.keys = [RoccatMacroEvent { keycode: 0, flag: 0, time: 0 }; ROCCAT_MAX_MACRO_LENGTH];
+    let mut count = 0;
+    for event in &button.macro_entries {
+        let (flag, keycode) = match *event {
+            MacroEvent::KeyPress(code) => (ROCCAT_MACRO_KEY_PRESS, code),
+            MacroEvent::KeyRelease(code) => (ROCCAT_MACRO_KEY_RELEASE, code),
+            MacroEvent::Wait(ms) if count > 0 => {
+                let key = &mut report.keys[count - 1];
+                key.time = (u32::from(key.time) + ms).min(u32::from(u16::MAX)) as u16;
+                continue;
+            }
+            other => {
+                tracing::warn!(
+                    "Roccat: dropping unsupported macro event {:?} on btn {}",
+                    other, button.index
+                );
+                continue;
+            }
+        };
+        if count >= ROCCAT_MAX_MACRO_LENGTH {
+            tracing::warn!("Roccat: macro on btn {} truncated to {} keystrokes",
+                           button.index, ROCCAT_MAX_MACRO_LENGTH);
+            break;
+        }
+        report.keys[count] = RoccatMacroEvent { keycode: keycode as u8, flag, time: 0 };
+        count += 1;
+    }
+    report.length = count as u16;
+    report
+}
+
+/* Apply a special report to `info`.  Profile and CPI switches move the
+ * active profile and the active profile's active resolution; the other
+ * types (quicklaunch, timer, sensitivity, multimedia, Easy-Shift presses)
+ * carry no state the daemon tracks.  Returns true if `info` changed. */
+fn apply_special_report(report: &[u8], info: &mut DeviceInfo) -> bool {
+    let [ROCCAT_REPORT_ID_SPECIAL, _, kind, data1, ..] = *report else {
+        return false;
+    };
+    let Some(index) = data1.checked_sub(1).map(u32::from) else {
+        return false;
+    };
+
+    let mut changed = false;
+    match kind {
+        ROCCAT_SPECIAL_TYPE_PROFILE if index <= u32::from(ROCCAT_PROFILE_MAX) => {
+            for profile in &mut info.profiles {
+                let should_be_active = profile.index == index;
+                if profile.is_active != should_be_active {
+                    profile.is_active = should_be_active;
+                    changed = true;
+                }
+            }
+            if changed {
+                debug!("Roccat: hardware profile switch detected -> profile {index}");
+            }
+        }
+        ROCCAT_SPECIAL_TYPE_CPI if index < u32::from(ROCCAT_NUM_DPI) => {
+            if let Some(profile) = info.profiles.iter_mut().find(|p| p.is_active) {
+                for res in &mut profile.resolutions {
+                    let should_be_active = res.index == index;
+                    if res.is_active != should_be_active {
+                        res.is_active = should_be_active;
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                debug!("Roccat: hardware resolution switch detected -> index {index}");
+            }
+        }
+        _ => {}
+    }
+    changed
+}
+
+/* True if two macro reports differ anywhere but their checksum. */
+fn macro_changed(a: &RoccatMacro, b: &RoccatMacro) -> bool {
+    a.into_bytes()[..2080] != b.into_bytes()[..2080]
+}
+
 pub struct RoccatDriver {
     name: String,
     /* Cache of the latest settings report per profile, updated during */
@@ -218,6 +583,9 @@ pub struct RoccatDriver {
     cached_settings: [Option<RoccatSettingsReport>; (ROCCAT_PROFILE_MAX + 1) as usize],
     /* Cache of the latest key mapping report per profile. */
     cached_profiles: [Option<RoccatProfileReport>; (ROCCAT_PROFILE_MAX + 1) as usize],
+    /* Macro reports as last read from or written to the device, keyed by
+     * (profile, button), so commit only rewrites macros that changed. */
+    cached_macros: BTreeMap<(u8, u8), RoccatMacro>,
 }
 
 /* Translate a raw Roccat bytecode to a unified (ActionType, mapping_value).
@@ -305,6 +673,7 @@ impl RoccatDriver {
             name: name.to_string(),
             cached_settings: [None; 5],
             cached_profiles: [None; 5],
+            cached_macros: BTreeMap::new(),
         }
     }
 
@@ -524,6 +893,16 @@ impl DeviceDriver for RoccatDriver {
         &self.name
     }
 
+    /* Profile and DPI buttons on the mouse report the switch in a
+     * special input report; watch for them while idle. */
+    fn wants_unsolicited_events(&self) -> bool {
+        true
+    }
+
+    fn is_event_report(&self, report: &[u8]) -> bool {
+        report.first() == Some(&ROCCAT_REPORT_ID_SPECIAL)
+    }
+
     async fn probe(&mut self, io: &mut DeviceIo) -> Result<()> {
         let mut buf = [0u8; 3];
         buf[0] = ROCCAT_REPORT_ID_PROFILE;
@@ -557,10 +936,19 @@ impl DeviceDriver for RoccatDriver {
 
                             if let Some(res) = profile.resolutions.iter_mut().find(|r| r.index == res_idx as u32) {
                                 res.is_active = is_active;
+                                res.is_disabled = !is_enabled;
                                 res.dpi = crate::engine::device::Dpi::Separate { x: dpi_x, y: dpi_y };
                             }
                         }
 
+                        for led_idx in 0..ROCCAT_NUM_LEDS {
+                            let led = decode_light(&settings, led_idx);
+                            match profile.find_led_mut(led_idx as u32) {
+                                Some(existing) => *existing = led,
+                                None => profile.leds.push(led),
+                            }
+                        }
+
                         let rates = [125, 250, 500, 1000];
                         if let Some(&rate) = rates.get(settings.report_rate as usize) {
                             profile.report_rate = rate;
@@ -590,21 +978,10 @@ impl DeviceDriver for RoccatDriver {
                                 if action_type == crate::engine::device::ActionType::Macro {
                                     match self.read_macro(io, profile_idx, btn_idx as u8).await {
                                         Ok(macro_rep) => {
-                                            let mut entries = Vec::new();
-                                            for j in 0..macro_rep.length as usize {
-                                                if j >= ROCCAT_MAX_MACRO_LENGTH { break; }
-                                                let ev = macro_rep.keys[j];
-                                                // Using ratbag conventions: 0=Press, 1=Release, 2=Wait
-                                                if ev.flag & 0x01 != 0 {
-                                                    entries.push((0, ev.keycode as u32));
-                                                } else if ev.flag & 0x02 != 0 {
-                                                    entries.push((1, ev.keycode as u32));
-                                                }
-                                                // Every key event has an associated wait time
-                                                let time = if ev.time > 0 { ev.time } else { 50 };
-                                                entries.push((2, time as u32));
-                                            }
+                                            let (entries, metadata) = decode_macro(&macro_rep);
                                             button_info.macro_entries = entries;
+                                            button_info.macro_metadata = metadata;
+                                            self.cached_macros.insert((profile_idx, btn_idx as u8), macro_rep);
                                         }
                                         Err(e) => tracing::warn!("Roccat: failed to read macro for btn {}: {}", btn_idx, e),
                                     }
@@ -634,6 +1011,13 @@ impl DeviceDriver for RoccatDriver {
                     let r_idx = res.index as usize;
                     if r_idx >= ROCCAT_NUM_DPI as usize { continue; }
 
+                    /* A disabled slot reads back as 0 DPI; keep its stored value. */
+                    if res.is_disabled {
+                        settings.dpi_mask &= !(1 << r_idx);
+                        continue;
+                    }
+                    settings.dpi_mask |= 1 << r_idx;
+
                     match res.dpi {
                         crate::engine::device::Dpi::Separate { x, y } => {
                             settings.xres[r_idx] = (x / 50) as u8;
@@ -655,6 +1039,8 @@ impl DeviceDriver for RoccatDriver {
                     settings.report_rate = idx as u8;
                 }
 
+                apply_lights(&mut settings, &profile.leds);
+
                 if let Err(e) = self.write_settings(io, &mut settings).await {
                     tracing::warn!("Roccat: failed to commit settings for profile {}: {}", profile.index, e);
                 } else {
@@ -671,49 +1057,17 @@ impl DeviceDriver for RoccatDriver {
                         profile_report.buttons[btn_idx * ROCCAT_BUTTON_STRIDE] = raw_action;
 
                         if button_info.action_type == crate::engine::device::ActionType::Macro {
-                            let mut macro_rep = RoccatMacro {
-                                report_id: ROCCAT_REPORT_ID_MACRO,
-                                report_length: 0x0822,
-                                profile: profile.index as u8,
-                                button_index: btn_idx as u8,
-                                active: 0x01,
-                                padding: [0; 24],
-                                group: [0; 24],
-                                name: [0; 24],
-                                length: 0,
-                                keys: [RoccatMacroEvent { keycode: 0, flag: 0, time: 0 }; ROCCAT_MAX_MACRO_LENGTH],
-                                checksum: 0,
-                            };
-                            
-                            // Initialize group and name with default values as C driver does
-                            macro_rep.group[0] = b'g'; macro_rep.group[1] = b'0';
-                            
-                            let mut count = 0;
-                            for (ev_type, val) in &button_info.macro_entries {
-                                if count >= ROCCAT_MAX_MACRO_LENGTH { break; }
-                                match *ev_type {
-                                    0 => { 
-                                        macro_rep.keys[count].flag = 0x01;
-                                        macro_rep.keys[count].keycode = *val as u8;
-                                        count += 1;
-                                    }
-                                    1 => {
-                                        macro_rep.keys[count].flag = 0x02;
-                                        macro_rep.keys[count].keycode = *val as u8;
-                                        count += 1;
-                                    }
-                                    2 => {
-                                        if count > 0 {
-                                            macro_rep.keys[count - 1].time = *val as u16;
-                                        }
-                                    }
-                                    _ => {}
-                                }
+                            let key = (profile.index as u8, btn_idx as u8);
+                            let cached = self.cached_macros.get(&key);
+                            let mut macro_rep = encode_macro(cached, profile.index as u8, button_info);
+                            if cached.is_some_and(|c| !macro_changed(c, &macro_rep)) {
+                                continue;
                             }
-                            macro_rep.length = count as u16;
-                            
+
                             if let Err(e) = self.write_macro(io, &mut macro_rep).await {
                                 tracing::warn!("Roccat: failed to write macro for btn {}: {}", btn_idx, e);
+                            } else {
+                                self.cached_macros.insert(key, macro_rep);
                             }
                         }
                     }
@@ -739,6 +1093,10 @@ impl DeviceDriver for RoccatDriver {
 
         Ok(())
     }
+
+    async fn handle_event(&mut self, report: &[u8], info: &mut DeviceInfo) -> Result<bool> {
+        Ok(apply_special_report(report, info))
+    }
 }
 
 #[cfg(test)]
@@ -773,4 +1131,244 @@ mod tests {
         assert_eq!(RoccatDriver::compute_crc(&buf), crc);
         assert!(RoccatDriver::crc_is_valid(&buf));
     }
+
+    fn macro_button(events: Vec<MacroEvent>, metadata: MacroMetadata) -> ButtonInfo {
+        ButtonInfo {
+            index: 7,
+            action_type: crate::engine::device::ActionType::Macro,
+            macro_entries: events,
+            macro_metadata: metadata,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_roccat_macro_round_trips_names_loops_and_timing() {
+        let events = vec![
+            MacroEvent::KeyPress(0x04),
+            MacroEvent::Wait(20),
+            MacroEvent::KeyRelease(0x04),
+            MacroEvent::KeyPress(0x05),
+            MacroEvent::Wait(300),
+            MacroEvent::KeyRelease(0x05),
+        ];
+        let metadata = MacroMetadata {
+            name: "Reload".into(),
+            group: "Shooter".into(),
+            loop_count: 3,
+        };
+        let report = encode_macro(None, 2, &macro_button(events.clone(), metadata.clone()));
+
+        assert_eq!((report.profile, report.button_index, report.loop_count), (2, 7, 3));
+        assert_eq!(report.length, 4);
+        assert_eq!(report.keys[0].flag, ROCCAT_MACRO_KEY_PRESS);
+        assert_eq!((report.keys[0].time, report.keys[1].time, report.keys[2].time), (20, 0, 300));
+        assert_eq!(&report.name[..7], b"Reload\0");
+
+        /* Through the wire format and back. */
+        let decoded = RoccatMacro::from_bytes(&report.into_bytes());
+        assert_eq!(decode_macro(&decoded), (events, metadata));
+    }
+
+    #[test]
+    fn test_roccat_macro_defaults_and_truncation() {
+        let report = encode_macro(
+            None,
+            0,
+            &macro_button(
+                vec![MacroEvent::Wait(10), MacroEvent::KeyPress(0x04), MacroEvent::Wheel(1)],
+                MacroMetadata { name: "x".repeat(40), ..Default::default() },
+            ),
+        );
+        /* C defaults: group "g0", played once.  The leading wait and the
+         * wheel step cannot be stored. */
+        assert_eq!(macro_string(&report.group), "g0");
+        assert_eq!(report.loop_count, 1);
+        assert_eq!(report.length, 1);
+        assert_eq!(report.keys[0].time, 0);
+        assert_eq!(macro_string(&report.name).len(), ROCCAT_MACRO_STRING_LEN - 1);
+    }
+
+    #[test]
+    fn test_roccat_unchanged_macro_is_not_rewritten() {
+        let mut read = encode_macro(
+            None,
+            1,
+            &macro_button(vec![MacroEvent::KeyPress(0x04)], MacroMetadata::default()),
+        );
+        read.padding[0] = 0xAA; /* device-private byte */
+        read.checksum = 0x1234;
+
+        let (events, metadata) = decode_macro(&read);
+        let mut button = macro_button(events, metadata);
+        let rebuilt = encode_macro(Some(&read), 1, &button);
+        assert!(!macro_changed(&read, &rebuilt));
+        assert_eq!(rebuilt.padding[0], 0xAA);
+
+        button.macro_metadata.name = "Renamed".into();
+        assert!(macro_changed(&read, &encode_macro(Some(&read), 1, &button)));
+    }
+
+    fn sample_settings() -> RoccatSettingsReport {
+        let mut buf = [0u8; 43];
+        buf[0] = ROCCAT_REPORT_ID_SETTINGS;
+        buf[1] = 43;
+        buf[6] = 0b0_0111;
+        buf[20] = 0x03; /* lights_enabled */
+        buf[21] = 0x01; /* custom colours */
+        buf[22] = ROCCAT_COLOR_FLOW_OFF;
+        buf[23] = ROCCAT_LIGHT_EFFECT_BLINKING;
+        buf[24] = 2;
+        for i in 0..ROCCAT_NUM_LEDS {
+            buf[25 + i * 4..29 + i * 4].copy_from_slice(&[i as u8 + 5, 0x10 * i as u8, 0x80, 0xff]);
+        }
+        RoccatSettingsReport::from_bytes(&buf)
+    }
+
+    #[test]
+    fn test_roccat_settings_light_fields_round_trip() {
+        let settings = sample_settings();
+        assert_eq!(settings.lights[2], RoccatLight { index: 7, red: 0x20, green: 0x80, blue: 0xff });
+        assert_eq!(RoccatSettingsReport::from_bytes(&settings.into_bytes()).into_bytes(),
+                   settings.into_bytes());
+
+        /* Blinking has no ratbag mode and reads as Solid; speed 2 is 2 s. */
+        let led = decode_light(&settings, 1);
+        assert_eq!(led.mode, LedMode::Solid);
+        assert_eq!(led.color.to_rgb(), RgbColor { r: 0x10, g: 0x80, b: 0xff });
+        assert_eq!(led.effect_duration, 2000);
+    }
+
+    #[test]
+    fn test_roccat_apply_lights_keeps_unchanged_effect() {
+        let mut settings = sample_settings();
+        let mut leds: Vec<LedInfo> = (0..ROCCAT_NUM_LEDS).map(|i| decode_light(&settings, i)).collect();
+        leds[3].color = Color::from_rgb(RgbColor { r: 1, g: 2, b: 3 });
+        apply_lights(&mut settings, &leds);
+
+        /* Only the colour changed: blinking, speed and table index stay. */
+        assert_eq!(settings.light_effect_type, ROCCAT_LIGHT_EFFECT_BLINKING);
+        assert_eq!(settings.light_effect_speed, 2);
+        assert_eq!(settings.lights[3], RoccatLight { index: 8, red: 1, green: 2, blue: 3 });
+    }
+
+    #[test]
+    fn test_roccat_apply_lights_shares_first_changed_mode() {
+        let mut settings = sample_settings();
+        let mut leds: Vec<LedInfo> = (0..ROCCAT_NUM_LEDS).map(|i| decode_light(&settings, i)).collect();
+        leds[2].mode = LedMode::Cycle;
+        leds[2].effect_duration = 1000;
+        apply_lights(&mut settings, &leds);
+        assert_eq!(settings.color_flow_effect, ROCCAT_COLOR_FLOW_SIMULTANEOUS);
+        assert_eq!(settings.light_effect_speed, ROCCAT_LIGHT_SPEED_MAX);
+        assert_eq!(light_mode(&settings), LedMode::Cycle);
+
+        leds[0].mode = LedMode::Breathing;
+        apply_lights(&mut settings, &leds);
+        assert_eq!(settings.color_flow_effect, ROCCAT_COLOR_FLOW_OFF);
+        assert_eq!(settings.light_effect_type, ROCCAT_LIGHT_EFFECT_BREATHING);
+
+        /* Turning one LED off only clears its enable bit. */
+        leds[1].mode = LedMode::Off;
+        apply_lights(&mut settings, &leds);
+        assert_eq!(settings.light_effect_type, ROCCAT_LIGHT_EFFECT_BREATHING);
+        assert_eq!(settings.lights_enabled, 0b0101);
+        assert_eq!(decode_light(&settings, 1).mode, LedMode::Off);
+    }
+
+    #[test]
+    fn test_roccat_disabled_lights_read_as_off() {
+        let settings = sample_settings();
+        /* lights_enabled = 0x03: lights 2 and 3 are dark. */
+        assert_eq!(decode_light(&settings, 0).mode, LedMode::Solid);
+        assert_eq!(decode_light(&settings, 2).mode, LedMode::Off);
+        assert_eq!(decode_light(&settings, 3).mode, LedMode::Off);
+    }
+
+    #[test]
+    fn test_roccat_new_colour_selects_custom_colours() {
+        let mut settings = sample_settings();
+        settings.light_effect_mode = ROCCAT_LIGHT_SOURCE_TABLE;
+        let mut leds: Vec<LedInfo> = (0..ROCCAT_NUM_LEDS).map(|i| decode_light(&settings, i)).collect();
+
+        apply_lights(&mut settings, &leds);
+        assert_eq!(settings.light_effect_mode, ROCCAT_LIGHT_SOURCE_TABLE);
+
+        leds[0].color = Color::from_rgb(RgbColor { r: 0xff, g: 0, b: 0 });
+        apply_lights(&mut settings, &leds);
+        assert_eq!(settings.light_effect_mode, ROCCAT_LIGHT_SOURCE_CUSTOM);
+    }
+
+    #[test]
+    fn test_roccat_effect_on_follows_led_modes() {
+        let mut settings = sample_settings();
+        settings.light_effect_type = ROCCAT_LIGHT_EFFECT_ALL_OFF;
+        settings.lights_enabled = 0x0f;
+        let mut leds: Vec<LedInfo> = (0..ROCCAT_NUM_LEDS).map(|i| decode_light(&settings, i)).collect();
+        assert!(leds.iter().all(|l| l.mode == LedMode::Off));
+
+        leds[2].mode = LedMode::Solid;
+        apply_lights(&mut settings, &leds);
+        assert_eq!(settings.light_effect_type, ROCCAT_LIGHT_EFFECT_FULLY_LIGHTED);
+        /* Only the LED that was turned on lights up. */
+        assert_eq!(settings.lights_enabled, 0b0100);
+    }
+
+    fn make_info() -> DeviceInfo {
+        use crate::engine::device_database::{DeviceEntry, DriverConfig};
+
+        let entry = DeviceEntry {
+            name: "Roccat Kone XTD".into(),
+            driver: "roccat".into(),
+            device_type: "mouse".into(),
+            matches: Vec::new(),
+            driver_config: Some(DriverConfig {
+                profiles: Some(5),
+                dpis: Some(5),
+                ..DriverConfig::default()
+            }),
+        };
+        DeviceInfo::from_entry("test0", "Roccat Kone XTD", 0x03, 0x1e7d, 0x2e22, &entry)
+    }
+
+    #[tokio::test]
+    async fn test_roccat_special_reports_follow_hardware_switches() {
+        let mut drv = RoccatDriver::new("roccat");
+        let mut info = make_info();
+        assert!(drv.is_event_report(&[ROCCAT_REPORT_ID_SPECIAL, 0, ROCCAT_SPECIAL_TYPE_CPI, 2]));
+        assert!(!drv.is_event_report(&[0x01, 0, 0, 0]));
+
+        /* Profile 3 on the device is index 2. */
+        let report = [ROCCAT_REPORT_ID_SPECIAL, 0, ROCCAT_SPECIAL_TYPE_PROFILE, 3, 0, 0, 0, 0];
+        assert!(drv.handle_event(&report, &mut info).await.unwrap());
+        let active: Vec<u32> = info.profiles.iter().filter(|p| p.is_active).map(|p| p.index).collect();
+        assert_eq!(active, vec![2]);
+        assert!(!drv.handle_event(&report, &mut info).await.unwrap());
+
+        let report = [ROCCAT_REPORT_ID_SPECIAL, 0, ROCCAT_SPECIAL_TYPE_CPI, 4, 0, 0, 0, 0];
+        assert!(drv.handle_event(&report, &mut info).await.unwrap());
+        let profile = &info.profiles[2];
+        let active: Vec<u32> = profile.resolutions.iter().filter(|r| r.is_active).map(|r| r.index).collect();
+        assert_eq!(active, vec![3]);
+        assert!(!info.profiles[0].resolutions[3].is_active);
+        assert!(info.profiles.iter().all(|p| !p.is_dirty));
+    }
+
+    #[tokio::test]
+    async fn test_roccat_ignores_unknown_and_out_of_range_special_reports() {
+        let mut drv = RoccatDriver::new("roccat");
+        let mut info = make_info();
+        let before = info.clone();
+
+        for report in [
+            &[ROCCAT_REPORT_ID_SPECIAL, 0, ROCCAT_SPECIAL_TYPE_PROFILE, 0][..],
+            &[ROCCAT_REPORT_ID_SPECIAL, 0, ROCCAT_SPECIAL_TYPE_PROFILE, 6],
+            &[ROCCAT_REPORT_ID_SPECIAL, 0, ROCCAT_SPECIAL_TYPE_CPI, 6],
+            &[ROCCAT_REPORT_ID_SPECIAL, 0, 0x60, 1], /* quicklaunch */
+            &[ROCCAT_REPORT_ID_SPECIAL, 0, ROCCAT_SPECIAL_TYPE_PROFILE],
+        ] {
+            assert!(!drv.handle_event(report, &mut info).await.unwrap());
+        }
+        assert_eq!(info.profiles[0].is_active, before.profiles[0].is_active);
+    }
 }
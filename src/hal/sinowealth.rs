@@ -9,7 +9,8 @@ use async_trait::async_trait;
 use tracing::{debug, warn};
 
 use crate::engine::device::{
-    ActionType, ButtonInfo, Color, DeviceInfo, Dpi, LedInfo, LedMode, ProfileInfo, RgbColor,
+    ActionType, BUTTON_LAYER_BASE, ButtonInfo, Color, DeviceInfo, Dpi, LedEffect, LedEffectParam,
+    LedEffectState, LedInfo, LedMode, MacroEvent, MacroMetadata, ProfileInfo, RgbColor, SensorInfo,
 };
 use crate::engine::device_database::SinowealthLedType;
 use crate::hal::{DeviceDriver, DeviceIo};
@@ -67,9 +68,18 @@ pub const SINOWEALTH_NUM_PROFILES_MAX: usize = 3;
 pub const SINOWEALTH_NUM_BUTTONS: usize = 20;
 pub const SINOWEALTH_MACRO_LENGTH_MAX: usize = 168;
 pub const SINOWEALTH_MACRO_EVENT_SIZE: usize = 3;
+/* Macro report: report ID, profile, button, then 3-byte events
+ * (type, HID usage, delay after the event in 10 ms units). */
+const SINOWEALTH_MACRO_HEADER_SIZE: usize = 3;
+const SINOWEALTH_MACRO_DELAY_UNIT_MS: u32 = 10;
+/* Macro event type bytes. Bit 0 marks a press; 0 ends the event list. */
+const SINOWEALTH_MACRO_EVENT_PRESS: u8 = 0x01;
+const SINOWEALTH_MACRO_EVENT_RELEASE: u8 = 0x02;
 
 pub const SINOWEALTH_DEBOUNCE_TIMES: &[u32] = &[4, 6, 8, 10, 12, 14, 16];
 pub const SINOWEALTH_REPORT_RATES: &[u32] = &[125, 250, 500, 1000];
+/* Lift-off distances in mm; the wire value is the distance itself. */
+pub const SINOWEALTH_LIFT_OFF_DISTANCES: &[u32] = &[1, 2];
 
 /// Bit 3 of the config byte: independent X/Y DPI.
 pub const SINOWEALTH_XY_INDEPENDENT: u8 = 0b0000_1000;
@@ -79,6 +89,8 @@ pub const SINOWEALTH_XY_INDEPENDENT: u8 = 0b0000_1000;
 mod offset {
     pub const CONFIG_FLAGS: usize = 4;
     pub const DPI_COUNT: usize = 6;
+    /* Only the cycled slots: the config has no separate shift/sniper
+     * resolution for RESOLUTION_ALTERNATE, so none is flagged `is_shift`. */
     pub const DPI_SLOTS: usize = 7;
     pub const DPI_ACTIVE_COLOR: usize = 23;
     pub const DPI_COLORS: usize = 24;
@@ -86,6 +98,9 @@ mod offset {
     pub const LED_EFFECT: usize = 77;
     pub const LED_COLOR: usize = 78;
     pub const LED_SPEED: usize = 81;
+    /* Effect-specific: direction for Glorious/Wave, colour count for
+     * Breathing7. */
+    pub const LED_EFFECT_PARAM: usize = 82;
     pub const LED_BRIGHTNESS: usize = 83;
 }
 
@@ -117,6 +132,15 @@ impl Sensor {
         }
     }
 
+    pub fn name(self) -> &'static str {
+        match self {
+            Sensor::Pmw3360 => "PMW3360",
+            Sensor::Pmw3212 => "PMW3212",
+            Sensor::Pmw3327 => "PMW3327",
+            Sensor::Pmw3389 => "PMW3389",
+        }
+    }
+
     /// PMW3389: `raw * 100`, all others: `(raw + 1) * 100`.
     pub fn raw_to_dpi(self, raw: u8) -> u32 {
         match self {
@@ -185,6 +209,52 @@ impl RgbEffect {
             _ => RgbEffect::Off,
         }
     }
+
+    /* Effects that show the configured colour rather than a built-in
+     * palette. */
+    fn uses_color(self) -> bool {
+        matches!(
+            self,
+            RgbEffect::Single
+                | RgbEffect::Constant
+                | RgbEffect::Breathing
+                | RgbEffect::Breathing1
+                | RgbEffect::Breathing7
+        )
+    }
+}
+
+const EFFECT_SPEED: LedEffectParam =
+    LedEffectParam { name: LedEffectParam::SPEED, min: 0, max: 100 };
+const EFFECT_DIRECTION: LedEffectParam =
+    LedEffectParam { name: LedEffectParam::DIRECTION, min: 0, max: 1 };
+const EFFECT_COLORS: LedEffectParam =
+    LedEffectParam { name: LedEffectParam::COLORS, min: 1, max: 7 };
+
+/* Native effects offered over DBus, ids being the hardware effect codes. */
+const SINOWEALTH_EFFECTS: &[(RgbEffect, &str, &[LedEffectParam])] = &[
+    (RgbEffect::Off, "off", &[]),
+    (RgbEffect::Glorious, "glorious", &[EFFECT_SPEED, EFFECT_DIRECTION]),
+    (RgbEffect::Single, "single", &[]),
+    (RgbEffect::Breathing7, "breathing7", &[EFFECT_SPEED, EFFECT_COLORS]),
+    (RgbEffect::Tail, "tail", &[EFFECT_SPEED]),
+    (RgbEffect::Breathing, "breathing", &[EFFECT_SPEED]),
+    (RgbEffect::Constant, "constant", &[]),
+    (RgbEffect::Rave, "rave", &[EFFECT_SPEED]),
+    (RgbEffect::Random, "random", &[EFFECT_SPEED]),
+    (RgbEffect::Wave, "wave", &[EFFECT_SPEED, EFFECT_DIRECTION]),
+    (RgbEffect::Breathing1, "breathing1", &[EFFECT_SPEED]),
+];
+
+fn sinowealth_effects() -> Vec<LedEffect> {
+    SINOWEALTH_EFFECTS
+        .iter()
+        .map(|&(effect, name, params)| LedEffect {
+            id: u32::from(effect as u8),
+            name,
+            params: params.to_vec(),
+        })
+        .collect()
 }
 
 /* ------------------------------------------------------------------ */
@@ -357,6 +427,84 @@ fn encode_button(action_type: ActionType, value: u32) -> [u8; 4] {
     }
 }
 
+/* ------------------------------------------------------------------ */
+/* Macro encoding / decoding                                            */
+/* ------------------------------------------------------------------ */
+
+/// Decode a macro report into engine events.  Each keystroke's delay
+/// becomes a `Wait` after it; zero delays are omitted so the events
+/// encode back to the same report.
+fn decode_macro(buf: &[u8]) -> Vec<MacroEvent> {
+    let mut events = Vec::new();
+    let keys = buf
+        .get(SINOWEALTH_MACRO_HEADER_SIZE..)
+        .unwrap_or_default()
+        .chunks_exact(SINOWEALTH_MACRO_EVENT_SIZE)
+        .take(SINOWEALTH_MACRO_LENGTH_MAX);
+    for key in keys {
+        let (ev_type, keycode, delay) = (key[0], u32::from(key[1]), u32::from(key[2]));
+        if ev_type == 0 {
+            break;
+        }
+        events.push(if ev_type & SINOWEALTH_MACRO_EVENT_PRESS != 0 {
+            MacroEvent::KeyPress(keycode)
+        } else {
+            MacroEvent::KeyRelease(keycode)
+        });
+        if delay > 0 {
+            events.push(MacroEvent::Wait(delay * SINOWEALTH_MACRO_DELAY_UNIT_MS));
+        }
+    }
+    events
+}
+
+/// Build the macro report for a button.  Waits add to the preceding
+/// keystroke's delay, rounded to the 10 ms unit and capped at what one
+/// byte holds; a leading wait and non-key events have no place in the
+/// format and are dropped.
+fn encode_macro(
+    report_id: ReportId,
+    profile_idx: u8,
+    button_idx: u8,
+    events: &[MacroEvent],
+) -> Vec<u8> {
+    let mut buf = vec![0u8; SINOWEALTH_MACRO_SIZE];
+    buf[0] = report_id as u8;
+    buf[1] = profile_idx;
+    buf[2] = button_idx;
+
+    let mut count = 0;
+    for event in events {
+        let (ev_type, keycode) = match *event {
+            MacroEvent::KeyPress(key) => (SINOWEALTH_MACRO_EVENT_PRESS, key),
+            MacroEvent::KeyRelease(key) => (SINOWEALTH_MACRO_EVENT_RELEASE, key),
+            MacroEvent::Wait(ms) if count > 0 => {
+                let off = SINOWEALTH_MACRO_HEADER_SIZE + (count - 1) * SINOWEALTH_MACRO_EVENT_SIZE;
+                let unit = SINOWEALTH_MACRO_DELAY_UNIT_MS;
+                let units = (ms + unit / 2) / unit;
+                buf[off + 2] = (u32::from(buf[off + 2]) + units).min(u32::from(u8::MAX)) as u8;
+                continue;
+            }
+            other => {
+                warn!("Skipping macro event {:?} the device cannot store", other);
+                continue;
+            }
+        };
+        if count >= SINOWEALTH_MACRO_LENGTH_MAX {
+            warn!(
+                "Macro on button {} truncated to {} keystrokes",
+                button_idx, SINOWEALTH_MACRO_LENGTH_MAX
+            );
+            break;
+        }
+        let off = SINOWEALTH_MACRO_HEADER_SIZE + count * SINOWEALTH_MACRO_EVENT_SIZE;
+        buf[off] = ev_type;
+        buf[off + 1] = keycode as u8;
+        count += 1;
+    }
+    buf
+}
+
 /* ------------------------------------------------------------------ */
 /* Cached hardware state                                                */
 /* ------------------------------------------------------------------ */
@@ -376,6 +524,9 @@ struct SinowealthData {
     /// Per-profile raw button buffers (up to 3).
     buttons: Vec<Vec<u8>>,
     active_profile: u8,
+    /// Angle snapping and lift-off distance as read with the 0x1b
+    /// command; `None` if the device does not answer it.
+    angle_snapping_and_lod: Option<(bool, u8)>,
 }
 
 /* ------------------------------------------------------------------ */
@@ -688,6 +839,45 @@ impl SinowealthDriver {
         }
         led.effect_duration = u32::from(cfg[offset::LED_SPEED]) * 100;
         led.brightness = u32::from(cfg[offset::LED_BRIGHTNESS]);
+
+        /* Only a native effect the mode would not write back is kept, so
+         * the generic mode and duration stay in charge of the others.  The
+         * parameters keep their raw bytes: a commit must not change what
+         * the user never touched. */
+        led.effect = if Self::effect_for_mode(mode) == Some(effect) {
+            None
+        } else {
+            SINOWEALTH_EFFECTS
+                .iter()
+                .find(|(e, _, _)| *e == effect)
+                .map(|&(e, _, params)| LedEffectState {
+                    id: u32::from(e as u8),
+                    params: params
+                        .iter()
+                        .map(|p| {
+                            let raw = match p.name {
+                                LedEffectParam::SPEED => cfg[offset::LED_SPEED],
+                                _ => cfg[offset::LED_EFFECT_PARAM],
+                            };
+                            (p.name.to_string(), u32::from(raw))
+                        })
+                        .collect(),
+                })
+        };
+    }
+
+    /* The native effect a generic LED mode is written as. */
+    fn effect_for_mode(mode: LedMode) -> Option<RgbEffect> {
+        match mode {
+            LedMode::Off => Some(RgbEffect::Off),
+            LedMode::Solid => Some(RgbEffect::Single),
+            LedMode::Breathing => Some(RgbEffect::Breathing1),
+            LedMode::Cycle => Some(RgbEffect::Glorious),
+            LedMode::ColorWave => Some(RgbEffect::Wave),
+            LedMode::Starlight => Some(RgbEffect::Random),
+            LedMode::TriColor => Some(RgbEffect::Rave),
+            LedMode::Unknown => None,
+        }
     }
 
     /* ---- Config encoding from DeviceInfo ---- */
@@ -752,22 +942,32 @@ impl SinowealthDriver {
             return;
         }
 
-        let (effect, use_color) = match led.mode {
-            LedMode::Off => (RgbEffect::Off, false),
-            LedMode::Solid => (RgbEffect::Single, true),
-            LedMode::Breathing => (RgbEffect::Breathing1, true),
-            LedMode::Cycle => (RgbEffect::Glorious, false),
-            LedMode::ColorWave => (RgbEffect::Wave, false),
-            LedMode::Starlight => (RgbEffect::Random, false),
-            LedMode::TriColor => (RgbEffect::Rave, false),
+        let effect = match &led.effect {
+            Some(state) => RgbEffect::from_byte(state.id as u8),
+            None => match Self::effect_for_mode(led.mode) {
+                Some(effect) => effect,
+                /* Nothing known to write: keep the stored effect. */
+                None => return,
+            },
         };
 
         cfg[offset::LED_EFFECT] = effect as u8;
-        if use_color {
+        if effect.uses_color() {
             let color_bytes = Self::write_color(led_type, led.color.to_rgb());
             cfg[offset::LED_COLOR..offset::LED_COLOR + 3].copy_from_slice(&color_bytes);
         }
-        cfg[offset::LED_SPEED] = (led.effect_duration / 100).min(255) as u8;
+        match &led.effect {
+            Some(state) => {
+                let speed = state.param(LedEffectParam::SPEED, u32::from(cfg[offset::LED_SPEED]));
+                cfg[offset::LED_SPEED] = speed as u8;
+                for name in [LedEffectParam::DIRECTION, LedEffectParam::COLORS] {
+                    if let Some(&value) = state.params.get(name) {
+                        cfg[offset::LED_EFFECT_PARAM] = value as u8;
+                    }
+                }
+            }
+            None => cfg[offset::LED_SPEED] = (led.effect_duration / 100).min(255) as u8,
+        }
         cfg[offset::LED_BRIGHTNESS] = led.brightness.min(255) as u8;
     }
 
@@ -810,11 +1010,36 @@ impl SinowealthDriver {
             if off + BUTTON_ENTRY_SIZE > btn_buf.len() {
                 break;
             }
-            let encoded = encode_button(button.action_type, button.mapping_value);
+            /* A macro button plays the macro stored under its own index. */
+            let value = if button.action_type == ActionType::Macro {
+                i as u32
+            } else {
+                button.mapping_value
+            };
+            let encoded = encode_button(button.action_type, value);
             btn_buf[off..off + 4].copy_from_slice(&encoded);
         }
     }
 
+    /* The 0x1b command carrying the angle snapping and lift-off distance
+     * of the first dirty profile that changes them, or None if no profile
+     * does.  The setting is device-wide, so it can be edited on any
+     * profile; `current` is what the device holds. */
+    fn angle_snapping_and_lod_cmd(
+        current: (bool, u8),
+        profiles: &[ProfileInfo],
+    ) -> Option<[u8; SINOWEALTH_CMD_SIZE]> {
+        let (snapping, lod) = profiles
+            .iter()
+            .filter(|p| p.is_dirty)
+            .map(|p| (p.angle_snapping > 0, p.lift_off_distance.max(0) as u8))
+            .find(|&wanted| wanted != current)?;
+        let mut cmd = build_cmd(CommandId::LongAngleSnappingAndLod);
+        cmd[2] = u8::from(snapping);
+        cmd[3] = lod;
+        Some(cmd)
+    }
+
     /* ---- Macro read/write ---- */
 
     fn read_macro(
@@ -822,7 +1047,7 @@ impl SinowealthDriver {
         report_id: ReportId,
         profile_idx: u8,
         button_idx: u8,
-    ) -> Result<Vec<(u32, u32)>> {
+    ) -> Result<Vec<MacroEvent>> {
         let mut cmd = build_cmd(CommandId::Macro);
         cmd[2] = profile_idx;
         cmd[3] = button_idx;
@@ -833,23 +1058,7 @@ impl SinowealthDriver {
         buf[0] = report_id as u8;
         io.get_feature_report(&mut buf)
             .context("read_macro: get_feature")?;
-
-        let mut events = Vec::new();
-        let header = 3; // report_id + 2 bytes header
-        for i in 0..SINOWEALTH_MACRO_LENGTH_MAX {
-            let off = header + i * SINOWEALTH_MACRO_EVENT_SIZE;
-            if off + SINOWEALTH_MACRO_EVENT_SIZE > buf.len() {
-                break;
-            }
-            let ev_type = buf[off];
-            if ev_type == 0 {
-                break;
-            }
-            let keycode = buf[off + 1];
-            // ev_type: bit 0 = press/release, keycode = HID usage
-            events.push((u32::from(ev_type), u32::from(keycode)));
-        }
-        Ok(events)
+        Ok(decode_macro(&buf))
     }
 
     fn write_macro(
@@ -857,23 +1066,9 @@ impl SinowealthDriver {
         report_id: ReportId,
         profile_idx: u8,
         button_idx: u8,
-        events: &[(u32, u32)],
+        events: &[MacroEvent],
     ) -> Result<()> {
-        let mut buf = vec![0u8; SINOWEALTH_MACRO_SIZE];
-        buf[0] = report_id as u8;
-        buf[1] = profile_idx;
-        buf[2] = button_idx;
-
-        let header = 3;
-        for (i, &(ev_type, keycode)) in events.iter().enumerate() {
-            if i >= SINOWEALTH_MACRO_LENGTH_MAX {
-                break;
-            }
-            let off = header + i * SINOWEALTH_MACRO_EVENT_SIZE;
-            buf[off] = ev_type as u8;
-            buf[off + 1] = keycode as u8;
-            // buf[off + 2] = delay, left as 0 (instant)
-        }
+        let buf = encode_macro(report_id, profile_idx, button_idx, events);
 
         let mut cmd = build_cmd(CommandId::Macro);
         cmd[2] = profile_idx;
@@ -939,6 +1134,7 @@ impl DeviceDriver for SinowealthDriver {
             configs: vec![config0],
             buttons: Vec::new(),
             active_profile: 0,
+            angle_snapping_and_lod: None,
         });
 
         Ok(())
@@ -1016,11 +1212,38 @@ impl DeviceDriver for SinowealthDriver {
             debug!("Active profile: {}", data.active_profile);
         }
 
+        // 3b. Angle snapping and lift-off distance: device-wide, and only
+        // long-report firmware answers the command.
+        if data.is_long {
+            let cmd = build_cmd(CommandId::LongAngleSnappingAndLod);
+            match Self::query_read(io, &cmd) {
+                Ok(resp) => {
+                    data.angle_snapping_and_lod = Some((resp[2] != 0, resp[3]));
+                    debug!("Angle snapping: {}, lift-off distance: {}", resp[2], resp[3]);
+                }
+                Err(e) => warn!("Failed to read angle snapping and lift-off distance: {}", e),
+            }
+        }
+
         // 4. Build DPI list from sensor
         let max_dpi = data.sensor.max_dpi();
         let dpi_list: Vec<u32> = (SINOWEALTH_DPI_MIN..=max_dpi)
             .step_by(SINOWEALTH_DPI_STEP as usize)
             .collect();
+        info.sensor = Some(SensorInfo {
+            name: data.sensor.name().to_string(),
+            dpi_min: SINOWEALTH_DPI_MIN,
+            dpi_max: max_dpi,
+            dpi_step: SINOWEALTH_DPI_STEP,
+            separate_xy: true,
+            lift_off_distances: if data.angle_snapping_and_lod.is_some() {
+                SINOWEALTH_LIFT_OFF_DISTANCES.to_vec()
+            } else {
+                Vec::new()
+            },
+            /* One 0x1b command covers every profile. */
+            device_wide_settings: true,
+        });
 
         // 5. Rebuild profiles in DeviceInfo with correct counts
         let num_leds = if data.led_type != LedType::None { 1 } else { 0 };
@@ -1036,6 +1259,8 @@ impl DeviceDriver for SinowealthDriver {
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: SINOWEALTH_DEBOUNCE_TIMES.to_vec(),
+                lift_off_distance: -1,
+                lift_off_distances: Vec::new(),
                 capabilities: Vec::new(),
                 resolutions: (0..SINOWEALTH_NUM_DPIS as u32)
                     .map(|ri| crate::engine::device::ResolutionInfo {
@@ -1049,6 +1274,7 @@ impl DeviceDriver for SinowealthDriver {
                         is_active: ri == 0,
                         is_default: ri == 0,
                         is_disabled: false,
+                        is_shift: false,
                     })
                     .collect(),
                 buttons: (0..data.num_buttons as u32)
@@ -1058,6 +1284,8 @@ impl DeviceDriver for SinowealthDriver {
                         action_types: vec![0, 1, 2, 3, 4],
                         mapping_value: 0x110 + bi, // default: left, right, middle, ...
                         macro_entries: Vec::new(),
+                        macro_metadata: MacroMetadata::default(),
+                        layer: BUTTON_LAYER_BASE,
                     })
                     .collect(),
                 leds: (0..num_leds as u32)
@@ -1077,6 +1305,8 @@ impl DeviceDriver for SinowealthDriver {
                         color_depth: 1,
                         effect_duration: 0,
                         brightness: 255,
+                        effects: sinowealth_effects(),
+                        effect: None,
                     })
                     .collect(),
             })
@@ -1086,6 +1316,12 @@ impl DeviceDriver for SinowealthDriver {
         for profile_idx in 0..data.num_profiles {
             Self::parse_config_into_profile(data, profile_idx, &mut info.profiles[profile_idx]);
             Self::parse_buttons_into_profile(data, profile_idx, &mut info.profiles[profile_idx]);
+            if let Some((snapping, lod)) = data.angle_snapping_and_lod {
+                let profile = &mut info.profiles[profile_idx];
+                profile.angle_snapping = i32::from(snapping);
+                profile.lift_off_distance = i32::from(lod);
+                profile.lift_off_distances = SINOWEALTH_LIFT_OFF_DISTANCES.to_vec();
+            }
 
             // Load macros for buttons that reference them
             for btn_idx in 0..data.num_buttons {
@@ -1177,6 +1413,19 @@ impl DeviceDriver for SinowealthDriver {
             }
         }
 
+        // 5b. Angle snapping and lift-off distance travel together
+        if let Some(current) = data.angle_snapping_and_lod
+            && let Some(cmd) = Self::angle_snapping_and_lod_cmd(current, &info.profiles)
+        {
+            Self::query_write(io, &cmd)
+                .context("Failed to set angle snapping and lift-off distance")?;
+            data.angle_snapping_and_lod = Some((cmd[2] != 0, cmd[3]));
+            debug!(
+                "SinoWealth: set angle snapping {}, lift-off distance {} mm",
+                cmd[2], cmd[3]
+            );
+        }
+
         // 6. Set active profile if changed
         if let Some(active) = info.profiles.iter().find(|p| p.is_active) {
             let mut cmd = build_cmd(CommandId::Profile);
@@ -1199,3 +1448,251 @@ pub fn build_cmd(cmd_id: CommandId) -> [u8; SINOWEALTH_CMD_SIZE] {
     buf[1] = cmd_id as u8;
     buf
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn macro_round_trips_keys_and_delays() {
+        let events = vec![
+            MacroEvent::KeyPress(0x04),
+            MacroEvent::Wait(50),
+            MacroEvent::KeyRelease(0x04),
+            MacroEvent::KeyPress(0x05),
+            MacroEvent::KeyRelease(0x05),
+            MacroEvent::Wait(2550),
+        ];
+        let buf = encode_macro(ReportId::ConfigLong, 1, 4, &events);
+
+        assert_eq!(buf.len(), SINOWEALTH_MACRO_SIZE);
+        assert_eq!(&buf[..3], &[ReportId::ConfigLong as u8, 1, 4]);
+        assert_eq!(&buf[3..6], &[SINOWEALTH_MACRO_EVENT_PRESS, 0x04, 5]);
+        assert_eq!(&buf[6..9], &[SINOWEALTH_MACRO_EVENT_RELEASE, 0x04, 0]);
+        assert_eq!(decode_macro(&buf), events);
+    }
+
+    #[test]
+    fn macro_encoding_drops_what_the_format_cannot_hold() {
+        let events = vec![
+            MacroEvent::Wait(100),
+            MacroEvent::ButtonPress(1),
+            MacroEvent::KeyPress(0x2c),
+            MacroEvent::Wait(4000),
+            MacroEvent::Wait(4000),
+        ];
+        let buf = encode_macro(ReportId::Config, 0, 0, &events);
+
+        /* Leading wait and mouse button dropped; the delay saturates. */
+        assert_eq!(
+            decode_macro(&buf),
+            vec![MacroEvent::KeyPress(0x2c), MacroEvent::Wait(2550)]
+        );
+
+        let long: Vec<_> = (0..200).map(|_| MacroEvent::KeyPress(0x04)).collect();
+        let buf = encode_macro(ReportId::Config, 0, 0, &long);
+        assert_eq!(decode_macro(&buf).len(), SINOWEALTH_MACRO_LENGTH_MAX);
+    }
+
+    /* A macro button plays the macro stored under its own index, whatever
+     * mapping value the button still carries. */
+    #[test]
+    fn macro_buttons_point_at_their_own_slot() {
+        let mut data = rgb_data();
+        data.num_buttons = 3;
+        data.buttons = vec![vec![0; SINOWEALTH_BUTTON_SIZE]];
+        let profile = ProfileInfo {
+            buttons: vec![
+                ButtonInfo {
+                    index: 0,
+                    action_type: ActionType::Button,
+                    mapping_value: 1,
+                    ..Default::default()
+                },
+                ButtonInfo {
+                    index: 1,
+                    action_type: ActionType::Button,
+                    mapping_value: 2,
+                    ..Default::default()
+                },
+                ButtonInfo {
+                    index: 2,
+                    action_type: ActionType::Macro,
+                    mapping_value: 7,
+                    macro_entries: vec![MacroEvent::KeyPress(0x04), MacroEvent::KeyRelease(0x04)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        SinowealthDriver::encode_buttons_from_profile(&mut data, 0, &profile);
+
+        let off = BUTTON_ENTRY_OFFSET + 2 * BUTTON_ENTRY_SIZE;
+        let entry = &data.buttons[0][off..off + BUTTON_ENTRY_SIZE];
+        assert_eq!(entry, &[HwButtonType::Macro as u8, 2, 0, 0]);
+    }
+
+    /* A lift-off distance edited on the second profile reaches the 0x1b
+     * command; clean profiles and unchanged values write nothing. */
+    #[test]
+    fn lift_off_distance_is_written_from_the_edited_profile() {
+        let profile = |index, is_dirty, lod| ProfileInfo {
+            index,
+            is_dirty,
+            angle_snapping: 1,
+            lift_off_distance: lod,
+            ..Default::default()
+        };
+        let current = (true, 1);
+
+        let profiles = vec![profile(0, false, 1), profile(1, true, 2)];
+        let cmd = SinowealthDriver::angle_snapping_and_lod_cmd(current, &profiles).unwrap();
+        assert_eq!(
+            &cmd[..4],
+            &[ReportId::Cmd as u8, CommandId::LongAngleSnappingAndLod as u8, 1, 2]
+        );
+
+        let profiles = vec![profile(0, true, 1), profile(1, false, 2)];
+        assert_eq!(SinowealthDriver::angle_snapping_and_lod_cmd(current, &profiles), None);
+    }
+
+    /* The setting is device-wide: once set on p1 and committed, a later
+     * commit of an unrelated edit on p0 must not write p0's old value. */
+    #[test]
+    fn lift_off_distance_survives_a_commit_of_another_profile() {
+        use crate::engine::device_database::{DeviceEntry, DriverConfig};
+
+        let entry = DeviceEntry {
+            name: "Test Mouse".into(),
+            driver: "sinowealth".into(),
+            device_type: "mouse".into(),
+            matches: Vec::new(),
+            driver_config: Some(DriverConfig {
+                profiles: Some(2),
+                ..DriverConfig::default()
+            }),
+        };
+        let mut info = DeviceInfo::from_entry("test0", "Test Mouse", 0x03, 0x258a, 0x0033, &entry);
+        info.sensor = Some(SensorInfo {
+            device_wide_settings: true,
+            ..SensorInfo::default()
+        });
+        for profile in &mut info.profiles {
+            profile.angle_snapping = 0;
+            profile.lift_off_distance = 1;
+        }
+        let mut device = (false, 1);
+
+        let info = info.with_profile_lift_off_distance(1, 2);
+        assert!(info.profiles.iter().all(|p| p.lift_off_distance == 2));
+        let cmd = SinowealthDriver::angle_snapping_and_lod_cmd(device, &info.profiles).unwrap();
+        assert_eq!(cmd[3], 2);
+        device = (cmd[2] != 0, cmd[3]);
+
+        let info = info.with_cleared_dirty_flags().with_profile_report_rate(0, 500);
+        assert_eq!(SinowealthDriver::angle_snapping_and_lod_cmd(device, &info.profiles), None);
+
+        let info = info.with_profile_angle_snapping(0, 1);
+        let cmd = SinowealthDriver::angle_snapping_and_lod_cmd(device, &info.profiles).unwrap();
+        assert_eq!(&cmd[2..4], &[1, 2]);
+        assert!(info.profiles.iter().all(|p| p.angle_snapping == 1));
+    }
+
+    fn rgb_data() -> SinowealthData {
+        SinowealthData {
+            firmware_version: [0; 2],
+            firmware_version_string: String::new(),
+            is_long: false,
+            sensor: Sensor::Pmw3360,
+            led_type: LedType::Rgb,
+            num_buttons: 0,
+            num_profiles: 1,
+            config_size: SINOWEALTH_CONFIG_SIZE_MIN,
+            configs: Vec::new(),
+            buttons: Vec::new(),
+            active_profile: 0,
+            angle_snapping_and_lod: None,
+        }
+    }
+
+    fn test_led() -> LedInfo {
+        LedInfo {
+            index: 0,
+            mode: LedMode::Solid,
+            modes: Vec::new(),
+            color: Color::default(),
+            secondary_color: Color::default(),
+            tertiary_color: Color::default(),
+            color_depth: 1,
+            effect_duration: 0,
+            brightness: 255,
+            effects: sinowealth_effects(),
+            effect: None,
+        }
+    }
+
+    #[test]
+    fn led_effect_parameters_round_trip() {
+        let mut led = test_led();
+        led.effect = Some(LedEffectState {
+            id: u32::from(RgbEffect::Breathing7 as u8),
+            params: [(LedEffectParam::SPEED, 40), (LedEffectParam::COLORS, 5)]
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        });
+
+        let mut cfg = vec![0u8; SINOWEALTH_CONFIG_SIZE_MIN];
+        SinowealthDriver::encode_led_effect(LedType::Rgb, &mut cfg, &led);
+        assert_eq!(cfg[offset::LED_EFFECT], RgbEffect::Breathing7 as u8);
+        assert_eq!(cfg[offset::LED_SPEED], 40);
+        assert_eq!(cfg[offset::LED_EFFECT_PARAM], 5);
+
+        let mut parsed = test_led();
+        SinowealthDriver::parse_led_effect(&rgb_data(), &cfg, &mut parsed);
+        assert_eq!(parsed.mode, LedMode::Breathing);
+        assert_eq!(parsed.effect, led.effect);
+    }
+
+    #[test]
+    fn led_mode_is_used_without_an_effect() {
+        let mut led = test_led();
+        led.mode = LedMode::ColorWave;
+        let mut cfg = vec![0u8; SINOWEALTH_CONFIG_SIZE_MIN];
+        SinowealthDriver::encode_led_effect(LedType::Rgb, &mut cfg, &led);
+        assert_eq!(cfg[offset::LED_EFFECT], RgbEffect::Wave as u8);
+
+        /* Out-of-schema bytes are kept as read and written back as is. */
+        cfg[offset::LED_EFFECT] = RgbEffect::Tail as u8;
+        cfg[offset::LED_SPEED] = 200;
+        SinowealthDriver::parse_led_effect(&rgb_data(), &cfg, &mut led);
+        let effect = led.effect.clone().unwrap();
+        assert_eq!(effect.param(LedEffectParam::SPEED, 0), 200);
+
+        let mut out = cfg.clone();
+        SinowealthDriver::encode_led_effect(LedType::Rgb, &mut out, &led);
+        assert_eq!(out, cfg);
+    }
+
+    /* A native effect with a generic equivalent follows the mode, so a new
+     * EffectDuration reaches the speed byte. */
+    #[test]
+    fn effect_duration_changes_the_speed_byte() {
+        let mut cfg = vec![0u8; SINOWEALTH_CONFIG_SIZE_MIN];
+        cfg[offset::LED_EFFECT] = RgbEffect::Breathing1 as u8;
+        cfg[offset::LED_SPEED] = 3;
+        cfg[offset::LED_BRIGHTNESS] = 255;
+
+        let mut led = test_led();
+        SinowealthDriver::parse_led_effect(&rgb_data(), &cfg, &mut led);
+        assert_eq!(led.mode, LedMode::Breathing);
+        assert_eq!(led.effect, None);
+        assert_eq!(led.effect_duration, 300);
+
+        led.effect_duration = 700;
+        SinowealthDriver::encode_led_effect(LedType::Rgb, &mut cfg, &led);
+        assert_eq!(cfg[offset::LED_EFFECT], RgbEffect::Breathing1 as u8);
+        assert_eq!(cfg[offset::LED_SPEED], 7);
+    }
+}
@@ -19,8 +19,13 @@
  *
  * Every command must be spaced out by a short settle delay; `dispatch()` folds
  * that in so individual writers never sleep by hand. There is no host-side
- * cache: `load_profiles` seeds defaults and then overlays whatever the hardware
- * reports, and `commit` writes the active profile straight to the device. */
+ * cache: `load_profiles` seeds the layout and then overlays whatever the
+ * hardware reports, and `commit` writes the active profile straight to the
+ * device.  DPI values, the report rate and LED modes that the protocol
+ * version cannot read back stay unknown (`Dpi::Unknown`, rate 0,
+ * `LedMode::Unknown`) rather than guessed, and `commit` leaves them alone
+ * until the user sets them.  An LED is written as a whole, so its colour
+ * only reaches the device once its mode is known too. */
 
 use std::time::Duration;
 
@@ -30,8 +35,8 @@ use thiserror::Error;
 use tracing::{debug, warn};
 
 use crate::engine::device::{
-    ActionType, ButtonInfo, Color, DeviceInfo, Dpi, LedInfo, LedMode, ProfileInfo, ResolutionInfo,
-    special_action,
+    ActionType, BUTTON_LAYER_BASE, BatteryStatus, ButtonInfo, Color, DeviceInfo, Dpi, LedInfo,
+    LedMode, MacroEvent, MacroMetadata, PowerInfo, ProfileInfo, ResolutionInfo, special_action,
 };
 use crate::hal::{DeviceDriver, DeviceIo};
 
@@ -76,6 +81,15 @@ const STEELSERIES_ID_SETTINGS_PROTOCOL3: u8 = 0x16;
 const STEELSERIES_ID_DPI_PROTOCOL4: u8 = 0x15;
 const STEELSERIES_ID_REPORT_RATE_PROTOCOL4: u8 = 0x17;
 
+/* Opcodes - wireless V3/V4 devices (Wireless=1 in the device database) */
+const STEELSERIES_ID_BATTERY_WIRELESS: u8 = 0x92;
+const STEELSERIES_ID_SLEEP_TIMER_WIRELESS: u8 = 0x69;
+/* Battery reply byte 1: bit 7 = charging, bits 0-6 = level in 5% steps
+ * offset by one (1 = 0%, 21 = 100%). */
+const STEELSERIES_BATTERY_CHARGING: u8 = 0x80;
+/* The sleep timer takes whole minutes from 1 to 20, sent in ms. */
+const STEELSERIES_SLEEP_MINUTES_MAX: u32 = 20;
+
 /* Buttons */
 const STEELSERIES_BUTTON_OFF: u8 = 0x00;
 const STEELSERIES_BUTTON_RES_CYCLE: u8 = 0x30;
@@ -246,6 +260,10 @@ pub enum SteelSeriesError {
      * load_profiles has parsed the config. */
     #[error("SteelSeries: driver used before load_profiles initialized the protocol version")]
     NotInitialized,
+
+    /* Battery and sleep commands exist only on wireless V3/V4 devices. */
+    #[error("SteelSeries: device has no battery or sleep timer")]
+    NoPowerSupport,
 }
 
 /* ---------------------------------------------------------------------- */
@@ -284,11 +302,13 @@ pub struct SteelseriesDriver {
     /* Parsed alongside `version`; only read from paths that already run
      * behind the version() initialization guard. */
     quirks: DeviceQuirks,
+    /* Wireless V3/V4 device: speaks the battery and sleep timer commands. */
+    has_power: bool,
 }
 
 impl SteelseriesDriver {
     pub fn new() -> Self {
-        Self { version: None, quirks: DeviceQuirks::default() }
+        Self { version: None, quirks: DeviceQuirks::default(), has_power: false }
     }
 
     /* The parsed protocol version.  Every command path runs after
@@ -358,10 +378,11 @@ impl DeviceDriver for SteelseriesDriver {
                     index: res_id,
                     is_active: res_id == 0,
                     is_default: res_id == 0,
-                    dpi: Dpi::Unified(800 * (res_id + 1)),
+                    dpi: Dpi::Unknown,
                     dpi_list: dpi_list.clone(),
                     capabilities: vec![],
                     is_disabled: false,
+                    is_shift: false,
                 })
                 .collect();
 
@@ -379,18 +400,20 @@ impl DeviceDriver for SteelseriesDriver {
                 is_active: true,
                 is_enabled: true,
                 is_dirty: false,
-                report_rate: 1000,
+                report_rate: 0,
                 report_rates: report_rates.clone(),
                 angle_snapping: -1,
                 debounce: -1,
                 debounces: vec![],
+                lift_off_distance: -1,
+                lift_off_distances: Vec::new(),
                 capabilities: vec![],
                 resolutions,
                 buttons,
                 leds,
             };
 
-            /* Attempt to override defaults by reading active hardware settings. */
+            /* Fill in whatever this protocol version can read back. */
             if let Err(e) = self.read_settings(io, &mut profile).await {
                 warn!("SteelSeries: failed to read hardware settings: {e}");
             }
@@ -404,6 +427,26 @@ impl DeviceDriver for SteelseriesDriver {
             Err(e) => debug!("SteelSeries: firmware version unavailable: {e}"),
         }
 
+        self.has_power = info.driver_config.wireless
+            && matches!(version, ProtocolVersion::V3 | ProtocolVersion::V4);
+        if self.has_power {
+            /* The sleep timer is write-only: unknown until the user sets it. */
+            let battery = match self.read_battery_status(io).await {
+                Ok(status) => Some(status),
+                /* A sleeping device does not answer; it is read again later. */
+                Err(e) => {
+                    debug!("SteelSeries: battery level unavailable: {e}");
+                    None
+                }
+            };
+            info.power = Some(PowerInfo {
+                battery,
+                sleep_timeout: None,
+                sleep_timeouts: (1..=STEELSERIES_SLEEP_MINUTES_MAX).map(|m| m * 60).collect(),
+                ..PowerInfo::default()
+            });
+        }
+
         Ok(())
     }
 
@@ -419,23 +462,40 @@ impl DeviceDriver for SteelseriesDriver {
                 )
             })?;
 
-        if let Some(res) = profile.resolutions.iter().find(|r| r.is_active) {
+        /* An unknown DPI, rate or LED mode was never read from the device
+         * nor set by the user; writing a guess would clobber the real value. */
+        if let Some(res) = profile.resolutions.iter().find(|r| r.is_active)
+            && !matches!(res.dpi, Dpi::Unknown)
+        {
             self.write_dpi(io, res, info).await?;
         }
 
         self.write_buttons(io, profile, info).await?;
 
-        for led in &profile.leds {
+        for led in profile.leds.iter().filter(|l| l.mode != LedMode::Unknown) {
             self.write_led(io, led).await?;
         }
 
-        self.write_report_rate(io, profile.report_rate).await?;
+        if profile.report_rate != 0 {
+            self.write_report_rate(io, profile.report_rate).await?;
+        }
+
+        if let Some(power) = &info.power
+            && power.is_dirty
+            && let Some(seconds) = power.sleep_timeout
+        {
+            self.write_sleep_timer(io, seconds).await?;
+        }
 
         /* Persist everything to the device's EEPROM. */
         self.write_save(io).await?;
 
         Ok(())
     }
+
+    async fn read_battery(&mut self, io: &mut DeviceIo) -> Result<Option<BatteryStatus>> {
+        Ok(Some(self.read_battery_status(io).await?))
+    }
 }
 
 /* ---------------------------------------------------------------------- */
@@ -478,6 +538,8 @@ fn build_button(btn_id: u32, button_count: u32, senseiraw: bool) -> ButtonInfo {
         action_types,
         mapping_value,
         macro_entries: vec![],
+        macro_metadata: MacroMetadata::default(),
+        layer: BUTTON_LAYER_BASE,
     }
 }
 
@@ -491,24 +553,24 @@ fn build_led(version: ProtocolVersion, led_id: u32, senseiraw: bool) -> LedInfo
         modes.push(LedMode::Cycle);
     }
 
-    let (color_depth, color, brightness) = if senseiraw {
-        /* Monochrome – brightness controls intensity */
-        (1, Color::default(), 255)
-    } else {
-        /* RGB_888 – default to blue as in the C driver */
-        (3, Color { red: 0, green: 0, blue: 255 }, 255)
-    };
+    /* Monochrome (brightness controls intensity) or RGB_888. */
+    let color_depth = if senseiraw { 1 } else { 3 };
 
+    /* No protocol version reads back the LED mode: the LED stays unknown,
+     * with zeroed colour, duration and brightness, until the user sets a
+     * mode.  V2 fills in the colour from its settings report. */
     LedInfo {
         index: led_id,
-        mode: LedMode::Solid,
+        mode: LedMode::Unknown,
         modes,
-        color,
+        color: Color::default(),
         secondary_color: Color::default(),
         tertiary_color: Color::default(),
         color_depth,
-        effect_duration: 1000,
-        brightness,
+        effect_duration: 0,
+        brightness: 0,
+        effects: Vec::new(),
+        effect: None,
     }
 }
 
@@ -712,6 +774,19 @@ impl SteelseriesDriver {
         }
     }
 
+    /* Wireless only: idle time before the mouse sleeps, rounded to whole
+     * minutes and sent as a 24-bit little-endian millisecond count. */
+    async fn write_sleep_timer(&self, io: &mut DeviceIo, seconds: u32) -> Result<(), SteelSeriesError> {
+        if !self.has_power {
+            return Err(SteelSeriesError::NoPowerSupport);
+        }
+        let minutes = seconds.div_ceil(60).clamp(1, STEELSERIES_SLEEP_MINUTES_MAX);
+        let [b0, b1, b2, _] = (minutes * 60 * 1000).to_le_bytes();
+        let mut report = Report::output(STEELSERIES_ID_SLEEP_TIMER_WIRELESS, STEELSERIES_REPORT_SIZE);
+        report.param(1, b0)?.param(2, b1)?.param(3, b2)?;
+        self.send(io, &report).await
+    }
+
     async fn write_save(&self, io: &mut DeviceIo) -> Result<(), SteelSeriesError> {
         let (opcode, len) = match self.version()? {
             ProtocolVersion::V1 => (STEELSERIES_ID_SAVE_SHORT, STEELSERIES_REPORT_SIZE_SHORT),
@@ -760,7 +835,11 @@ impl SteelseriesDriver {
                 }
             }
             /* V1 hardware has no cycle/wave-style animations. */
-            LedMode::Cycle | LedMode::ColorWave | LedMode::Starlight | LedMode::TriColor => {
+            LedMode::Cycle
+            | LedMode::ColorWave
+            | LedMode::Starlight
+            | LedMode::TriColor
+            | LedMode::Unknown => {
                 return Err(SteelSeriesError::UnsupportedLedMode { mode: led.mode });
             }
         };
@@ -898,6 +977,21 @@ impl SteelseriesDriver {
         Ok(format!("{major}.{minor}"))
     }
 
+    async fn read_battery_status(&self, io: &mut DeviceIo) -> Result<BatteryStatus, SteelSeriesError> {
+        if !self.has_power {
+            return Err(SteelSeriesError::NoPowerSupport);
+        }
+        let opcode = STEELSERIES_ID_BATTERY_WIRELESS;
+        self.send(io, &Report::output(opcode, STEELSERIES_REPORT_SIZE)).await?;
+
+        let mut buf = [0u8; STEELSERIES_REPORT_SIZE];
+        let n = self.read_reply(io, opcode, &mut buf).await?;
+        if n < 2 {
+            return Err(SteelSeriesError::MalformedReport { opcode, len: n, expected: 2 });
+        }
+        Ok(battery_status(buf[1]))
+    }
+
     async fn read_settings(
         &self,
         io: &mut DeviceIo,
@@ -908,7 +1002,7 @@ impl SteelseriesDriver {
             ProtocolVersion::V2 => STEELSERIES_ID_SETTINGS,
             ProtocolVersion::V3 => STEELSERIES_ID_SETTINGS_PROTOCOL3,
             /* V1 and V4 have no settings-read command (C driver parity):
-             * the defaults seeded by load_profiles stand. */
+             * DPI and report rate stay unknown. */
             ProtocolVersion::V1 | ProtocolVersion::V4 => return Ok(()),
         };
 
@@ -942,6 +1036,8 @@ impl SteelseriesDriver {
                     }
                 }
             }
+            /* V3 only reports the active slot; the DPI values are not
+             * part of the reply. */
             ProtocolVersion::V3 => {
                 let active_resolution = buf.get(0).copied().unwrap_or(0).saturating_sub(1);
                 for res in &mut profile.resolutions {
@@ -956,6 +1052,15 @@ impl SteelseriesDriver {
     }
 }
 
+/* Decode the battery byte of a wireless battery reply. */
+fn battery_status(raw: u8) -> BatteryStatus {
+    let steps = u32::from(raw & !STEELSERIES_BATTERY_CHARGING);
+    BatteryStatus {
+        level: (steps.saturating_sub(1) * 5).min(100),
+        is_charging: raw & STEELSERIES_BATTERY_CHARGING != 0,
+    }
+}
+
 /* ---------------------------------------------------------------------- */
 /* Button key/modifier packing                                            */
 /* ---------------------------------------------------------------------- */
@@ -980,9 +1085,9 @@ fn pack_key_button(
     let mut modifiers = 0u8;
     let mut final_key = 0u8;
 
-    for &(ev_type, k) in &button.macro_entries {
-        if ev_type == 0 {
-            /* Key press. HID usages 224..=231 are the eight modifiers. */
+    for event in &button.macro_entries {
+        if let MacroEvent::KeyPress(k) = *event {
+            /* HID usages 224..=231 are the eight modifiers. */
             match k {
                 224..=231 => modifiers |= 1 << (k - 224),
                 _ => final_key = (k % 256) as u8,
@@ -1090,7 +1195,7 @@ fn build_cycle_points(led: &LedInfo) -> Result<(bool, Vec<CyclePoint>, u16), Ste
         }
         /* No cycle-buffer encoding exists for these animations; reject the
          * commit instead of silently writing a black LED. */
-        LedMode::ColorWave | LedMode::Starlight | LedMode::TriColor => {
+        LedMode::ColorWave | LedMode::Starlight | LedMode::TriColor | LedMode::Unknown => {
             Err(SteelSeriesError::UnsupportedLedMode { mode: led.mode })
         }
     }
@@ -1223,6 +1328,9 @@ mod tests {
 
         let profile = &info.profiles[0];
         assert_eq!(profile.resolutions.len(), STEELSERIES_NUM_DPI as usize);
+        /* V1 cannot read back DPI or report rate: both stay unknown. */
+        assert!(profile.resolutions.iter().all(|r| matches!(r.dpi, Dpi::Unknown)));
+        assert_eq!(profile.report_rate, 0);
         assert_eq!(profile.buttons.len(), 6);
         /* V1 LEDs must not advertise Cycle. */
         assert_eq!(
@@ -1439,4 +1547,142 @@ mod tests {
         assert_eq!(dpi[4], 7);
         assert_eq!(dpi[7], STEELSERIES_DPI_MAGIC_MARKER);
     }
+
+    /* V2 reads back the active slot, both DPI values and the LED colour;
+     * the report rate is not in the reply and stays unknown. */
+    #[tokio::test]
+    async fn load_profiles_v2_reads_back_dpi_and_colour() {
+        let settings_request = Report::output(STEELSERIES_ID_SETTINGS, STEELSERIES_REPORT_SIZE);
+        let fw_request = Report::output(STEELSERIES_ID_FIRMWARE_PROTOCOL2, STEELSERIES_REPORT_SIZE);
+        let mut reply = vec![0u8; STEELSERIES_REPORT_SIZE];
+        reply[1] = 2; /* active slot, 1-based */
+        reply[2] = 3; /* 400 DPI */
+        reply[4] = 15; /* 1600 DPI */
+        reply[6..9].copy_from_slice(&[0x12, 0x34, 0x56]);
+        let (mut io, handle) = DeviceIo::with_mock(vec![
+            MockExchange::expect_reply(settings_request.bytes().to_vec(), reply),
+            MockExchange::expect_reply(fw_request.bytes().to_vec(), vec![0x00, 0x01]),
+        ]);
+        let mut info = make_info(Some(2), &[]);
+        let mut drv = SteelseriesDriver::new();
+
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+
+        let profile = &info.profiles[0];
+        assert!(matches!(profile.resolutions[0].dpi, Dpi::Unified(400)));
+        assert!(matches!(profile.resolutions[1].dpi, Dpi::Unified(1600)));
+        assert!(!profile.resolutions[0].is_active);
+        assert!(profile.resolutions[1].is_active);
+        assert_eq!(profile.leds[0].color, Color { red: 0x12, green: 0x34, blue: 0x56 });
+        assert_eq!(profile.report_rate, 0);
+        assert!(handle.script_exhausted());
+    }
+
+    /* Committing a V1 device that was never configured must not write
+     * guessed DPI, report rate or LED values over the real ones; an LED
+     * is written once the user picks its mode. */
+    #[tokio::test]
+    async fn commit_skips_unknown_dpi_rate_and_leds() {
+        let fw_request =
+            Report::output(STEELSERIES_ID_FIRMWARE_PROTOCOL1, STEELSERIES_REPORT_SIZE_SHORT);
+        let (mut io, _handle) = DeviceIo::with_mock(vec![MockExchange::expect_reply(
+            fw_request.bytes().to_vec(),
+            vec![0x00, 0x01],
+        )]);
+        let mut info = make_info(Some(1), &[]);
+        let mut drv = SteelseriesDriver::new();
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+
+        assert!(info.profiles[0].leds.iter().all(|l| l.mode == LedMode::Unknown));
+
+        let script = (0..2).map(|_| MockExchange::reply(Vec::new())).collect();
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        drv.commit(&mut io, &info).await.expect("commit should succeed");
+
+        let opcodes: Vec<u8> = handle.writes().iter().map(|w| w[1]).collect();
+        assert_eq!(opcodes, vec![STEELSERIES_ID_BUTTONS, STEELSERIES_ID_SAVE_SHORT]);
+
+        let info = info.with_led_mode(0, 0, LedMode::Solid);
+        let script = (0..4).map(|_| MockExchange::reply(Vec::new())).collect();
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        drv.commit(&mut io, &info).await.expect("commit should succeed");
+
+        let opcodes: Vec<u8> = handle.writes().iter().map(|w| w[1]).collect();
+        assert_eq!(
+            opcodes,
+            vec![
+                STEELSERIES_ID_BUTTONS,
+                STEELSERIES_ID_LED_EFFECT_SHORT,
+                STEELSERIES_ID_LED_COLOR_SHORT,
+                STEELSERIES_ID_SAVE_SHORT,
+            ]
+        );
+    }
+
+    #[test]
+    fn battery_byte_decodes_level_and_charging() {
+        assert_eq!(battery_status(0x01), BatteryStatus { level: 0, is_charging: false });
+        assert_eq!(battery_status(0x0b), BatteryStatus { level: 50, is_charging: false });
+        assert_eq!(battery_status(0x80 | 21), BatteryStatus { level: 100, is_charging: true });
+        /* Out-of-range steps clamp instead of overflowing the percentage. */
+        assert_eq!(battery_status(0x7f).level, 100);
+        assert_eq!(battery_status(0x00).level, 0);
+    }
+
+    /* A wireless V4 mouse gets a power state with its battery reading; a
+     * changed sleep timeout is written before the save on commit. */
+    #[tokio::test]
+    async fn wireless_v4_reads_battery_and_writes_sleep_timer() {
+        let battery_request =
+            Report::output(STEELSERIES_ID_BATTERY_WIRELESS, STEELSERIES_REPORT_SIZE);
+        let (mut io, handle) = DeviceIo::with_mock(vec![MockExchange::expect_reply(
+            battery_request.bytes().to_vec(),
+            vec![STEELSERIES_ID_BATTERY_WIRELESS, 0x80 | 15],
+        )]);
+        let mut info = make_info(Some(4), &[]);
+        info.driver_config.leds = Some(0);
+        info.driver_config.wireless = true;
+        let mut drv = SteelseriesDriver::new();
+
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+        assert!(handle.script_exhausted());
+        let power = info.power.as_ref().expect("wireless V4 exposes power state");
+        assert_eq!(power.battery, Some(BatteryStatus { level: 70, is_charging: true }));
+        assert_eq!(power.sleep_timeout, None);
+        assert_eq!(power.sleep_timeouts.first(), Some(&60));
+        assert_eq!(power.sleep_timeouts.last(), Some(&1200));
+
+        let info = info.with_sleep_timeout(300);
+        let script = (0..3).map(|_| MockExchange::reply(Vec::new())).collect();
+        let (mut io, handle) = DeviceIo::with_mock(script);
+        drv.commit(&mut io, &info).await.expect("commit should succeed");
+
+        let opcodes: Vec<u8> = handle.writes().iter().map(|w| w[1]).collect();
+        assert_eq!(
+            opcodes,
+            vec![
+                STEELSERIES_ID_BUTTONS,
+                STEELSERIES_ID_SLEEP_TIMER_WIRELESS,
+                STEELSERIES_ID_SAVE_PROTOCOL3,
+            ]
+        );
+        /* 5 minutes = 300000 ms = 0x0493e0, little-endian. */
+        assert_eq!(&handle.writes()[1][2..5], &[0xe0, 0x93, 0x04]);
+    }
+
+    #[tokio::test]
+    async fn wired_device_has_no_power_state() {
+        let (mut io, _handle) = DeviceIo::with_mock(Vec::new());
+        let mut info = make_info(Some(4), &[]);
+        info.driver_config.leds = Some(0);
+        let mut drv = SteelseriesDriver::new();
+
+        drv.load_profiles(&mut io, &mut info).await.unwrap();
+        assert!(info.power.is_none());
+        let err = drv.read_battery(&mut io).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SteelSeriesError>(),
+            Some(SteelSeriesError::NoPowerSupport)
+        ));
+    }
 }
@@ -0,0 +1,309 @@
+/* Battery monitoring: polls the battery of wireless devices through their actor, keeps the Power
+ * interface current and, when configured, turns low-battery and charging changes into desktop
+ * notifications sent to org.freedesktop.Notifications on the daemon's own bus. */
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::RwLock;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, warn};
+use zbus::zvariant::Value;
+
+use crate::engine::actor::ActorHandle;
+use crate::engine::battery::{BatteryAlert, BatteryConfig, BatteryWatch};
+use crate::engine::device::{BatteryStatus, DeviceInfo};
+
+use super::power::RatbagPower;
+
+const NOTIFICATIONS_NAME: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+const NOTIFICATIONS_IFACE: &str = "org.freedesktop.Notifications";
+
+/* Values of the `urgency` hint from the notification specification. */
+const URGENCY_LOW: u8 = 0;
+const URGENCY_NORMAL: u8 = 1;
+const URGENCY_CRITICAL: u8 = 2;
+
+/* Let the notification server pick how long a notification stays up. */
+const EXPIRE_DEFAULT: i32 = -1;
+
+/* Desktop notifications about the battery of one device.  Each device
+ * keeps a single notification on screen: a new one replaces the last. */
+pub struct BatteryNotifier {
+    conn: zbus::Connection,
+    config: Arc<BatteryConfig>,
+    device_name: String,
+    watch: BatteryWatch,
+    /* Id of the notification last shown, 0 before the first. */
+    notification_id: u32,
+}
+
+impl BatteryNotifier {
+    pub fn new(conn: zbus::Connection, config: Arc<BatteryConfig>, device_name: String) -> Self {
+        Self {
+            conn,
+            config,
+            device_name,
+            watch: BatteryWatch::default(),
+            notification_id: 0,
+        }
+    }
+
+    /* Feed a battery reading taken at `now` and show the notification it
+     * calls for, if any.  Returns the alert that was shown. */
+    pub async fn reading(&mut self, status: BatteryStatus, now: Instant) -> Option<BatteryAlert> {
+        let alert = self.watch.update(&self.config, status, now)?;
+        match self.notify(alert).await {
+            Ok(id) => {
+                self.notification_id = id;
+                Some(alert)
+            }
+            Err(e) => {
+                warn!("Battery notification for {} failed: {e}", self.device_name);
+                None
+            }
+        }
+    }
+
+    /* Send one Notify call and return the id the server assigned. */
+    async fn notify(&self, alert: BatteryAlert) -> zbus::Result<u32> {
+        let name = &self.device_name;
+        let (summary, body, icon, urgency) = match alert {
+            BatteryAlert::Low { level, threshold } => (
+                format!("{name} battery low"),
+                format!("{level}% remaining"),
+                "battery-caution",
+                if self.config.low_levels.last() == Some(&threshold) {
+                    URGENCY_CRITICAL
+                } else {
+                    URGENCY_NORMAL
+                },
+            ),
+            BatteryAlert::ChargingStarted { level } => (
+                format!("{name} is charging"),
+                format!("{level}% charged"),
+                "battery-good-charging",
+                URGENCY_LOW,
+            ),
+            BatteryAlert::ChargingStopped { level } => (
+                format!("{name} stopped charging"),
+                format!("{level}% charged"),
+                "battery-good",
+                URGENCY_LOW,
+            ),
+        };
+        let hints = HashMap::from([("urgency", Value::from(urgency))]);
+
+        let reply = self
+            .conn
+            .call_method(
+                Some(NOTIFICATIONS_NAME),
+                NOTIFICATIONS_PATH,
+                Some(NOTIFICATIONS_IFACE),
+                "Notify",
+                &(
+                    "ratbagd",
+                    self.notification_id,
+                    icon,
+                    summary,
+                    body,
+                    Vec::<&str>::new(),
+                    hints,
+                    EXPIRE_DEFAULT,
+                ),
+            )
+            .await?;
+        reply.body().deserialize()
+    }
+}
+
+/* Poll a battery-powered device every `config.poll_interval` through its
+ * actor, announce changed readings on the Power interface at
+ * `device_path` and feed them to a notifier when notifications are on.
+ *
+ * The task runs until aborted, which the server does on device removal. */
+pub fn spawn_battery_poller(
+    conn: zbus::Connection,
+    device_path: String,
+    info: Arc<RwLock<DeviceInfo>>,
+    actor: ActorHandle,
+    config: Arc<BatteryConfig>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let (name, mut last) = {
+            let info = info.read().await;
+            let power = info.power.as_ref().map(|p| (p.battery, p.is_asleep));
+            (info.name.clone(), power)
+        };
+
+        let mut notifier = config
+            .notifies()
+            .then(|| BatteryNotifier::new(conn.clone(), Arc::clone(&config), name));
+        /* The reading taken while loading the device counts too, so a
+         * mouse plugged in nearly flat warns straight away. */
+        if let (Some(notifier), Some((Some(status), _))) = (notifier.as_mut(), last) {
+            notifier.reading(status, Instant::now()).await;
+        }
+
+        let period = config.poll_interval;
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            let status = match actor.read_battery().await {
+                Ok(status) => status,
+                Err(e) => {
+                    debug!("Battery poll of {device_path} failed: {e}");
+                    continue;
+                }
+            };
+
+            /* The actor stored the reading before replying. */
+            let power = info.read().await.power.as_ref().map(|p| (p.battery, p.is_asleep));
+            if power != last {
+                last = power;
+                if let Ok(iface) = conn
+                    .object_server()
+                    .interface::<_, RatbagPower>(device_path.as_str())
+                    .await
+                {
+                    iface.get().await.battery_changed(iface.signal_emitter()).await;
+                }
+            }
+
+            if let (Some(notifier), Some(status)) = (notifier.as_mut(), status) {
+                notifier.reading(status, Instant::now()).await;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use zbus::connection::Builder;
+    use zbus::interface;
+
+    use super::*;
+
+    /* One Notify call as the fake server saw it. */
+    #[derive(Debug, Clone)]
+    struct Notification {
+        replaces_id: u32,
+        summary: String,
+        body: String,
+        urgency: u8,
+    }
+
+    /* Stand-in for a desktop notification server. */
+    struct FakeNotifications {
+        received: Arc<Mutex<Vec<Notification>>>,
+    }
+
+    #[interface(name = "org.freedesktop.Notifications")]
+    impl FakeNotifications {
+        #[allow(clippy::too_many_arguments)]
+        async fn notify(
+            &self,
+            _app_name: String,
+            replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            body: String,
+            _actions: Vec<String>,
+            hints: HashMap<String, zbus::zvariant::OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let urgency = hints
+                .get("urgency")
+                .and_then(|v| u8::try_from(v).ok())
+                .unwrap_or(URGENCY_NORMAL);
+            let mut received = self.received.lock().unwrap();
+            received.push(Notification {
+                replaces_id,
+                summary,
+                body,
+                urgency,
+            });
+            /* Reuse the replaced id, as real servers do. */
+            if replaces_id != 0 {
+                replaces_id
+            } else {
+                received.len() as u32
+            }
+        }
+    }
+
+    /* A client connection talking to a fake notification server over a
+     * private socket pair, plus the calls the server has received. */
+    async fn private_bus() -> (zbus::Connection, zbus::Connection, Arc<Mutex<Vec<Notification>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let fake = FakeNotifications {
+            received: Arc::clone(&received),
+        };
+        let (server_socket, client_socket) = tokio::net::UnixStream::pair().unwrap();
+        let server = Builder::unix_stream(server_socket)
+            .server(zbus::Guid::generate())
+            .unwrap()
+            .p2p()
+            .serve_at(NOTIFICATIONS_PATH, fake)
+            .unwrap()
+            .build();
+        let client = Builder::unix_stream(client_socket).p2p().build();
+        let (server, client) = tokio::join!(server, client);
+        (server.unwrap(), client.unwrap(), received)
+    }
+
+    fn reading(level: u32, is_charging: bool) -> BatteryStatus {
+        BatteryStatus { level, is_charging }
+    }
+
+    #[tokio::test]
+    async fn notifications_reach_the_server_and_replace_each_other() {
+        let (_server, client, received) = private_bus().await;
+        let config = Arc::new(BatteryConfig::parse("[battery]\nlow = 20, 5\n").unwrap());
+        let mut notifier = BatteryNotifier::new(client, config, "G700".into());
+        let now = Instant::now();
+
+        assert_eq!(notifier.reading(reading(40, false), now).await, None);
+        assert_eq!(
+            notifier.reading(reading(15, false), now).await,
+            Some(BatteryAlert::Low { level: 15, threshold: 20 })
+        );
+        assert!(notifier.reading(reading(4, false), now).await.is_some());
+        assert!(notifier.reading(reading(4, true), now).await.is_some());
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0].summary, "G700 battery low");
+        assert_eq!(received[0].body, "15% remaining");
+        assert_eq!(received[0].replaces_id, 0);
+        assert_eq!(received[0].urgency, URGENCY_NORMAL);
+        /* The lowest level is critical and replaces the first warning. */
+        assert_eq!(received[1].replaces_id, 1);
+        assert_eq!(received[1].urgency, URGENCY_CRITICAL);
+        assert_eq!(received[2].summary, "G700 is charging");
+        assert_eq!(received[2].urgency, URGENCY_LOW);
+    }
+
+    #[tokio::test]
+    async fn flapping_charger_is_debounced() {
+        let (_server, client, received) = private_bus().await;
+        let config = Arc::new(BatteryConfig::parse("[battery]\nlow =\ndebounce = 30\n").unwrap());
+        let mut notifier = BatteryNotifier::new(client, config, "Rival 3".into());
+        let start = Instant::now();
+
+        notifier.reading(reading(70, false), start).await;
+        for i in 1..=6 {
+            let at = start + std::time::Duration::from_secs(i * 5);
+            notifier.reading(reading(70, i % 2 == 1), at).await;
+        }
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].summary, "Rival 3 is charging");
+    }
+}
@@ -6,7 +6,7 @@ use tokio::sync::RwLock;
 use zbus::interface;
 use zbus::zvariant::{OwnedValue, Value};
 
-use crate::engine::device::{ActionType, DeviceInfo};
+use crate::engine::device::{ActionType, DeviceInfo, MacroEvent, MacroMetadata};
 
 use super::fallback_owned_value;
 
@@ -34,6 +34,31 @@ impl RatbagButton {
             button_id,
         }
     }
+
+    /* Read the button's macro metadata. */
+    async fn macro_metadata(&self) -> MacroMetadata {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .and_then(|p| p.find_button(self.button_id))
+            .map(|b| b.macro_metadata.clone())
+            .unwrap_or_default()
+    }
+
+    /* Apply `edit` to the button's macro metadata, marking the profile dirty. */
+    async fn edit_macro_metadata(
+        &self,
+        edit: impl FnOnce(&mut MacroMetadata),
+    ) -> zbus::fdo::Result<()> {
+        let mut info = self.device_info.write().await;
+        let mut metadata = info
+            .find_profile(self.profile_id)
+            .and_then(|p| p.find_button(self.button_id))
+            .map(|b| b.macro_metadata.clone())
+            .ok_or_else(|| zbus::fdo::Error::Failed("Button not found".into()))?;
+        edit(&mut metadata);
+        *info = info.with_button_macro_metadata(self.profile_id, self.button_id, metadata);
+        Ok(())
+    }
 }
 
 /// Intermediate representation for parsed button mapping values.
@@ -42,7 +67,7 @@ impl RatbagButton {
 /// keeping the critical section as short as possible.
 enum ParsedMapping {
     None,
-    Macro(Vec<(u32, u32)>),
+    Macro(Vec<MacroEvent>),
     Simple(u32),
 }
 
@@ -60,7 +85,8 @@ impl RatbagButton {
     /// - Button (1): `u32` button number
     /// - Special (2): `u32` special value
     /// - Key (3): `u32` keycode
-    /// - Macro (4): `Vec<(u32, u32)>` key events
+    /// - Macro (4): `Vec<(u32, u32)>` of `(type, value)` events; wheel and
+    ///   pointer-move steps are omitted, `MacroEvents` carries them
     /// - None (0) / Unknown (1000): `u32` with value 0
     #[zbus(property)]
     async fn mapping(&self) -> (u32, OwnedValue) {
@@ -75,8 +101,9 @@ impl RatbagButton {
 
         let value: OwnedValue = match button.action_type {
             ActionType::Macro => {
-                OwnedValue::try_from(Value::from(button.macro_entries.clone()))
-                    .unwrap_or_else(|_| fallback_owned_value())
+                let pairs: Vec<(u32, u32)> =
+                    button.macro_entries.iter().filter_map(|e| e.to_pair()).collect();
+                OwnedValue::try_from(Value::from(pairs)).unwrap_or_else(|_| fallback_owned_value())
             }
             ActionType::Button | ActionType::Special | ActionType::Key => {
                 OwnedValue::try_from(Value::from(button.mapping_value))
@@ -156,7 +183,13 @@ impl RatbagButton {
                             )
                             .into());
                         };
-                        entries.push((*a, *b));
+                        let Some(event) = MacroEvent::from_pair(*a, *b) else {
+                            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                                "Unsupported macro event type {a}"
+                            ))
+                            .into());
+                        };
+                        entries.push(event);
                     }
                     Some(ParsedMapping::Macro(entries))
                 } else {
@@ -222,6 +255,110 @@ impl RatbagButton {
             );
         }
         let _ = self.mapping_changed(&emitter).await;
+        let _ = self.macro_events_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Macro bound to the button as `(type, value, data)` events.
+    ///
+    /// Types: 0 key press, 1 key release, 2 wait, 3 mouse button press,
+    /// 4 mouse button release, 5 consumer key, 6 wheel, 7 pointer move,
+    /// 8 wait for button release, 9 repeat while pressed.  `value` is the
+    /// HID usage, mouse button number or delay in milliseconds.  `data`
+    /// is `i32` wheel ticks for type 6 and `(i32, i32)` for type 7; other
+    /// types carry a `u32` 0.  Empty unless the button is a macro.
+    #[zbus(property)]
+    async fn macro_events(&self) -> Vec<(u32, u32, OwnedValue)> {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .and_then(|p| p.find_button(self.button_id))
+            .filter(|b| b.action_type == ActionType::Macro)
+            .map(|b| b.macro_entries.iter().map(|e| macro_event_to_dbus(*e)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Bind a macro to the button, switching its action type to Macro.
+    #[zbus(property)]
+    async fn set_macro_events(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        events: Vec<(u32, u32, OwnedValue)>,
+    ) -> zbus::Result<()> {
+        let entries = events
+            .into_iter()
+            .map(|(kind, value, data)| macro_event_from_dbus(kind, value, data.into()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        {
+            let mut info = self.device_info.write().await;
+            let _ = info
+                .find_profile(self.profile_id)
+                .and_then(|p| p.find_button(self.button_id))
+                .ok_or_else(|| zbus::fdo::Error::Failed("Button not found".into()))?;
+
+            *info = info.with_button_mapping(
+                self.profile_id,
+                self.button_id,
+                ActionType::Macro,
+                0,
+                entries,
+            );
+        }
+        let _ = self.mapping_changed(&emitter).await;
+        let _ = self.macro_events_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Name stored with the button's macro on devices that keep one
+    /// (read-write).  Empty otherwise.
+    #[zbus(property)]
+    async fn macro_name(&self) -> String {
+        self.macro_metadata().await.name
+    }
+
+    #[zbus(property)]
+    async fn set_macro_name(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        name: String,
+    ) -> zbus::Result<()> {
+        self.edit_macro_metadata(|m| m.name = name).await?;
+        let _ = self.macro_name_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Macro set (group) the macro is filed under (read-write).
+    #[zbus(property)]
+    async fn macro_group(&self) -> String {
+        self.macro_metadata().await.group
+    }
+
+    #[zbus(property)]
+    async fn set_macro_group(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        group: String,
+    ) -> zbus::Result<()> {
+        self.edit_macro_metadata(|m| m.group = group).await?;
+        let _ = self.macro_group_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Number of times the macro plays per press, 0 for the device
+    /// default (read-write).
+    #[zbus(property)]
+    async fn macro_loop_count(&self) -> u32 {
+        self.macro_metadata().await.loop_count
+    }
+
+    #[zbus(property)]
+    async fn set_macro_loop_count(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        count: u32,
+    ) -> zbus::Result<()> {
+        self.edit_macro_metadata(|m| m.loop_count = count).await?;
+        let _ = self.macro_loop_count_changed(&emitter).await;
         Ok(())
     }
 
@@ -234,4 +371,56 @@ impl RatbagButton {
             .map(|b| b.action_types.clone())
             .unwrap_or_default()
     }
+
+    /// Binding layer this button belongs to (constant): 0 for the base
+    /// layer, 1 for bindings active only while shift mode is held.
+    #[zbus(property)]
+    async fn layer(&self) -> u32 {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .and_then(|p| p.find_button(self.button_id))
+            .map_or(0, |b| b.layer)
+    }
+}
+
+/* Convert a macro event into its `(type, value, data)` DBus triple. */
+fn macro_event_to_dbus(event: MacroEvent) -> (u32, u32, OwnedValue) {
+    let (value, data) = match event {
+        MacroEvent::Wheel(ticks) => (0, Value::from(ticks)),
+        MacroEvent::PointerMove { x, y } => (0, Value::from((x, y))),
+        other => (other.to_pair().map_or(0, |(_, v)| v), Value::from(0_u32)),
+    };
+    let data = OwnedValue::try_from(data).unwrap_or_else(|_| fallback_owned_value());
+    (event.kind(), value, data)
+}
+
+/* Parse one `(type, value, data)` DBus triple into a macro event. */
+fn macro_event_from_dbus(
+    kind: u32,
+    value: u32,
+    mut data: Value<'_>,
+) -> Result<MacroEvent, zbus::fdo::Error> {
+    while let Value::Value(boxed) = data {
+        data = *boxed;
+    }
+    let event = match kind {
+        MacroEvent::WHEEL => match data {
+            Value::I32(ticks) => Some(MacroEvent::Wheel(ticks)),
+            _ => None,
+        },
+        MacroEvent::POINTER_MOVE => match &data {
+            Value::Structure(s) => match s.fields() {
+                [Value::I32(x), Value::I32(y)] => Some(MacroEvent::PointerMove { x: *x, y: *y }),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => MacroEvent::from_pair(kind, value),
+    };
+    event.ok_or_else(|| {
+        zbus::fdo::Error::InvalidArgs(format!(
+            "Invalid macro event of type {kind} with data {}",
+            data.value_signature()
+        ))
+    })
 }
@@ -7,8 +7,10 @@ use zbus::interface;
 use zbus::zvariant::ObjectPath;
 
 use crate::engine::actor::ActorHandle;
-use crate::engine::device::DeviceInfo;
+use crate::engine::device::{Color, DeviceInfo, LedMode};
+use crate::engine::rules::ProfileRules;
 
+use super::led::{color_from_tuple, RatbagLed};
 use super::profile::RatbagProfile;
 
 /// The `org.freedesktop.ratbag1.Device` interface.
@@ -26,6 +28,121 @@ impl RatbagDevice {
     pub fn new(info: Arc<RwLock<DeviceInfo>>, path: String, actor: Option<ActorHandle>) -> Self {
         Self { info, path, actor }
     }
+
+    pub(crate) fn actor(&self) -> Option<&ActorHandle> {
+        self.actor.as_ref()
+    }
+
+    /// Switch to the profile `rules` pick for the focused `app`.
+    ///
+    /// Returns None if no rule applies or the profile is already active,
+    /// otherwise the profile and the outcome of the switch.  Devices
+    /// without an actor have their state switched but report an error.
+    pub(crate) async fn apply_focus_rule(
+        &self,
+        server: &zbus::ObjectServer,
+        rules: &ProfileRules,
+        app: &str,
+    ) -> Option<(u32, Result<(), String>)> {
+        let profile = {
+            let info = self.info.read().await;
+            let rule = rules.rule_for(app, &info.model, &info.name)?;
+            let target = match info.find_profile(rule.profile) {
+                None => Err(format!("Rule [{}]: no profile {}", rule.name, rule.profile)),
+                Some(p) if !p.is_enabled => {
+                    Err(format!("Rule [{}]: profile {} is disabled", rule.name, rule.profile))
+                }
+                Some(p) if p.is_active => return None,
+                Some(p) => Ok(p.index),
+            };
+            match target {
+                Ok(profile) => profile,
+                Err(e) => return Some((rule.profile, Err(e))),
+            }
+        };
+
+        let result = match &self.actor {
+            Some(actor) => actor.switch_profile(profile).await,
+            None => {
+                let mut info = self.info.write().await;
+                *info = info.with_active_profile(profile);
+                Err("No driver actor for this device".to_string())
+            }
+        };
+
+        let indices: Vec<u32> = self.info.read().await.profiles.iter().map(|p| p.index).collect();
+        for index in indices {
+            let path = format!("{}/p{}", self.path, index);
+            if let Ok(iface_ref) = server.interface::<_, RatbagProfile>(path.as_str()).await {
+                let iface = iface_ref.get().await;
+                let _ = iface.is_active_changed(iface_ref.signal_emitter()).await;
+                let _ = iface.is_dirty_changed(iface_ref.signal_emitter()).await;
+            }
+        }
+        Some((profile, result))
+    }
+
+    /// Whether any profile or power setting awaits a commit.
+    pub(crate) async fn is_dirty(&self) -> bool {
+        self.info.read().await.is_dirty()
+    }
+
+    /// Apply a `Manager.SetLightingAll` request to every LED of every
+    /// profile and notify listeners of the LEDs that changed.
+    ///
+    /// Returns `(profile, led, mode)` for every LED that changed, in any
+    /// profile; LEDs with no comparable mode are left out.
+    pub(crate) async fn apply_lighting(
+        &self,
+        server: &zbus::ObjectServer,
+        mode: LedMode,
+        colors: &[Color],
+        brightness: u32,
+    ) -> Vec<(u32, u32, LedMode)> {
+        let mut mapping = Vec::new();
+        {
+            let mut info = self.info.write().await;
+            *info = info.with_lighting_all(mode, colors, brightness);
+            for prof in &info.profiles {
+                for led in &prof.leds {
+                    if let Some(m) = mode.nearest(&led.modes) {
+                        mapping.push((prof.index, led.index, m));
+                    }
+                }
+            }
+        }
+
+        for &(profile, led, _) in &mapping {
+            let path = format!("{}/p{}/l{}", self.path, profile, led);
+            if let Ok(iface_ref) = server.interface::<_, RatbagLed>(path.as_str()).await {
+                let led = iface_ref.get().await;
+                let emitter = iface_ref.signal_emitter();
+                let _ = led.mode_changed(emitter).await;
+                let _ = led.effect_changed(emitter).await;
+                let _ = led.color_changed(emitter).await;
+                let _ = led.secondary_color_changed(emitter).await;
+                let _ = led.tertiary_color_changed(emitter).await;
+                let _ = led.brightness_changed(emitter).await;
+            }
+        }
+        mapping
+    }
+
+    /// Emit `PropertiesChanged` for `IsDirty` on each profile once the
+    /// actor has cleared the dirty flags after a successful commit.
+    pub(crate) async fn notify_committed(&self, server: &zbus::ObjectServer) {
+        let info = self.info.read().await;
+        for prof in &info.profiles {
+            let path = format!("{}/p{}", self.path, prof.index);
+            if let Ok(iface_ref) = server.interface::<_, RatbagProfile>(path.as_str()).await {
+                let _ = iface_ref
+                    .get()
+                    .await
+                    .is_dirty_changed(iface_ref.signal_emitter())
+                    .await;
+            }
+        }
+    }
 }
 
 #[interface(name = "org.freedesktop.ratbag1.Device")]
@@ -54,6 +171,24 @@ impl RatbagDevice {
         self.info.read().await.device_type
     }
 
+    /// Sensor descriptor `(name, dpi_min, dpi_max, dpi_step, separate_xy,
+    /// lift_off_distances)`.  The name is empty when the sensor part is
+    /// unknown, a step of 0 means the DPI values are not evenly spaced, and
+    /// all-zero limits mean nothing is known about the sensor.
+    #[zbus(property)]
+    async fn sensor(&self) -> (String, u32, u32, u32, bool, Vec<u32>) {
+        let info = self.info.read().await;
+        let sensor = info.sensor.clone().unwrap_or_default();
+        (
+            sensor.name,
+            sensor.dpi_min,
+            sensor.dpi_max,
+            sensor.dpi_step,
+            sensor.separate_xy,
+            sensor.lift_off_distances,
+        )
+    }
+
     /// Array of object paths to this device's profiles.
     #[zbus(property)]
     async fn profiles(&self) -> Vec<ObjectPath<'static>> {
@@ -84,24 +219,9 @@ impl RatbagDevice {
         };
 
         match actor.commit().await {
-            Ok(()) => {
+            Ok(_) => {
                 tracing::info!("Commit succeeded for {}", self.path);
-
-                /* Notify frontends that dirty flags have been cleared. */
-                let info = self.info.read().await;
-                for prof in &info.profiles {
-                    let path = format!("{}/p{}", self.path, prof.index);
-                    if let Ok(iface_ref) =
-                        server.interface::<_, RatbagProfile>(path.as_str()).await
-                    {
-                        let _ = iface_ref
-                            .get()
-                            .await
-                            .is_dirty_changed(iface_ref.signal_emitter())
-                            .await;
-                    }
-                }
-
+                self.notify_committed(server).await;
                 0
             }
             Err(e) => {
@@ -112,7 +232,47 @@ impl RatbagDevice {
         }
     }
 
+    /// Show a custom colour on several LEDs at once.
+    ///
+    /// Takes an array of `(led index, (r, g, b))`, using the LED indices of
+    /// the active profile.  The frame is applied immediately and bypasses
+    /// the profiles: nothing is marked dirty, and the stored lighting comes
+    /// back on the next commit or power cycle.
+    async fn set_led_frame(&self, frame: Vec<(u32, (u32, u32, u32))>) -> zbus::fdo::Result<()> {
+        {
+            let info = self.info.read().await;
+            let profile = info
+                .profiles
+                .iter()
+                .find(|p| p.is_active)
+                .ok_or_else(|| zbus::fdo::Error::Failed("No active profile".into()))?;
+            if let Some((led, _)) = frame.iter().find(|(led, _)| profile.find_led(*led).is_none()) {
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "Led {led} not found in profile {}",
+                    profile.index
+                )));
+            }
+        }
+
+        let Some(ref actor) = self.actor else {
+            return Err(zbus::fdo::Error::Failed(
+                "No driver actor for this device".into(),
+            ));
+        };
+
+        let colors = frame
+            .into_iter()
+            .map(|(led, rgb)| (led, color_from_tuple(rgb)))
+            .collect();
+        actor.set_led_frame(colors).await.map_err(|e| {
+            tracing::error!("LED frame failed for {}: {e}", self.path);
+            zbus::fdo::Error::Failed(e)
+        })
+    }
+
     /// Signal emitted when an error occurs during commit.
     #[zbus(signal)]
-    async fn resync(signal_emitter: &zbus::object_server::SignalEmitter<'_>) -> zbus::Result<()>;
+    pub(crate) async fn resync(
+        signal_emitter: &zbus::object_server::SignalEmitter<'_>,
+    ) -> zbus::Result<()>;
 }
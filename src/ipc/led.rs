@@ -1,11 +1,17 @@
-/* DBus LED interface: per-LED object managing mode, colors, brightness, and effect duration for a
- * profile LED, writing changes into DeviceInfo and committing via the actor. */
+/* DBus LED interface: per-LED object managing mode, vendor effects, colors, brightness, and effect
+ * duration for a profile LED, writing changes into DeviceInfo and committing via the actor. */
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use tokio::sync::RwLock;
 use zbus::interface;
 
-use crate::engine::device::{Color, DeviceInfo, LedMode};
+use crate::engine::actor::ActorHandle;
+use crate::engine::device::{Color, DeviceInfo, LedEffectState, LedMode};
+
+/// `Effect` id reported while the LED follows `Mode` rather than a
+/// vendor effect.
+pub const LED_EFFECT_NONE: u32 = u32::MAX;
 
 /// The `org.freedesktop.ratbag1.Led` interface.
 ///
@@ -18,6 +24,7 @@ pub struct RatbagLed {
     device_info: Arc<RwLock<DeviceInfo>>,
     profile_id: u32,
     led_id: u32,
+    actor: Option<ActorHandle>,
 }
 
 impl RatbagLed {
@@ -25,18 +32,20 @@ impl RatbagLed {
         device_info: Arc<RwLock<DeviceInfo>>,
         profile_id: u32,
         led_id: u32,
+        actor: Option<ActorHandle>,
     ) -> Self {
         Self {
             device_info,
             profile_id,
             led_id,
+            actor,
         }
     }
 }
 
 /// Convert a DBus RGB tuple `(u32, u32, u32)` into a [`Color`], clamping to 255.
 #[inline]
-fn color_from_tuple(t: (u32, u32, u32)) -> Color {
+pub(super) fn color_from_tuple(t: (u32, u32, u32)) -> Color {
     Color {
         red: t.0.min(255),
         green: t.1.min(255),
@@ -58,7 +67,8 @@ impl RatbagLed {
         self.led_id
     }
 
-    /// Current LED mode as a u32 discriminant (read-write).
+    /// Current LED mode as a u32 discriminant (read-write); 1000 means
+    /// the device cannot report it.
     #[zbus(property)]
     async fn mode(&self) -> u32 {
         let info = self.device_info.read().await;
@@ -97,6 +107,7 @@ impl RatbagLed {
             *info = info.with_led_mode(self.profile_id, self.led_id, led_mode);
         }
         let _ = self.mode_changed(&emitter).await;
+        let _ = self.effect_changed(&emitter).await;
         Ok(())
     }
 
@@ -110,6 +121,89 @@ impl RatbagLed {
             .unwrap_or_default()
     }
 
+    /// Vendor effects beyond `Modes` (constant), as `(id, name, params)`
+    /// where each parameter is `(name, min, max)`.  Well-known parameter
+    /// names are "speed", "direction" and "colors" (colour count).
+    #[zbus(property)]
+    async fn effects(&self) -> Vec<(u32, String, Vec<(String, u32, u32)>)> {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .and_then(|p| p.find_led(self.led_id))
+            .map(|l| {
+                l.effects
+                    .iter()
+                    .map(|e| {
+                        let params = e
+                            .params
+                            .iter()
+                            .map(|p| (p.name.to_string(), p.min, p.max))
+                            .collect();
+                        (e.id, e.name.to_string(), params)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Selected vendor effect and its parameters (read-write), or
+    /// `(LED_EFFECT_NONE, {})` while the LED follows `Mode`.  Setting
+    /// `Mode` clears the effect.
+    #[zbus(property)]
+    async fn effect(&self) -> (u32, HashMap<String, u32>) {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .and_then(|p| p.find_led(self.led_id))
+            .and_then(|l| l.effect.as_ref())
+            .map(|e| (e.id, e.params.clone().into_iter().collect()))
+            .unwrap_or((LED_EFFECT_NONE, HashMap::new()))
+    }
+
+    /// Select a vendor effect.  Parameters must be in the effect's schema
+    /// and range; omitted ones take their minimum.
+    #[zbus(property)]
+    async fn set_effect(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        effect: (u32, HashMap<String, u32>),
+    ) -> zbus::Result<()> {
+        let (id, values) = effect;
+        {
+            let mut info = self.device_info.write().await;
+            let led = info
+                .find_profile(self.profile_id)
+                .and_then(|p| p.find_led(self.led_id))
+                .ok_or_else(|| {
+                    zbus::fdo::Error::Failed(format!(
+                        "Led {} not found in profile {}", self.led_id, self.profile_id
+                    ))
+                })?;
+            let descriptor = led.effects.iter().find(|e| e.id == id).ok_or_else(|| {
+                zbus::fdo::Error::InvalidArgs(format!("Effect {id} not supported by this LED"))
+            })?;
+            let unknown = values.keys().find(|k| !descriptor.params.iter().any(|p| p.name == *k));
+            if let Some(name) = unknown {
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "Effect {} has no parameter '{name}'", descriptor.name
+                )).into());
+            }
+            let mut params = BTreeMap::new();
+            for spec in &descriptor.params {
+                let value = values.get(spec.name).copied().unwrap_or(spec.min);
+                if !(spec.min..=spec.max).contains(&value) {
+                    return Err(zbus::fdo::Error::InvalidArgs(format!(
+                        "Effect parameter {} = {value} out of range {}-{}",
+                        spec.name, spec.min, spec.max
+                    )).into());
+                }
+                params.insert(spec.name.to_string(), value);
+            }
+            let state = LedEffectState { id, params };
+            *info = info.with_led_effect(self.profile_id, self.led_id, state);
+        }
+        let _ = self.effect_changed(&emitter).await;
+        Ok(())
+    }
+
     /// Primary LED color as an RGB triplet (read-write).
     #[zbus(property)]
     async fn color(&self) -> (u32, u32, u32) {
@@ -299,4 +393,36 @@ impl RatbagLed {
         let _ = self.brightness_changed(&emitter).await;
         Ok(())
     }
+
+    /// Show this LED's current settings on the device without persisting
+    /// them.
+    ///
+    /// Uses the driver's volatile LED path, which skips the flash or
+    /// EEPROM write that `Commit` does, so a colour picker can update the
+    /// device continuously.  Nothing is marked clean: `Commit` still
+    /// persists the settings, and until then the stored lighting returns
+    /// on the next commit or power cycle.
+    async fn preview(&self) -> zbus::fdo::Result<()> {
+        let led = {
+            let info = self.device_info.read().await;
+            info.find_profile(self.profile_id)
+                .and_then(|p| p.find_led(self.led_id))
+                .cloned()
+                .ok_or_else(|| {
+                    zbus::fdo::Error::Failed(format!(
+                        "Led {} not found in profile {}", self.led_id, self.profile_id
+                    ))
+                })?
+        };
+
+        let Some(ref actor) = self.actor else {
+            return Err(zbus::fdo::Error::Failed(
+                "No driver actor for this device".into(),
+            ));
+        };
+        actor.preview_led(led).await.map_err(|e| {
+            tracing::error!("LED preview failed for led {}: {e}", self.led_id);
+            zbus::fdo::Error::Failed(e)
+        })
+    }
 }
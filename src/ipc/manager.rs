@@ -1,8 +1,19 @@
 /* DBus Manager interface: entry point that tracks device object paths and, under dev-hooks, injects
  * or resets synthetic test devices. */
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tokio::task::JoinSet;
+use tracing::{info, warn};
 use zbus::interface;
 use zbus::zvariant::ObjectPath;
 
+use crate::engine::device::{Color, LedMode};
+use crate::engine::rules::ProfileRules;
+
+use super::device::RatbagDevice;
+use super::led::color_from_tuple;
+
 /// DBus API version. Must match the C daemon's value for client compatibility.
 pub const API_VERSION: i32 = 2;
 
@@ -10,8 +21,6 @@ pub const API_VERSION: i32 = 2;
 use crate::udev_monitor::DeviceAction;
 #[cfg(feature = "dev-hooks")]
 use tokio::sync::mpsc;
-#[cfg(feature = "dev-hooks")]
-use tracing::{info, warn};
 
 /// The `org.freedesktop.ratbag1.Manager` interface.
 ///
@@ -21,6 +30,14 @@ use tracing::{info, warn};
 pub struct RatbagManager {
     devices: Vec<String>,
 
+    /// Profile switching rules for `SetFocusedApplication`; empty if the
+    /// rules engine is not configured.
+    rules: ProfileRules,
+    /// The application most recently reported as focused, once every
+    /// switch it asked for succeeded.  Behind a lock so that focus reports
+    /// do not hold the interface's write lock while devices switch.
+    focused_app: Mutex<Option<String>>,
+
     /// Channel to inject synthetic test devices into the main event loop.
     /// Only present when the `dev-hooks` feature is enabled.
     #[cfg(feature = "dev-hooks")]
@@ -37,6 +54,8 @@ impl Default for RatbagManager {
     fn default() -> Self {
         Self {
             devices: Vec::new(),
+            rules: ProfileRules::default(),
+            focused_app: Mutex::new(None),
             #[cfg(feature = "dev-hooks")]
             test_device_tx: None,
             #[cfg(feature = "dev-hooks")]
@@ -58,6 +77,11 @@ impl RatbagManager {
         self.devices.retain(|p| p != path);
     }
 
+    /// Install the profile switching rules used by `SetFocusedApplication`.
+    pub fn set_rules(&mut self, rules: ProfileRules) {
+        self.rules = rules;
+    }
+
     /// Wire up the test device channel.
     ///
     /// Must be called before `LoadTestDevice` will function.
@@ -84,6 +108,260 @@ impl RatbagManager {
             .collect()
     }
 
+    /// Apply one lighting setup to every LED of every device, then commit
+    /// all of them.
+    ///
+    /// `mode` is a Led `Mode` value, `colors` fill the primary, secondary
+    /// and tertiary colours in order (fewer leave the rest unchanged) and
+    /// `brightness` is 0-255.  Each LED shows the nearest mode it supports,
+    /// e.g. wave falls back to cycle, then breathing, then solid.
+    ///
+    /// Returns `(device, [(profile, led, mode)], error)` for every device:
+    /// the mode each LED of each profile was set to, and an error message
+    /// that is empty unless the device could not be committed.  Devices are
+    /// committed concurrently and a failure on one does not stop the rest.
+    async fn set_lighting_all(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        mode: u32,
+        colors: Vec<(u32, u32, u32)>,
+        brightness: u32,
+    ) -> zbus::fdo::Result<Vec<(ObjectPath<'static>, Vec<(u32, u32, u32)>, String)>> {
+        let mode = LedMode::from_u32(mode)
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("Invalid LED mode {mode}")))?;
+        if colors.len() > 3 {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "At most 3 colors are supported, got {}",
+                colors.len()
+            )));
+        }
+        if brightness > 255 {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "Brightness {brightness} out of range 0-255"
+            )));
+        }
+        let colors: Vec<Color> = colors.into_iter().map(color_from_tuple).collect();
+
+        let mut results = Vec::new();
+        let mut devices = Vec::new();
+        let mut commits = JoinSet::new();
+        let mut tasks = HashMap::new();
+        for path in &self.devices {
+            let (Ok(object_path), Ok(iface_ref)) = (
+                ObjectPath::try_from(path.clone()),
+                server.interface::<_, RatbagDevice>(path.as_str()).await,
+            ) else {
+                continue;
+            };
+            let device = iface_ref.get().await;
+            let mapping = device.apply_lighting(server, mode, &colors, brightness).await;
+
+            /* Nothing to write if no LED could show the requested mode. */
+            let mut error = String::new();
+            if !mapping.is_empty() {
+                match device.actor() {
+                    Some(actor) => {
+                        let actor = actor.clone();
+                        let idx = results.len();
+                        let task = commits.spawn(async move { (idx, actor.commit().await) });
+                        tasks.insert(task.id(), idx);
+                    }
+                    None => error = "No driver actor for this device".to_string(),
+                }
+            }
+            drop(device);
+
+            let mapping = mapping.into_iter().map(|(p, led, m)| (p, led, m as u32)).collect();
+            results.push((object_path, mapping, error));
+            devices.push(iface_ref);
+        }
+
+        while let Some(joined) = commits.join_next().await {
+            let (idx, outcome) = match joined {
+                Ok(joined) => joined,
+                Err(e) => (tasks[&e.id()], Err(format!("Commit task failed: {e}"))),
+            };
+            let iface_ref = &devices[idx];
+            match outcome {
+                Ok(_) => iface_ref.get().await.notify_committed(server).await,
+                Err(e) => {
+                    warn!("SetLightingAll: commit failed for {}: {e}", results[idx].0);
+                    let _ = RatbagDevice::resync(iface_ref.signal_emitter()).await;
+                    results[idx].2 = e;
+                }
+            }
+        }
+
+        info!(
+            "SetLightingAll: mode {:?} applied to {} device(s)",
+            mode,
+            results.len()
+        );
+        Ok(results)
+    }
+
+    /// Commit every device with pending changes.
+    ///
+    /// Devices are committed concurrently.  Returns a map from each dirty
+    /// device to an error message that is empty if the device now holds
+    /// the new settings.  When `rollback` is true and any commit fails,
+    /// the devices that did commit get their previous settings written
+    /// back and report why; a device whose rollback fails reports that
+    /// error instead.  A device that another client committed to in the
+    /// meantime keeps that commit and reports the rollback as refused.
+    /// Devices whose state changed emit `Resync`.
+    async fn commit_all(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        rollback: bool,
+    ) -> HashMap<ObjectPath<'static>, String> {
+        let mut results: Vec<(ObjectPath<'static>, String)> = Vec::new();
+        let mut devices = Vec::new();
+        let mut actors = Vec::new();
+        let mut commits = JoinSet::new();
+        let mut tasks = HashMap::new();
+        for path in &self.devices {
+            let (Ok(object_path), Ok(iface_ref)) = (
+                ObjectPath::try_from(path.clone()),
+                server.interface::<_, RatbagDevice>(path.as_str()).await,
+            ) else {
+                continue;
+            };
+            let device = iface_ref.get().await;
+            if !device.is_dirty().await {
+                continue;
+            }
+
+            let mut error = String::new();
+            let actor = device.actor().cloned();
+            match &actor {
+                Some(actor) => {
+                    let actor = actor.clone();
+                    let idx = results.len();
+                    let task = commits.spawn(async move { (idx, actor.commit().await) });
+                    tasks.insert(task.id(), idx);
+                }
+                None => error = "No driver actor for this device".to_string(),
+            }
+            drop(device);
+
+            results.push((object_path, error));
+            devices.push(iface_ref);
+            actors.push(actor);
+        }
+
+        let mut committed = Vec::new();
+        while let Some(joined) = commits.join_next().await {
+            let (idx, outcome) = match joined {
+                Ok(joined) => joined,
+                /* A panicked commit may have left the device half-written. */
+                Err(e) => (tasks[&e.id()], Err(format!("Commit task failed: {e}"))),
+            };
+            match outcome {
+                Ok(generation) => committed.push((idx, generation)),
+                Err(e) => {
+                    warn!("CommitAll: commit failed for {}: {e}", results[idx].0);
+                    results[idx].1 = e;
+                }
+            }
+        }
+
+        let failed = results.iter().any(|(_, error)| !error.is_empty());
+        if failed && rollback {
+            let mut rollbacks = JoinSet::new();
+            let mut tasks = HashMap::new();
+            for &(idx, generation) in &committed {
+                if let Some(actor) = actors[idx].clone() {
+                    let task =
+                        rollbacks.spawn(async move { (idx, actor.rollback(generation).await) });
+                    tasks.insert(task.id(), idx);
+                }
+            }
+            committed.clear();
+            while let Some(joined) = rollbacks.join_next().await {
+                let (idx, outcome) = match joined {
+                    Ok(joined) => joined,
+                    Err(e) => (tasks[&e.id()], Err(format!("Rollback task failed: {e}"))),
+                };
+                results[idx].1 = match outcome {
+                    Ok(()) => "Rolled back after another device failed to commit".to_string(),
+                    Err(e) => {
+                        warn!("CommitAll: rollback failed for {}: {e}", results[idx].0);
+                        format!("Rollback failed: {e}")
+                    }
+                };
+            }
+        }
+
+        for (idx, iface_ref) in devices.iter().enumerate() {
+            if committed.iter().any(|&(i, _)| i == idx) {
+                iface_ref.get().await.notify_committed(server).await;
+            } else {
+                let _ = RatbagDevice::resync(iface_ref.signal_emitter()).await;
+            }
+        }
+
+        info!(
+            "CommitAll: {} of {} dirty device(s) committed",
+            committed.len(),
+            results.len()
+        );
+        results.into_iter().collect()
+    }
+
+    /// Report the application that has keyboard focus.
+    ///
+    /// `app_id` is a Wayland app id or an X11 window class, as sent by a
+    /// compositor script on every focus change.  Each device switches to
+    /// the profile the rules file assigns to the application; devices no
+    /// rule covers are left alone, as are repeated reports of the same
+    /// application.  Without a rules file this does nothing.
+    ///
+    /// Returns `(device, profile, error)` for every device that switched
+    /// or tried to; the error is empty on success.  After a failed switch,
+    /// reporting the same application again retries it.
+    async fn set_focused_application(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        app_id: String,
+    ) -> Vec<(ObjectPath<'static>, u32, String)> {
+        let already_focused = self.focused_app.lock().unwrap().as_deref() == Some(app_id.as_str());
+        if self.rules.is_empty() || already_focused {
+            return Vec::new();
+        }
+
+        let mut results = Vec::new();
+        for path in &self.devices {
+            let (Ok(object_path), Ok(iface_ref)) = (
+                ObjectPath::try_from(path.clone()),
+                server.interface::<_, RatbagDevice>(path.as_str()).await,
+            ) else {
+                continue;
+            };
+            let device = iface_ref.get().await;
+            let Some((profile, outcome)) =
+                device.apply_focus_rule(server, &self.rules, &app_id).await
+            else {
+                continue;
+            };
+            let error = match outcome {
+                Ok(()) => {
+                    info!("Focus on '{app_id}': {path} switched to profile {profile}");
+                    String::new()
+                }
+                Err(e) => {
+                    warn!("Focus on '{app_id}': {path} could not switch to profile {profile}: {e}");
+                    e
+                }
+            };
+            results.push((object_path, profile, error));
+        }
+        if results.iter().all(|(_, _, error)| error.is_empty()) {
+            *self.focused_app.lock().unwrap() = Some(app_id);
+        }
+        results
+    }
+
     /// Load a synthetic test device from a JSON description.
     ///
     /// The JSON format mirrors the C `ratbagd-json.c` schema.
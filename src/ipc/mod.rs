@@ -1,10 +1,14 @@
-/* DBus surface: zbus interface implementations for Manager/Device/Profile/Resolution/Button/LED,
- * plus helpers to register devices and translate device actions from udev. */
+/* DBus surface: zbus interface implementations for Manager/Device/Profile/Resolution/Button/LED
+ * plus the optional Receiver/Power device interfaces, battery polling and notifications, and
+ * helpers to register devices and translate device actions from udev. */
+pub mod battery_monitor;
 pub mod button;
 pub mod device;
 pub mod led;
 pub mod manager;
+pub mod power;
 pub mod profile;
+pub mod receiver;
 pub mod resolution;
 
 use std::collections::{HashMap, HashSet};
@@ -18,8 +22,10 @@ use zbus::connection::Builder;
 use zbus::zvariant::OwnedValue;
 
 use crate::engine::actor::{self, ActorHandle};
+use crate::engine::battery::{self, BatteryConfig};
 use crate::engine::device::DeviceInfo;
 use crate::engine::device_database::{BusType, DeviceDb};
+use crate::engine::rules::{self, ProfileRules};
 use crate::hal;
 use crate::udev_monitor::DeviceAction;
 
@@ -46,10 +52,13 @@ fn is_permission_denied(err: &anyhow::Error) -> bool {
 
 /* D-Bus interface tag stored alongside each object path so that teardown
  * removes only the correct interface type in O(n) rather than blindly
- * attempting all five types per path. */
+ * attempting every type per path.  The device path carries extra
+ * entries for the Receiver and Power interfaces when registered. */
 #[derive(Debug, Clone, Copy)]
 enum IfaceKind {
     Device,
+    Receiver,
+    Power,
     Profile,
     Resolution,
     Button,
@@ -75,7 +84,7 @@ async fn register_device_on_dbus(
     let device_obj = device::RatbagDevice::new(
         Arc::clone(&shared_info),
         device_path.to_owned(),
-        actor_handle,
+        actor_handle.clone(),
     );
 
     if let Err(e) = object_server.at(device_path, device_obj).await {
@@ -85,6 +94,33 @@ async fn register_device_on_dbus(
     }
     object_paths.push((device_path.to_owned(), IfaceKind::Device));
 
+    /* Devices behind a wireless receiver also get the Receiver interface
+     * on the same path; it needs the actor to reach the hardware. */
+    if shared_info.read().await.has_receiver
+        && let Some(actor) = &actor_handle
+    {
+        let receiver_obj = receiver::RatbagReceiver::new(device_path.to_owned(), actor.clone());
+        if let Err(e) = object_server.at(device_path, receiver_obj).await {
+            warn!("Failed to register receiver at {device_path}: {e}");
+        } else {
+            object_paths.push((device_path.to_owned(), IfaceKind::Receiver));
+        }
+    }
+
+    /* Battery-powered devices also get the Power interface. */
+    if shared_info.read().await.power.is_some() {
+        let power_obj = power::RatbagPower::new(
+            Arc::clone(&shared_info),
+            device_path.to_owned(),
+            actor_handle.clone(),
+        );
+        if let Err(e) = object_server.at(device_path, power_obj).await {
+            warn!("Failed to register power interface at {device_path}: {e}");
+        } else {
+            object_paths.push((device_path.to_owned(), IfaceKind::Power));
+        }
+    }
+
     /* Register Profile, Resolution, Button, LED child objects.
      * We snapshot the structure for iteration but children hold the shared
      * Arc so mutations propagate correctly to the commit path. */
@@ -95,6 +131,7 @@ async fn register_device_on_dbus(
             Arc::clone(&shared_info),
             device_path.to_owned(),
             prof.index,
+            actor_handle.clone(),
         );
         if let Err(e) = object_server.at(profile_path.as_str(), profile_obj).await {
             warn!("Failed to register profile {profile_path}: {e}");
@@ -134,6 +171,7 @@ async fn register_device_on_dbus(
                 Arc::clone(&shared_info),
                 prof.index,
                 led_info.index,
+                actor_handle.clone(),
             );
             if let Err(e) = object_server.at(led_path.as_str(), led_obj).await {
                 warn!("Failed to register LED {led_path}: {e}");
@@ -170,6 +208,10 @@ async fn remove_device(
             let result = match kind {
                 IfaceKind::Device =>
                     object_server.remove::<device::RatbagDevice, _>(path.as_str()).await,
+                IfaceKind::Receiver =>
+                    object_server.remove::<receiver::RatbagReceiver, _>(path.as_str()).await,
+                IfaceKind::Power =>
+                    object_server.remove::<power::RatbagPower, _>(path.as_str()).await,
                 IfaceKind::Profile =>
                     object_server.remove::<profile::RatbagProfile, _>(path.as_str()).await,
                 IfaceKind::Resolution =>
@@ -269,6 +311,10 @@ struct ServerState {
     sysname_to_dedup_key: HashMap<String, String>,
     /* Probe-failed devices awaiting a wake-triggered re-probe. */
     pending_devices: HashMap<String, PendingDevice>,
+    /* Battery pollers of registered wireless devices, by sysname. */
+    battery_pollers: HashMap<String, tokio::task::JoinHandle<()>>,
+    /* Battery polling and notification settings. */
+    battery_config: Arc<BatteryConfig>,
 }
 
 /* Outcome of `probe_and_register` for one hidraw node. */
@@ -505,6 +551,17 @@ async fn probe_and_register(
         );
     }
 
+    if shared_info.read().await.power.is_some() {
+        let poller = battery_monitor::spawn_battery_poller(
+            conn.clone(),
+            device_path.clone(),
+            Arc::clone(&shared_info),
+            actor_handle.clone(),
+            Arc::clone(&state.battery_config),
+        );
+        state.battery_pollers.insert(dev.sysname.clone(), poller);
+    }
+
     state
         .actor_handles
         .insert(dev.sysname.clone(), actor_handle);
@@ -527,6 +584,51 @@ async fn probe_and_register(
     AddOutcome::Registered
 }
 
+/* Load the optional profile switching rules.  A missing file simply
+ * leaves the rules engine off; a broken one is reported and ignored. */
+fn load_profile_rules() -> ProfileRules {
+    let Some(path) = rules::default_rules_path() else {
+        return ProfileRules::default();
+    };
+    if !path.exists() {
+        info!("No profile rules at {}; focus-based switching disabled", path.display());
+        return ProfileRules::default();
+    }
+    match ProfileRules::load(&path) {
+        Ok(rules) => {
+            info!("Loaded {} profile rule(s) from {}", rules.len(), path.display());
+            rules
+        }
+        Err(e) => {
+            warn!("Ignoring profile rules in {}: {e}", path.display());
+            ProfileRules::default()
+        }
+    }
+}
+
+/* Load the battery polling and notification settings.  Without the
+ * file the battery is polled but nothing is notified; a broken one is
+ * reported and ignored. */
+fn load_battery_config() -> BatteryConfig {
+    let Some(path) = battery::default_config_path() else {
+        return BatteryConfig::default();
+    };
+    if !path.exists() {
+        info!("No battery settings at {}; battery notifications disabled", path.display());
+        return BatteryConfig::default();
+    }
+    match BatteryConfig::load(&path) {
+        Ok(config) => {
+            info!("Loaded battery settings from {}", path.display());
+            config
+        }
+        Err(e) => {
+            warn!("Ignoring battery settings in {}: {e}", path.display());
+            BatteryConfig::default()
+        }
+    }
+}
+
 /// Start the DBus server and register all interfaces.
 ///
 /// This function blocks until the daemon is shut down. It receives device
@@ -535,7 +637,8 @@ pub async fn run_server(
     mut device_rx: mpsc::Receiver<DeviceAction>,
     device_db: DeviceDb,
 ) -> Result<()> {
-    let manager = manager::RatbagManager::default();
+    let mut manager = manager::RatbagManager::default();
+    manager.set_rules(load_profile_rules());
 
     let conn = Builder::session()?
         .name("org.freedesktop.ratbag1")?
@@ -561,7 +664,10 @@ pub async fn run_server(
     };
 
     /* All mutable bookkeeping for the event loop; see ServerState. */
-    let mut state = ServerState::default();
+    let mut state = ServerState {
+        battery_config: Arc::new(load_battery_config()),
+        ..ServerState::default()
+    };
 
     /* Internal channel through which wake-watchers request a re-probe
      * of a parked device.  `reprobe_tx` is kept alive here so the recv
@@ -731,6 +837,9 @@ pub async fn run_server(
             }
 
             DeviceAction::Remove { sysname } => {
+                if let Some(poller) = state.battery_pollers.remove(&sysname) {
+                    poller.abort();
+                }
                 /* Cancel any pending wake-watcher for the unplugged node. */
                 if let Some(pending) = state.pending_devices.remove(&sysname) {
                     pending.watcher.abort();
@@ -815,11 +924,14 @@ pub async fn run_server(
         }
     }
 
-    /* Cancel any wake-watchers still parked so the runtime does not
-     * keep polling vanished nodes during shutdown. */
+    /* Cancel any wake-watchers still parked and the battery pollers so
+     * the runtime does not keep polling devices during shutdown. */
     for (_, pending) in state.pending_devices.drain() {
         pending.watcher.abort();
     }
+    for (_, poller) in state.battery_pollers.drain() {
+        poller.abort();
+    }
 
     info!("udev monitor channel closed, shutting down");
     Ok(())
@@ -0,0 +1,178 @@
+/* DBus Power interface: battery charge, sleep timeout and low-battery alert of wireless devices,
+ * registered on the device object and backed by the shared DeviceInfo plus the device actor for
+ * battery reads. */
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+use zbus::interface;
+use zbus::object_server::SignalEmitter;
+
+use crate::engine::actor::ActorHandle;
+use crate::engine::device::{DeviceInfo, PowerInfo};
+
+/// The `org.freedesktop.ratbag1.Power` interface.
+///
+/// Registered alongside the Device interface when the driver reports
+/// battery and power-saving state for the device.
+pub struct RatbagPower {
+    info: Arc<RwLock<DeviceInfo>>,
+    path: String,
+    actor: Option<ActorHandle>,
+}
+
+impl RatbagPower {
+    pub fn new(info: Arc<RwLock<DeviceInfo>>, path: String, actor: Option<ActorHandle>) -> Self {
+        Self { info, path, actor }
+    }
+
+    /* Announce a new battery reading: level, charging and asleep state. */
+    pub async fn battery_changed(&self, emitter: &SignalEmitter<'_>) {
+        let _ = self.battery_level_changed(emitter).await;
+        let _ = self.is_charging_changed(emitter).await;
+        let _ = self.is_asleep_changed(emitter).await;
+    }
+
+    /* Read a value from the power state; `default` if the device has none. */
+    async fn power<T>(&self, default: T, f: impl FnOnce(&PowerInfo) -> T) -> T {
+        self.info.read().await.power.as_ref().map_or(default, f)
+    }
+}
+
+#[interface(name = "org.freedesktop.ratbag1.Power")]
+impl RatbagPower {
+    /// Battery charge in percent (0-100), or -1 if it has not been read.
+    #[zbus(property)]
+    async fn battery_level(&self) -> i32 {
+        self.power(-1, |p| p.battery.map_or(-1, |b| b.level as i32))
+            .await
+    }
+
+    /// True while the battery is charging.
+    #[zbus(property)]
+    async fn is_charging(&self) -> bool {
+        self.power(false, |p| p.battery.is_some_and(|b| b.is_charging))
+            .await
+    }
+
+    /// True if the last battery read found the device asleep.
+    /// `BatteryLevel` then still holds the previous reading.
+    #[zbus(property)]
+    async fn is_asleep(&self) -> bool {
+        self.power(false, |p| p.is_asleep).await
+    }
+
+    /// Idle time in seconds before the device goes to sleep, or -1 if the
+    /// device cannot report it.  0 means the device never sleeps.
+    #[zbus(property)]
+    async fn sleep_timeout(&self) -> i32 {
+        self.power(-1, |p| p.sleep_timeout.map_or(-1, |t| t as i32))
+            .await
+    }
+
+    /// Set the sleep timeout in seconds; must be one of `SleepTimeouts`.
+    ///
+    /// Takes effect on the next Device `Commit`.
+    #[zbus(property)]
+    async fn set_sleep_timeout(
+        &self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        seconds: i32,
+    ) -> zbus::Result<()> {
+        {
+            let mut info = self.info.write().await;
+            let allowed = info
+                .power
+                .as_ref()
+                .map(|p| p.sleep_timeouts.clone())
+                .unwrap_or_default();
+            let seconds = u32::try_from(seconds)
+                .ok()
+                .filter(|s| allowed.contains(s))
+                .ok_or_else(|| {
+                    zbus::fdo::Error::InvalidArgs(format!(
+                        "Sleep timeout {seconds} not supported (expected one of {allowed:?})"
+                    ))
+                })?;
+            *info = info.with_sleep_timeout(seconds);
+        }
+        let _ = self.sleep_timeout_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Sleep timeouts the device accepts, in seconds.  Empty if the
+    /// timeout cannot be changed.
+    #[zbus(property)]
+    async fn sleep_timeouts(&self) -> Vec<u32> {
+        self.power(Vec::new(), |p| p.sleep_timeouts.clone()).await
+    }
+
+    /// Battery level in percent at which the device warns the user, or -1
+    /// if the device has no low-battery alert.  0 turns the alert off.
+    #[zbus(property)]
+    async fn low_battery_alert(&self) -> i32 {
+        self.power(-1, |p| p.low_battery_alert.map_or(-1, |a| a as i32))
+            .await
+    }
+
+    /// Set the low-battery alert threshold; must be one of
+    /// `LowBatteryAlerts`.
+    ///
+    /// Takes effect on the next Device `Commit`.
+    #[zbus(property)]
+    async fn set_low_battery_alert(
+        &self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+        percent: i32,
+    ) -> zbus::Result<()> {
+        {
+            let mut info = self.info.write().await;
+            let allowed = info
+                .power
+                .as_ref()
+                .map(|p| p.low_battery_alerts.clone())
+                .unwrap_or_default();
+            let percent = u32::try_from(percent)
+                .ok()
+                .filter(|p| allowed.contains(p))
+                .ok_or_else(|| {
+                    zbus::fdo::Error::InvalidArgs(format!(
+                        "Low-battery alert {percent}% not supported (expected one of {allowed:?})"
+                    ))
+                })?;
+            *info = info.with_low_battery_alert(percent);
+        }
+        let _ = self.low_battery_alert_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Low-battery alert thresholds the device accepts, in percent.  Empty
+    /// if the device has no such alert.
+    #[zbus(property)]
+    async fn low_battery_alerts(&self) -> Vec<u32> {
+        self.power(Vec::new(), |p| p.low_battery_alerts.clone()).await
+    }
+
+    /// Read the battery from the device now.
+    ///
+    /// Updates `BatteryLevel`, `IsCharging` and `IsAsleep` and returns the
+    /// new level, or -1 if the device is asleep and has no earlier reading.
+    async fn refresh_battery(
+        &self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<i32> {
+        let Some(ref actor) = self.actor else {
+            return Err(zbus::fdo::Error::Failed(
+                "No driver actor for this device".into(),
+            ));
+        };
+        let status = actor.read_battery().await.map_err(|e| {
+            tracing::error!("Battery read failed for {}: {e}", self.path);
+            zbus::fdo::Error::Failed(e)
+        })?;
+        self.battery_changed(&emitter).await;
+        match status {
+            Some(status) => Ok(status.level as i32),
+            None => Ok(self.battery_level().await),
+        }
+    }
+}
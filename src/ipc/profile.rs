@@ -12,8 +12,13 @@ use tokio::sync::RwLock;
 use zbus::interface;
 use zbus::zvariant::ObjectPath;
 
+use crate::engine::actor::ActorHandle;
 use crate::engine::device::{DeviceInfo, ProfileInfo};
 
+use super::button::RatbagButton;
+use super::led::RatbagLed;
+use super::resolution::RatbagResolution;
+
 /// The `org.freedesktop.ratbag1.Profile` interface.
 ///
 /// Represents one of a device's configurable profiles, containing
@@ -27,6 +32,7 @@ pub struct RatbagProfile {
     device_info: Arc<RwLock<DeviceInfo>>,
     device_path: String,
     profile_id: u32,
+    actor: Option<ActorHandle>,
 }
 
 impl RatbagProfile {
@@ -34,11 +40,83 @@ impl RatbagProfile {
         device_info: Arc<RwLock<DeviceInfo>>,
         device_path: String,
         profile_id: u32,
+        actor: Option<ActorHandle>,
     ) -> Self {
         Self {
             device_info,
             device_path,
             profile_id,
+            actor,
+        }
+    }
+
+    /* Emit `PropertiesChanged` for every property a profile reset or
+     * copy may have touched, on this profile and on each of its child
+     * objects. */
+    async fn emit_profile_changes(
+        &self,
+        server: &zbus::ObjectServer,
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+    ) {
+        let _ = self.name_changed(emitter).await;
+        let _ = self.disabled_changed(emitter).await;
+        let _ = self.report_rate_changed(emitter).await;
+        let _ = self.angle_snapping_changed(emitter).await;
+        let _ = self.debounce_changed(emitter).await;
+        let _ = self.is_dirty_changed(emitter).await;
+
+        let (resolutions, buttons, leds) = {
+            let info = self.device_info.read().await;
+            let Some(profile) = info.find_profile(self.profile_id) else {
+                return;
+            };
+            (
+                profile.resolutions.iter().map(|r| r.index).collect::<Vec<_>>(),
+                profile.buttons.iter().map(|b| b.index).collect::<Vec<_>>(),
+                profile.leds.iter().map(|l| l.index).collect::<Vec<_>>(),
+            )
+        };
+        let base = format!("{}/p{}", self.device_path, self.profile_id);
+
+        for r in resolutions {
+            let path = format!("{base}/r{r}");
+            if let Ok(iface_ref) = server.interface::<_, RatbagResolution>(path.as_str()).await {
+                let iface = iface_ref.get().await;
+                let emitter = iface_ref.signal_emitter();
+                let _ = iface.resolution_changed(emitter).await;
+                let _ = iface.is_active_changed(emitter).await;
+                let _ = iface.is_default_changed(emitter).await;
+                let _ = iface.is_shift_changed(emitter).await;
+                let _ = iface.is_disabled_changed(emitter).await;
+            }
+        }
+
+        for b in buttons {
+            let path = format!("{base}/b{b}");
+            if let Ok(iface_ref) = server.interface::<_, RatbagButton>(path.as_str()).await {
+                let iface = iface_ref.get().await;
+                let emitter = iface_ref.signal_emitter();
+                let _ = iface.mapping_changed(emitter).await;
+                let _ = iface.macro_events_changed(emitter).await;
+                let _ = iface.macro_name_changed(emitter).await;
+                let _ = iface.macro_group_changed(emitter).await;
+                let _ = iface.macro_loop_count_changed(emitter).await;
+            }
+        }
+
+        for l in leds {
+            let path = format!("{base}/l{l}");
+            if let Ok(iface_ref) = server.interface::<_, RatbagLed>(path.as_str()).await {
+                let iface = iface_ref.get().await;
+                let emitter = iface_ref.signal_emitter();
+                let _ = iface.mode_changed(emitter).await;
+                let _ = iface.effect_changed(emitter).await;
+                let _ = iface.color_changed(emitter).await;
+                let _ = iface.secondary_color_changed(emitter).await;
+                let _ = iface.tertiary_color_changed(emitter).await;
+                let _ = iface.effect_duration_changed(emitter).await;
+                let _ = iface.brightness_changed(emitter).await;
+            }
         }
     }
 }
@@ -216,7 +294,9 @@ impl RatbagProfile {
     // Angle snapping / debounce / report rate
     // ------------------------------------------------------------------
 
-    /// Sensor angle snapping (-1 = unsupported, 0 = off, 1 = on).
+    /// Sensor angle snapping (-1 = unsupported, 0 = off, 1 = on).  Where
+    /// the device has one setting for all profiles, writing it on one
+    /// profile changes it on every profile.
     #[zbus(property)]
     async fn angle_snapping(&self) -> i32 {
         let info = self.device_info.read().await;
@@ -279,7 +359,53 @@ impl RatbagProfile {
             .unwrap_or_default()
     }
 
-    /// Report rate in Hz.
+    /// Sensor lift-off distance in millimetres (-1 = unsupported).
+    #[zbus(property)]
+    async fn lift_off_distance(&self) -> i32 {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .map(|p| p.lift_off_distance)
+            .unwrap_or(-1)
+    }
+
+    /// Set the lift-off distance; must be one of `LiftOffDistances`.  Like
+    /// angle snapping, a device-wide distance changes on every profile.
+    #[zbus(property)]
+    async fn set_lift_off_distance(
+        &self,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        value: i32,
+    ) -> zbus::Result<()> {
+        {
+            let mut info = self.device_info.write().await;
+            let profile = info
+                .find_profile(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            if !u32::try_from(value).is_ok_and(|v| profile.lift_off_distances.contains(&v)) {
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "Lift-off distance {value} not supported (expected one of {:?})",
+                    profile.lift_off_distances
+                ))
+                .into());
+            }
+            *info = info.with_profile_lift_off_distance(self.profile_id, value);
+        }
+        let _ = self.lift_off_distance_changed(&emitter).await;
+        let _ = self.is_dirty_changed(&emitter).await;
+        Ok(())
+    }
+
+    /// Permitted lift-off distances in millimetres (constant).  Empty if
+    /// the distance cannot be changed.
+    #[zbus(property)]
+    async fn lift_off_distances(&self) -> Vec<u32> {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .map(|p| p.lift_off_distances.clone())
+            .unwrap_or_default()
+    }
+
+    /// Report rate in Hz, or 0 if the device cannot report it.
     #[zbus(property)]
     async fn report_rate(&self) -> u32 {
         let info = self.device_info.read().await;
@@ -391,4 +517,96 @@ impl RatbagProfile {
         tracing::info!("Profile {} set as active", self.profile_id);
         Ok(())
     }
+
+    /// Restore this profile to its factory defaults.
+    ///
+    /// Devices that keep a ROM copy of their profiles (HID++ 2.0) have it
+    /// written back to flash immediately, so the profile is clean afterwards.
+    /// Other drivers reset the profile to a driver-defined default and leave
+    /// it dirty; call `Commit` on the device to apply it.
+    async fn reset_to_default(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let _ = self
+            .device_info
+            .read()
+            .await
+            .find_profile(self.profile_id)
+            .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+
+        let Some(ref actor) = self.actor else {
+            return Err(zbus::fdo::Error::Failed(
+                "No driver actor for this device".into(),
+            ));
+        };
+
+        actor.reset_profile(self.profile_id).await.map_err(|e| {
+            tracing::error!("Profile {} reset failed: {e}", self.profile_id);
+            zbus::fdo::Error::Failed(e)
+        })?;
+
+        self.emit_profile_changes(server, &emitter).await;
+
+        tracing::info!("Profile {} reset to defaults", self.profile_id);
+        Ok(())
+    }
+
+    /// Copy the resolutions, buttons, LEDs, report rate and debounce of
+    /// another profile onto this one.
+    ///
+    /// `source` may be a profile of this device or of another device of
+    /// the same model.  Name and enabled state are kept.  The profile is
+    /// marked dirty; call `Commit` on the device to apply it.
+    async fn copy_from(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+        #[zbus(signal_emitter)] emitter: zbus::object_server::SignalEmitter<'_>,
+        source: ObjectPath<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let source_ref = server
+            .interface::<_, RatbagProfile>(&source)
+            .await
+            .map_err(|_| zbus::fdo::Error::InvalidArgs(format!("No profile at {source}")))?;
+        let (source_info, source_id) = {
+            let source = source_ref.get().await;
+            (Arc::clone(&source.device_info), source.profile_id)
+        };
+        let same_device = Arc::ptr_eq(&source_info, &self.device_info);
+        if same_device && source_id == self.profile_id {
+            return Err(zbus::fdo::Error::InvalidArgs(
+                "Cannot copy a profile onto itself".into(),
+            ));
+        }
+
+        /* Take the source snapshot first so at most one lock is held. */
+        let (model, profile) = {
+            let info = source_info.read().await;
+            let profile = info
+                .find_profile(source_id)
+                .cloned()
+                .ok_or_else(|| zbus::fdo::Error::Failed("Source profile not found".into()))?;
+            (info.model.clone(), profile)
+        };
+
+        {
+            let mut info = self.device_info.write().await;
+            if info.model != model {
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "Cannot copy a {model} profile to a {} device",
+                    info.model
+                )));
+            }
+            let _ = info
+                .find_profile(self.profile_id)
+                .ok_or_else(|| zbus::fdo::Error::Failed("Profile not found".into()))?;
+            *info = info.with_profile_copied_from(self.profile_id, &profile);
+        }
+
+        self.emit_profile_changes(server, &emitter).await;
+
+        tracing::info!("Profile {} copied from {source}", self.profile_id);
+        Ok(())
+    }
 }
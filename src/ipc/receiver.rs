@@ -0,0 +1,124 @@
+/* DBus Receiver interface: pairing management for devices reached through a wireless receiver,
+ * registered on the device object and forwarded to the device actor. */
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use zbus::interface;
+use zbus::object_server::SignalEmitter;
+
+use crate::engine::actor::ActorHandle;
+use crate::engine::device::PairingOutcome;
+
+/// The `org.freedesktop.ratbag1.Receiver` interface.
+///
+/// Registered alongside the Device interface when the driver manages the
+/// pairing slots of the receiver the device is connected through.
+pub struct RatbagReceiver {
+    path: String,
+    actor: ActorHandle,
+    /* Set while a pairing window is open, so overlapping requests are
+     * refused instead of queueing behind it in the actor. */
+    pairing: Arc<AtomicBool>,
+}
+
+impl RatbagReceiver {
+    pub fn new(path: String, actor: ActorHandle) -> Self {
+        Self {
+            path,
+            actor,
+            pairing: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+#[interface(name = "org.freedesktop.ratbag1.Receiver")]
+impl RatbagReceiver {
+    /// List the receiver's occupied pairing slots as `(slot, wireless PID, name)`.
+    ///
+    /// Slots are numbered from 1.  The name is the one the receiver stored
+    /// at pairing time and may be empty.
+    async fn list_paired(&self) -> zbus::fdo::Result<Vec<(u32, u32, String)>> {
+        let devices = self.actor.paired_devices().await.map_err(|e| {
+            tracing::error!("Listing paired devices failed for {}: {e}", self.path);
+            zbus::fdo::Error::Failed(e)
+        })?;
+        Ok(devices
+            .into_iter()
+            .map(|d| (u32::from(d.slot), u32::from(d.wpid), d.name))
+            .collect())
+    }
+
+    /// Open the pairing window for `timeout` seconds (1-255).
+    ///
+    /// Returns once the pairing request has been queued for the device,
+    /// before the window is actually open; failures to open it and the
+    /// result arrive through the `PairingFinished` signal.  The device
+    /// does not process other requests until the window closes.
+    async fn start_pairing(
+        &self,
+        timeout: u32,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let timeout = u8::try_from(timeout)
+            .ok()
+            .filter(|t| *t > 0)
+            .ok_or_else(|| {
+                zbus::fdo::Error::InvalidArgs(format!(
+                    "Pairing timeout {timeout} out of range (1-255 seconds)"
+                ))
+            })?;
+        if self.pairing.swap(true, Ordering::AcqRel) {
+            return Err(zbus::fdo::Error::Failed(
+                "A pairing window is already open".into(),
+            ));
+        }
+
+        let actor = self.actor.clone();
+        let pairing = Arc::clone(&self.pairing);
+        let path = self.path.clone();
+        let emitter = emitter.to_owned();
+        tokio::spawn(async move {
+            let outcome = actor.pair_device(timeout).await.unwrap_or_else(|e| {
+                tracing::error!("Pairing failed for {path}: {e}");
+                PairingOutcome::Failed
+            });
+            pairing.store(false, Ordering::Release);
+
+            let (slot, wpid) = match outcome {
+                PairingOutcome::Paired { slot, wpid } => (u32::from(slot), u32::from(wpid)),
+                _ => (0, 0),
+            };
+            if let Err(e) = Self::pairing_finished(&emitter, outcome.status(), slot, wpid).await {
+                tracing::warn!("Failed to emit PairingFinished for {path}: {e}");
+            }
+        });
+        Ok(())
+    }
+
+    /// Unpair the device in receiver slot `slot`.
+    async fn unpair(&self, slot: u32) -> zbus::fdo::Result<()> {
+        let slot = u8::try_from(slot)
+            .ok()
+            .filter(|s| (1..=6).contains(s))
+            .ok_or_else(|| {
+                zbus::fdo::Error::InvalidArgs(format!("Receiver slot {slot} out of range (1-6)"))
+            })?;
+        self.actor.unpair_device(slot).await.map_err(|e| {
+            tracing::error!("Unpairing slot {slot} failed for {}: {e}", self.path);
+            zbus::fdo::Error::Failed(e)
+        })
+    }
+
+    /// Signal emitted when a pairing window closes.
+    ///
+    /// `status` is 0 = paired, 1 = timed out, 2 = unsupported device,
+    /// 3 = too many paired devices, 4 = failed.  `slot` and `wpid` describe
+    /// the new device and are 0 unless pairing succeeded.
+    #[zbus(signal)]
+    async fn pairing_finished(
+        signal_emitter: &SignalEmitter<'_>,
+        status: u32,
+        slot: u32,
+        wpid: u32,
+    ) -> zbus::Result<()>;
+}
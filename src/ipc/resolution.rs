@@ -6,7 +6,9 @@ use tokio::sync::RwLock;
 use zbus::interface;
 use zbus::zvariant::{OwnedValue, Value};
 
-use crate::engine::device::{DeviceInfo, Dpi, RATBAG_RESOLUTION_CAP_SEPARATE_XY_RESOLUTION};
+use crate::engine::device::{
+    DeviceInfo, Dpi, RATBAG_RESOLUTION_CAP_SEPARATE_XY_RESOLUTION, RATBAG_RESOLUTION_CAP_SHIFT,
+};
 
 use super::fallback_owned_value;
 use super::profile::RatbagProfile;
@@ -119,6 +121,16 @@ impl RatbagResolution {
             .is_some_and(|r| r.is_default)
     }
 
+    /// Whether this is the shift ("sniper") resolution used while a
+    /// DPI-shift button is held (read-only, see `SetShift`).
+    #[zbus(property)]
+    async fn is_shift(&self) -> bool {
+        let info = self.device_info.read().await;
+        info.find_profile(self.profile_id)
+            .and_then(|p| p.find_resolution(self.resolution_id))
+            .is_some_and(|r| r.is_shift)
+    }
+
     /// Whether this resolution is disabled (read-write).
     #[zbus(property)]
     async fn is_disabled(&self) -> bool {
@@ -156,6 +168,7 @@ impl RatbagResolution {
     }
 
     /// DPI value as a variant: either a `u32` or a `(u32, u32)` tuple.
+    /// A plain 0 means the device cannot report the value.
     #[zbus(property)]
     async fn resolution(&self) -> OwnedValue {
         let info = self.device_info.read().await;
@@ -250,12 +263,17 @@ impl RatbagResolution {
                 ))
             })?;
             sibling_count = profile.resolutions.len();
-            let _ = profile.find_resolution(self.resolution_id).ok_or_else(|| {
+            let res = profile.find_resolution(self.resolution_id).ok_or_else(|| {
                 zbus::fdo::Error::Failed(format!(
                     "Resolution {} not found in profile {}",
                     self.resolution_id, self.profile_id
                 ))
             })?;
+            if res.is_dedicated_shift() {
+                return Err(zbus::fdo::Error::NotSupported(
+                    "The shift resolution cannot be made active".to_string(),
+                ));
+            }
             *info = info.with_active_resolution(self.profile_id, self.resolution_id);
         }
 
@@ -313,12 +331,17 @@ impl RatbagResolution {
                 ))
             })?;
             sibling_count = profile.resolutions.len();
-            let _ = profile.find_resolution(self.resolution_id).ok_or_else(|| {
+            let res = profile.find_resolution(self.resolution_id).ok_or_else(|| {
                 zbus::fdo::Error::Failed(format!(
                     "Resolution {} not found in profile {}",
                     self.resolution_id, self.profile_id
                 ))
             })?;
+            if res.is_dedicated_shift() {
+                return Err(zbus::fdo::Error::NotSupported(
+                    "The shift resolution cannot be made default".to_string(),
+                ));
+            }
             *info = info.with_default_resolution(self.profile_id, self.resolution_id);
         }
 
@@ -356,4 +379,68 @@ impl RatbagResolution {
         );
         Ok(0)
     }
+
+    /// Make this the shift resolution used while a DPI-shift button is held.
+    ///
+    /// Only allowed on resolutions with the SHIFT capability; clears
+    /// `IsShift` on every sibling and emits `PropertiesChanged` for each.
+    /// Returns 0 on success, like `SetDefault`.
+    async fn set_shift(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+    ) -> zbus::fdo::Result<u32> {
+        let sibling_count;
+        {
+            let mut info = self.device_info.write().await;
+            let profile = info.find_profile(self.profile_id).ok_or_else(|| {
+                zbus::fdo::Error::Failed(format!(
+                    "Profile {} not found", self.profile_id
+                ))
+            })?;
+            sibling_count = profile.resolutions.len();
+            let res = profile.find_resolution(self.resolution_id).ok_or_else(|| {
+                zbus::fdo::Error::Failed(format!(
+                    "Resolution {} not found in profile {}",
+                    self.resolution_id, self.profile_id
+                ))
+            })?;
+            if !res.capabilities.contains(&RATBAG_RESOLUTION_CAP_SHIFT) {
+                return Err(zbus::fdo::Error::NotSupported(
+                    "Device cannot use this resolution as the shift resolution".to_string(),
+                ));
+            }
+            *info = info.with_shift_resolution(self.profile_id, self.resolution_id);
+        }
+
+        for i in 0..sibling_count as u32 {
+            let path = format!("{}/p{}/r{}", self.device_path, self.profile_id, i);
+            if let Ok(iface_ref) =
+                server.interface::<_, RatbagResolution>(path.as_str()).await
+            {
+                let _ = iface_ref
+                    .get()
+                    .await
+                    .is_shift_changed(iface_ref.signal_emitter())
+                    .await;
+            }
+        }
+
+        let profile_path = format!("{}/p{}", self.device_path, self.profile_id);
+        if let Ok(iface_ref) =
+            server.interface::<_, RatbagProfile>(profile_path.as_str()).await
+        {
+            let _ = iface_ref
+                .get()
+                .await
+                .is_dirty_changed(iface_ref.signal_emitter())
+                .await;
+        }
+
+        tracing::info!(
+            "Resolution {} in profile {} set as shift resolution",
+            self.resolution_id,
+            self.profile_id,
+        );
+        Ok(0)
+    }
 }
@@ -113,6 +113,40 @@ class RatbagDBusClient:
             )
         )
 
+    def manager_set_lighting_all(
+        self, mode: int, colors: list[tuple[int, int, int]], brightness: int
+    ) -> dict[str, tuple[list[tuple[int, int, int]], str]]:
+        """Apply one lighting setup everywhere; returns {path: (mapping, error)}."""
+        result = self._call_method(
+            MANAGER_PATH,
+            MANAGER_IFACE,
+            "SetLightingAll",
+            dbus.UInt32(mode),
+            dbus.Array(
+                [dbus.Struct([dbus.UInt32(c) for c in color], signature="uuu") for color in colors],
+                signature="(uuu)",
+            ),
+            dbus.UInt32(brightness),
+        )
+        return {
+            str(path): ([(int(p), int(led), int(m)) for p, led, m in mapping], str(error))
+            for path, mapping, error in result
+        }
+
+    def manager_commit_all(self, rollback: bool) -> dict[str, str]:
+        """Commit every dirty device; returns {path: error}, "" on success."""
+        result = self._call_method(
+            MANAGER_PATH, MANAGER_IFACE, "CommitAll", dbus.Boolean(rollback)
+        )
+        return {str(path): str(error) for path, error in result.items()}
+
+    def manager_set_focused_application(self, app_id: str) -> list[tuple[str, int, str]]:
+        """Report a focus change; returns [(device, profile, error)]."""
+        result = self._call_method(
+            MANAGER_PATH, MANAGER_IFACE, "SetFocusedApplication", app_id
+        )
+        return [(str(path), int(profile), str(error)) for path, profile, error in result]
+
     def reset_test_device(self):
         """Remove the currently injected test device (requires dev-hooks)."""
         self._call_method(MANAGER_PATH, MANAGER_IFACE, "ResetTestDevice")
@@ -221,6 +255,9 @@ class RatbagDBusClient:
     def profile_set_active(self, path: str):
         self._call_method(path, PROFILE_IFACE, "SetActive")
 
+    def profile_copy_from(self, path: str, source: str):
+        self._call_method(path, PROFILE_IFACE, "CopyFrom", dbus.ObjectPath(source))
+
     def profile_capabilities(self, path: str) -> list[int]:
         caps = self._get_property(path, PROFILE_IFACE, "Capabilities")
         return [int(c) for c in caps]
@@ -367,3 +404,6 @@ class RatbagDBusClient:
         self._set_property(
             path, LED_IFACE, "EffectDuration", dbus.UInt32(duration)
         )
+
+    def led_preview(self, path: str):
+        self._call_method(path, LED_IFACE, "Preview")
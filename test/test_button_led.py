@@ -362,3 +362,16 @@ class TestLed:
         assert len(leds) == 1
         r, g, b = dbus_client.led_color(leds[0])
         assert r == 0 and g == 255 and b == 0
+
+    def test_led_preview_needs_a_driver(self, dbus_client: RatbagDBusClient):
+        """Preview talks to hardware, so a test device rejects it unchanged."""
+        path = _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)
+        profile = _first_profile(dbus_client, path)
+        leds = dbus_client.profile_leds(profile)
+        before = dbus_client.led_color(leds[0])
+
+        with pytest.raises(dbus.exceptions.DBusException):
+            dbus_client.led_preview(leds[0])
+
+        assert dbus_client.led_color(leds[0]) == before
+        assert not dbus_client.profile_is_dirty(profile)
@@ -7,6 +7,7 @@ using synthetic test devices injected through the dev-hooks feature.
 
 import time
 
+import dbus
 import pytest
 
 from .conftest import (
@@ -86,6 +87,69 @@ class TestManager:
         assert path1 not in devices
         assert path2 in devices
 
+    @pytest.mark.requires_dev_hooks
+    def test_set_lighting_all_uses_nearest_mode(self, dbus_client: RatbagDBusClient):
+        """Starlight is not offered by the test LED and falls back to breathing."""
+        path = _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)
+        results = dbus_client.manager_set_lighting_all(5, [(0, 0, 255)], 128)
+        mapping, error = results[path]
+        assert mapping == [(0, 0, 3)]
+        # Test devices have no actor, so the state changes but cannot commit
+        assert error != ""
+
+        led = dbus_client.profile_leds(dbus_client.device_profiles(path)[0])[0]
+        assert dbus_client.led_mode(led) == 3
+        assert dbus_client.led_color(led) == (0, 0, 255)
+        assert dbus_client.led_brightness(led) == 128
+
+    @pytest.mark.requires_dev_hooks
+    def test_set_lighting_all_rejects_bad_mode(self, dbus_client: RatbagDBusClient):
+        """An unknown mode is rejected before any device is touched."""
+        _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)
+        with pytest.raises(dbus.exceptions.DBusException):
+            dbus_client.manager_set_lighting_all(99, [], 255)
+
+    @pytest.mark.requires_dev_hooks
+    def test_commit_all_skips_clean_devices(self, dbus_client: RatbagDBusClient):
+        """A device without pending changes is not part of the outcome."""
+        path = _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)
+        assert path not in dbus_client.manager_commit_all(False)
+
+    @pytest.mark.requires_dev_hooks
+    def test_commit_all_reports_dirty_devices(self, dbus_client: RatbagDBusClient):
+        """A dirty test device has no actor and reports an error."""
+        path = _load_and_get_device(dbus_client, SIMPLE_DEVICE_JSON)
+        profile = dbus_client.device_profiles(path)[0]
+        dbus_client.set_profile_name(profile, "Pending")
+        assert dbus_client.profile_is_dirty(profile)
+
+        results = dbus_client.manager_commit_all(True)
+        assert results[path] != ""
+        assert dbus_client.profile_is_dirty(profile)
+
+    @pytest.mark.requires_dev_hooks
+    def test_focus_switches_profile(self, dbus_client: RatbagDBusClient):
+        """Focus changes follow test/profile-rules.ini, if the daemon loaded it."""
+        path = _load_and_get_device(dbus_client, MULTI_PROFILE_DEVICE_JSON)
+        profiles = dbus_client.device_profiles(path)
+        # No rule names this app; it only resets the last focused one
+        assert dbus_client.manager_set_focused_application("ratbag-test-none") == []
+
+        results = dbus_client.manager_set_focused_application("ratbag-test-game")
+        if not results:
+            pytest.skip("daemon not started with RATBAGD_PROFILE_RULES=test/profile-rules.ini")
+        (device, profile, error), = results
+        assert (device, profile) == (path, 1)
+        # Test devices have no actor, so the state switches but the device cannot
+        assert error != ""
+        assert dbus_client.profile_is_active(profiles[1])
+
+        # Repeated reports of the same application do nothing
+        assert dbus_client.manager_set_focused_application("ratbag-test-game") == []
+
+        dbus_client.manager_set_focused_application("ratbag-test-home")
+        assert dbus_client.profile_is_active(profiles[0])
+
 
 # ===========================================================================
 # Device tests
@@ -7,6 +7,7 @@ child object enumeration, and resolution read/write (unified & separate DPI).
 
 import time
 
+import dbus
 import pytest
 
 from .conftest import (
@@ -188,6 +189,30 @@ class TestProfile:
         leds = dbus_client.profile_leds(profile)
         assert len(leds) == 1
 
+    def test_profile_copy_from(self, dbus_client: RatbagDBusClient):
+        """CopyFrom takes settings but keeps the target's name and state."""
+        path = _load_and_get_device(dbus_client, MULTI_PROFILE_DEVICE_JSON)
+        profiles = dbus_client.device_profiles(path)
+        name = dbus_client.profile_name(profiles[2])
+
+        dbus_client.profile_copy_from(profiles[2], profiles[0])
+        assert dbus_client.profile_report_rate(profiles[2]) == 1000
+        res = dbus_client.profile_resolutions(profiles[2])[0]
+        assert int(dbus_client.resolution_value(res)) == 800
+        button = dbus_client.profile_buttons(profiles[2])[0]
+        assert int(dbus_client.button_mapping(button)[0]) == 1
+        assert dbus_client.profile_name(profiles[2]) == name
+        assert dbus_client.profile_disabled(profiles[2]) is True
+        assert dbus_client.profile_is_dirty(profiles[2]) is True
+        assert dbus_client.profile_is_dirty(profiles[0]) is False
+
+    def test_profile_copy_onto_itself_rejected(self, dbus_client: RatbagDBusClient):
+        """A profile cannot be its own source."""
+        path = _load_and_get_device(dbus_client, MULTI_PROFILE_DEVICE_JSON)
+        profile = _first_profile(dbus_client, path)
+        with pytest.raises(dbus.exceptions.DBusException):
+            dbus_client.profile_copy_from(profile, profile)
+
 
 # ===========================================================================
 # Resolution tests